@@ -0,0 +1,169 @@
+// 基准测试，结果以 JSON Lines 的格式输出，便于比较不同版本的性能
+//
+// cargo bench                                  // 运行所有
+// cargo bench -- switch                        // 只运行名称包含 switch 的
+// cargo bench -- --quick                       // 减少迭代次数
+// cargo bench -- --output target/bench.jsonl   // 同时写入文件
+//
+// 每一行的格式：
+// {"name": "switch/fanout/10", "iters": 100000, "ns_per_op": 812.3, "ops_per_sec": 1231000.0}
+// 测量延迟的项目还会带有 p50_us、p90_us、p99_us 和 max_us
+
+use std::env;
+use std::fs::File;
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+
+mod bench_switch;
+mod bench_wire;
+mod bench_codec;
+mod bench_net;
+
+pub struct Bench {
+    filter: Vec<String>,
+    quick: bool,
+    output: Option<File>
+}
+
+impl Bench {
+    fn from_args() -> Bench {
+        let mut bench = Bench {
+            filter: Vec::new(),
+            quick: false,
+            output: None
+        };
+
+        let mut args = env::args().skip(1);
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--quick" => bench.quick = true,
+                "--output" => {
+                    let path = args.next().expect("--output <path>");
+                    bench.output = Some(File::create(&path).expect("create output file"));
+                }
+                // cargo bench 会传入 --bench
+                _ if arg.starts_with("--") => (),
+                _ => bench.filter.push(arg)
+            }
+        }
+
+        bench
+    }
+
+    pub fn enabled(&self, name: &str) -> bool {
+        self.filter.is_empty() || self.filter.iter().any(|f| name.contains(f.as_str()))
+    }
+
+    // 迭代次数，--quick 时缩小到 1/10
+    pub fn iters(&self, iters: u64) -> u64 {
+        if self.quick {
+            (iters / 10).max(1)
+        } else {
+            iters
+        }
+    }
+
+    // f 执行 iters 次操作，返回实际执行的次数
+    pub fn run(&mut self, name: &str, iters: u64, mut f: impl FnMut(u64) -> u64) {
+        if !self.enabled(name) {
+            return
+        }
+
+        let iters = self.iters(iters);
+
+        // 预热
+        f((iters / 10).max(1));
+
+        let start = Instant::now();
+        let ops = f(iters);
+        let elapsed = start.elapsed();
+
+        self.report(name, ops, elapsed, json!({}));
+    }
+
+    // f 执行一次操作，记录每次的耗时，统计延迟分布
+    pub fn latency(&mut self, name: &str, iters: u64, mut f: impl FnMut()) {
+        if !self.enabled(name) {
+            return
+        }
+
+        let iters = self.iters(iters);
+
+        for _ in 0..(iters / 10).max(1) {
+            f();
+        }
+
+        let mut samples = Vec::with_capacity(iters as usize);
+
+        let start = Instant::now();
+
+        for _ in 0..iters {
+            let now = Instant::now();
+            f();
+            samples.push(now.elapsed());
+        }
+
+        let elapsed = start.elapsed();
+
+        samples.sort_unstable();
+
+        let percentile = |p: f64| -> f64 {
+            let index = ((samples.len() as f64 * p) as usize).min(samples.len() - 1);
+            micros(samples[index])
+        };
+
+        let extra = json!({
+            "p50_us": percentile(0.5),
+            "p90_us": percentile(0.9),
+            "p99_us": percentile(0.99),
+            "max_us": micros(*samples.last().unwrap())
+        });
+
+        self.report(name, iters, elapsed, extra);
+    }
+
+    fn report(&mut self, name: &str, ops: u64, elapsed: Duration, extra: Value) {
+        let nanos = elapsed.as_nanos() as f64;
+
+        let mut line = json!({
+            "name": name,
+            "iters": ops,
+            "ns_per_op": round(nanos / ops as f64),
+            "ops_per_sec": round(ops as f64 / (nanos / 1e9))
+        });
+
+        if let (Some(line), Some(extra)) = (line.as_object_mut(), extra.as_object()) {
+            for (key, value) in extra {
+                line.insert(key.clone(), value.clone());
+            }
+        }
+
+        let line = line.to_string();
+
+        println!("{}", line);
+
+        if let Some(output) = &mut self.output {
+            writeln!(output, "{}", line).expect("write output file");
+        }
+    }
+}
+
+fn micros(duration: Duration) -> f64 {
+    round(duration.as_nanos() as f64 / 1e3)
+}
+
+fn round(n: f64) -> f64 {
+    (n * 10.0).round() / 10.0
+}
+
+fn main() {
+    let mut bench = Bench::from_args();
+
+    bench_switch::run(&mut bench);
+    bench_wire::run(&mut bench);
+    bench_codec::run(&mut bench);
+    bench_net::run(&mut bench);
+}
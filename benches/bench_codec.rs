@@ -0,0 +1,56 @@
+use queen::nson::{Message, MessageId, msg};
+use queen::net::{Codec, NsonCodec};
+use queen::crypto::{Crypto, Method};
+use queen::dict::*;
+
+use super::Bench;
+
+pub fn run(bench: &mut Bench) {
+    let methods = [
+        None,
+        Some(Method::Aes128Gcm),
+        Some(Method::Aes256Gcm),
+        Some(Method::ChaCha20Poly1305)
+    ];
+
+    for size in &[64, 1024, 64 * 1024] {
+        let message = message(*size);
+
+        for method in &methods {
+            let name = method.as_ref().map(|m| m.as_str()).unwrap_or("none");
+            let crypto = method.as_ref().map(|m| Crypto::new(m, b"queen-bench-secret"));
+
+            let mut codec = NsonCodec::new();
+
+            let iters = if *size >= 64 * 1024 { 10_000 } else { 200_000 };
+
+            bench.run(&format!("codec/encode/{}/{}", name, size), iters, |iters| {
+                for _ in 0..iters {
+                    codec.encode(&crypto, message.clone()).unwrap();
+                }
+
+                iters
+            });
+
+            let bytes = codec.encode(&crypto, message.clone()).unwrap();
+
+            bench.run(&format!("codec/decode/{}/{}", name, size), iters, |iters| {
+                for _ in 0..iters {
+                    codec.decode(&crypto, bytes.clone()).unwrap();
+                }
+
+                iters
+            });
+        }
+    }
+}
+
+// 除了 CHAN、ID 等常见字段外，带有 size 字节的数据
+fn message(size: usize) -> Message {
+    msg!{
+        CHAN: "hello",
+        ID: MessageId::new(),
+        FROM: MessageId::new(),
+        "data": vec![7u8; size]
+    }
+}
@@ -0,0 +1,88 @@
+use std::net::TcpListener;
+use std::time::Duration;
+
+use queen::{Socket, Node, Port};
+use queen::nson::{MessageId, msg};
+use queen::net::{NsonCodec, KeepAlive};
+use queen::error::{Code, SendError};
+use queen::dict::*;
+
+use super::Bench;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+pub fn run(bench: &mut Bench) {
+    if !bench.enabled("net/") {
+        return
+    }
+
+    let socket = Socket::new(MessageId::new(), ()).unwrap();
+
+    let addr = free_addr();
+
+    let _node = Node::<NsonCodec>::new(
+        socket.clone(),
+        1,
+        vec![addr.parse().unwrap()],
+        KeepAlive::default(),
+        ()
+    ).unwrap();
+
+    let port = Port::<NsonCodec>::new(KeepAlive::default()).unwrap();
+
+    // 吞吐量，Port 发送，Socket 中的 SLOT 接收
+    {
+        let sub = socket.connect(MessageId::new(), false, msg!{}, Some(4096), None).unwrap();
+
+        let _ = sub.send(msg!{CHAN: ATTACH, VALUE: "hello"});
+        assert!(Code::get(&sub.wait(Some(TIMEOUT)).unwrap()) == Some(Code::Ok));
+
+        let wire = port.connect(&addr, MessageId::new(), false, msg!{}, None, Some(4096)).unwrap();
+
+        bench.run("net/throughput", 200_000, |iters| {
+            let mut sent = 0;
+            let mut recv = 0;
+
+            // 发送的同时接收，避免接收方的 wire 被填满而丢弃消息
+            while recv < iters {
+                while sent < iters {
+                    match wire.send(msg!{CHAN: "hello", "n": 1}) {
+                        Ok(()) => sent += 1,
+                        Err(SendError::Full(_)) => break,
+                        Err(err) => panic!("{:?}", err)
+                    }
+
+                    if sent - recv >= 2048 {
+                        break
+                    }
+                }
+
+                while sub.recv().is_ok() {
+                    recv += 1;
+                }
+
+                if sent - recv >= 2048 || sent == iters {
+                    sub.wait(Some(TIMEOUT)).unwrap();
+                    recv += 1;
+                }
+            }
+
+            iters
+        });
+    }
+
+    // 延迟，PING 往返
+    {
+        let wire = port.connect(&addr, MessageId::new(), false, msg!{}, None, None).unwrap();
+
+        bench.latency("net/ping", 20_000, || {
+            wire.send(msg!{CHAN: PING}).unwrap();
+            wire.wait(Some(TIMEOUT)).unwrap();
+        });
+    }
+}
+
+fn free_addr() -> String {
+    let socket = TcpListener::bind("127.0.0.1:0").unwrap();
+    socket.local_addr().unwrap().to_string()
+}
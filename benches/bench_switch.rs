@@ -0,0 +1,127 @@
+use queen::socket::Sim;
+use queen::nson::{MessageId, msg};
+use queen::dict::*;
+
+use super::Bench;
+
+// SLOT 的 wire 容量，每发送 BATCH 条消息清空一次
+const CAPACITY: usize = 4096;
+const BATCH: u64 = 1000;
+
+pub fn run(bench: &mut Bench) {
+    // 1 -> N
+    for n in &[1, 10, 100] {
+        let mut sim = sim();
+
+        let publisher = sim.connect(MessageId::new(), false, msg!{}).unwrap();
+        let subs = subscribe(&mut sim, *n, false);
+
+        bench.run(&format!("switch/fanout/{}", n), 100_000, |iters| {
+            publish(&mut sim, publisher, &subs, iters, || msg!{CHAN: "hello", "n": 1})
+        });
+    }
+
+    // SHARE，只投递给其中一个
+    {
+        let mut sim = sim();
+
+        let publisher = sim.connect(MessageId::new(), false, msg!{}).unwrap();
+        let subs = subscribe(&mut sim, 10, true);
+
+        bench.run("switch/share/10", 100_000, |iters| {
+            publish(&mut sim, publisher, &subs, iters, || msg!{CHAN: "hello", "n": 1})
+        });
+    }
+
+    // TO 数组
+    {
+        let mut sim = sim();
+
+        let publisher = sim.connect(MessageId::new(), false, msg!{}).unwrap();
+
+        let mut subs = Vec::new();
+        let mut ids = Vec::new();
+
+        for _ in 0..10 {
+            let id = MessageId::new();
+            subs.push(sim.connect(id, false, msg!{}).unwrap());
+            ids.push(id);
+        }
+
+        bench.run("switch/to/10", 100_000, |iters| {
+            publish(&mut sim, publisher, &subs, iters, || msg!{CHAN: "hello", TO: ids.clone(), "n": 1})
+        });
+    }
+
+    // BIND，10 个 SLOT 绑定了发送者
+    {
+        let mut sim = sim();
+
+        let publisher_id = MessageId::new();
+        let publisher = sim.connect(publisher_id, false, msg!{}).unwrap();
+
+        let mut subs = subscribe(&mut sim, 1, false);
+
+        for _ in 0..10 {
+            let token = sim.connect(MessageId::new(), false, msg!{}).unwrap();
+
+            sim.send(token, msg!{CHAN: BIND, SLOT_ID: publisher_id}).unwrap();
+            sim.drain(token);
+
+            subs.push(token);
+        }
+
+        bench.run("switch/bind/10", 100_000, |iters| {
+            publish(&mut sim, publisher, &subs, iters, || msg!{CHAN: "hello", "n": 1})
+        });
+    }
+}
+
+fn sim() -> Sim<()> {
+    let mut sim = Sim::new(MessageId::new(), ()).unwrap();
+    sim.set_capacity(CAPACITY);
+    sim
+}
+
+fn subscribe(sim: &mut Sim<()>, n: usize, share: bool) -> Vec<usize> {
+    let mut subs = Vec::new();
+
+    for _ in 0..n {
+        let token = sim.connect(MessageId::new(), false, msg!{}).unwrap();
+
+        sim.send(token, msg!{CHAN: ATTACH, VALUE: "hello", SHARE: share}).unwrap();
+        sim.drain(token);
+
+        subs.push(token);
+    }
+
+    subs
+}
+
+fn publish(
+    sim: &mut Sim<()>,
+    publisher: usize,
+    subs: &[usize],
+    iters: u64,
+    message: impl Fn() -> queen::nson::Message
+) -> u64 {
+    for i in 0..iters {
+        sim.send(publisher, message()).unwrap();
+
+        if i % BATCH == BATCH - 1 {
+            drain(sim, publisher, subs);
+        }
+    }
+
+    drain(sim, publisher, subs);
+
+    iters
+}
+
+fn drain(sim: &Sim<()>, publisher: usize, subs: &[usize]) {
+    sim.drain(publisher);
+
+    for sub in subs {
+        sim.drain(*sub);
+    }
+}
@@ -0,0 +1,66 @@
+use std::thread;
+
+use queen::Wire;
+use queen::nson::{Message, msg};
+use queen::error::SendError;
+
+use super::Bench;
+
+pub fn run(bench: &mut Bench) {
+    // 同一个线程中发送和接收
+    {
+        let (wire1, wire2) = Wire::<Message>::pipe(1024, msg!{}).unwrap();
+
+        bench.run("wire/send_recv", 1_000_000, |iters| {
+            let mut i = 0;
+
+            while i < iters {
+                let batch = (iters - i).min(1000);
+
+                for _ in 0..batch {
+                    wire1.send(msg!{"n": 1}).unwrap();
+                }
+
+                for _ in 0..batch {
+                    wire2.recv().unwrap();
+                }
+
+                i += batch;
+            }
+
+            iters
+        });
+    }
+
+    // 跨线程，发送方在 wire 满时重试
+    bench.run("wire/cross_thread", 1_000_000, |iters| {
+        let (wire1, wire2) = Wire::<Message>::pipe(1024, msg!{}).unwrap();
+
+        let handle = thread::spawn(move || {
+            for _ in 0..iters {
+                let mut message = msg!{"n": 1};
+
+                loop {
+                    match wire1.send(message) {
+                        Ok(()) => break,
+                        Err(SendError::Full(m)) => {
+                            message = m;
+                            thread::yield_now();
+                        }
+                        Err(err) => panic!("{:?}", err)
+                    }
+                }
+            }
+
+            wire1
+        });
+
+        for _ in 0..iters {
+            wire2.wait(None).unwrap();
+        }
+
+        drop(handle.join().unwrap());
+
+        iters
+    });
+}
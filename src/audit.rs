@@ -0,0 +1,314 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use nson::{Message, MessageId, Array, msg};
+
+use crate::{Socket, Wire};
+use crate::dict::*;
+use crate::error::{Result, Error, Code};
+use crate::socket::{self, Slot, Switch};
+use crate::node;
+
+// 审计日志
+//
+// 记录以下事件：
+// login:     ROOT 权限的 SLOT 连接
+// kill:      ROOT 的每次 KILL 尝试，VALUE 为目标 SLOT_ID
+// ctrl:      ROOT 的每次 CTRL 尝试，KEY 为消息中操作相关的字段，比如 ACL、RULES、SCHEMA
+// denied:    Switch 返回 PermissionDenied，或者 Hook 拒绝时设置的自定义错误码，包括被拒绝的 KILL 和 CTRL
+// handshake: Node 握手失败
+//
+// 每条记录的格式：
+// {
+//     TIME: $unix_millis,
+//     EVENT: $event,
+//     CODE: $code,         // kill 和 ctrl 在执行前记录，没有 CODE
+//     SLOT_ID: $slot_id,
+//     ROOT: $root,
+//     USERNAME: $username,
+//     ADDR: $peer_addr,
+//     CHAN: $chan,
+//     ...
+// }
+//
+// Audit 同时实现了 socket::Hook 和 node::Hook，可以和其他 Hook 组合使用，比如 (Acl, audit.clone())
+// 注意，组合时前面的 Hook 返回 false 后，后面的 Hook 不会执行，因此 Audit 应当放在前面
+#[derive(Clone)]
+pub struct Audit {
+    inner: Arc<Inner>
+}
+
+struct Inner {
+    sinks: Vec<Box<dyn Sink>>,
+    slot_id: MessageId,
+    wire: Mutex<Option<Wire<Message>>>
+}
+
+// 审计记录的存储，可以自行实现
+pub trait Sink: Send + Sync + 'static {
+    fn write(&self, record: &Message);
+}
+
+impl<F: Fn(&Message) + Send + Sync + 'static> Sink for F {
+    fn write(&self, record: &Message) {
+        self(record)
+    }
+}
+
+impl Audit {
+    pub fn new() -> Self {
+        Audit::with_sinks(Vec::new())
+    }
+
+    pub fn with_sinks(sinks: Vec<Box<dyn Sink>>) -> Self {
+        Audit {
+            inner: Arc::new(Inner {
+                sinks,
+                slot_id: MessageId::new(),
+                wire: Mutex::new(None)
+            })
+        }
+    }
+
+    pub fn with_sink(sink: impl Sink) -> Self {
+        Audit::with_sinks(vec![Box::new(sink)])
+    }
+
+    // 连接到 Socket，记录会发送到 AUDIT 频道，只有 ROOT 可以 ATTACH 该频道
+    pub fn attach(&self, socket: &Socket) -> Result<()> {
+        let wire = socket.connect(self.inner.slot_id, true, msg!{}, None, Some(Duration::from_secs(10)))?;
+
+        *self.inner.wire.lock().unwrap() = Some(wire);
+
+        Ok(())
+    }
+
+    pub fn record(&self, mut record: Message) {
+        record.insert(TIME, now_millis());
+
+        for sink in &self.inner.sinks {
+            sink.write(&record);
+        }
+
+        if let Some(wire) = &*self.inner.wire.lock().unwrap() {
+            // 避免阻塞，满了就丢弃
+            let _ = wire.send(msg!{
+                CHAN: AUDIT,
+                VALUE: record
+            });
+        }
+    }
+
+    fn slot_record(&self, event: &str, slot: &Slot) -> Message {
+        let attr = slot.wire.attr();
+
+        let mut record = msg!{
+            EVENT: event,
+            SLOT_ID: slot.id,
+            ROOT: slot.root
+        };
+
+        let username = attr.get_str(USERNAME).ok()
+            .or_else(|| attr.get_message(ORIGIN).ok().and_then(|origin| origin.get_str(USERNAME).ok()));
+
+        if let Some(username) = username {
+            record.insert(USERNAME, username);
+        }
+
+        if let Ok(addr) = attr.get_str(ADDR) {
+            record.insert(ADDR, addr);
+        }
+
+        record
+    }
+}
+
+impl Default for Audit {
+    fn default() -> Self {
+        Audit::new()
+    }
+}
+
+impl socket::Hook for Audit {
+    fn accept(&self, slot: &Slot) -> bool {
+        // 不记录自己
+        if slot.root && slot.id != self.inner.slot_id {
+            let mut record = self.slot_record("login", slot);
+            record.insert(CODE, Code::Ok.code());
+
+            self.record(record);
+        }
+
+        true
+    }
+
+    // 在回复时记录被拒绝的操作，这时可以拿到错误码
+    fn send(&self, slot: &Slot, message: &mut Message) -> bool {
+        let code = match Code::get(message) {
+            Some(code) => code,
+            None => return true
+        };
+
+        // 转发给其他 SLOT 的消息带有 FROM，其中的 CODE 不是拒绝
+        if code == Code::PermissionDenied || (code.is_user() && !message.contains_key(FROM)) {
+            let mut record = self.slot_record("denied", slot);
+            record.insert(CODE, code.code());
+            record.insert(CHAN, message.get_str(CHAN).unwrap_or_default());
+
+            if let Ok(value) = message.get_str(VALUE) {
+                record.insert(VALUE, value);
+            } else if let Ok(target) = message.get_message_id(SLOT_ID) {
+                record.insert(VALUE, target);
+            }
+
+            self.record(record);
+        }
+
+        true
+    }
+
+    // ROOT 的每次 KILL 都会调用，在检查目标是否存在之前
+    fn kill(&self, slot: &Slot, message: &mut Message) -> bool {
+        let mut record = self.slot_record("kill", slot);
+
+        if let Some(target) = message.get(SLOT_ID) {
+            record.insert(VALUE, target.clone());
+        }
+
+        self.record(record);
+
+        true
+    }
+
+    // ROOT 的每次 CTRL 都会调用，记录操作相关的字段
+    fn ctrl(&self, switch: &mut Switch, token: usize, message: &mut Message) {
+        let slot = match switch.slots.get(token) {
+            Some(slot) => slot,
+            None => return
+        };
+
+        let mut record = self.slot_record("ctrl", slot);
+
+        let mut keys = Array::new();
+
+        for key in message.keys().filter(|key| ![CHAN, ID, VALUE].contains(&key.as_str())) {
+            keys.push(key.as_str());
+        }
+
+        record.insert(KEY, keys);
+
+        if let Ok(value) = message.get_str(VALUE) {
+            record.insert(VALUE, value);
+        }
+
+        self.record(record);
+    }
+}
+
+impl node::Hook for Audit {
+    fn fail(&self, addr: &SocketAddr, err: &Error) {
+        let code = err.code().unwrap_or(Code::UnknownError);
+
+        self.record(msg!{
+            EVENT: "handshake",
+            CODE: code.code(),
+            ADDR: addr.to_string(),
+            ERROR: err.to_string()
+        });
+    }
+}
+
+// 以 JSON Lines 格式写入文件，超过 max_size 时轮转：
+// audit.log -> audit.log.1 -> audit.log.2 ... 最多保留 max_files 个旧文件
+pub struct FileSink {
+    inner: Mutex<FileInner>
+}
+
+struct FileInner {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_size: u64,
+    max_files: usize
+}
+
+impl FileSink {
+    pub fn new(path: impl AsRef<Path>, max_size: u64, max_files: usize) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+
+        Ok(FileSink {
+            inner: Mutex::new(FileInner {
+                path,
+                file,
+                size,
+                max_size,
+                max_files
+            })
+        })
+    }
+}
+
+impl FileInner {
+    fn rotate(&mut self) -> Result<()> {
+        let name = |i: usize| {
+            let mut path = self.path.clone().into_os_string();
+            path.push(format!(".{}", i));
+            PathBuf::from(path)
+        };
+
+        if self.max_files == 0 {
+            self.file = OpenOptions::new().create(true).write(true).truncate(true).open(&self.path)?;
+            self.size = 0;
+
+            return Ok(())
+        }
+
+        let _ = fs::remove_file(name(self.max_files));
+
+        for i in (1..self.max_files).rev() {
+            let from = name(i);
+
+            if from.exists() {
+                fs::rename(from, name(i + 1))?;
+            }
+        }
+
+        fs::rename(&self.path, name(1))?;
+
+        self.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        self.size = 0;
+
+        Ok(())
+    }
+}
+
+impl Sink for FileSink {
+    fn write(&self, record: &Message) {
+        let mut inner = self.inner.lock().unwrap();
+
+        let json: serde_json::Value = record.clone().into();
+        let line = format!("{}\n", json);
+
+        if inner.size > 0 && inner.size + line.len() as u64 > inner.max_size {
+            if let Err(err) = inner.rotate() {
+                log::error!("audit rotate: {}", err);
+            }
+        }
+
+        match inner.file.write_all(line.as_bytes()) {
+            Ok(()) => inner.size += line.len() as u64,
+            Err(err) => log::error!("audit write: {}", err)
+        }
+    }
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0)
+}
@@ -0,0 +1,262 @@
+use std::env;
+use std::process;
+use std::time::Duration;
+
+use queen::Port;
+use queen::Wire;
+use queen::nson::{Message, MessageId, msg};
+use queen::net::{NsonCodec, KeepAlive, CryptoOptions};
+use queen::crypto::Method;
+use queen::util::config;
+use queen::dict::*;
+use queen::error::{Code, RecvError};
+
+const USAGE: &str = "usage: queen-cli [options] <command> [args]
+
+commands:
+    ping                       ping the broker
+    mine                       show the current slot
+    pub <chan> [json]          publish a message
+    sub <chan>...              subscribe and print messages
+    query [json]               root QUERY
+    ctrl [json]                root CTRL
+    kill <slot_id>             root KILL
+
+options:
+    -a, --addr <addr>          broker address, default: 127.0.0.1:8888
+    -r, --root                 connect as root
+    -i, --id <slot_id>         slot id, default: random
+        --attr <json>          handshake attributes
+    -m, --method <method>      aes-128-gcm, aes-256-gcm or chacha20-poly1305
+        --access <key>         access key, or env QUEEN_ACCESS
+        --share                SHARE for pub and sub
+        --to <slot_id>         TO for pub, can be repeated
+    -t, --timeout <secs>       reply timeout, default: 5
+    -h, --help                 print this message
+
+env:
+    QUEEN_SECRET               crypto secret, required with --method
+    QUEEN_ACCESS               access key";
+
+struct Options {
+    addr: String,
+    root: bool,
+    slot_id: MessageId,
+    attr: Message,
+    method: Option<Method>,
+    access: Option<String>,
+    share: bool,
+    to: Vec<MessageId>,
+    timeout: Duration,
+    command: Vec<String>
+}
+
+fn main() {
+    let options = parse_args();
+
+    let port = match Port::<NsonCodec>::new(KeepAlive::default()) {
+        Ok(port) => port,
+        Err(err) => exit(&format!("start port: {}", err))
+    };
+
+    let mut attr = options.attr.clone();
+
+    if let Some(access) = &options.access {
+        attr.insert(ACCESS, access);
+    }
+
+    let crypto = options.method.map(|method| {
+        let secret = match env::var("QUEEN_SECRET") {
+            Ok(secret) => secret,
+            Err(_) => exit("QUEEN_SECRET is required with --method")
+        };
+
+        CryptoOptions::new(method, &secret)
+    });
+
+    let wire = match port.connect(&options.addr, options.slot_id, options.root, attr, crypto, None) {
+        Ok(wire) => wire,
+        Err(err) => exit(&format!("connect {}: {}", options.addr, err))
+    };
+
+    let command = options.command[0].as_str();
+    let args = &options.command[1..];
+
+    let ret = match command {
+        "ping" => call(&wire, &options, msg!{CHAN: PING}),
+        "mine" => call(&wire, &options, msg!{CHAN: MINE}),
+        "pub" => {
+            let chan = args.first().unwrap_or_else(|| exit(USAGE));
+
+            let mut message = parse_json(args.get(1).map(String::as_str).unwrap_or("{}"));
+            message.insert(CHAN, chan);
+            message.insert(ID, MessageId::new());
+
+            if options.share {
+                message.insert(SHARE, true);
+            }
+
+            match options.to.len() {
+                0 => (),
+                1 => { message.insert(TO, options.to[0]); }
+                _ => { message.insert(TO, options.to.clone()); }
+            }
+
+            // 发布的消息没有回复，除非出错，这里等待一下以便打印错误
+            let _ = wire.send(message);
+
+            match wire.wait(Some(Duration::from_millis(200))) {
+                Ok(ret) => Ok(ret),
+                Err(RecvError::TimedOut) => return,
+                Err(err) => Err(err.to_string())
+            }
+        }
+        "sub" => {
+            if args.is_empty() {
+                exit(USAGE)
+            }
+
+            for chan in args {
+                let mut message = msg!{
+                    CHAN: ATTACH,
+                    VALUE: chan
+                };
+
+                if options.share {
+                    message.insert(SHARE, true);
+                }
+
+                if let Err(err) = call(&wire, &options, message) {
+                    exit(&format!("attach {}: {}", chan, err))
+                }
+            }
+
+            loop {
+                match wire.wait(None) {
+                    Ok(message) => print(&message),
+                    Err(err) => exit(&format!("{}", err))
+                }
+            }
+        }
+        "query" => {
+            let mut message = parse_json(args.first().map(String::as_str).unwrap_or("{}"));
+            message.insert(CHAN, QUERY);
+
+            call(&wire, &options, message)
+        }
+        "ctrl" => {
+            let mut message = parse_json(args.first().map(String::as_str).unwrap_or("{}"));
+            message.insert(CHAN, CTRL);
+
+            call(&wire, &options, message)
+        }
+        "kill" => {
+            let slot_id = args.first().map(|id| parse_id(id)).unwrap_or_else(|| exit(USAGE));
+
+            call(&wire, &options, msg!{
+                CHAN: SLOT_KILL,
+                SLOT_ID: slot_id
+            })
+        }
+        _ => exit(USAGE)
+    };
+
+    match ret {
+        Ok(message) => {
+            print(&message);
+
+            if let Some(code) = Code::get(&message) {
+                if code != Code::Ok {
+                    process::exit(1)
+                }
+            }
+        }
+        Err(err) => exit(&err)
+    }
+}
+
+fn parse_args() -> Options {
+    let mut options = Options {
+        addr: "127.0.0.1:8888".to_string(),
+        root: false,
+        slot_id: MessageId::new(),
+        attr: Message::new(),
+        method: None,
+        access: env::var("QUEEN_ACCESS").ok(),
+        share: false,
+        to: Vec::new(),
+        timeout: Duration::from_secs(5),
+        command: Vec::new()
+    };
+
+    let mut args = env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().unwrap_or_else(|| exit(USAGE));
+
+        match arg.as_str() {
+            "-a" | "--addr" => options.addr = value(),
+            "-r" | "--root" => options.root = true,
+            "-i" | "--id" => options.slot_id = parse_id(&value()),
+            "--attr" => options.attr = parse_json(&value()),
+            "-m" | "--method" => {
+                let method = value();
+                options.method = Some(Method::from_name(&method).unwrap_or_else(|| exit(&format!("unknown method: {}", method))));
+            }
+            "--access" => options.access = Some(value()),
+            "--share" => options.share = true,
+            "--to" => options.to.push(parse_id(&value())),
+            "-t" | "--timeout" => {
+                let secs = value();
+                options.timeout = Duration::from_secs(secs.parse().unwrap_or_else(|_| exit(&format!("invalid timeout: {}", secs))));
+            }
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0)
+            }
+            _ if arg.starts_with('-') && options.command.is_empty() => exit(USAGE),
+            _ => options.command.push(arg)
+        }
+    }
+
+    if options.command.is_empty() {
+        exit(USAGE)
+    }
+
+    options
+}
+
+// 发送消息并等待回复，只接收 ID 相同的回复，其他消息会被忽略
+fn call(wire: &Wire<Message>, options: &Options, mut message: Message) -> Result<Message, String> {
+    let id = MessageId::new();
+    message.insert(ID, id);
+
+    wire.send(message).map_err(|err| err.to_string())?;
+
+    loop {
+        let ret = wire.wait(Some(options.timeout)).map_err(|err| err.to_string())?;
+
+        if ret.get_message_id(ID) == Ok(&id) {
+            return Ok(ret)
+        }
+    }
+}
+
+fn parse_json(s: &str) -> Message {
+    config::parse_json(s).unwrap_or_else(|err| exit(&format!("invalid json: {}", err)))
+}
+
+fn parse_id(s: &str) -> MessageId {
+    MessageId::with_string(s).unwrap_or_else(|_| exit(&format!("invalid slot id: {}", s)))
+}
+
+fn print(message: &Message) {
+    let json: serde_json::Value = message.clone().into();
+
+    println!("{}", serde_json::to_string_pretty(&json).unwrap_or_default());
+}
+
+fn exit(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(1)
+}
@@ -0,0 +1,99 @@
+use std::env;
+use std::mem;
+use std::process;
+use std::ptr;
+
+use queen::{Broker, BrokerConfig};
+
+const USAGE: &str = "usage: queen [-c|--config <path>]
+
+    -c, --config <path>    config file, default: queen.toml
+    -h, --help             print this message
+
+signals:
+    SIGHUP                 reload acl, crypto settings and routing rules
+    SIGINT, SIGTERM        stop";
+
+fn main() {
+    let mut path = "queen.toml".to_string();
+
+    let mut args = env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-c" | "--config" => {
+                path = match args.next() {
+                    Some(path) => path,
+                    None => exit(USAGE)
+                }
+            }
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return
+            }
+            _ => exit(USAGE)
+        }
+    }
+
+    let config = match BrokerConfig::from_file(&path) {
+        Ok(config) => config,
+        Err(err) => exit(&format!("load config {}: {}", path, err))
+    };
+
+    // 必须在启动其他线程之前屏蔽信号，新线程会继承信号掩码，
+    // 这样信号只会在主线程中通过 sigwait 处理
+    let signals = unsafe { block_signals() };
+
+    let broker = match Broker::new(config.clone()) {
+        Ok(broker) => broker,
+        Err(err) => exit(&format!("start broker: {}", err))
+    };
+
+    eprintln!("queen {} listening on {:?}", config.socket_id, config.listen);
+
+    loop {
+        let mut signal: libc::c_int = 0;
+
+        if unsafe { libc::sigwait(&signals, &mut signal) } != 0 {
+            continue
+        }
+
+        match signal {
+            libc::SIGHUP => {
+                let ret = BrokerConfig::from_file(&path).and_then(|config| broker.reload(&config));
+
+                match ret {
+                    Ok(()) => eprintln!("config reloaded"),
+                    // 加载失败时保留原有配置
+                    Err(err) => eprintln!("reload config {}: {}", path, err)
+                }
+            }
+            libc::SIGINT | libc::SIGTERM => {
+                eprintln!("stopping");
+
+                broker.stop();
+
+                break
+            }
+            _ => ()
+        }
+    }
+}
+
+unsafe fn block_signals() -> libc::sigset_t {
+    let mut signals: libc::sigset_t = mem::zeroed();
+
+    libc::sigemptyset(&mut signals);
+    libc::sigaddset(&mut signals, libc::SIGHUP);
+    libc::sigaddset(&mut signals, libc::SIGINT);
+    libc::sigaddset(&mut signals, libc::SIGTERM);
+
+    libc::pthread_sigmask(libc::SIG_BLOCK, &signals, ptr::null_mut());
+
+    signals
+}
+
+fn exit(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(1)
+}
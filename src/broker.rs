@@ -0,0 +1,707 @@
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::io::ErrorKind::WouldBlock;
+use std::net::{SocketAddr, TcpListener};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use nson::{Message, MessageId, Value, Array, msg};
+
+use crate::{Socket, Node, Wire, Switch};
+use crate::socket::{self, Acl, AclRules, DedupConfig, DedupScope, Rules};
+use crate::node::{self, NodeConfig, Credentials, PasswordAuth, TokenAuth, ChallengeAuth};
+use crate::net::{NsonCodec, KeepAlive, FlowControl, Overflow, NetStats, HandshakeConfig, AdmissionConfig, Cidr};
+use crate::crypto::Method;
+use crate::dict::*;
+use crate::error::{Result, Error, ErrorInfo};
+use crate::util::config;
+use crate::util::lock::Lock;
+
+// 独立运行的消息代理，由配置文件描述
+//
+// 配置文件示例（TOML）：
+//
+// socket_id = "5f0c..."              # 可选，默认随机生成
+// listen = ["0.0.0.0:8888"]
+// workers = 2
+// metrics = "127.0.0.1:9888"         # 可选，HTTP 接口，返回 JSON 格式的运行统计
+//
+// [keep_alive]
+// idle = 60
+// interval = 10
+// count = 3
+//
+// [flow]                             # 可选，流量控制
+// max_buffer = 4194304               # 每个连接写缓冲的最大字节数
+// overflow = "wait"                  # 超出时 "wait" 暂停转发，"close" 断开连接
+//
+// [handshake]                        # 可选，握手的限制
+// max_size = 2048                    # 握手消息的最大字节数
+// timeout = 5                        # 秒，连接后需要在这个时间内完成握手
+// max_pending = 1024                 # 同时进行中的握手的最大数量
+//
+// [admission]                        # 可选，连接的准入控制，不配置的项不限制
+// max_conns = 10000                  # 总连接数
+// max_per_ip = 100                   # 每个来源 IP 的连接数
+// ipv6_prefix = 64                   # 同一网段的地址合并计算，默认 ipv4_prefix = 32，ipv6_prefix = 128
+// rate = 100                         # 每秒接受的连接数
+// burst = 200                        # 可以积攒的突发连接数，默认等于 rate
+// allow = ["10.0.0.0/8"]             # 不为空时，只接受这些网段的连接
+// deny = ["10.0.0.1"]                # 拒绝这些网段的连接，优先于 allow
+//
+// [crypto]                           # 可选，配置后只允许加密连接
+// methods = ["aes-128-gcm", "aes-256-gcm", "chacha20-poly1305"]
+// secrets = { "access key" = "secret" }
+//
+// [auth]                             # 可选，客户端认证，见 node::PasswordAuth、node::TokenAuth、node::ChallengeAuth
+// credentials = "users.toml"         # 用户凭据，格式同 node::Credentials，也可以是 table，用于 password 和 challenge 认证
+// token_key = "..."                  # token 认证的签名密钥
+//
+// 握手消息带有 TOKEN 时使用 token 认证，带有 PASSWORD 时使用 password 认证，否则使用 challenge 认证
+// 没有配置 auth 时，不需要认证，但不允许通过网络以 ROOT 身份连接
+//
+// [acl]                              # 可选，格式同 socket::Acl，也可以是规则文件的路径
+// default = "allow"
+//
+// [[durable]]                        # 持久频道，没有订阅者时暂存消息
+// chan = "order"
+// capacity = 1024
+//
+// [[queue]]                          # 工作队列，每条消息只投递给一个消费者，ACK 之后移除
+// chan = "task"
+// capacity = 65536
+//
+// [[rules]]                          # 消息转换和路由规则，格式同 socket::Rules
+// chans = ["orders"]
+// actions = [{ op = "publish", chan = "audit/orders" }]
+//
+// [dedup]                            # 可选，带有 ID 的消息在时间窗口内只投递一次
+// window = 60                        # 秒
+// capacity = 1024                    # 每个发布者（或每个 CHAN）最多记住的 ID 数
+// scope = "slot"                     # "slot" 按发布者区分，"chan" 按 CHAN 区分
+//
+// 重新加载时，只有 acl、crypto 和 rules 会生效，其他配置需要重启
+#[derive(Debug, Clone)]
+pub struct BrokerConfig {
+    pub socket_id: MessageId,
+    pub listen: Vec<SocketAddr>,
+    pub workers: usize,
+    pub keep_alive: KeepAlive,
+    pub flow: FlowControl,
+    pub handshake: HandshakeConfig,
+    pub admission: AdmissionConfig,
+    pub methods: Vec<Method>,
+    pub secrets: HashMap<String, String>,
+    pub credentials: Option<Credentials>,
+    pub token_key: Option<String>,
+    // 格式同 socket::Acl 的规则文件
+    pub acl: Message,
+    pub durable: Vec<(String, usize)>,
+    pub queue: Vec<(String, usize)>,
+    // 格式同 socket::Rules
+    pub rules: Array,
+    pub dedup: Option<DedupConfig>,
+    pub metrics: Option<SocketAddr>
+}
+
+impl BrokerConfig {
+    pub const DEFAULT_DURABLE_CAPACITY: usize = 1024;
+    pub const DEFAULT_QUEUE_CAPACITY: usize = 65536;
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let mut message = config::load(path)?;
+
+        // acl 为文件路径时，相对于配置文件所在的目录
+        if let Ok(acl) = message.get_str("acl") {
+            let acl = path.parent().map(|dir| dir.join(acl)).unwrap_or_else(|| PathBuf::from(acl));
+            message.insert("acl", config::load(acl)?);
+        }
+
+        // 凭据同样可以是文件路径
+        if let Ok(mut auth) = message.get_message("auth").cloned() {
+            if let Ok(credentials) = auth.get_str("credentials") {
+                let credentials = path.parent().map(|dir| dir.join(credentials)).unwrap_or_else(|| PathBuf::from(credentials));
+                auth.insert("credentials", config::load(credentials)?);
+                message.insert("auth", auth);
+            }
+        }
+
+        BrokerConfig::from_message(&message)
+    }
+
+    pub fn from_message(message: &Message) -> Result<Self> {
+        let socket_id = match message.get("socket_id") {
+            Some(Value::MessageId(id)) => *id,
+            Some(Value::String(hex)) => MessageId::with_string(hex).map_err(|_| invalid("socket_id", "message id"))?,
+            Some(_) => return Err(invalid("socket_id", "message id")),
+            None => MessageId::new()
+        };
+
+        let mut listen = Vec::new();
+
+        match message.get("listen") {
+            Some(Value::String(addr)) => listen.push(parse_addr("listen", addr)?),
+            Some(Value::Array(array)) => {
+                for addr in array {
+                    let addr = addr.as_str().ok_or_else(|| invalid("listen", "array of address"))?;
+                    listen.push(parse_addr("listen", addr)?);
+                }
+            }
+            Some(_) => return Err(invalid("listen", "address or array of address")),
+            None => ()
+        }
+
+        if listen.is_empty() {
+            return Err(invalid("listen", "at least one address"))
+        }
+
+        let workers = match message.get("workers") {
+            Some(value) => as_usize(value).filter(|n| *n > 0).ok_or_else(|| invalid("workers", "positive integer"))?,
+            None => 1
+        };
+
+        let mut keep_alive = KeepAlive::default();
+
+        if let Some(value) = message.get("keep_alive") {
+            let table = value.as_message().ok_or_else(|| invalid("keep_alive", "table"))?;
+
+            let get = |field: &str, default: u32| -> Result<u32> {
+                match table.get(field) {
+                    Some(value) => as_usize(value).map(|n| n as u32).ok_or_else(|| invalid(field, "non-negative integer")),
+                    None => Ok(default)
+                }
+            };
+
+            keep_alive = KeepAlive::new(
+                get("idle", keep_alive.idle)?,
+                get("interval", keep_alive.interval)?,
+                get("count", keep_alive.count)?
+            );
+        }
+
+        let mut flow = FlowControl::default();
+
+        if let Some(value) = message.get("flow") {
+            let table = value.as_message().ok_or_else(|| invalid("flow", "table"))?;
+
+            if let Some(value) = table.get("max_buffer") {
+                flow.max_buffer = as_usize(value).filter(|n| *n > 0).ok_or_else(|| invalid("max_buffer", "positive integer"))?;
+            }
+
+            if let Some(value) = table.get("overflow") {
+                flow.overflow = value.as_str().and_then(Overflow::from_name).ok_or_else(|| invalid("overflow", "\"wait\" or \"close\""))?;
+            }
+        }
+
+        let mut handshake = HandshakeConfig::default();
+
+        if let Some(value) = message.get("handshake") {
+            let table = value.as_message().ok_or_else(|| invalid("handshake", "table"))?;
+
+            if let Some(value) = table.get("max_size") {
+                handshake.max_size = as_usize(value).filter(|n| *n >= 5).ok_or_else(|| invalid("max_size", "integer not less than 5"))?;
+            }
+
+            if let Some(value) = table.get("timeout") {
+                handshake.timeout = as_usize(value).filter(|n| *n > 0).map(|n| Duration::from_secs(n as u64))
+                    .ok_or_else(|| invalid("timeout", "positive integer"))?;
+            }
+
+            if let Some(value) = table.get("max_pending") {
+                handshake.max_pending = as_usize(value).filter(|n| *n > 0).ok_or_else(|| invalid("max_pending", "positive integer"))?;
+            }
+        }
+
+        let mut admission = AdmissionConfig::default();
+
+        if let Some(value) = message.get("admission") {
+            let table = value.as_message().ok_or_else(|| invalid("admission", "table"))?;
+
+            let get = |field: &str| -> Result<Option<usize>> {
+                match table.get(field) {
+                    Some(value) => as_usize(value).filter(|n| *n > 0).map(Some).ok_or_else(|| invalid(field, "positive integer")),
+                    None => Ok(None)
+                }
+            };
+
+            admission.max_conns = get("max_conns")?;
+            admission.max_per_ip = get("max_per_ip")?;
+
+            if let Some(prefix) = get("ipv4_prefix")? {
+                admission.ipv4_prefix = Some(prefix).filter(|n| *n <= 32).ok_or_else(|| invalid("ipv4_prefix", "integer between 1 and 32"))? as u8;
+            }
+
+            if let Some(prefix) = get("ipv6_prefix")? {
+                admission.ipv6_prefix = Some(prefix).filter(|n| *n <= 128).ok_or_else(|| invalid("ipv6_prefix", "integer between 1 and 128"))? as u8;
+            }
+
+            admission.rate = get("rate")?.map(|n| n.min(u32::MAX as usize) as u32);
+            admission.burst = get("burst")?.map(|n| n.min(u32::MAX as usize) as u32).or(admission.rate).unwrap_or(0);
+
+            let cidrs = |field: &str| -> Result<Vec<Cidr>> {
+                match table.get(field) {
+                    Some(Value::Array(array)) => {
+                        array.iter().map(|cidr| {
+                            cidr.as_str().and_then(|cidr| cidr.parse().ok()).ok_or_else(|| invalid(field, "array of cidr"))
+                        }).collect()
+                    }
+                    Some(_) => Err(invalid(field, "array of cidr")),
+                    None => Ok(Vec::new())
+                }
+            };
+
+            admission.allow = cidrs("allow")?;
+            admission.deny = cidrs("deny")?;
+        }
+
+        let mut methods = Vec::new();
+        let mut secrets = HashMap::new();
+
+        if let Some(value) = message.get("crypto") {
+            let table = value.as_message().ok_or_else(|| invalid("crypto", "table"))?;
+
+            match table.get("methods") {
+                Some(Value::Array(array)) => {
+                    for method in array {
+                        let method = method.as_str().and_then(Method::from_name)
+                            .ok_or_else(|| invalid("methods", "array of aes-128-gcm, aes-256-gcm or chacha20-poly1305"))?;
+                        methods.push(method);
+                    }
+                }
+                Some(_) => return Err(invalid("methods", "array")),
+                None => methods = vec![Method::Aes128Gcm, Method::Aes256Gcm, Method::ChaCha20Poly1305]
+            }
+
+            if let Some(value) = table.get("secrets") {
+                let table = value.as_message().ok_or_else(|| invalid("secrets", "table"))?;
+
+                for (access, secret) in table {
+                    let secret = secret.as_str().ok_or_else(|| invalid("secrets", "table of string"))?;
+                    secrets.insert(access.to_string(), secret.to_string());
+                }
+            }
+        }
+
+        let mut credentials = None;
+        let mut token_key = None;
+
+        if let Some(value) = message.get("auth") {
+            let table = value.as_message().ok_or_else(|| invalid("auth", "table"))?;
+
+            match table.get("credentials") {
+                Some(Value::Message(users)) => credentials = Some(Credentials::from_message(users)?),
+                Some(_) => return Err(invalid("credentials", "table or file path")),
+                None => ()
+            }
+
+            match table.get("token_key") {
+                Some(Value::String(key)) if !key.is_empty() => token_key = Some(key.to_string()),
+                Some(_) => return Err(invalid("token_key", "non-empty string")),
+                None => ()
+            }
+        }
+
+        let acl = match message.get("acl") {
+            Some(Value::Message(rules)) => {
+                AclRules::from_message(rules)?;
+                rules.clone()
+            }
+            // 没有配置时，允许所有操作
+            None => msg!{"default": "allow"},
+            Some(_) => return Err(invalid("acl", "table or file path"))
+        };
+
+        let mut durable = Vec::new();
+
+        if let Some(value) = message.get("durable") {
+            let array = value.as_array().ok_or_else(|| invalid("durable", "array"))?;
+
+            for item in array {
+                match item {
+                    Value::String(chan) => durable.push((chan.to_string(), Self::DEFAULT_DURABLE_CAPACITY)),
+                    Value::Message(table) => {
+                        let chan = table.get_str("chan").map_err(|_| invalid("durable.chan", "string"))?;
+
+                        let capacity = match table.get("capacity") {
+                            Some(value) => as_usize(value).ok_or_else(|| invalid("durable.capacity", "non-negative integer"))?,
+                            None => Self::DEFAULT_DURABLE_CAPACITY
+                        };
+
+                        durable.push((chan.to_string(), capacity));
+                    }
+                    _ => return Err(invalid("durable", "array of string or table"))
+                }
+            }
+        }
+
+        let mut queue = Vec::new();
+
+        if let Some(value) = message.get("queue") {
+            let array = value.as_array().ok_or_else(|| invalid("queue", "array"))?;
+
+            for item in array {
+                match item {
+                    Value::String(chan) => queue.push((chan.to_string(), Self::DEFAULT_QUEUE_CAPACITY)),
+                    Value::Message(table) => {
+                        let chan = table.get_str("chan").map_err(|_| invalid("queue.chan", "string"))?;
+
+                        let capacity = match table.get("capacity") {
+                            Some(value) => as_usize(value).filter(|n| *n > 0).ok_or_else(|| invalid("queue.capacity", "positive integer"))?,
+                            None => Self::DEFAULT_QUEUE_CAPACITY
+                        };
+
+                        queue.push((chan.to_string(), capacity));
+                    }
+                    _ => return Err(invalid("queue", "array of string or table"))
+                }
+            }
+        }
+
+        let rules = match message.get("rules") {
+            Some(Value::Array(array)) => {
+                Rules::from_array(array)?;
+                array.clone()
+            }
+            Some(_) => return Err(invalid("rules", "array of table")),
+            None => Array::new()
+        };
+
+        let dedup = match message.get("dedup") {
+            Some(value) => {
+                let table = value.as_message().ok_or_else(|| invalid("dedup", "table"))?;
+
+                let mut dedup = DedupConfig::default();
+
+                if let Some(value) = table.get("window") {
+                    dedup.window = as_usize(value).filter(|n| *n > 0 && *n <= u32::MAX as usize).ok_or_else(|| invalid("dedup.window", "positive integer"))? as u32;
+                }
+
+                if let Some(value) = table.get("capacity") {
+                    dedup.capacity = as_usize(value).filter(|n| *n > 0).ok_or_else(|| invalid("dedup.capacity", "positive integer"))?;
+                }
+
+                if let Some(value) = table.get("scope") {
+                    dedup.scope = value.as_str().and_then(|scope| DedupScope::from_str(scope).ok()).ok_or_else(|| invalid("dedup.scope", "\"slot\" or \"chan\""))?;
+                }
+
+                Some(dedup)
+            }
+            None => None
+        };
+
+        let metrics = match message.get("metrics") {
+            Some(Value::String(addr)) => Some(parse_addr("metrics", addr)?),
+            Some(_) => return Err(invalid("metrics", "address")),
+            None => None
+        };
+
+        Ok(BrokerConfig {
+            socket_id,
+            listen,
+            workers,
+            keep_alive,
+            flow,
+            handshake,
+            admission,
+            methods,
+            secrets,
+            credentials,
+            token_key,
+            acl,
+            durable,
+            queue,
+            rules,
+            dedup,
+            metrics
+        })
+    }
+}
+
+pub struct Broker {
+    socket: Socket,
+    node: Node<NsonCodec>,
+    methods: Arc<Lock<Vec<Method>>>,
+    secrets: Arc<Lock<HashMap<String, String>>>,
+    // 用于发送 CTRL 命令的 ROOT SLOT
+    ctrl: Wire<Message>,
+    run: Arc<AtomicBool>
+}
+
+impl Broker {
+    pub fn new(config: BrokerConfig) -> Result<Self> {
+        let hook = Acl::with_hook(AclRules::from_message(&config.acl)?, BrokerHook {
+            durable: config.durable.clone(),
+            queue: config.queue.clone(),
+            rules: Rules::from_array(&config.rules)?,
+            dedup: config.dedup.clone()
+        });
+
+        let socket = Socket::new(config.socket_id, hook)?;
+
+        let methods = Arc::new(Lock::new(config.methods.clone()));
+        let secrets = Arc::new(Lock::new(config.secrets.clone()));
+
+        let auth = BrokerAuth {
+            password: config.credentials.clone().map(PasswordAuth::new),
+            token: config.token_key.as_ref().map(|key| TokenAuth::new(key.as_bytes())),
+            challenge: config.credentials.clone().map(ChallengeAuth::new)
+        };
+
+        let node = Node::<NsonCodec>::with_config(
+            socket.clone(),
+            config.workers,
+            config.listen.clone(),
+            NodeConfig {
+                keep_alive: config.keep_alive.clone(),
+                flow: config.flow.clone(),
+                handshake: config.handshake.clone(),
+                admission: config.admission.clone()
+            },
+            (auth, Secure {
+                methods: methods.clone(),
+                secrets: secrets.clone()
+            })
+        )?;
+
+        let ctrl = socket.connect(MessageId::new(), true, msg!{}, None, Some(Duration::from_secs(10)))?;
+
+        let run = Arc::new(AtomicBool::new(true));
+
+        if let Some(addr) = config.metrics {
+            let listener = TcpListener::bind(addr)?;
+            listener.set_nonblocking(true)?;
+
+            let wire = socket.connect(MessageId::new(), true, msg!{}, None, Some(Duration::from_secs(10)))?;
+            let stats = node.stats();
+            let run = run.clone();
+
+            thread::Builder::new().name("metrics".to_string()).spawn(move || {
+                serve_metrics(listener, wire, stats, run)
+            }).unwrap();
+        }
+
+        Ok(Broker {
+            socket,
+            node,
+            methods,
+            secrets,
+            ctrl,
+            run
+        })
+    }
+
+    pub fn socket(&self) -> &Socket {
+        &self.socket
+    }
+
+    // 重新加载 acl、crypto 和 rules，只影响之后的握手，已经建立的连接不变
+    pub fn reload(&self, config: &BrokerConfig) -> Result<()> {
+        let _ = self.ctrl.send(msg!{
+            CHAN: CTRL,
+            ACL: config.acl.clone()
+        });
+
+        let ret = self.ctrl.wait(Some(Duration::from_secs(10)))?;
+
+        if let Some(info) = ErrorInfo::get(&ret) {
+            return Err(info.into())
+        }
+
+        let _ = self.ctrl.send(msg!{
+            CHAN: CTRL,
+            RULES: config.rules.clone()
+        });
+
+        let ret = self.ctrl.wait(Some(Duration::from_secs(10)))?;
+
+        if let Some(info) = ErrorInfo::get(&ret) {
+            return Err(info.into())
+        }
+
+        *self.methods.lock() = config.methods.clone();
+        *self.secrets.lock() = config.secrets.clone();
+
+        Ok(())
+    }
+
+    pub fn stop(&self) {
+        self.run.store(false, Ordering::Relaxed);
+        self.node.stop();
+        self.socket.stop();
+    }
+
+    pub fn running(&self) -> bool {
+        self.run.load(Ordering::Relaxed) && self.socket.running() && self.node.running()
+    }
+}
+
+struct BrokerHook {
+    durable: Vec<(String, usize)>,
+    queue: Vec<(String, usize)>,
+    rules: Rules,
+    dedup: Option<DedupConfig>
+}
+
+impl socket::Hook for BrokerHook {
+    fn start(&self, switch: &mut Switch) {
+        for (chan, capacity) in &self.durable {
+            switch.set_durable(chan.to_string(), *capacity);
+        }
+
+        for (chan, capacity) in &self.queue {
+            switch.set_queue(chan.to_string(), *capacity);
+        }
+
+        switch.set_rules(self.rules.clone());
+        switch.set_dedup(self.dedup.clone());
+    }
+}
+
+// 按照握手消息中的字段选择认证方式
+struct BrokerAuth {
+    password: Option<PasswordAuth>,
+    token: Option<TokenAuth>,
+    challenge: Option<ChallengeAuth>
+}
+
+impl node::Hook for BrokerAuth {
+    fn start(&self, slot_id: MessageId, root: bool, message: &mut Message) -> bool {
+        if let (Some(auth), true) = (&self.token, message.contains_key(TOKEN)) {
+            return auth.start(slot_id, root, message)
+        }
+
+        if let (Some(auth), true) = (&self.password, message.contains_key(PASSWORD)) {
+            return auth.start(slot_id, root, message)
+        }
+
+        if let Some(auth) = &self.challenge {
+            return auth.start(slot_id, root, message)
+        }
+
+        // 没有配置认证时允许匿名连接，但 ROOT 可以修改 ACL、KILL 其他 SLOT，不能匿名
+        self.token.is_none() && !root
+    }
+}
+
+// 没有配置加密方式时不开启加密
+struct Secure {
+    methods: Arc<Lock<Vec<Method>>>,
+    secrets: Arc<Lock<HashMap<String, String>>>
+}
+
+impl node::Hook for Secure {
+    fn enable_secure(&self) -> bool {
+        !self.methods.lock().is_empty()
+    }
+
+    fn access(&self, _slot_id: MessageId, _root: bool, message: &mut Message) -> Option<String> {
+        let method = message.get_str(METHOD).ok().and_then(|method| Method::from_str(method).ok())?;
+
+        if !self.methods.lock().iter().any(|m| m.as_str() == method.as_str()) {
+            return None
+        }
+
+        let access = message.get_str(ACCESS).ok()?;
+
+        self.secrets.lock().get(access).cloned()
+    }
+}
+
+fn serve_metrics(listener: TcpListener, wire: Wire<Message>, stats: Arc<NetStats>, run: Arc<AtomicBool>) {
+    while run.load(Ordering::Relaxed) {
+        let mut stream = match listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(err) => {
+                if err.kind() == WouldBlock {
+                    thread::sleep(Duration::from_millis(100));
+                    continue
+                }
+
+                log::error!("metrics accept: {}", err);
+
+                return
+            }
+        };
+
+        let _ = stream.set_nonblocking(false);
+        let _ = stream.set_read_timeout(Some(Duration::from_secs(5)));
+
+        // 只读取请求头，不关心请求的路径
+        let mut buf = [0u8; 1024];
+        let _ = stream.read(&mut buf);
+
+        let _ = wire.send(msg!{
+            CHAN: QUERY,
+            VALUE: METRICS
+        });
+
+        let (status, body) = match wire.wait(Some(Duration::from_secs(5))) {
+            Ok(ret) => match ret.get_message(METRICS) {
+                Ok(metrics) => {
+                    let mut metrics = metrics.clone();
+
+                    for (key, value) in stats.to_message() {
+                        metrics.insert(format!("net_{}", key), value);
+                    }
+
+                    ("200 OK", metrics_json(&metrics).to_string())
+                }
+                Err(_) => ("500 Internal Server Error", "{}".to_string())
+            },
+            Err(_) => ("503 Service Unavailable", "{}".to_string())
+        };
+
+        let response = format!(
+            "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        );
+
+        let _ = stream.write_all(response.as_bytes());
+    }
+}
+
+// 转换成普通的 JSON，不使用 {"$u64": ...} 这样的扩展格式，方便监控系统采集
+fn metrics_json(metrics: &Message) -> serde_json::Value {
+    let mut map = serde_json::Map::new();
+
+    for (key, value) in metrics {
+        let value = match value {
+            Value::I32(n) => serde_json::Value::from(*n),
+            Value::I64(n) => serde_json::Value::from(*n),
+            Value::U32(n) => serde_json::Value::from(*n),
+            Value::U64(n) => serde_json::Value::from(*n),
+            Value::F64(n) => serde_json::Value::from(*n),
+            Value::MessageId(id) => serde_json::Value::from(id.to_hex()),
+            value => value.clone().into()
+        };
+
+        map.insert(key.to_string(), value);
+    }
+
+    serde_json::Value::Object(map)
+}
+
+fn parse_addr(field: &str, addr: &str) -> Result<SocketAddr> {
+    addr.parse().map_err(|_| invalid(field, "socket address"))
+}
+
+fn as_usize(value: &Value) -> Option<usize> {
+    match value {
+        Value::I32(n) if *n >= 0 => Some(*n as usize),
+        Value::I64(n) if *n >= 0 => Some(*n as usize),
+        Value::U32(n) => Some(*n as usize),
+        Value::U64(n) => Some(*n as usize),
+        _ => None
+    }
+}
+
+fn invalid(field: &str, expect: &str) -> Error {
+    Error::InvalidData(format!("broker: `{}` must be {}", field, expect))
+}
@@ -9,8 +9,9 @@ use nson::MessageId;
 
 use crate::dict;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Default)]
 pub enum Method {
+    #[default]
     Aes128Gcm,
     Aes256Gcm,
     ChaCha20Poly1305
@@ -25,6 +26,16 @@ impl Method {
         }
     }
 
+    // 可读的名称，也接受 dict 中的缩写，用于配置文件和命令行
+    pub fn from_name(s: &str) -> Option<Self> {
+        match s {
+            "aes-128-gcm" => Some(Method::Aes128Gcm),
+            "aes-256-gcm" => Some(Method::Aes256Gcm),
+            "chacha20-poly1305" => Some(Method::ChaCha20Poly1305),
+            _ => Method::from_str(s).ok()
+        }
+    }
+
     pub fn as_str(&self) -> &'static str {
         match self {
             Method::Aes128Gcm => dict::AES_128_GCM,
@@ -47,12 +58,6 @@ impl FromStr for Method {
     }
 }
 
-impl Default for Method {
-    fn default() -> Self {
-        Method::Aes128Gcm
-    }
-}
-
 #[derive(Debug)]
 pub struct Crypto {
     inner: LessSafeKey
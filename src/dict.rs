@@ -12,6 +12,15 @@ pub const QUERY:       &str = "_qu";
 pub const MINE:        &str = "_mi";
 pub const CUSTOM:      &str = "_cu";
 pub const CTRL:        &str = "_ct";
+pub const CANCEL:      &str = "_cn";
+pub const LOOKUP:      &str = "_lk";
+pub const WATCH:       &str = "_wa";
+pub const UNWATCH:     &str = "_uw";
+pub const STREAM:      &str = "_stm";
+pub const CLOSE:       &str = "_cl";
+pub const ACK:         &str = "_ak";
+pub const GATHER:      &str = "_ga";
+pub const REPLY:       &str = "_rp";
 
 // params
 pub const SOCKET_ID:   &str = "_so";
@@ -19,6 +28,7 @@ pub const SLOT_ID:     &str = "_sl";
 pub const VALUE:       &str = "_va";
 pub const TO:          &str = "_to";
 pub const TO_SOCKET:   &str = "_ts";
+pub const TO_ATTR:     &str = "_ta";
 pub const FROM:        &str = "_fr";
 pub const FROM_SOCKET: &str = "_fs";
 pub const SHARE:       &str = "_sh";
@@ -30,13 +40,54 @@ pub const SHARE_CHANS: &str = "_sc";
 pub const BINDED:      &str = "_bd";
 pub const BOUNDED:     &str = "_bo";
 pub const JOINED:      &str = "_jd";
+pub const ACL:         &str = "_ac";
+pub const EVENT:       &str = "_ev";
+pub const DELAY:       &str = "_dl";
+pub const DELIVER_AT:  &str = "_da";
+pub const SCHEDULED:   &str = "_sd";
+pub const GROUP:       &str = "_gp";
+pub const GROUPS:      &str = "_gs";
+pub const STRATEGY:    &str = "_sy";
+pub const KEY:         &str = "_ky";
+pub const MEMBERS:     &str = "_mb";
+pub const SCHEMA:      &str = "_sm";
+pub const METRICS:     &str = "_mt";
+pub const WILL:        &str = "_wi";
+pub const SLOTS:       &str = "_ss";
+pub const HEARTBEAT:   &str = "_hb";
+pub const PREFETCH:    &str = "_pf";
+pub const REQUEUE:     &str = "_rq";
+pub const TIMEOUT:     &str = "_tmo";
+pub const COUNT:       &str = "_cnt";
+pub const REPLIES:     &str = "_rps";
+pub const MISSING:     &str = "_mis";
+pub const RULES:       &str = "_rl";
+
+// stream
+pub const STREAM_ID:   &str = "_si";
+pub const SEQ:         &str = "_sq";
+pub const SIZE:        &str = "_sz";
+pub const DIGEST:      &str = "_dg";
+
+// trace
+pub const TRACE:       &str = "_tr";
+pub const HOPS:        &str = "_hs";
+pub const HOP:         &str = "_ho";
+pub const SPAN:        &str = "_sp";
+pub const PARENT:      &str = "_pa";
+pub const TIME:        &str = "_ti";
 
 // message id
 pub const ID:        &str = "_id";
+// 重复的消息，见 socket::Dedup
+pub const DUPLICATE: &str = "_dup";
 
 // error
 pub const CODE:      &str = "_co";
 pub const ERROR:     &str = "_er";
+pub const REASON:    &str = "_re";
+pub const FIELD:     &str = "_fd";
+pub const DETAILS:   &str = "_dt";
 
 // slot event channel
 pub const SLOT_READY:  &str = "_slre";
@@ -47,6 +98,31 @@ pub const SLOT_KILL:   &str = "_slki";
 pub const SLOT_SEND:   &str = "_slse";
 pub const SLOT_RECV:   &str = "_slrc";
 
+// slot break reason
+pub const BREAK_CLOSED:     &str = "closed";
+pub const BREAK_LOST:       &str = "lost";
+pub const BREAK_KILLED:     &str = "killed";
+pub const BREAK_HEARTBEAT:  &str = "heartbeat";
+pub const BREAK_KEEP_ALIVE: &str = "keep_alive";
+pub const BREAK_DECODE:     &str = "decode";
+pub const BREAK_CRYPTO:     &str = "crypto";
+pub const BREAK_OVERSIZE:   &str = "oversize";
+pub const BREAK_SHUTDOWN:   &str = "shutdown";
+pub const BREAK_OVERFLOW:   &str = "overflow";
+
+// presence event channel
+pub const PRESENCE:    &str = "_prse";
+
+// audit channel
+pub const AUDIT:       &str = "_audi";
+
+// trace event channel
+pub const TRACE_HOP:   &str = "_trho";
+
+// group event channel
+pub const GROUP_JOIN:  &str = "_gpjo";
+pub const GROUP_LEAVE: &str = "_gple";
+
 // bind event channel
 pub const BIND_SEND:   &str = "_bdse";
 pub const BIND_RECV:   &str = "_bdre";
@@ -65,5 +141,11 @@ pub const HAND:        &str = "_ha";
 pub const KEEP_ALIVE:  &str = "_ke";
 
 pub const METHOD:      &str = "_me";
+pub const ACCESS:      &str = "_acce";
+pub const USERNAME:    &str = "_un";
+pub const PASSWORD:    &str = "_pw";
+pub const TOKEN:       &str = "_tk";
+pub const CHALLENGE:   &str = "_cg";
+pub const RESPONSE:    &str = "_rs";
 pub const SECURE:      &str = "_se";
 pub const ORIGIN:      &str = "_or";
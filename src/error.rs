@@ -2,7 +2,7 @@ use std::io;
 use std::{error, result};
 use std::fmt;
 
-use nson::Message;
+use nson::{Message, Value, msg};
 
 use crate::dict::*;
 
@@ -22,6 +22,8 @@ pub enum Error {
     Exit(String),
     IoError(io::Error),
     ErrorCode(Code),
+    // 对方回复的错误
+    Reply(Box<ErrorInfo>),
     RecvError(RecvError)
 }
 
@@ -39,6 +41,12 @@ impl From<Code> for Error {
     }
 }
 
+impl From<ErrorInfo> for Error {
+    fn from(err: ErrorInfo) -> Error {
+        Error::Reply(Box::new(err))
+    }
+}
+
 impl From<RecvError> for Error {
     fn from(err: RecvError) -> Error {
         Error::RecvError(err)
@@ -62,11 +70,23 @@ impl fmt::Display for Error {
             Error::Exit(ref inner) => write!(fmt, "Exit: {}", inner),
             Error::IoError(ref inner) => inner.fmt(fmt),
             Error::ErrorCode(ref inner) => inner.fmt(fmt),
+            Error::Reply(ref inner) => inner.fmt(fmt),
             Error::RecvError(ref inner) => inner.fmt(fmt)
         }
     }
 }
 
+impl Error {
+    // ErrorCode 和 Reply 的错误码
+    pub fn code(&self) -> Option<Code> {
+        match self {
+            Error::ErrorCode(code) => Some(*code),
+            Error::Reply(info) => Some(info.code),
+            _ => None
+        }
+    }
+}
+
 impl error::Error for Error {
     fn source(&self) -> Option<&(dyn error::Error + 'static)> {
         match *self {
@@ -76,127 +96,243 @@ impl error::Error for Error {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
-#[repr(i32)]
-pub enum Code {
-    Ok = 0,
-
-    Unauthorized = 10,
-    AuthenticationFailed = 11,
-    PermissionDenied = 12,
-
-    DuplicateSlotId = 20,
-    TargetSlotIdNotExist = 21,
-    CannotGetChanField = 22,
-    UnsupportedChan = 23,
-    CannotGetValueField = 24,
-    CannotGetSlotIdField = 25,
-    InvalidSlotIdFieldType = 26,
-    InvalidToFieldType = 27,
-    InvalidRootFieldType = 28,
-    InvalidShareFieldType = 29,
-    InvalidToSocketFieldType = 210,
+macro_rules! codes {
+    ($($name: ident = $code: literal, $reason: expr, $field: expr;)+) => {
+        #[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+        pub enum Code {
+            $($name,)+
+            // 用户自定义的错误码，比如在 Hook 中返回的错误，建议使用 Code::USER_MIN 以上的值
+            // 收到未知的错误码时，也会原样保留在这里
+            User(i32)
+        }
 
-    InternalError = 30,
-    UnsupportedFormat = 31,
-    EmptyFieldName = 32,
-    EmptyFieldValue = 33,
-    KeyTooLong = 34,
-    BadValue = 35,
-    NotFound = 36,
+        impl Code {
+            pub fn code(self) -> i32 {
+                match self {
+                    $(Code::$name => $code,)+
+                    Code::User(code) => code
+                }
+            }
+
+            pub fn from_i32(code: i32) -> Code {
+                match code {
+                    $($code => Code::$name,)+
+                    _ => Code::User(code)
+                }
+            }
+
+            pub fn to_str(&self) -> &str {
+                match self {
+                    $(Code::$name => stringify!($name),)+
+                    Code::User(_) => "User"
+                }
+            }
+
+            // 可读的错误原因
+            pub fn reason(&self) -> &'static str {
+                match self {
+                    $(Code::$name => $reason,)+
+                    Code::User(_) => "user defined error"
+                }
+            }
+
+            // 出错的字段
+            pub fn field(&self) -> Option<&'static str> {
+                match self {
+                    $(Code::$name => $field,)+
+                    Code::User(_) => None
+                }
+            }
+        }
+    };
+}
 
-    UnknownError = -1,
+codes! {
+    Ok = 0, "ok", None;
+
+    Unauthorized = 10, "unauthorized", None;
+    AuthenticationFailed = 11, "authentication failed", None;
+    PermissionDenied = 12, "permission denied", None;
+
+    DuplicateSlotId = 20, "slot id already exists", Some(SLOT_ID);
+    TargetSlotIdNotExist = 21, "target slot id does not exist", Some(SLOT_ID);
+    CannotGetChanField = 22, "missing chan field", Some(CHAN);
+    UnsupportedChan = 23, "unsupported chan", Some(CHAN);
+    CannotGetValueField = 24, "missing value field", Some(VALUE);
+    CannotGetSlotIdField = 25, "missing slot id field", Some(SLOT_ID);
+    InvalidSlotIdFieldType = 26, "slot id must be a message id", Some(SLOT_ID);
+    InvalidToFieldType = 27, "to must be a message id or an array of message id", Some(TO);
+    InvalidRootFieldType = 28, "root must be a bool", Some(ROOT);
+    InvalidShareFieldType = 29, "share must be a bool", Some(SHARE);
+    InvalidToSocketFieldType = 210, "to socket must be a message id", Some(TO_SOCKET);
+    InvalidDelayFieldType = 211, "delay must be a non-negative integer", Some(DELAY);
+    InvalidGroupFieldType = 212, "group must be a string", Some(GROUP);
+    InvalidStrategyFieldType = 213, "unknown group strategy", Some(STRATEGY);
+    InvalidWillFieldType = 214, "will must be a message with a non-system chan", Some(WILL);
+    InvalidAttrFieldType = 215, "attr must be a message", Some(ATTR);
+    CannotGetToField = 216, "missing to field", Some(TO);
+    InvalidHeartbeatFieldType = 217, "heartbeat must be a positive integer", Some(HEARTBEAT);
+    InvalidPrefetchFieldType = 218, "prefetch must be a positive integer", Some(PREFETCH);
+    CannotGetIdField = 219, "missing id field", Some(ID);
+    InvalidTimeoutFieldType = 220, "timeout must be a positive integer", Some(TIMEOUT);
+    InvalidCountFieldType = 221, "count must be a positive integer", Some(COUNT);
+    DuplicateId = 222, "id already exists", Some(ID);
+
+    InternalError = 30, "internal error", None;
+    UnsupportedFormat = 31, "unsupported format", None;
+    EmptyFieldName = 32, "empty field name", None;
+    EmptyFieldValue = 33, "empty field value", None;
+    KeyTooLong = 34, "key too long", None;
+    BadValue = 35, "bad value", None;
+    NotFound = 36, "not found", None;
+    SchemaMismatch = 37, "message does not match the chan schema", None;
+    QueueFull = 38, "queue is full", None;
+
+    UnknownError = -1, "unknown error", None;
 }
 
 impl Code {
-    pub fn code(self) -> i32 {
-        self as i32
+    // 用户自定义错误码的范围
+    pub const USER_MIN: i32 = 1000;
+    pub const USER_MAX: i32 = i32::MAX;
+
+    pub fn is_user(&self) -> bool {
+        matches!(self, Code::User(code) if (Code::USER_MIN..=Code::USER_MAX).contains(code))
     }
 
-    pub fn from_i32(code: i32) -> Code {
-        match code {
-            0 => Code::Ok,
+    // 设置 CODE，出错时同时设置 ERROR
+    pub fn set(self, message: &mut Message) {
+        if self == Code::Ok {
+            message.insert(CODE, self.code());
+        } else {
+            ErrorInfo::new(self).set(message);
+        }
+    }
+
+    pub fn get(message: &Message) -> Option<Code> {
+        if let Ok(code) = message.get_i32(CODE) {
+            return Some(Code::from_i32(code))
+        }
 
-            10 => Code::Unauthorized,
-            11 => Code::AuthenticationFailed,
-            12 => Code::PermissionDenied,
+        None
+    }
+}
 
-            20 => Code::DuplicateSlotId,
-            21 => Code::TargetSlotIdNotExist,
-            22 => Code::CannotGetChanField,
-            23 => Code::UnsupportedChan,
-            24 => Code::CannotGetValueField,
-            25 => Code::CannotGetSlotIdField,
-            26 => Code::InvalidSlotIdFieldType,
-            27 => Code::InvalidToFieldType,
-            28 => Code::InvalidRootFieldType,
-            29 => Code::InvalidShareFieldType,
-            210 => Code::InvalidToSocketFieldType,
+impl fmt::Display for Code {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "code: {}, error: {}", self.code(), self.to_str())
+    }
+}
 
-            30 => Code::InternalError,
-            31 => Code::UnsupportedFormat,
-            32 => Code::EmptyFieldName,
-            33 => Code::EmptyFieldValue,
-            34 => Code::KeyTooLong,
-            35 => Code::BadValue,
-            36 => Code::NotFound,
+// 出错时，回复的消息中除了 CODE，还会带有 ERROR：
+// {
+//     CODE: $code,
+//     ERROR: {
+//         CODE: $code,
+//         REASON: $reason,
+//         FIELD: $field,    // 可选，出错的字段
+//         DETAILS: $details // 可选
+//     }
+// }
+#[derive(Debug, Clone, PartialEq)]
+pub struct ErrorInfo {
+    pub code: Code,
+    pub reason: String,
+    pub field: Option<String>,
+    pub details: Option<Message>
+}
 
-            _ => Code::UnknownError
+impl ErrorInfo {
+    pub fn new(code: Code) -> Self {
+        ErrorInfo {
+            code,
+            reason: code.reason().to_string(),
+            field: code.field().map(ToString::to_string),
+            details: None
         }
     }
 
-    pub fn to_str(&self) -> &str {
-        match self {
-            Code::Ok => "OK",
-
-            Code::Unauthorized => "Unauthorized",
-            Code::AuthenticationFailed => "AuthenticationFailed",
-            Code::PermissionDenied => "PermissionDenied",
-
-            Code::DuplicateSlotId => "DuplicatePortId",
-            Code::TargetSlotIdNotExist => "TargetPortIdNotExist",
-            Code::CannotGetChanField => "CannotGetChanField",
-            Code::UnsupportedChan => "UnsupportedChan",
-            Code::CannotGetValueField => "CannotGetValueField",
-            Code::CannotGetSlotIdField => "CannotGetSlotIdField",
-            Code::InvalidSlotIdFieldType => "InvalidPortIdFieldType",
-            Code::InvalidToFieldType => "InvalidToFieldType",
-            Code::InvalidRootFieldType => "InvalidRootFieldType",
-            Code::InvalidShareFieldType => "InvalidShareFieldType",
-            Code::InvalidToSocketFieldType => "InvalidToSocketFieldType",
-
-            Code::InternalError => "InternalError",
-            Code::UnsupportedFormat => "UnsupportedFormat",
-            Code::EmptyFieldName => "EmptyFieldName",
-            Code::EmptyFieldValue => "EmptyFieldValue",
-            Code::KeyTooLong => "KeyTooLong",
-            Code::BadValue => "BadValue",
-            Code::NotFound => "NotFound",
-            Code::UnknownError => "UnknownError"
+    pub fn reason(mut self, reason: impl Into<String>) -> Self {
+        self.reason = reason.into();
+        self
+    }
+
+    pub fn field(mut self, field: impl Into<String>) -> Self {
+        self.field = Some(field.into());
+        self
+    }
+
+    pub fn details(mut self, details: Message) -> Self {
+        self.details = Some(details);
+        self
+    }
+
+    pub fn to_message(&self) -> Message {
+        let mut message = msg!{
+            CODE: self.code.code(),
+            REASON: &self.reason
+        };
+
+        if let Some(field) = &self.field {
+            message.insert(FIELD, field);
+        }
+
+        if let Some(details) = &self.details {
+            message.insert(DETAILS, details.clone());
         }
+
+        message
     }
 
-    pub fn set(self, message: &mut Message) {
-        let code = self.code();
-        message.insert(CODE, code);
+    pub fn set(&self, message: &mut Message) {
+        message.insert(CODE, self.code.code());
+        message.insert(ERROR, self.to_message());
     }
 
-    pub fn get(message: &Message) -> Option<Code> {
-        if let Ok(code) = message.get_i32(CODE) {
-            return Some(Code::from_i32(code))
+    // 从回复的消息中读取错误，CODE 不存在或为 Ok 时返回 None
+    pub fn get(message: &Message) -> Option<ErrorInfo> {
+        let code = Code::get(message)?;
+
+        if code == Code::Ok {
+            return None
         }
 
-        None
+        let mut info = ErrorInfo::new(code);
+
+        match message.get(ERROR) {
+            Some(Value::Message(error)) => {
+                if let Ok(reason) = error.get_str(REASON) {
+                    info.reason = reason.to_string();
+                }
+
+                if let Ok(field) = error.get_str(FIELD) {
+                    info.field = Some(field.to_string());
+                }
+
+                if let Ok(details) = error.get_message(DETAILS) {
+                    info.details = Some(details.clone());
+                }
+            }
+            Some(Value::String(reason)) => info.reason = reason.to_string(),
+            _ => ()
+        }
+
+        Some(info)
     }
 }
 
-impl fmt::Display for Code {
+impl fmt::Display for ErrorInfo {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-        write!(fmt, "code: {}, error: {}", self.code(), self.to_str())
+        write!(fmt, "code: {}, error: {}, reason: {}", self.code.code(), self.code.to_str(), self.reason)?;
+
+        if let Some(field) = &self.field {
+            write!(fmt, ", field: {}", field)?;
+        }
+
+        Ok(())
     }
 }
 
-
 #[derive(PartialEq, Eq, Clone, Copy)]
 pub enum SendError<T> {
     Full(T),
@@ -241,3 +377,53 @@ impl fmt::Display for RecvError {
 }
 
 impl error::Error for RecvError {}
+
+#[cfg(test)]
+mod tests {
+    use nson::msg;
+
+    use crate::dict::*;
+
+    use super::{Code, ErrorInfo};
+
+    #[test]
+    fn test_code() {
+        for code in &[Code::Ok, Code::DuplicateSlotId, Code::InvalidToSocketFieldType, Code::SchemaMismatch, Code::UnknownError] {
+            assert!(Code::from_i32(code.code()) == *code);
+        }
+
+        assert!(Code::DuplicateSlotId.to_str() == "DuplicateSlotId");
+        assert!(Code::InvalidToFieldType.field() == Some(TO));
+
+        assert!(Code::from_i32(1001) == Code::User(1001));
+        assert!(Code::User(1001).code() == 1001);
+        assert!(Code::User(1001).is_user());
+        assert!(!Code::User(500).is_user());
+    }
+
+    #[test]
+    fn test_error_info() {
+        let mut message = msg!{};
+        Code::CannotGetChanField.set(&mut message);
+
+        let info = ErrorInfo::get(&message).unwrap();
+        assert!(info.code == Code::CannotGetChanField);
+        assert!(info.field.as_deref() == Some(CHAN));
+
+        let mut message = msg!{};
+        ErrorInfo::new(Code::User(1001)).reason("quota exceeded").details(msg!{"limit": 10}).set(&mut message);
+
+        let info = ErrorInfo::get(&message).unwrap();
+        assert!(info.code == Code::User(1001));
+        assert!(info.reason == "quota exceeded");
+        assert!(info.details.unwrap().get_i32("limit").unwrap() == 10);
+
+        // ERROR 为字符串时作为 reason
+        let info = ErrorInfo::get(&msg!{CODE: 35, ERROR: "bad"}).unwrap();
+        assert!(info.code == Code::BadValue);
+        assert!(info.reason == "bad");
+
+        assert!(ErrorInfo::get(&msg!{CODE: 0}).is_none());
+        assert!(ErrorInfo::get(&msg!{}).is_none());
+    }
+}
@@ -1,5 +1,7 @@
 pub mod socket;
 pub mod wire;
+pub mod typed;
+pub mod stream;
 pub mod node;
 pub mod net;
 pub mod port;
@@ -8,12 +10,17 @@ pub mod dict;
 pub mod timer;
 pub mod util;
 pub mod error;
+pub mod audit;
+pub mod broker;
 
 pub use nson;
 
 pub const MAX_MESSAGE_LEN: usize = 64 * 1024 * 1024; // 64 MB
 
-pub use crate::socket::{Socket, Switch, Slot, Hook, NonHook};
+pub use crate::socket::{Socket, Switch, Slot, Hook, NonHook, Acl};
 pub use crate::wire::Wire;
+pub use crate::typed::TypedWire;
 pub use crate::node::Node;
 pub use crate::port::Port;
+pub use crate::audit::Audit;
+pub use crate::broker::{Broker, BrokerConfig};
@@ -3,10 +3,16 @@ use crate::crypto::Method;
 pub use codec::{Codec, NsonCodec};
 pub use network::{Packet, NetWork};
 pub use keepalive::KeepAlive;
+pub use flow::{FlowControl, Overflow, NetStats};
+pub use handshake::{HandshakeConfig, HandStep, Acceptor};
+pub use admission::{AdmissionConfig, Admission, Cidr, Ticket};
 
 mod codec;
 mod network;
 mod keepalive;
+mod flow;
+mod handshake;
+mod admission;
 pub mod tcp_ext;
 
 #[derive(Debug, Clone)]
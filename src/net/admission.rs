@@ -0,0 +1,297 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::Ordering;
+use std::time::Instant;
+
+use crate::error::{Result, Error};
+
+use super::NetStats;
+
+// 连接的准入控制，在接受连接时、握手之前检查
+//
+// 依次检查：
+// 1. deny 列表，匹配时拒绝
+// 2. allow 列表，不为空且不匹配时拒绝
+// 3. 接受连接的速率，令牌桶，每秒 rate 个，最多积攒 burst 个
+// 4. 总连接数，包括正在握手的连接
+// 5. 每个来源 IP 的连接数，同一网段（ipv4_prefix、ipv6_prefix）的地址合并计算
+#[derive(Debug, Clone)]
+pub struct AdmissionConfig {
+    pub max_conns: Option<usize>,
+    pub max_per_ip: Option<usize>,
+    pub ipv4_prefix: u8,
+    pub ipv6_prefix: u8,
+    // 每秒接受的连接数
+    pub rate: Option<u32>,
+    pub burst: u32,
+    pub allow: Vec<Cidr>,
+    pub deny: Vec<Cidr>
+}
+
+impl Default for AdmissionConfig {
+    fn default() -> Self {
+        Self {
+            max_conns: None,
+            max_per_ip: None,
+            ipv4_prefix: 32,
+            ipv6_prefix: 128,
+            rate: None,
+            burst: 0,
+            allow: Vec::new(),
+            deny: Vec::new()
+        }
+    }
+}
+
+impl AdmissionConfig {
+    pub fn new() -> Self {
+        AdmissionConfig::default()
+    }
+}
+
+// 网段，比如 "10.0.0.0/8"、"fe80::/10"，不带前缀长度时表示单个地址
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cidr {
+    addr: IpAddr,
+    prefix: u8
+}
+
+impl Cidr {
+    pub fn new(addr: IpAddr, prefix: u8) -> Result<Self> {
+        if prefix > max_prefix(&addr) {
+            return Err(Error::InvalidData(format!("invalid prefix length: {}/{}", addr, prefix)))
+        }
+
+        Ok(Cidr {
+            addr: mask(addr, prefix),
+            prefix
+        })
+    }
+
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        let ip = ip.to_canonical();
+
+        ip.is_ipv4() == self.addr.is_ipv4() && mask(ip, self.prefix) == self.addr
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || Error::InvalidData(format!("invalid cidr: {}", s));
+
+        match s.split_once('/') {
+            Some((addr, prefix)) => {
+                let addr = IpAddr::from_str(addr).map_err(|_| invalid())?;
+                let prefix = u8::from_str(prefix).map_err(|_| invalid())?;
+
+                Cidr::new(addr.to_canonical(), prefix)
+            }
+            None => {
+                let addr = IpAddr::from_str(s).map_err(|_| invalid())?.to_canonical();
+
+                Cidr::new(addr, max_prefix(&addr))
+            }
+        }
+    }
+}
+
+pub struct Admission {
+    config: AdmissionConfig,
+    stats: Arc<NetStats>,
+    state: Mutex<State>
+}
+
+struct State {
+    conns: usize,
+    per_ip: HashMap<IpAddr, usize>,
+    tokens: f64,
+    last: Instant
+}
+
+impl Admission {
+    pub fn new(config: AdmissionConfig, stats: Arc<NetStats>) -> Arc<Self> {
+        let tokens = f64::from(config.burst.max(1));
+
+        Arc::new(Admission {
+            config,
+            stats,
+            state: Mutex::new(State {
+                conns: 0,
+                per_ip: HashMap::new(),
+                tokens,
+                last: Instant::now()
+            })
+        })
+    }
+
+    pub fn config(&self) -> &AdmissionConfig {
+        &self.config
+    }
+
+    // 允许连接时返回 Ticket，Ticket 被丢弃时释放占用的名额
+    pub fn admit(self: &Arc<Self>, addr: &SocketAddr) -> Result<Ticket> {
+        let ip = addr.ip().to_canonical();
+
+        if self.config.deny.iter().any(|cidr| cidr.contains(&ip)) ||
+            (!self.config.allow.is_empty() && !self.config.allow.iter().any(|cidr| cidr.contains(&ip)))
+        {
+            self.stats.denied.fetch_add(1, Ordering::Relaxed);
+
+            return Err(Error::PermissionDenied(format!("{} is not allowed", ip)))
+        }
+
+        let group = self.group(ip);
+
+        let mut state = self.state.lock().unwrap();
+
+        if let Some(rate) = self.config.rate {
+            let now = Instant::now();
+            let elapsed = now.duration_since(state.last).as_secs_f64();
+            let burst = f64::from(self.config.burst.max(1));
+
+            state.tokens = (state.tokens + elapsed * f64::from(rate)).min(burst);
+            state.last = now;
+
+            if state.tokens < 1.0 {
+                self.stats.rate_limited.fetch_add(1, Ordering::Relaxed);
+
+                return Err(Error::Full(format!("accept rate exceeds {}/s", rate)))
+            }
+
+            state.tokens -= 1.0;
+        }
+
+        if let Some(max) = self.config.max_conns {
+            if state.conns >= max {
+                self.stats.conn_rejects.fetch_add(1, Ordering::Relaxed);
+
+                return Err(Error::Full(format!("too many connections, max: {}", max)))
+            }
+        }
+
+        let count = state.per_ip.get(&group).copied().unwrap_or(0);
+
+        if let Some(max) = self.config.max_per_ip {
+            if count >= max {
+                self.stats.ip_rejects.fetch_add(1, Ordering::Relaxed);
+
+                return Err(Error::Full(format!("too many connections from {}, max: {}", group, max)))
+            }
+        }
+
+        state.conns += 1;
+        state.per_ip.insert(group, count + 1);
+
+        Ok(Ticket {
+            admission: self.clone(),
+            group
+        })
+    }
+
+    // 当前占用名额的连接数
+    pub fn conns(&self) -> usize {
+        self.state.lock().unwrap().conns
+    }
+
+    // 来源 IP（或网段）当前的连接数
+    pub fn conns_of(&self, ip: &IpAddr) -> usize {
+        let group = self.group(ip.to_canonical());
+
+        self.state.lock().unwrap().per_ip.get(&group).copied().unwrap_or(0)
+    }
+
+    fn group(&self, ip: IpAddr) -> IpAddr {
+        match ip {
+            IpAddr::V4(_) => mask(ip, self.config.ipv4_prefix),
+            IpAddr::V6(_) => mask(ip, self.config.ipv6_prefix)
+        }
+    }
+
+    fn release(&self, group: &IpAddr) {
+        let mut state = self.state.lock().unwrap();
+
+        state.conns -= 1;
+
+        if let Some(count) = state.per_ip.get_mut(group) {
+            *count -= 1;
+
+            if *count == 0 {
+                state.per_ip.remove(group);
+            }
+        }
+    }
+}
+
+// 连接占用的名额，连接关闭时随之释放
+pub struct Ticket {
+    admission: Arc<Admission>,
+    group: IpAddr
+}
+
+impl Drop for Ticket {
+    fn drop(&mut self) {
+        self.admission.release(&self.group);
+    }
+}
+
+fn max_prefix(addr: &IpAddr) -> u8 {
+    match addr {
+        IpAddr::V4(_) => 32,
+        IpAddr::V6(_) => 128
+    }
+}
+
+fn mask(addr: IpAddr, prefix: u8) -> IpAddr {
+    match addr {
+        IpAddr::V4(v4) => {
+            let prefix = u32::from(prefix.min(32));
+            let bits = u32::from(v4);
+            let mask = u32::MAX.checked_shl(32 - prefix).unwrap_or(0);
+
+            IpAddr::V4((bits & mask).into())
+        }
+        IpAddr::V6(v6) => {
+            let prefix = u32::from(prefix.min(128));
+            let bits = u128::from(v6);
+            let mask = u128::MAX.checked_shl(128 - prefix).unwrap_or(0);
+
+            IpAddr::V6((bits & mask).into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::IpAddr;
+
+    use super::Cidr;
+
+    #[test]
+    fn cidr() {
+        let cidr: Cidr = "10.1.0.0/16".parse().unwrap();
+
+        assert!(cidr.contains(&"10.1.2.3".parse().unwrap()));
+        assert!(!cidr.contains(&"10.2.0.1".parse().unwrap()));
+        assert!(cidr.contains(&"::ffff:10.1.0.1".parse::<IpAddr>().unwrap()));
+
+        let cidr: Cidr = "10.1.2.3".parse().unwrap();
+        assert!(cidr.contains(&"10.1.2.3".parse().unwrap()));
+        assert!(!cidr.contains(&"10.1.2.4".parse().unwrap()));
+
+        let cidr: Cidr = "0.0.0.0/0".parse().unwrap();
+        assert!(cidr.contains(&"1.2.3.4".parse().unwrap()));
+        assert!(!cidr.contains(&"::1".parse().unwrap()));
+
+        let cidr: Cidr = "fe80::/10".parse().unwrap();
+        assert!(cidr.contains(&"fe80::1".parse().unwrap()));
+        assert!(!cidr.contains(&"fe00::1".parse().unwrap()));
+
+        assert!("10.0.0.0/33".parse::<Cidr>().is_err());
+        assert!("10.0.0/8".parse::<Cidr>().is_err());
+        assert!("::/129".parse::<Cidr>().is_err());
+    }
+}
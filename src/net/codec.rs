@@ -19,8 +19,9 @@ impl Codec for NsonCodec {
 
     fn decode(&mut self, crypto: &Option<Crypto>, mut bytes: Vec<u8>) -> Result<Message> {
         if let Some(crypto) = &crypto {
+            // 解密失败说明密钥不对，与数据格式错误区分开
             crypto.decrypt(&mut bytes).map_err(|err|
-                Error::InvalidData(format!("{}", err))
+                Error::PermissionDenied(format!("decrypt: {}", err))
             )?;
         }
 
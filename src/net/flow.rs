@@ -0,0 +1,146 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use nson::{Message, msg};
+
+// 连接的流量控制
+//
+// 读：wire 满时停止从 TCP 连接中读取，wire 有空位后再继续，不会丢弃消息
+// 写：限制每个连接写缓冲的字节数，超出时按 overflow 处理
+#[derive(Debug, Clone)]
+pub struct FlowControl {
+    // 每个连接写缓冲的最大字节数
+    pub max_buffer: usize,
+    pub overflow: Overflow
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overflow {
+    // 暂停从 wire 中取消息，消息积压在 wire 中，直到写缓冲低于上限
+    Wait,
+    // 写缓冲超出上限时断开连接，适用于不希望被慢速读取方拖累的场景
+    Close
+}
+
+impl Default for FlowControl {
+    fn default() -> Self {
+        Self {
+            max_buffer: 4 * 1024 * 1024,
+            overflow: Overflow::Wait
+        }
+    }
+}
+
+impl FlowControl {
+    pub fn new(max_buffer: usize, overflow: Overflow) -> Self {
+        Self {
+            max_buffer,
+            overflow
+        }
+    }
+}
+
+impl Overflow {
+    pub fn from_name(name: &str) -> Option<Overflow> {
+        match name {
+            "wait" => Some(Overflow::Wait),
+            "close" => Some(Overflow::Close),
+            _ => None
+        }
+    }
+}
+
+// 网络线程的运行统计，同一个 Node 的多个网络线程共用
+#[derive(Debug, Default)]
+pub struct NetStats {
+    pub(crate) conns: AtomicUsize,
+    pub(crate) paused: AtomicUsize,
+    pub(crate) buffered: AtomicUsize,
+    pub(crate) pauses: AtomicUsize,
+    pub(crate) overflows: AtomicUsize,
+    pub(crate) handshakes: AtomicUsize,
+    pub(crate) hand_timeouts: AtomicUsize,
+    pub(crate) hand_rejects: AtomicUsize,
+    pub(crate) denied: AtomicUsize,
+    pub(crate) rate_limited: AtomicUsize,
+    pub(crate) conn_rejects: AtomicUsize,
+    pub(crate) ip_rejects: AtomicUsize
+}
+
+impl NetStats {
+    // 当前连接数
+    pub fn conns(&self) -> usize {
+        self.conns.load(Ordering::Relaxed)
+    }
+
+    // 当前因 wire 已满而暂停读取的连接数
+    pub fn paused(&self) -> usize {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    // 所有连接写缓冲的总字节数
+    pub fn buffered(&self) -> usize {
+        self.buffered.load(Ordering::Relaxed)
+    }
+
+    // 累计暂停读取的次数
+    pub fn pauses(&self) -> usize {
+        self.pauses.load(Ordering::Relaxed)
+    }
+
+    // 累计因写缓冲超出上限而断开的连接数
+    pub fn overflows(&self) -> usize {
+        self.overflows.load(Ordering::Relaxed)
+    }
+
+    // 当前进行中的握手数
+    pub fn handshakes(&self) -> usize {
+        self.handshakes.load(Ordering::Relaxed)
+    }
+
+    // 累计握手超时的连接数
+    pub fn hand_timeouts(&self) -> usize {
+        self.hand_timeouts.load(Ordering::Relaxed)
+    }
+
+    // 累计因进行中的握手过多而直接断开的连接数
+    pub fn hand_rejects(&self) -> usize {
+        self.hand_rejects.load(Ordering::Relaxed)
+    }
+
+    // 累计被 allow、deny 列表拒绝的连接数
+    pub fn denied(&self) -> usize {
+        self.denied.load(Ordering::Relaxed)
+    }
+
+    // 累计因接受连接的速率超出限制而拒绝的连接数
+    pub fn rate_limited(&self) -> usize {
+        self.rate_limited.load(Ordering::Relaxed)
+    }
+
+    // 累计因总连接数超出限制而拒绝的连接数
+    pub fn conn_rejects(&self) -> usize {
+        self.conn_rejects.load(Ordering::Relaxed)
+    }
+
+    // 累计因来源 IP 的连接数超出限制而拒绝的连接数
+    pub fn ip_rejects(&self) -> usize {
+        self.ip_rejects.load(Ordering::Relaxed)
+    }
+
+    pub fn to_message(&self) -> Message {
+        msg!{
+            "conns": self.conns() as u64,
+            "paused": self.paused() as u64,
+            "buffered": self.buffered() as u64,
+            "pauses": self.pauses() as u64,
+            "overflows": self.overflows() as u64,
+            "handshakes": self.handshakes() as u64,
+            "hand_timeouts": self.hand_timeouts() as u64,
+            "hand_rejects": self.hand_rejects() as u64,
+            "denied": self.denied() as u64,
+            "rate_limited": self.rate_limited() as u64,
+            "conn_rejects": self.conn_rejects() as u64,
+            "ip_rejects": self.ip_rejects() as u64
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use nson::Message;
+
+use crate::Wire;
+use crate::crypto::Crypto;
+use crate::error::Error;
+
+// 握手的限制
+#[derive(Debug, Clone)]
+pub struct HandshakeConfig {
+    // 握手消息的最大字节数
+    pub max_size: usize,
+    // 连接建立后，需要在这个时间内完成握手
+    pub timeout: Duration,
+    // 同时进行中的握手的最大数量，超出时新的连接会被直接断开，只对 Node 有效
+    pub max_pending: usize
+}
+
+impl Default for HandshakeConfig {
+    fn default() -> Self {
+        Self {
+            max_size: 2048,
+            timeout: Duration::from_secs(5),
+            max_pending: 1024
+        }
+    }
+}
+
+impl HandshakeConfig {
+    pub fn new(max_size: usize, timeout: Duration, max_pending: usize) -> Self {
+        Self {
+            max_size,
+            timeout,
+            max_pending
+        }
+    }
+}
+
+// 握手的每一步
+#[allow(clippy::large_enum_variant)]
+pub enum HandStep {
+    // 发回消息，等待对方的下一条握手消息，比如挑战应答
+    Continue(Message),
+    // 已发起连接，wire 收到第一条消息（连接的结果）后调用 Acceptor.connected，不在网络线程中等待
+    Connect(Message, Wire<Message>, Option<Crypto>),
+    // 握手成功，发回消息后开始正常收发
+    Finish(Message, Wire<Message>, Option<Crypto>),
+    // 握手失败，发回消息（如果有）后断开
+    Fail(Error, Option<Message>)
+}
+
+// 服务端的握手逻辑，由网络线程以非阻塞的方式驱动，多个网络线程共用
+pub trait Acceptor: Send + Sync + 'static {
+    // prev 为上一轮发回的消息，第一轮为 None
+    fn hand(&self, addr: &SocketAddr, prev: Option<&Message>, message: Message) -> HandStep;
+
+    // HandStep::Connect 之后调用，ret 为 wire 收到的第一条消息
+    fn connected(
+        &self,
+        addr: &SocketAddr,
+        message: Message,
+        wire: Wire<Message>,
+        crypto: Option<Crypto>,
+        ret: Message
+    ) -> HandStep;
+
+    // 握手失败时调用，包括超时和网络错误
+    fn fail(&self, addr: &SocketAddr, err: &Error);
+}
@@ -8,27 +8,35 @@ use std::io::{
     ErrorKind::{WouldBlock, Interrupted, InvalidData, BrokenPipe}
 };
 use std::mem;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
 
 use queen_io::{
     epoll::{Epoll, Event, Events, Token, Ready, EpollOpt},
     queue::mpsc::Queue,
     net::tcp::TcpStream,
-    plus::slab::Slab
+    plus::slab::Slab,
+    waker::Waker
 };
 use queen_io::sys::timerfd::{TimerFd, TimerSpec, SetTimeFlags};
 
-use nson::{Message, msg};
+use nson::{Message, Value, msg};
 
 use crate::Wire;
 use crate::crypto::Crypto;
-use crate::error::{Error, Result, RecvError, Code};
+use crate::error::{Error, Result, RecvError, SendError, Code};
 use crate::dict::*;
 use crate::timer::wheel::Wheel;
+use crate::util::trace;
 use crate::MAX_MESSAGE_LEN;
 
 use super::Codec;
 use super::KeepAlive;
+use super::{FlowControl, Overflow, NetStats};
+use super::{HandshakeConfig, HandStep, Acceptor, Ticket};
 
+#[allow(clippy::large_enum_variant)]
 pub enum Packet<C: Codec> {
     NewConn {
         wire: Wire<Message>,
@@ -36,6 +44,12 @@ pub enum Packet<C: Codec> {
         codec: C,
         crypto: Option<Crypto>
     },
+    // 尚未握手的连接，由 Acceptor 在网络线程中完成握手
+    Accept {
+        stream: TcpStream,
+        addr: SocketAddr,
+        ticket: Option<Ticket>
+    },
     Close
 }
 
@@ -50,11 +64,25 @@ pub struct NetWork<C: Codec> {
     time_id_counter: usize,
     wheel: Wheel<(usize, usize)>,
     instant: Instant,
+    name: &'static str,
+    flow: FlowControl,
+    stats: Arc<NetStats>,
+    // 因 wire 已满而暂停读取的连接
+    paused: Vec<usize>,
+    // 暂停的连接的 wire 有了空位时唤醒
+    space: Waker,
+    acceptor: Option<Arc<dyn Acceptor>>,
+    handshake: HandshakeConfig,
+    // 正在握手的连接
+    hands: Slab<Hand<C>>
 }
 
 impl<C: Codec> NetWork<C> {
     const QUEUE_TOKEN: usize = usize::MAX;
     const TIMER_TOKEN: usize = usize::MAX - 1;
+    const SPACE_TOKEN: usize = usize::MAX - 2;
+    // 正在握手的连接使用 HAND_TOKEN 之后的 token，偶数为连接，奇数为等待连接结果的 wire
+    const HAND_TOKEN: usize = usize::MAX / 2;
 
     pub fn new(queue: Queue<Packet<C>>, keep_alive: KeepAlive) -> Result<Self> {
         Ok(Self {
@@ -67,10 +95,45 @@ impl<C: Codec> NetWork<C> {
             timer: TimerFd::new()?,
             time_id_counter: 0,
             wheel: Wheel::default(),
-            instant: Instant::now()
+            instant: Instant::now(),
+            name: "net",
+            flow: FlowControl::default(),
+            stats: Arc::new(NetStats::default()),
+            paused: Vec::new(),
+            space: Waker::new()?,
+            acceptor: None,
+            handshake: HandshakeConfig::default(),
+            hands: Slab::new()
         })
     }
 
+    // 用于追踪消息时标记是哪一跳，比如 "node"、"port"
+    pub fn set_name(&mut self, name: &'static str) {
+        self.name = name;
+    }
+
+    pub fn set_flow_control(&mut self, flow: FlowControl) {
+        self.flow = flow;
+    }
+
+    // 多个网络线程可以共用同一个统计
+    pub fn set_stats(&mut self, stats: Arc<NetStats>) {
+        self.stats = stats;
+    }
+
+    pub fn stats(&self) -> Arc<NetStats> {
+        self.stats.clone()
+    }
+
+    // 设置后才能处理 Packet::Accept
+    pub fn set_acceptor(&mut self, acceptor: Arc<dyn Acceptor>) {
+        self.acceptor = Some(acceptor);
+    }
+
+    pub fn set_handshake(&mut self, handshake: HandshakeConfig) {
+        self.handshake = handshake;
+    }
+
     fn next_time_id(&mut self) -> usize {
         self.time_id_counter = self.time_id_counter.wrapping_add(1);
         self.time_id_counter
@@ -79,6 +142,7 @@ impl<C: Codec> NetWork<C> {
     pub fn run(&mut self) -> Result<()> {
         self.epoll.add(&self.queue, Token(Self::QUEUE_TOKEN), Ready::readable(), EpollOpt::level())?;
         self.epoll.add(&self.timer, Token(Self::TIMER_TOKEN), Ready::readable(), EpollOpt::edge())?;
+        self.epoll.add(&self.space, Token(Self::SPACE_TOKEN), Ready::readable(), EpollOpt::edge())?;
 
         let timerspec = TimerSpec {
             interval: Duration::new(1, 0),
@@ -107,44 +171,27 @@ impl<C: Codec> NetWork<C> {
                         if let Some(packet) = self.queue.pop() {
                             match packet {
                                 Packet::NewConn { wire, stream, codec, crypto } => {
-                                    let time_id = self.next_time_id();
-
-                                    let entry1 = self.wires.vacant_entry();
-                                    let entry2 = self.nets.vacant_entry();
-
-                                    assert_eq!(entry1.key(), entry2.key());
-
-                                    let token = entry1.key() * 2;
-                                    let token2 = token + 1;
-
-                                    self.epoll.add(
-                                        &wire,
-                                        Token(token),
-                                        Ready::readable(),
-                                        EpollOpt::edge()
-                                    )?;
-
-                                    self.epoll.add(
-                                        &stream,
-                                        Token(token2),
-                                        Ready::readable() | Ready::hup(),
-                                        EpollOpt::edge()
-                                    )?;
-
-                                    entry1.insert(wire);
-
-                                    let conn = NetConn::new(token2, stream, codec, crypto, time_id, self.keep_alive.clone());
-                                    // timer
-                                    self.wheel.insert((token, time_id), conn.keep_alive.idle).expect("can't insert id into wheel");
-
-                                    entry2.insert(conn);
+                                    self.add_conn(wire, stream, codec, crypto)?;
+                                }
+                                Packet::Accept { stream, addr, ticket } => {
+                                    self.add_hand(stream, addr, ticket)?;
                                 }
                                 Packet::Close => {
+                                    let indexs: Vec<usize> = self.nets.iter().map(|(index, _)| index).collect();
+
+                                    for index in indexs {
+                                        self.remove_conn(index, BREAK_SHUTDOWN)?;
+                                    }
+
                                     return Ok(())
                                 }
                             }
                         }
                     }
+                    // 暂停的连接在循环的最后重新尝试
+                    Self::SPACE_TOKEN => {
+                        self.space.finish()?;
+                    }
                     Self::TIMER_TOKEN => {
                         match self.timer.read() {
                             Ok(_) => (),
@@ -159,12 +206,19 @@ impl<C: Codec> NetWork<C> {
 
                         self.instant = Instant::now();
 
+                        self.expire_hands()?;
+
                         let list = self.wheel.tick();
 
                         for (token, time_id) in list {
                             let index = token / 2;
                             if let Some(net_conn) = self.nets.get_mut(index) {
                                 if time_id == net_conn.time_id {
+                                    // 暂停读取期间对方可能一直在发送，不应该判定为超时
+                                    if net_conn.pending.is_some() {
+                                        net_conn.keep_alive.reset(self.instant);
+                                    }
+
                                     if let Some((delay, detect)) = net_conn.keep_alive.tick(self.instant) {
                                         self.wheel.insert((net_conn.token, time_id), delay).expect("can't insert id into wheel");
 
@@ -178,7 +232,7 @@ impl<C: Codec> NetWork<C> {
                                             net_conn.want_write(&self.epoll)?;
                                         }
                                     } else {
-                                        self.remove_conn(index)?;
+                                        self.remove_conn(index, BREAK_KEEP_ALIVE)?;
                                     }
                                 }
                             }
@@ -189,13 +243,23 @@ impl<C: Codec> NetWork<C> {
                     }
                 }
             }
+
+            self.resume()?;
         }
     }
 
     fn dispatch(&mut self, event: Event) -> Result<()> {
         let token = event.token().0;
 
-        if token % 2 == 0 {
+        if token >= Self::HAND_TOKEN {
+            let token = token - Self::HAND_TOKEN;
+
+            if token.is_multiple_of(2) {
+                self.dispatch_hand(token / 2, event.readiness())?;
+            } else {
+                self.dispatch_hand_wire(token / 2)?;
+            }
+        } else if token.is_multiple_of(2) {
             self.dispatch_wire(token / 2)?;
         } else {
             self.dispatch_stream(token / 2, event.readiness())?;
@@ -204,78 +268,310 @@ impl<C: Codec> NetWork<C> {
         Ok(())
     }
 
-    fn dispatch_wire(&mut self, index: usize) -> Result<()> {
-        if let Some(_wire) = self.wires.get(index) {
-            if let Some(net_conn) = self.nets.get_mut(index) {
-                net_conn.want_write(&self.epoll)?;
+    fn add_conn(&mut self, wire: Wire<Message>, stream: TcpStream, codec: C, crypto: Option<Crypto>) -> Result<usize> {
+        let time_id = self.next_time_id();
+
+        let entry1 = self.wires.vacant_entry();
+        let entry2 = self.nets.vacant_entry();
+
+        assert_eq!(entry1.key(), entry2.key());
+
+        let index = entry1.key();
+        let token = index * 2;
+        let token2 = token + 1;
+
+        self.epoll.add(
+            &wire,
+            Token(token),
+            Ready::readable(),
+            EpollOpt::edge()
+        )?;
+
+        self.epoll.add(
+            &stream,
+            Token(token2),
+            Ready::readable() | Ready::hup(),
+            EpollOpt::edge()
+        )?;
+
+        entry1.insert(wire);
+
+        let conn = NetConn::new(token2, stream, codec, crypto, time_id, self.keep_alive.clone(), self.stats.clone());
+        // timer
+        self.wheel.insert((token, time_id), conn.keep_alive.idle).expect("can't insert id into wheel");
+
+        entry2.insert(conn);
+
+        self.stats.conns.fetch_add(1, Ordering::Relaxed);
+
+        Ok(index)
+    }
+
+    fn add_hand(&mut self, stream: TcpStream, addr: SocketAddr, ticket: Option<Ticket>) -> Result<()> {
+        if self.acceptor.is_none() {
+            log::error!("{}: no acceptor, drop connection from {}", self.name, addr);
+            self.stats.handshakes.fetch_sub(1, Ordering::Relaxed);
+
+            return Ok(())
+        }
+
+        let entry = self.hands.vacant_entry();
+        let token = Self::HAND_TOKEN + entry.key() * 2;
+
+        // 对方可能一直不发送握手消息，也可能一直不读取，都会在超时后断开
+        self.epoll.add(
+            &stream,
+            Token(token),
+            Ready::readable() | Ready::writable() | Ready::hup(),
+            EpollOpt::edge()
+        )?;
+
+        entry.insert(Hand {
+            token,
+            stream,
+            addr,
+            codec: C::new(),
+            r_buffer: (0, Vec::new()),
+            w_buffer: Vec::new(),
+            prev: None,
+            connecting: None,
+            deadline: Instant::now() + self.handshake.timeout,
+            ticket
+        });
+
+        Ok(())
+    }
+
+    fn dispatch_hand(&mut self, index: usize, ready: Ready) -> Result<()> {
+        let acceptor = match &self.acceptor {
+            Some(acceptor) => acceptor.clone(),
+            None => return Ok(())
+        };
+
+        let ret = match self.hands.get_mut(index) {
+            Some(hand) => {
+                if ready.is_hup() || ready.is_error() {
+                    Err(Error::BrokenPipe("Hand.read".to_string()))
+                } else {
+                    hand.advance(&self.epoll, &*acceptor, self.handshake.max_size)
+                }
+            }
+            None => return Ok(())
+        };
+
+        self.handed(index, &*acceptor, ret)
+    }
+
+    // 连接的结果到达
+    fn dispatch_hand_wire(&mut self, index: usize) -> Result<()> {
+        let acceptor = match &self.acceptor {
+            Some(acceptor) => acceptor.clone(),
+            None => return Ok(())
+        };
+
+        let ret = match self.hands.get_mut(index) {
+            Some(hand) => hand.connected(&self.epoll, &*acceptor),
+            None => return Ok(())
+        };
+
+        self.handed(index, &*acceptor, ret)
+    }
+
+    fn handed(&mut self, index: usize, acceptor: &dyn Acceptor, ret: Result<Option<Handed>>) -> Result<()> {
+        match ret {
+            Ok(None) => Ok(()),
+            Ok(Some((reply, wire, crypto))) => {
+                let hand = self.remove_hand(index)?;
+
+                let index = self.add_conn(wire, hand.stream, hand.codec, crypto)?;
+
+                // 握手消息不加密，并且要先于其他消息发出
+                let net_conn = &mut self.nets[index];
+                net_conn.push_bytes(hand.w_buffer);
+                net_conn.push_bytes(reply);
+                net_conn.ticket = hand.ticket;
+                net_conn.accepted = true;
+
+                self.flush(index)
+            }
+            Err(err) => {
+                let hand = self.remove_hand(index)?;
+
+                log::debug!("{}: handshake with {} failed: {}", self.name, hand.addr, err);
+                acceptor.fail(&hand.addr, &err);
+
+                Ok(())
+            }
+        }
+    }
+
+    fn expire_hands(&mut self) -> Result<()> {
+        let expired: Vec<usize> = self.hands.iter()
+            .filter(|(_, hand)| hand.deadline <= self.instant)
+            .map(|(index, _)| index)
+            .collect();
+
+        for index in expired {
+            let hand = self.remove_hand(index)?;
+
+            self.stats.hand_timeouts.fetch_add(1, Ordering::Relaxed);
+
+            let err = Error::TimedOut(format!("handshake with {}", hand.addr));
+
+            log::debug!("{}: {}", self.name, err);
+
+            if let Some(acceptor) = &self.acceptor {
+                acceptor.fail(&hand.addr, &err);
             }
         }
 
         Ok(())
     }
 
+    fn remove_hand(&mut self, index: usize) -> Result<Hand<C>> {
+        let hand = self.hands.remove(index);
+        self.epoll.delete(&hand.stream)?;
+
+        if let Some((_, wire, _)) = &hand.connecting {
+            self.epoll.delete(wire)?;
+        }
+
+        self.stats.handshakes.fetch_sub(1, Ordering::Relaxed);
+
+        Ok(hand)
+    }
+
+    fn dispatch_wire(&mut self, index: usize) -> Result<()> {
+        self.flush(index)
+    }
+
     fn dispatch_stream(&mut self, index: usize, ready: Ready) -> Result<()> {
-        let mut remove = ready.is_hup() || ready.is_error();
+        let mut remove = if ready.is_hup() || ready.is_error() {
+            Some(BREAK_LOST)
+        } else {
+            None
+        };
 
         if ready.is_readable() {
             if let Some(net_conn) = self.nets.get_mut(index) {
-                let ret = net_conn.read(&self.epoll, &self.wires[index], self.instant);
-                if ret.is_err() {
-                    log::debug!("net_conn.read: {:?}", ret);
-                    remove = true;
+                match net_conn.read(&self.epoll, &self.wires[index], self.instant, self.name) {
+                    Ok(true) => {
+                        self.wires[index].notify_space(self.space.clone());
+                        self.paused.push(index);
+                    }
+                    Ok(false) => (),
+                    Err(err) => {
+                        log::debug!("net_conn.read: {:?}", err);
+                        remove = Some(break_reason(&err));
+                    }
                 }
             }
         }
 
+        if let Some(reason) = remove {
+            return self.remove_conn(index, reason)
+        }
+
         if ready.is_writable() {
-            if let Some(wire) = self.wires.get(index) {
-                if let Some(net_conn) = self.nets.get_mut(index) {
-
-                    if !net_conn.w_buffer.is_empty() {
-                        let ret = net_conn.write(&self.epoll);
-                        if ret.is_err() {
-                            log::debug!("net_conn.write: {:?}", ret);
-                            remove = true;
-                        }
-                    } else {
-                        match wire.recv() {
-                            Ok(message) => {
-                                net_conn.push_data(message)?;
-
-                                let ret = net_conn.write(&self.epoll);
-                                if ret.is_err() {
-                                    log::debug!("net_conn.write: {:?}", ret);
-                                    remove = true;
-                                }
-                            }
-                            Err(err) => {
-                                if matches!(err, RecvError::Empty) {
-                                    if net_conn.w_buffer.is_empty() {
-                                        net_conn.interest.remove(Ready::writable());
-                                    }
-                                } else {
-                                    remove = true
-                                }
-                            }
-                        }
+            self.flush(index)?;
+        }
+
+        Ok(())
+    }
+
+    // 从 wire 中取出消息放入写缓冲，并写入连接
+    fn flush(&mut self, index: usize) -> Result<()> {
+        let mut remove = None;
+
+        if let (Some(wire), Some(net_conn)) = (self.wires.get(index), self.nets.get_mut(index)) {
+            if let Err(err) = net_conn.flush(&self.epoll, wire, &self.flow, self.name) {
+                log::debug!("net_conn.flush: {:?}", err);
+                remove = Some(break_reason(&err));
+            }
+        }
+
+        if let Some(reason) = remove {
+            self.remove_conn(index, reason)?;
+        }
+
+        Ok(())
+    }
+
+    // 把暂停时没能放入 wire 的消息重新放入，成功后恢复读取
+    // 每次事件循环的最后执行，wire 有了空位时由 space 唤醒
+    fn resume(&mut self) -> Result<()> {
+        if self.paused.is_empty() {
+            return Ok(())
+        }
+
+        for index in mem::take(&mut self.paused) {
+            let mut remove = None;
+
+            if let (Some(wire), Some(net_conn)) = (self.wires.get(index), self.nets.get_mut(index)) {
+                match net_conn.resume(&self.epoll, wire, self.instant, self.name) {
+                    Ok(true) => {
+                        wire.notify_space(self.space.clone());
+                        self.paused.push(index);
+                    }
+                    Ok(false) => (),
+                    Err(err) => {
+                        log::debug!("net_conn.resume: {:?}", err);
+                        remove = Some(break_reason(&err));
                     }
                 }
             }
-        }
 
-        if remove {
-            self.remove_conn(index)?;
+            if let Some(reason) = remove {
+                self.remove_conn(index, reason)?;
+            }
         }
 
         Ok(())
     }
 
-    fn remove_conn(&mut self, index: usize) -> Result<()> {
+    // 断开连接，并尽量告知两端断开的原因
+    // {
+    //     CHAN: CLOSE,
+    //     REASON: $reason
+    // }
+    fn remove_conn(&mut self, index: usize, reason: &'static str) -> Result<()> {
+        // 同一批事件中可能已经被移除，比如写出错后又收到 hup
+        if !self.nets.contains(index) {
+            return Ok(())
+        }
+
         let wire = self.wires.remove(index);
         self.epoll.delete(&wire)?;
 
-        let net = self.nets.remove(index);
-        self.epoll.delete(&net.stream)?;
+        let mut net = self.nets.remove(index);
+        if net.registered {
+            self.epoll.delete(&net.stream)?;
+        }
+
+        log::debug!("{}: remove conn, reason: {}", self.name, reason);
+
+        // 对端已经告知了原因，并且已经转发到了 wire
+        if !net.close_recv {
+            wire.set_reason(reason);
+            let _ = wire.send(msg!{CHAN: CLOSE, REASON: reason});
+
+            // 连接或 wire 意外断开时不告知对端，对端同样视为意外断开
+            if !net.close_sent && reason != BREAK_LOST {
+                let _ = net.push_data(msg!{CHAN: CLOSE, REASON: reason});
+            }
+        }
+
+        // 写缓冲中剩余的数据，包括 wire 中的最后一条消息，不再等待可写
+        net.write_rest();
+
+        self.paused.retain(|i| *i != index);
+
+        self.stats.conns.fetch_sub(1, Ordering::Relaxed);
+        self.stats.buffered.fetch_sub(net.buffered, Ordering::Relaxed);
+
+        if net.pending.is_some() {
+            self.stats.paused.fetch_sub(1, Ordering::Relaxed);
+        }
 
         Ok(())
     }
@@ -285,36 +581,72 @@ struct NetConn<C: Codec> {
     token: usize,
     stream: TcpStream,
     interest: Ready,
+    // 是否已加入 epoll
+    registered: bool,
     r_buffer: (usize, Vec<u8>), // offset, buffer
     w_buffer: VecDeque<(usize, Vec<u8>)>, // offset, buffer
+    // 写缓冲中未写出的字节数
+    buffered: usize,
+    // wire 已满时暂存的消息，不为空时表示暂停读取
+    pending: Option<Message>,
+    // 是否收到或发出了 CLOSE 消息
+    close_recv: bool,
+    close_sent: bool,
     codec: C,
     crypto: Option<Crypto>,
     time_id: usize,
-    keep_alive: KeepAlive
+    keep_alive: KeepAlive,
+    stats: Arc<NetStats>,
+    // 准入控制占用的名额，连接关闭时释放
+    #[allow(dead_code)]
+    ticket: Option<Ticket>,
+    // 服务端接受的连接，会将 KEEP_ALIVE 和追踪的 TRACE_HOP 事件转发给 Switch
+    accepted: bool
 }
 
 impl<C: Codec> NetConn<C> {
-    fn new(token: usize, stream: TcpStream, codec: C, crypto: Option<Crypto>, time_id: usize, mut keep_alive: KeepAlive) -> Self {
+    fn new(
+        token: usize,
+        stream: TcpStream,
+        codec: C,
+        crypto: Option<Crypto>,
+        time_id: usize,
+        mut keep_alive: KeepAlive,
+        stats: Arc<NetStats>
+    ) -> Self {
         keep_alive.reset(Instant::now());
 
         Self {
             token,
             stream,
             interest: Ready::readable() | Ready::hup() | Ready::error(),
+            registered: true,
             r_buffer: (0, Vec::new()),
             w_buffer: VecDeque::new(),
+            buffered: 0,
+            pending: None,
+            close_recv: false,
+            close_sent: false,
             codec,
             crypto,
             time_id,
-            keep_alive
+            keep_alive,
+            stats,
+            ticket: None,
+            accepted: false
         }
     }
 
-    fn read(&mut self, epoll: &Epoll, wire: &Wire<Message>, now: Instant) -> Result<()> {
+    // 返回 true 表示 wire 已满，暂停读取
+    fn read(&mut self, epoll: &Epoll, wire: &Wire<Message>, now: Instant, name: &str) -> Result<bool> {
         self.keep_alive.reset(now);
 
+        if self.pending.is_some() {
+            return Ok(false)
+        }
+
         loop {
-            let ret = read(&mut self.stream, &mut self.r_buffer);
+            let ret = read(&mut self.stream, &mut self.r_buffer, MAX_MESSAGE_LEN);
 
             match ret {
                 Ok(ret) => {
@@ -331,10 +663,41 @@ impl<C: Codec> NetConn<C> {
                                 self.want_write(epoll)?;
                             }
 
+                            // 连接空闲时只有 KEEP_ALIVE，转发给 Switch 表示 SLOT 还活着
+                            // wire 已满时 Switch 总会收到其他消息，直接丢弃即可
+                            if self.accepted {
+                                let _ = wire.send(msg!{CHAN: KEEP_ALIVE});
+                            }
+
+                            continue
+                        }
+
+                        // TRACE_HOP 事件只能由网络线程产生
+                        if self.accepted && message.get_str(CHAN) == Ok(TRACE_HOP) {
+                            log::debug!("drop trace hop message, addr: {:?}", self.stream.peer_addr()?);
+
                             continue
                         }
 
-                        let _ = wire.send(message);
+                        if message.contains_key(TRACE) {
+                            self.trace(wire, &mut message, name, "recv");
+                        }
+
+                        if message.get_str(CHAN) == Ok(CLOSE) {
+                            self.close_recv = true;
+                        }
+
+                        match wire.send(message) {
+                            Ok(()) => (),
+                            Err(SendError::Full(message)) => {
+                                self.pause(epoll, message)?;
+
+                                return Ok(true)
+                            }
+                            Err(SendError::Disconnected(_)) => {
+                                return Err(Error::Disconnected("NetConn.read".to_string()))
+                            }
+                        }
                     }
                 }
                 Err(err) => {
@@ -349,9 +712,96 @@ impl<C: Codec> NetConn<C> {
             }
         }
 
+        Ok(false)
+    }
+
+    fn pause(&mut self, epoll: &Epoll, message: Message) -> Result<()> {
+        self.pending = Some(message);
+
+        self.interest.remove(Ready::readable());
+
+        self.update_interest(epoll)?;
+
+        self.stats.paused.fetch_add(1, Ordering::Relaxed);
+        self.stats.pauses.fetch_add(1, Ordering::Relaxed);
+
         Ok(())
     }
 
+    // 返回 true 表示 wire 仍然是满的
+    fn resume(&mut self, epoll: &Epoll, wire: &Wire<Message>, now: Instant, name: &str) -> Result<bool> {
+        if let Some(message) = self.pending.take() {
+            match wire.send(message) {
+                Ok(()) => (),
+                Err(SendError::Full(message)) => {
+                    self.pending = Some(message);
+
+                    return Ok(true)
+                }
+                Err(SendError::Disconnected(_)) => {
+                    return Err(Error::Disconnected("NetConn.resume".to_string()))
+                }
+            }
+
+            self.stats.paused.fetch_sub(1, Ordering::Relaxed);
+        }
+
+        self.interest.insert(Ready::readable());
+
+        self.update_interest(epoll)?;
+
+        // 暂停期间到达的数据不会再触发事件，这里主动读取
+        self.read(epoll, wire, now, name)
+    }
+
+    // 写缓冲达到上限时 pull 会停下，写空之后要继续取，wire 只在从空变为非空时通知，
+    // 这里不继续的话剩下的消息会一直留在 wire 中
+    fn flush(&mut self, epoll: &Epoll, wire: &Wire<Message>, flow: &FlowControl, name: &str) -> Result<()> {
+        loop {
+            let capped = self.pull(wire, flow, name)?;
+
+            self.write(epoll)?;
+
+            // 没写完时等待可写事件
+            if !capped || !self.w_buffer.is_empty() {
+                return Ok(())
+            }
+        }
+    }
+
+    // 从 wire 中取出消息放入写缓冲，返回 true 表示因写缓冲达到上限而停止
+    fn pull(&mut self, wire: &Wire<Message>, flow: &FlowControl, name: &str) -> Result<bool> {
+        loop {
+            if flow.overflow == Overflow::Wait && self.buffered >= flow.max_buffer {
+                return Ok(true)
+            }
+
+            match wire.recv() {
+                Ok(mut message) => {
+                    if message.contains_key(TRACE) {
+                        self.trace(wire, &mut message, name, "send");
+                    }
+
+                    if message.get_str(CHAN) == Ok(CLOSE) {
+                        self.close_sent = true;
+                    }
+
+                    self.push_data(message)?;
+                }
+                Err(RecvError::Empty) => break,
+                Err(_) => return Err(Error::Disconnected("NetConn.pull".to_string()))
+            }
+        }
+
+        if flow.overflow == Overflow::Close && self.buffered > flow.max_buffer {
+            self.stats.overflows.fetch_add(1, Ordering::Relaxed);
+
+            return Err(Error::Full(format!("NetConn.pull: write buffer overflow, {} bytes", self.buffered)))
+        }
+
+        Ok(false)
+    }
+
     fn write(&mut self, epoll: &Epoll) -> Result<()> {
         while let Some((index, front)) = self.w_buffer.front_mut() {
             match self.stream.write(&front[*index..]) {
@@ -363,6 +813,9 @@ impl<C: Codec> NetConn<C> {
                     } else {
                         *index += size;
                     }
+
+                    self.buffered -= size;
+                    self.stats.buffered.fetch_sub(size, Ordering::Relaxed);
                 }
                 Err(err) => {
                     if err.kind() == WouldBlock {
@@ -376,48 +829,275 @@ impl<C: Codec> NetConn<C> {
             }
         }
 
-        if self.w_buffer.is_empty() {
-            if self.w_buffer.capacity() > 64 {
-                self.w_buffer.shrink_to_fit();
-            }
+        if self.w_buffer.is_empty() && self.w_buffer.capacity() > 64 {
+            self.w_buffer.shrink_to_fit();
+        }
+
+        if !self.w_buffer.is_empty() {
+            return self.want_write(epoll)
         }
 
         if self.interest.contains(Ready::writable()) {
-            epoll.modify(
-                &self.stream,
-                Token(self.token),
-                self.interest,
-                EpollOpt::edge()
-            )?;
+            self.interest.remove(Ready::writable());
+
+            self.update_interest(epoll)?;
         }
 
         Ok(())
     }
 
+    // 连接关闭前尽量写出剩余的数据，遇到 WouldBlock 或错误时放弃
+    fn write_rest(&mut self) {
+        while let Some((index, front)) = self.w_buffer.front_mut() {
+            match self.stream.write(&front[*index..]) {
+                Ok(0) => break,
+                Ok(size) => {
+                    if size >= front.len() - *index {
+                        self.w_buffer.pop_front();
+                    } else {
+                        *index += size;
+                    }
+                }
+                Err(err) => {
+                    if err.kind() == Interrupted {
+                        continue;
+                    }
+
+                    break;
+                }
+            }
+        }
+    }
+
     fn want_write(&mut self, epoll: &Epoll) -> Result<()> {
         if !self.interest.contains(Ready::writable()) {
             self.interest.insert(Ready::writable());
 
-            epoll.modify(
-                &self.stream,
-                Token(self.token),
-                self.interest,
-                EpollOpt::edge()
-            )?;
+            self.update_interest(epoll)?;
+        }
+
+        Ok(())
+    }
+
+    // epoll 要求 interest 至少包含 readable 或 writable，都没有时从 epoll 中移除，需要时再加入
+    fn update_interest(&mut self, epoll: &Epoll) -> Result<()> {
+        let active = self.interest.is_readable() || self.interest.is_writable();
+
+        match (self.registered, active) {
+            (true, true) => epoll.modify(&self.stream, Token(self.token), self.interest, EpollOpt::edge())?,
+            (true, false) => {
+                epoll.delete(&self.stream)?;
+                self.registered = false;
+            }
+            (false, true) => {
+                epoll.add(&self.stream, Token(self.token), self.interest, EpollOpt::edge())?;
+                self.registered = true;
+            }
+            (false, false) => ()
         }
 
         Ok(())
     }
 
+    // 追加一跳，服务端的连接还会通过 wire 发送 TRACE_HOP 事件，由 Switch 转发给 ATTACH 了 TRACE_HOP 的 ROOT
+    // wire 已满时丢弃事件，HOPS 中仍然有这一跳
+    fn trace(&self, wire: &Wire<Message>, message: &mut Message, name: &str, dir: &str) {
+        let addr = self.stream.peer_addr().map(|addr| addr.to_string()).unwrap_or_default();
+
+        let hop = trace::hop(message, &format!("{}.{}", name, dir), msg!{ADDR: addr});
+
+        if let (true, Some(hop)) = (self.accepted, hop) {
+            let _ = wire.send(msg!{
+                CHAN: TRACE_HOP,
+                TRACE: message.get(TRACE).cloned().unwrap_or(Value::Null),
+                VALUE: hop,
+                HOPS: message.get(HOPS).cloned().unwrap_or(Value::Null)
+            });
+        }
+    }
+
     fn push_data(&mut self, message: Message) -> Result<()> {
         let bytes = self.codec.encode(&self.crypto, message)?;
+
+        self.push_bytes(bytes);
+
+        Ok(())
+    }
+
+    fn push_bytes(&mut self, bytes: Vec<u8>) {
+        if bytes.is_empty() {
+            return
+        }
+
+        self.buffered += bytes.len();
+        self.stats.buffered.fetch_add(bytes.len(), Ordering::Relaxed);
+
         self.w_buffer.push_back((0, bytes));
+    }
+}
+
+// 握手成功，编码后的握手消息、wire 和加密方式
+type Handed = (Vec<u8>, Wire<Message>, Option<Crypto>);
+
+// 正在握手的连接
+struct Hand<C: Codec> {
+    token: usize,
+    stream: TcpStream,
+    addr: SocketAddr,
+    codec: C,
+    r_buffer: (usize, Vec<u8>),
+    // 未写出的握手消息
+    w_buffer: Vec<u8>,
+    // 上一轮发回的消息
+    prev: Option<Message>,
+    // 等待连接的结果，HandStep::Connect 中的消息、wire 和加密方式
+    connecting: Option<(Message, Wire<Message>, Option<Crypto>)>,
+    deadline: Instant,
+    ticket: Option<Ticket>
+}
+
+impl<C: Codec> Hand<C> {
+    // 读取并处理握手消息，返回 Some 表示握手成功，需要发回的消息已经编码
+    fn advance(&mut self, epoll: &Epoll, acceptor: &dyn Acceptor, max_size: usize) -> Result<Option<Handed>> {
+        self.write()?;
+
+        // 等待连接的结果时不再读取
+        if self.connecting.is_some() {
+            return Ok(None)
+        }
+
+        loop {
+            let bytes = match read(&mut self.stream, &mut self.r_buffer, max_size) {
+                Ok(Some(bytes)) => bytes,
+                Ok(None) => continue,
+                Err(err) => {
+                    if err.kind() == WouldBlock {
+                        return Ok(None)
+                    } else if err.kind() == Interrupted {
+                        continue
+                    } else {
+                        return Err(err.into())
+                    }
+                }
+            };
+
+            let message = self.codec.decode(&None, bytes)?;
+
+            let step = acceptor.hand(&self.addr, self.prev.as_ref(), message);
+
+            if let Some(handed) = self.step(epoll, step)? {
+                return Ok(Some(handed))
+            }
+
+            if self.connecting.is_some() {
+                return Ok(None)
+            }
+        }
+    }
+
+    // wire 收到连接的结果后继续握手
+    fn connected(&mut self, epoll: &Epoll, acceptor: &dyn Acceptor) -> Result<Option<Handed>> {
+        let (message, wire, crypto) = match self.connecting.take() {
+            Some(connecting) => connecting,
+            None => return Ok(None)
+        };
+
+        let ret = match wire.recv() {
+            Ok(ret) => ret,
+            Err(RecvError::Empty) => {
+                self.connecting = Some((message, wire, crypto));
+
+                return Ok(None)
+            }
+            Err(_) => {
+                epoll.delete(&wire)?;
+
+                return Err(Error::Disconnected("Hand.connected".to_string()))
+            }
+        };
+
+        epoll.delete(&wire)?;
+
+        let step = acceptor.connected(&self.addr, message, wire, crypto, ret);
+
+        self.step(epoll, step)
+    }
+
+    fn step(&mut self, epoll: &Epoll, step: HandStep) -> Result<Option<Handed>> {
+        match step {
+            HandStep::Continue(reply) => {
+                let bytes = self.codec.encode(&None, reply.clone())?;
+                self.w_buffer.extend_from_slice(&bytes);
+                self.prev = Some(reply);
+
+                self.write()?;
+
+                Ok(None)
+            }
+            HandStep::Connect(message, wire, crypto) => {
+                epoll.add(&wire, Token(self.token + 1), Ready::readable(), EpollOpt::edge())?;
+
+                self.connecting = Some((message, wire, crypto));
+
+                Ok(None)
+            }
+            HandStep::Finish(reply, wire, crypto) => {
+                let bytes = self.codec.encode(&None, reply)?;
+
+                Ok(Some((bytes, wire, crypto)))
+            }
+            HandStep::Fail(err, reply) => {
+                if let Some(reply) = reply {
+                    if let Ok(bytes) = self.codec.encode(&None, reply) {
+                        self.w_buffer.extend_from_slice(&bytes);
+                        let _ = self.write();
+                    }
+                }
+
+                Err(err)
+            }
+        }
+    }
+
+    fn write(&mut self) -> Result<()> {
+        while !self.w_buffer.is_empty() {
+            match self.stream.write(&self.w_buffer) {
+                Ok(0) => return Err(Error::BrokenPipe("Hand.write".to_string())),
+                Ok(size) => {
+                    self.w_buffer.drain(..size);
+                }
+                Err(err) => {
+                    if err.kind() == WouldBlock {
+                        break;
+                    } else if err.kind() == Interrupted {
+                        continue;
+                    } else {
+                        return Err(err.into())
+                    }
+                }
+            }
+        }
 
         Ok(())
     }
 }
 
-fn read(stream: &mut TcpStream, buffer: &mut (usize, Vec<u8>)) -> io::Result<Option<Vec<u8>>> {
+// 根据错误判断断开的原因
+fn break_reason(err: &Error) -> &'static str {
+    match err {
+        // 消息长度超出限制
+        Error::IoError(err) if err.kind() == InvalidData => BREAK_OVERSIZE,
+        Error::InvalidData(_) => BREAK_DECODE,
+        // 解密失败
+        Error::PermissionDenied(_) => BREAK_CRYPTO,
+        // 写缓冲超出上限
+        Error::Full(_) => BREAK_OVERFLOW,
+        // 网络错误，或者 wire 的另一端已经断开
+        _ => BREAK_LOST
+    }
+}
+
+fn read(stream: &mut TcpStream, buffer: &mut (usize, Vec<u8>), max_len: usize) -> io::Result<Option<Vec<u8>>> {
     if buffer.1.is_empty() {
         let mut len_bytes = [0u8; 4];
         let size = stream.peek(&mut len_bytes)?;
@@ -437,13 +1117,11 @@ fn read(stream: &mut TcpStream, buffer: &mut (usize, Vec<u8>)) -> io::Result<Opt
         }
 
         let len = u32::from_le_bytes(len_bytes) as usize;
-        if len < 5 || len > MAX_MESSAGE_LEN {
+        if !(5..=max_len).contains(&len) {
             return Err(io::Error::new(InvalidData, format!("Invalid length of {}", len)))
         }
 
-        // let mut buf = vec![0u8; len];
-        let mut buf = Vec::with_capacity(len);
-        unsafe { buf.set_len(len); }
+        let mut buf = vec![0u8; len];
 
         let size = stream.read(&mut buf)?;
 
@@ -469,13 +1147,12 @@ fn read(stream: &mut TcpStream, buffer: &mut (usize, Vec<u8>)) -> io::Result<Opt
 
             if buffer.0 == 4 {
                 let len = read_u32(&buffer.1, 0);
-                if len < 5 || len > MAX_MESSAGE_LEN {
+                if !(5..=max_len).contains(&len) {
                     return Err(io::Error::new(InvalidData, format!("Invalid length of {}", len)))
                 }
 
                 buffer.0 = 4;
-                buffer.1.reserve(len - 4);
-                unsafe { buffer.1.set_len(len); }
+                buffer.1.resize(len, 0);
             }
         } else {
             let size = stream.read(&mut buffer.1[buffer.0..])?;
@@ -495,7 +1172,7 @@ fn read(stream: &mut TcpStream, buffer: &mut (usize, Vec<u8>)) -> io::Result<Opt
         }
     }
 
-    return Ok(None)
+    Ok(None)
 }
 
 fn read_u32(buf: &[u8], start: usize) -> usize {
@@ -1,8 +1,6 @@
 use std::io::ErrorKind::{WouldBlock, Interrupted};
-use std::io::Write;
 use std::os::unix::io::AsRawFd;
 use std::thread;
-use std::time::Duration;
 use std::net::SocketAddr;
 use std::sync::{
     Arc,
@@ -13,7 +11,7 @@ use std::str::FromStr;
 use queen_io::{
     epoll::{Epoll, Events, Token, Ready, EpollOpt},
     queue::mpsc::Queue,
-    net::tcp::{TcpListener, TcpStream}
+    net::tcp::TcpListener
 };
 
 use rand::{SeedableRng, seq::SliceRandom, rngs::SmallRng};
@@ -22,24 +20,27 @@ use nson::{msg, Message, MessageId};
 
 use crate::Socket;
 use crate::Wire;
-use crate::net::{NetWork, Packet, Codec, KeepAlive};
+use crate::net::{NetWork, Packet, Codec, KeepAlive, FlowControl, NetStats, HandshakeConfig, HandStep, Acceptor};
+use crate::net::{AdmissionConfig, Admission};
 use crate::crypto::{Crypto, Method};
 use crate::dict::*;
-use crate::util::message::read_block;
-use crate::error::{Result, Error, Code};
+use crate::error::{Result, Error, Code, ErrorInfo};
 
-pub use hook::{Hook, NonHook};
+pub use hook::{Hook, NonHook, HookChain};
+pub use auth::{Credentials, User, PasswordAuth, TokenAuth, ChallengeAuth};
 
 mod hook;
+mod auth;
 
-pub trait Connector: Send + 'static {
+// 在网络线程中调用，不能阻塞
+pub trait Connector: Send + Sync + 'static {
+    // 不等待连接的结果，返回的 wire 收到的第一条消息即为结果，失败时带有错误信息
     fn connect(
         &self,
         slot_id: MessageId,
         root: bool,
         attr: Message,
-        capacity: Option<usize>,
-        timeout: Option<Duration>
+        capacity: Option<usize>
     ) -> Result<Wire<Message>>;
 
     fn running(&self) -> bool;
@@ -51,10 +52,9 @@ impl Connector for Socket {
         slot_id: MessageId,
         root: bool,
         attr: Message,
-        capacity: Option<usize>,
-        timeout: Option<Duration>
+        capacity: Option<usize>
     ) -> Result<Wire<Message>> {
-        self.connect(slot_id, root, attr, capacity, timeout)
+        self.connect_nowait(slot_id, root, attr, capacity)
     }
 
     fn running(&self) -> bool {
@@ -62,10 +62,19 @@ impl Connector for Socket {
     }
 }
 
+#[derive(Debug, Clone, Default)]
+pub struct NodeConfig {
+    pub keep_alive: KeepAlive,
+    pub flow: FlowControl,
+    pub handshake: HandshakeConfig,
+    pub admission: AdmissionConfig
+}
+
 pub struct Node<C: Codec> {
     #[allow(clippy::rc_buffer)]
     queues: Arc<Vec<Queue<Packet<C>>>>,
-    run: Arc<AtomicBool>
+    run: Arc<AtomicBool>,
+    stats: Arc<NetStats>
 }
 
 impl<C: Codec> Node<C> {
@@ -75,6 +84,33 @@ impl<C: Codec> Node<C> {
         addrs: Vec<SocketAddr>,
         keep_alive: KeepAlive,
         hook: impl Hook
+    ) -> Result<Self> {
+        Node::with_flow_control(connector, worker_num, addrs, keep_alive, FlowControl::default(), hook)
+    }
+
+    pub fn with_flow_control(
+        connector: impl Connector,
+        worker_num: usize,
+        addrs: Vec<SocketAddr>,
+        keep_alive: KeepAlive,
+        flow: FlowControl,
+        hook: impl Hook
+    ) -> Result<Self> {
+        let config = NodeConfig {
+            keep_alive,
+            flow,
+            ..NodeConfig::default()
+        };
+
+        Node::with_config(connector, worker_num, addrs, config, hook)
+    }
+
+    pub fn with_config(
+        connector: impl Connector,
+        worker_num: usize,
+        addrs: Vec<SocketAddr>,
+        config: NodeConfig,
+        hook: impl Hook
     ) -> Result<Self> {
         let mut queues = Vec::new();
 
@@ -85,14 +121,15 @@ impl<C: Codec> Node<C> {
 
         let node = Self {
             queues: Arc::new(queues),
-            run: Arc::new(AtomicBool::new(true))
+            run: Arc::new(AtomicBool::new(true)),
+            stats: Arc::new(NetStats::default())
         };
 
         let mut inner: Inner<C, _> = Inner::new(
             node.clone(),
             connector,
             addrs,
-            keep_alive,
+            config,
             hook
         )?;
 
@@ -116,16 +153,24 @@ impl<C: Codec> Node<C> {
     pub fn running(&self) -> bool {
         self.run.load(Ordering::Relaxed)
     }
+
+    // 所有网络线程的统计
+    #[inline]
+    pub fn stats(&self) -> Arc<NetStats> {
+        self.stats.clone()
+    }
 }
 
+// 接受连接，握手交给网络线程
 struct Inner<C: Codec, H: Hook> {
     node: Node<C>,
-    connector: Box<dyn Connector>,
+    acceptor: Arc<NodeAcceptor<H>>,
+    admission: Arc<Admission>,
     epoll: Epoll,
     events: Events,
     listens: Vec<TcpListener>,
     rand: SmallRng,
-    hook: H,
+    max_pending: usize
 }
 
 impl<C: Codec, H: Hook> Inner<C, H> {
@@ -133,17 +178,29 @@ impl<C: Codec, H: Hook> Inner<C, H> {
         node: Node<C>,
         connector: impl Connector,
         addrs: Vec<SocketAddr>,
-        keep_alive: KeepAlive,
+        config: NodeConfig,
         hook: H
     ) -> Result<Self> {
+        let NodeConfig { keep_alive, flow, handshake, admission } = config;
+
         let mut listens = Vec::new();
 
         for addr in addrs {
             listens.push(TcpListener::bind(addr)?);
         }
 
+        let acceptor = Arc::new(NodeAcceptor {
+            hook,
+            connector: Box::new(connector)
+        });
+
         for queue in node.queues.iter() {
             let mut net_work = NetWork::<C>::new(queue.clone(), keep_alive.clone())?;
+            net_work.set_name("node");
+            net_work.set_flow_control(flow.clone());
+            net_work.set_stats(node.stats.clone());
+            net_work.set_acceptor(acceptor.clone());
+            net_work.set_handshake(handshake.clone());
 
             let run2 = node.run.clone();
 
@@ -159,20 +216,23 @@ impl<C: Codec, H: Hook> Inner<C, H> {
             }).unwrap();
         }
 
+        let admission = Admission::new(admission, node.stats.clone());
+
         Ok(Self {
             node,
-            connector: Box::new(connector),
+            acceptor,
+            admission,
             epoll: Epoll::new()?,
             events: Events::with_capacity(16),
             listens,
-            hook,
-            rand: SmallRng::from_entropy()
+            rand: SmallRng::from_entropy(),
+            max_pending: handshake.max_pending
         })
     }
 
     #[inline]
     fn running(&self) -> bool {
-        self.node.run.load(Ordering::Relaxed)
+        self.node.run.load(Ordering::Relaxed) && self.acceptor.connector.running()
     }
 
     pub fn run(&mut self) -> Result<()> {
@@ -180,7 +240,7 @@ impl<C: Codec, H: Hook> Inner<C, H> {
             self.epoll.add(&listen.as_raw_fd(), Token(id), Ready::readable(), EpollOpt::edge())?;
         }
 
-        while self.running() && self.connector.running() {
+        while self.running() {
             let size = match self.epoll.wait(&mut self.events, None) {
                 Ok(size) => size,
                 Err(err) => {
@@ -209,36 +269,46 @@ impl<C: Codec, H: Hook> Inner<C, H> {
                             }
                         };
 
-                        if !self.hook.accept(&mut stream) {
-                            continue;
-                        }
+                        let hook = &self.acceptor.hook;
 
-                        stream.set_nodelay(true)?;
-                        // 握手开始
-                        stream.set_nonblocking(false)?;
-                        // 连接成功后，5秒内收不到握手消息应当断开
-                        stream.set_read_timeout(Some(Duration::from_secs(5)))?;
-                        stream.set_write_timeout(Some(Duration::from_secs(5)))?;
-
-                        let (wire, codec, crypto) = match Self::hand(&self.hook, &*self.connector, &mut stream, &addr) {
-                            Ok(ret) => ret,
+                        let ticket = match self.admission.admit(&addr) {
+                            Ok(ticket) => ticket,
                             Err(err) => {
-                                log::debug!("{}", err);
+                                log::info!("reject connection from {}: {}", addr, err);
+
+                                hook.fail(&addr, &err);
+
                                 continue;
                             }
                         };
 
+                        if !hook.accept(&mut stream) {
+                            continue;
+                        }
+
+                        // 进行中的握手过多时直接断开，避免被大量不握手的连接占满
+                        if self.node.stats.handshakes() >= self.max_pending {
+                            self.node.stats.hand_rejects.fetch_add(1, Ordering::Relaxed);
+
+                            let err = Error::Full(format!("too many pending handshakes, max: {}", self.max_pending));
+
+                            log::debug!("{}", err);
+
+                            hook.fail(&addr, &err);
+
+                            continue;
+                        }
+
+                        stream.set_nodelay(true)?;
                         stream.set_nonblocking(true)?;
-                        stream.set_read_timeout(None)?;
-                        stream.set_write_timeout(None)?;
-                        // 握手结束
 
                         if let Some(queue) = self.node.queues.choose(&mut self.rand) {
-                            queue.push(Packet::NewConn {
-                                wire,
+                            self.node.stats.handshakes.fetch_add(1, Ordering::Relaxed);
+
+                            queue.push(Packet::Accept {
                                 stream,
-                                codec,
-                                crypto
+                                addr,
+                                ticket: Some(ticket)
                             })
                         }
                     }
@@ -248,201 +318,220 @@ impl<C: Codec, H: Hook> Inner<C, H> {
 
         Ok(())
     }
+}
 
-    fn hand(
-        hook: &H,
-        connector: &dyn Connector,
-        stream: &mut TcpStream,
-        addr: &SocketAddr
-    ) -> Result<(Wire<Message>, C, Option<Crypto>)> {
-        let mut codec = C::new();
-
-        // 握手时的消息，不能超过 2048 字节
-        let bytes = read_block(stream, Some(2048))?;
-        let mut message = codec.decode(&None, bytes)?;
-
-        let chan = match message.get_str(CHAN) {
-            Ok(chan) => chan,
-            Err(_) => {
-                #[cfg(debug_assertions)]
-                {
-                    Code::CannotGetChanField.set(&mut message);
-                    let _ = Self::send(&mut codec, stream, message);
-                }
+// 服务端的握手，在网络线程中执行，Hook 不加锁，多个网络线程可以同时握手
+struct NodeAcceptor<H: Hook> {
+    hook: H,
+    connector: Box<dyn Connector>
+}
 
-                return Err(Error::ErrorCode(Code::CannotGetChanField))
-            }
+impl<H: Hook> NodeAcceptor<H> {
+    fn connect(
+        &self,
+        addr: &SocketAddr,
+        slot_id: MessageId,
+        root: bool,
+        secure: bool,
+        message: &Message
+    ) -> Result<Wire<Message>> {
+        // 这里会将原始的握手消息传入。
+        // 但是要注意，握手消息是没有加密的，不能传递敏感数据
+        let mut origin = message.clone();
+        // 去除一些冗余信息
+        origin.remove(CHAN);
+        origin.remove(ADDR);
+        origin.remove(SECURE);
+
+        let attr = msg!{
+            ADDR: addr.to_string(),
+            SECURE: secure,
+            ORIGIN: origin
         };
 
-        if chan != HAND {
-            #[cfg(debug_assertions)]
-            {
-                Code::UnsupportedChan.set(&mut message);
-                let _ = Self::send(&mut codec, stream, message);
-            }
+        self.connector.connect(slot_id, root, attr, None)
+    }
+}
 
-            return Err(Error::ErrorCode(Code::UnsupportedChan))
-        }
+impl<H: Hook> Acceptor for NodeAcceptor<H> {
+    fn hand(&self, addr: &SocketAddr, prev: Option<&Message>, mut message: Message) -> HandStep {
+        let hook = &self.hook;
 
-        // SLOT_ID
-        let slot_id = if let Some(slot_id) = message.get(SLOT_ID) {
-            if let Some(slot_id) = slot_id.as_message_id() {
-                *slot_id
-            } else {
-                #[cfg(debug_assertions)]
-                {
-                    Code::InvalidSlotIdFieldType.set(&mut message);
-                    let _ = Self::send(&mut codec, stream, message);
+        let (slot_id, root) = match prev {
+            None => {
+                let chan = match message.get_str(CHAN) {
+                    Ok(chan) => chan,
+                    Err(_) => return reject(Code::CannotGetChanField, message)
+                };
+
+                if chan != HAND {
+                    return reject(Code::UnsupportedChan, message)
                 }
 
-                return Err(Error::ErrorCode(Code::InvalidSlotIdFieldType));
-            }
-        } else {
-            let slot_id = MessageId::new();
-            message.insert(SLOT_ID, slot_id);
-            slot_id
-        };
+                // SLOT_ID
+                let slot_id = if let Some(slot_id) = message.get(SLOT_ID) {
+                    if let Some(slot_id) = slot_id.as_message_id() {
+                        *slot_id
+                    } else {
+                        return reject(Code::InvalidSlotIdFieldType, message)
+                    }
+                } else {
+                    let slot_id = MessageId::new();
+                    message.insert(SLOT_ID, slot_id);
+                    slot_id
+                };
+
+                // ROOT
+                let root = if let Some(root) = message.get(ROOT) {
+                    if let Some(root) = root.as_bool() {
+                        root
+                    } else {
+                        return reject(Code::InvalidRootFieldType, message)
+                    }
+                } else {
+                    false
+                };
 
-        // ROOT
-        let root = if let Some(root) = message.get(ROOT) {
-            if let Some(root) = root.as_bool() {
-                root
-            } else {
-                #[cfg(debug_assertions)]
-                {
-                    Code::InvalidRootFieldType.set(&mut message);
-                    let _ = Self::send(&mut codec, stream, message);
+                // 第一轮握手不接受客户端发送的挑战和应答
+                message.remove(CHALLENGE);
+                message.remove(RESPONSE);
+
+                // 握手消息是可以修改的，修改后的消息会发回客户端，因此可以携带自定义数据
+                // 但是对于一些握手必备的属性，请谨慎修改，比如加密方式（METHOD）
+                if !hook.start(slot_id, root, &mut message) {
+                    return reject(Code::AuthenticationFailed, message)
                 }
 
-                return Err(Error::ErrorCode(Code::InvalidRootFieldType));
-            }
-        } else {
-            false
-        };
+                // 挑战应答
+                // Hook.start 在握手消息中插入 CHALLENGE 时，会将握手消息发回客户端（不带 CODE），
+                // 客户端需要附带 RESPONSE 再次发送握手消息，然后再次调用 Hook.start 进行校验
+                // 只允许一轮挑战
+                if message.contains_key(CHALLENGE) && !message.contains_key(RESPONSE) {
+                    return HandStep::Continue(message)
+                }
 
-        // 握手消息是可以修改的，修改后的消息会发回客户端，因此可以携带自定义数据
-        // 但是对于一些握手必备的属性，请谨慎修改，比如加密方式（METHOD）
-        if !hook.start(slot_id, root, &mut message) {
-            #[cfg(debug_assertions)]
-            {
-                Code::AuthenticationFailed.set(&mut message);
-                let _ = Self::send(&mut codec, stream, message);
+                (slot_id, root)
             }
+            Some(prev) => {
+                let slot_id = match prev.get_message_id(SLOT_ID) {
+                    Ok(slot_id) => *slot_id,
+                    Err(_) => return HandStep::Fail(Error::ErrorCode(Code::AuthenticationFailed), None)
+                };
+
+                let root = prev.get_bool(ROOT).unwrap_or(false);
+
+                // 第二轮握手不能修改 SLOT_ID 和 ROOT
+                if message.get_str(CHAN) != Ok(HAND) ||
+                    message.get_message_id(SLOT_ID) != Ok(&slot_id) ||
+                    message.get_bool(ROOT).unwrap_or(false) != root
+                {
+                    return HandStep::Fail(Error::ErrorCode(Code::AuthenticationFailed), None)
+                }
 
-            return Err(Error::ErrorCode(Code::AuthenticationFailed));
-        }
+                // 挑战保存在连接的握手状态中，使用第一轮发出的 CHALLENGE 替换客户端发送的
+                if let Some(challenge) = prev.get(CHALLENGE) {
+                    message.insert(CHALLENGE, challenge.clone());
+                } else {
+                    return HandStep::Fail(Error::ErrorCode(Code::AuthenticationFailed), None)
+                }
+
+                if !hook.start(slot_id, root, &mut message) || message.contains_key(CHALLENGE) {
+                    return reject(Code::AuthenticationFailed, message)
+                }
+
+                (slot_id, root)
+            }
+        };
 
         if !hook.enable_secure() {
             // 没有开启加密
-
-            // 这里会将原始的握手消息传入。
-            // 但是要注意，握手消息是没有加密的，不能传递敏感数据
-            let mut origin = message.clone();
-            // 去除一些冗余信息
-            origin.remove(CHAN);
-            origin.remove(ADDR);
-            origin.remove(SECURE);
-
-            let attr = msg!{
-                ADDR: addr.to_string(),
-                SECURE: false,
-                ORIGIN: origin
+            let wire = match self.connect(addr, slot_id, root, false, &message) {
+                Ok(wire) => wire,
+                Err(err) => return HandStep::Fail(err, None)
             };
 
-            let wire = connector.connect(slot_id, root, attr, None, None)?;
-
-            // 这里可以修改 Wire 的属性
-            hook.finish(slot_id, root, &mut message, &wire);
-
-            Code::Ok.set(&mut message);
-
-            // 握手消息发回
-            Self::send(&mut codec, stream, message)?;
-
-            return Ok((wire, codec, None))
+            return HandStep::Connect(message, wire, None)
         }
 
         if let Ok(method) = message.get_str(METHOD) {
             let method = if let Ok(method) = Method::from_str(method) {
                 method
             } else {
-                #[cfg(debug_assertions)]
-                {
-                    Code::UnsupportedFormat.set(&mut message);
-                    let _ = Self::send(&mut codec, stream, message);
-                }
-
-                return Err(Error::ErrorCode(Code::UnsupportedFormat));
+                return reject(Code::UnsupportedFormat, message)
             };
 
             // 握手消息是可以修改的，修改后的消息会发回客户端，因此可以携带自定义数据
             // 但是对于一些握手必备的属性，请谨慎修改
             // 这里需要根据传递的自定义数据，返回一个加密密钥
-            // let secret = hook.access(&mut message).ok_or_else(|| Error::ErrorCode(Code::PermissionDenied))?;
             let secret = match hook.access(slot_id, root, &mut message) {
                 Some(s) => s,
-                None => {
-                    #[cfg(debug_assertions)]
-                    {
-                        Code::PermissionDenied.set(&mut message);
-                        let _ = Self::send(&mut codec, stream, message);
-                    }
-
-                    return Err(Error::ErrorCode(Code::PermissionDenied))
-                }
+                None => return reject(Code::PermissionDenied, message)
             };
 
-            // 这里会将原始的握手消息传入。
-            // 但是要注意，握手消息是没有加密的，不能传递敏感数据
-            let mut origin = message.clone();
-            // 去除一些冗余信息
-            origin.remove(CHAN);
-            origin.remove(ADDR);
-            origin.remove(SECURE);
-
-            let attr = msg!{
-                ADDR: addr.to_string(),
-                SECURE: true,
-                ORIGIN: origin
+            let wire = match self.connect(addr, slot_id, root, true, &message) {
+                Ok(wire) => wire,
+                Err(err) => return HandStep::Fail(err, None)
             };
 
-            let wire = connector.connect(slot_id, root, attr, None, None)?;
+            let crypto = Crypto::new(&method, secret.as_bytes());
 
-            // 这里可以修改 Wire 的属性
-            hook.finish(slot_id, root, &mut message, &wire);
+            return HandStep::Connect(message, wire, Some(crypto))
+        }
 
-            Code::Ok.set(&mut message);
+        reject(Code::PermissionDenied, message)
+    }
 
-            // 握手消息发回
-            Self::send(&mut codec, stream, message)?;
+    fn connected(
+        &self,
+        _addr: &SocketAddr,
+        mut message: Message,
+        wire: Wire<Message>,
+        crypto: Option<Crypto>,
+        ret: Message
+    ) -> HandStep {
+        if let Some(info) = ErrorInfo::get(&ret) {
+            return HandStep::Fail(info.into(), None)
+        }
 
-            let crypto = Crypto::new(&method, secret.as_bytes());
+        let slot_id = match message.get_message_id(SLOT_ID) {
+            Ok(slot_id) => *slot_id,
+            Err(_) => return HandStep::Fail(Error::ErrorCode(Code::CannotGetSlotIdField), None)
+        };
 
-            return Ok((wire, codec, Some(crypto)))
-        }
+        let root = message.get_bool(ROOT).unwrap_or(false);
 
-        #[cfg(debug_assertions)]
-        {
-            Code::PermissionDenied.set(&mut message);
-            let _ = Self::send(&mut codec, stream, message);
-        }
+        // 这里可以修改 Wire 的属性
+        self.hook.finish(slot_id, root, &mut message, &wire);
 
-        Err(Error::ErrorCode(Code::PermissionDenied))
-    }
+        Code::Ok.set(&mut message);
 
-    fn send(codec: &mut impl Codec, stream: &mut TcpStream, message: Message) -> Result<()> {
-        let bytes = codec.encode(&None, message)?;
-        stream.write_all(&bytes)?;
+        // 握手消息发回
+        HandStep::Finish(message, wire, crypto)
+    }
 
-        Ok(())
+    fn fail(&self, addr: &SocketAddr, err: &Error) {
+        self.hook.fail(addr, err)
     }
 }
 
+// 握手失败，只在 debug 模式下将错误发回客户端
+fn reject(code: Code, mut message: Message) -> HandStep {
+    let reply = if cfg!(debug_assertions) {
+        code.set(&mut message);
+        Some(message)
+    } else {
+        None
+    };
+
+    HandStep::Fail(Error::ErrorCode(code), reply)
+}
+
 impl<C: Codec> Clone for Node<C> {
     fn clone(&self) -> Self {
         Self {
             queues: self.queues.clone(),
-            run: self.run.clone()
+            run: self.run.clone(),
+            stats: self.stats.clone()
         }
     }
 }
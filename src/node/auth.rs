@@ -0,0 +1,478 @@
+use std::collections::HashMap;
+use std::num::NonZeroU32;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use ring::{hmac, pbkdf2, rand::{SecureRandom, SystemRandom}};
+
+use nson::{Message, MessageId};
+
+use crate::dict::*;
+use crate::error::{Result, Error};
+use crate::util::{config, to_hex, from_hex};
+use crate::util::lock::Lock;
+
+use super::Hook;
+
+// 认证成功后，用户名会写入握手消息的 USERNAME 字段，并随握手消息一起保存到 SLOT 的 ATTR.ORIGIN 中
+// 凭据中配置的 attr 也会合并到握手消息中，可以配合 socket::Acl 使用
+// 客户端在握手消息中自定义的字段（不以 `_` 开头）会被移除，避免冒充 attr 中的属性，比如 role
+// 认证失败时，握手返回 Code::AuthenticationFailed
+//
+// 凭据文件示例（TOML）：
+//
+// [users.alice]
+// salt = "9f1c..."              # 用户名密码认证
+// hash = "1b2e..."              # PBKDF2-HMAC-SHA256，可以用 Credentials::hash_password 生成
+// iterations = 10000
+// secret = "..."                # 挑战应答认证的共享密钥，不会在网络上传输
+// root = false                  # 是否允许以 ROOT 身份连接
+// attr = { role = "printer" }
+#[derive(Debug, Clone, Default)]
+pub struct Credentials {
+    pub users: HashMap<String, User>
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct User {
+    pub salt: Vec<u8>,
+    pub hash: Vec<u8>,
+    pub iterations: u32,
+    pub secret: Option<String>,
+    pub root: bool,
+    pub attr: Message
+}
+
+impl Credentials {
+    pub const DEFAULT_ITERATIONS: u32 = 10000;
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        Credentials::from_message(&config::load(path)?)
+    }
+
+    pub fn from_message(message: &Message) -> Result<Self> {
+        let mut users = HashMap::new();
+
+        if let Ok(table) = message.get_message("users") {
+            for (name, user) in table {
+                let user = user.as_message().ok_or_else(|| invalid(name, "table"))?;
+
+                let salt = match user.get_str("salt") {
+                    Ok(salt) => from_hex(salt).ok_or_else(|| invalid("salt", "hex string"))?,
+                    Err(_) => Vec::new()
+                };
+
+                let hash = match user.get_str("hash") {
+                    Ok(hash) => from_hex(hash).ok_or_else(|| invalid("hash", "hex string"))?,
+                    Err(_) => Vec::new()
+                };
+
+                let iterations = match user.get("iterations") {
+                    Some(value) => value.as_i32().filter(|i| *i > 0).ok_or_else(|| invalid("iterations", "positive integer"))? as u32,
+                    None => Self::DEFAULT_ITERATIONS
+                };
+
+                users.insert(name.to_string(), User {
+                    salt,
+                    hash,
+                    iterations,
+                    secret: user.get_str("secret").ok().map(ToString::to_string),
+                    root: user.get_bool("root").unwrap_or(false),
+                    attr: user.get_message("attr").cloned().unwrap_or_default()
+                });
+            }
+        }
+
+        Ok(Credentials { users })
+    }
+
+    pub fn add_password(&mut self, name: &str, password: &str, root: bool, attr: Message) {
+        let mut salt = vec![0u8; 16];
+        let _ = SystemRandom::new().fill(&mut salt);
+
+        let hash = Self::hash_password(password, &salt, Self::DEFAULT_ITERATIONS);
+
+        let user = self.users.entry(name.to_string()).or_default();
+        user.salt = salt;
+        user.hash = hash;
+        user.iterations = Self::DEFAULT_ITERATIONS;
+        user.root = root;
+        user.attr = attr;
+    }
+
+    pub fn add_secret(&mut self, name: &str, secret: &str, root: bool, attr: Message) {
+        let user = self.users.entry(name.to_string()).or_default();
+        user.secret = Some(secret.to_string());
+        user.root = root;
+        user.attr = attr;
+    }
+
+    pub fn hash_password(password: &str, salt: &[u8], iterations: u32) -> Vec<u8> {
+        let mut hash = vec![0u8; 32];
+
+        pbkdf2::derive(
+            pbkdf2::PBKDF2_HMAC_SHA256,
+            NonZeroU32::new(iterations.max(1)).unwrap(),
+            salt,
+            password.as_bytes(),
+            &mut hash
+        );
+
+        hash
+    }
+}
+
+impl User {
+    fn verify_password(&self, password: &str) -> bool {
+        if self.hash.is_empty() {
+            return false
+        }
+
+        pbkdf2::verify(
+            pbkdf2::PBKDF2_HMAC_SHA256,
+            NonZeroU32::new(self.iterations.max(1)).unwrap(),
+            &self.salt,
+            password.as_bytes(),
+            &self.hash
+        ).is_ok()
+    }
+}
+
+// 用户名密码认证
+// 客户端在握手消息中携带 USERNAME 和 PASSWORD
+// 注意，握手消息是明文传输的，应当在可信网络中使用
+pub struct PasswordAuth {
+    credentials: Lock<Credentials>
+}
+
+impl PasswordAuth {
+    pub fn new(credentials: Credentials) -> Self {
+        PasswordAuth {
+            credentials: Lock::new(credentials)
+        }
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(PasswordAuth::new(Credentials::from_file(path)?))
+    }
+
+    pub fn set_credentials(&self, credentials: Credentials) {
+        *self.credentials.lock() = credentials;
+    }
+}
+
+impl Hook for PasswordAuth {
+    fn start(&self, _slot_id: MessageId, root: bool, message: &mut Message) -> bool {
+        let password = match message.remove(PASSWORD) {
+            Some(password) => password,
+            None => return false
+        };
+
+        let (name, password) = match (message.get_str(USERNAME), password.as_str()) {
+            (Ok(name), Some(password)) => (name.to_string(), password),
+            _ => return false
+        };
+
+        // PBKDF2 比较耗时，先复制出用户信息再校验，不阻塞其它握手
+        let user = self.credentials.lock().users.get(&name).cloned();
+
+        match user {
+            Some(user) if user.verify_password(password) => grant(message, &name, user.root, &user.attr, root),
+            _ => {
+                log::debug!("password auth failed, username: {}", name);
+                false
+            }
+        }
+    }
+}
+
+// 签名令牌认证
+// 令牌的格式为 `用户名.过期时间.ROOT.签名`，签名为 HMAC-SHA256
+// 客户端在握手消息中携带 TOKEN
+pub struct TokenAuth {
+    key: hmac::Key
+}
+
+impl TokenAuth {
+    pub fn new(key: &[u8]) -> Self {
+        TokenAuth {
+            key: hmac::Key::new(hmac::HMAC_SHA256, key)
+        }
+    }
+
+    // 签发令牌
+    pub fn sign(&self, name: &str, root: bool, ttl: Duration) -> String {
+        self.sign_until(name, root, now_secs() + ttl.as_secs())
+    }
+
+    // 签发令牌，expire 为过期时间的 UNIX 时间戳（秒）
+    pub fn sign_until(&self, name: &str, root: bool, expire: u64) -> String {
+        let payload = format!("{}.{}.{}", name, expire, root as u8);
+        let tag = hmac::sign(&self.key, payload.as_bytes());
+
+        format!("{}.{}", payload, to_hex(tag.as_ref()))
+    }
+
+    // 校验令牌，返回用户名和 ROOT 权限
+    pub fn verify(&self, token: &str) -> Option<(String, bool)> {
+        let (payload, tag) = token.rsplit_once('.')?;
+        let tag = from_hex(tag)?;
+
+        hmac::verify(&self.key, payload.as_bytes(), &tag).ok()?;
+
+        let mut parts = payload.rsplitn(3, '.');
+        let root = parts.next()? == "1";
+        let expire: u64 = parts.next()?.parse().ok()?;
+        let name = parts.next()?;
+
+        if expire < now_secs() {
+            return None
+        }
+
+        Some((name.to_string(), root))
+    }
+}
+
+impl Hook for TokenAuth {
+    fn start(&self, _slot_id: MessageId, root: bool, message: &mut Message) -> bool {
+        let token = match message.remove(TOKEN) {
+            Some(token) => token,
+            None => return false
+        };
+
+        match token.as_str().and_then(|token| self.verify(token)) {
+            Some((name, allow_root)) => grant(message, &name, allow_root, &Message::new(), root),
+            None => {
+                log::debug!("token auth failed");
+                false
+            }
+        }
+    }
+}
+
+// 挑战应答认证，密钥不会在网络上传输
+// 1. 客户端发送带有 USERNAME 的握手消息
+// 2. 服务端返回带有 CHALLENGE 的握手消息
+// 3. 客户端将 RESPONSE = HEX(HMAC-SHA256(密钥, CHALLENGE + SLOT_ID)) 附加到握手消息中再次发送
+// 4. 服务端校验 RESPONSE
+// Port::connect_with_secret 会自动完成上面的流程
+//
+// 挑战保存在连接的握手状态中，Node 在第二轮握手时使用第一轮发出的 CHALLENGE，
+// 客户端发送的 CHALLENGE 会被忽略，因此不需要额外保存，进行中的挑战数量受 HandshakeConfig.max_pending 限制
+pub struct ChallengeAuth {
+    credentials: Lock<Credentials>
+}
+
+impl ChallengeAuth {
+    pub fn new(credentials: Credentials) -> Self {
+        ChallengeAuth {
+            credentials: Lock::new(credentials)
+        }
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(ChallengeAuth::new(Credentials::from_file(path)?))
+    }
+
+    pub fn set_credentials(&self, credentials: Credentials) {
+        *self.credentials.lock() = credentials;
+    }
+
+    pub fn response(secret: &str, challenge: &str, slot_id: &MessageId) -> String {
+        let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+
+        let mut ctx = hmac::Context::with_key(&key);
+        ctx.update(challenge.as_bytes());
+        ctx.update(&slot_id.bytes());
+
+        to_hex(ctx.sign().as_ref())
+    }
+}
+
+impl Hook for ChallengeAuth {
+    fn start(&self, slot_id: MessageId, root: bool, message: &mut Message) -> bool {
+        let name = match message.get_str(USERNAME) {
+            Ok(name) => name.to_string(),
+            Err(_) => return false
+        };
+
+        match message.remove(RESPONSE) {
+            None => {
+                let mut nonce = [0u8; 32];
+
+                if SystemRandom::new().fill(&mut nonce).is_err() {
+                    return false
+                }
+
+                message.insert(CHALLENGE, to_hex(&nonce));
+
+                true
+            }
+            Some(response) => {
+                let challenge = match message.remove(CHALLENGE) {
+                    Some(challenge) => challenge,
+                    None => return false
+                };
+
+                let challenge = match challenge.as_str() {
+                    Some(challenge) => challenge,
+                    None => return false
+                };
+
+                let user = match self.credentials.lock().users.get(&name) {
+                    Some(user) => user.clone(),
+                    None => return false
+                };
+
+                let secret = match &user.secret {
+                    Some(secret) => secret,
+                    None => return false
+                };
+
+                let expect = Self::response(secret, challenge, &slot_id);
+
+                let ok = match response.as_str() {
+                    Some(response) => ring::constant_time::verify_slices_are_equal(
+                        expect.as_bytes(),
+                        response.as_bytes()
+                    ).is_ok(),
+                    None => false
+                };
+
+                if !ok {
+                    log::debug!("challenge auth failed, username: {}", name);
+                    return false
+                }
+
+                grant(message, &name, user.root, &user.attr, root)
+            }
+        }
+    }
+}
+
+fn grant(message: &mut Message, name: &str, allow_root: bool, attr: &Message, root: bool) -> bool {
+    if root && !allow_root {
+        log::debug!("user {} is not allowed to be root", name);
+        return false
+    }
+
+    // ACL 匹配属性时会查找握手消息，只保留协议字段和凭据中配置的属性
+    message.retain(|key, _| key.starts_with('_'));
+
+    for (key, value) in attr {
+        message.insert(key.clone(), value.clone());
+    }
+
+    message.insert(USERNAME, name);
+
+    true
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+fn invalid(field: &str, expect: &str) -> Error {
+    Error::InvalidData(format!("credentials: `{}` must be {}", field, expect))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use nson::{msg, MessageId};
+
+    use crate::dict::*;
+    use crate::node::Hook;
+    use crate::util::{to_hex, from_hex};
+
+    use super::{Credentials, PasswordAuth, TokenAuth, ChallengeAuth};
+
+    #[test]
+    fn test_hex() {
+        assert!(to_hex(&[0, 15, 255]) == "000fff");
+        assert!(from_hex("000fff").unwrap() == vec![0, 15, 255]);
+        assert!(from_hex("0g").is_none());
+        assert!(from_hex("0").is_none());
+    }
+
+    #[test]
+    fn test_password() {
+        let mut credentials = Credentials::default();
+        credentials.add_password("alice", "123456", false, msg!{"role": "printer"});
+
+        let auth = PasswordAuth::new(credentials);
+
+        let mut message = msg!{USERNAME: "alice", PASSWORD: "123456"};
+        assert!(auth.start(MessageId::new(), false, &mut message));
+        assert!(message.get(PASSWORD).is_none());
+        assert!(message.get_str("role").unwrap() == "printer");
+
+        // 客户端不能自己设置属性
+        let mut message = msg!{USERNAME: "alice", PASSWORD: "123456", "role": "admin", "level": 9};
+        assert!(auth.start(MessageId::new(), false, &mut message));
+        assert!(message.get_str("role").unwrap() == "printer");
+        assert!(message.get("level").is_none());
+
+        let mut message = msg!{USERNAME: "alice", PASSWORD: "123456"};
+        assert!(!auth.start(MessageId::new(), true, &mut message));
+
+        let mut message = msg!{USERNAME: "alice", PASSWORD: "654321"};
+        assert!(!auth.start(MessageId::new(), false, &mut message));
+
+        let mut message = msg!{USERNAME: "bob", PASSWORD: "123456"};
+        assert!(!auth.start(MessageId::new(), false, &mut message));
+    }
+
+    #[test]
+    fn test_token() {
+        let auth = TokenAuth::new(b"key");
+
+        let token = auth.sign("a.b", true, Duration::from_secs(60));
+        assert!(auth.verify(&token) == Some(("a.b".to_string(), true)));
+
+        let mut message = msg!{TOKEN: token.clone(), USERNAME: "other"};
+        assert!(auth.start(MessageId::new(), true, &mut message));
+        assert!(message.get_str(USERNAME).unwrap() == "a.b");
+        assert!(message.get(TOKEN).is_none());
+
+        assert!(TokenAuth::new(b"key2").verify(&token).is_none());
+        assert!(auth.verify(&token.replace("a.b", "a.c")).is_none());
+
+        let token = auth.sign_until("alice", false, 1);
+        assert!(auth.verify(&token).is_none());
+    }
+
+    #[test]
+    fn test_challenge() {
+        let mut credentials = Credentials::default();
+        credentials.add_secret("alice", "secret", false, msg!{});
+
+        let auth = ChallengeAuth::new(credentials);
+        let slot_id = MessageId::new();
+
+        let mut message = msg!{USERNAME: "alice"};
+        assert!(auth.start(slot_id, false, &mut message));
+        let challenge = message.get_str(CHALLENGE).unwrap().to_string();
+
+        let mut answer = message.clone();
+        answer.insert(RESPONSE, ChallengeAuth::response("secret", &challenge, &slot_id));
+        assert!(auth.start(slot_id, false, &mut answer));
+        assert!(answer.get(CHALLENGE).is_none());
+
+        // 每次都是新的挑战
+        let mut message = msg!{USERNAME: "alice"};
+        assert!(auth.start(slot_id, false, &mut message));
+        assert!(message.get_str(CHALLENGE).unwrap() != challenge);
+        let challenge = message.get_str(CHALLENGE).unwrap().to_string();
+
+        let mut answer = message.clone();
+        answer.insert(RESPONSE, ChallengeAuth::response("wrong", &challenge, &slot_id));
+        assert!(!auth.start(slot_id, false, &mut answer));
+
+        // 必须带有 CHALLENGE
+        let mut answer = msg!{USERNAME: "alice"};
+        answer.insert(RESPONSE, ChallengeAuth::response("secret", &challenge, &slot_id));
+        assert!(!auth.start(slot_id, false, &mut answer));
+    }
+}
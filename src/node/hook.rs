@@ -1,10 +1,14 @@
+use std::net::SocketAddr;
+
 use queen_io::net::tcp::TcpStream;
 
 use nson::{Message, MessageId};
 
 use crate::Wire;
+use crate::error::Error;
 
-pub trait Hook: Send + 'static {
+// 多个网络线程会同时调用 Hook 进行握手，需要的话在内部加锁，不要在锁中执行耗时的操作
+pub trait Hook: Send + Sync + 'static {
     fn enable_secure(&self) -> bool { false }
 
     fn accept(&self, _: &mut TcpStream) -> bool { true }
@@ -14,9 +18,108 @@ pub trait Hook: Send + 'static {
     fn access(&self, _slot_id: MessageId, _root: bool, _: &mut Message) -> Option<String> { None }
 
     fn finish(&self, _slot_id: MessageId, _root: bool, _: &mut Message, _: &Wire<Message>) { }
+
+    // 握手失败时调用
+    fn fail(&self, _addr: &SocketAddr, _: &Error) { }
 }
 
 pub struct NonHook;
 
 impl Hook for NonHook {}
 impl Hook for () {}
+
+// 将多个 Hook 串联起来，按顺序执行
+// enable_secure: 任意一个 Hook 开启，即开启加密
+// accept、start: 遇到 false 时立即返回
+// access: 返回第一个不为 None 的密钥
+// finish、fail: 每个 Hook 都会执行
+#[derive(Default)]
+pub struct HookChain {
+    hooks: Vec<Box<dyn Hook>>
+}
+
+impl HookChain {
+    pub fn new() -> Self {
+        HookChain::default()
+    }
+
+    pub fn with(mut self, hook: impl Hook) -> Self {
+        self.hooks.push(Box::new(hook));
+        self
+    }
+
+    pub fn push(&mut self, hook: impl Hook) {
+        self.hooks.push(Box::new(hook));
+    }
+
+    pub fn len(&self) -> usize {
+        self.hooks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hooks.is_empty()
+    }
+}
+
+impl Hook for HookChain {
+    fn enable_secure(&self) -> bool {
+        self.hooks.iter().any(|hook| hook.enable_secure())
+    }
+
+    fn accept(&self, stream: &mut TcpStream) -> bool {
+        self.hooks.iter().all(|hook| hook.accept(stream))
+    }
+
+    fn start(&self, slot_id: MessageId, root: bool, message: &mut Message) -> bool {
+        self.hooks.iter().all(|hook| hook.start(slot_id, root, message))
+    }
+
+    fn access(&self, slot_id: MessageId, root: bool, message: &mut Message) -> Option<String> {
+        self.hooks.iter().find_map(|hook| hook.access(slot_id, root, message))
+    }
+
+    fn finish(&self, slot_id: MessageId, root: bool, message: &mut Message, wire: &Wire<Message>) {
+        self.hooks.iter().for_each(|hook| hook.finish(slot_id, root, message, wire))
+    }
+
+    fn fail(&self, addr: &SocketAddr, err: &Error) {
+        self.hooks.iter().for_each(|hook| hook.fail(addr, err))
+    }
+}
+
+// 元组也可以作为 Hook 使用，语义与 HookChain 相同
+macro_rules! tuple_hook {
+    ($($name: ident $index: tt),+) => {
+        impl<$($name: Hook),+> Hook for ($($name,)+) {
+            fn enable_secure(&self) -> bool {
+                $(self.$index.enable_secure())||+
+            }
+
+            fn accept(&self, stream: &mut TcpStream) -> bool {
+                $(self.$index.accept(stream))&&+
+            }
+
+            fn start(&self, slot_id: MessageId, root: bool, message: &mut Message) -> bool {
+                $(self.$index.start(slot_id, root, message))&&+
+            }
+
+            fn access(&self, slot_id: MessageId, root: bool, message: &mut Message) -> Option<String> {
+                None$(.or_else(|| self.$index.access(slot_id, root, message)))+
+            }
+
+            fn finish(&self, slot_id: MessageId, root: bool, message: &mut Message, wire: &Wire<Message>) {
+                $(self.$index.finish(slot_id, root, message, wire);)+
+            }
+
+            fn fail(&self, addr: &SocketAddr, err: &Error) {
+                $(self.$index.fail(addr, err);)+
+            }
+        }
+    };
+}
+
+tuple_hook!(A 0, B 1);
+tuple_hook!(A 0, B 1, C 2);
+tuple_hook!(A 0, B 1, C 2, D 3);
+tuple_hook!(A 0, B 1, C 2, D 3, E 4);
+tuple_hook!(A 0, B 1, C 2, D 3, E 4, F 5);
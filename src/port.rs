@@ -12,12 +12,13 @@ use queen_io::queue::mpsc::Queue;
 
 use nson::{Message, MessageId};
 
-use crate::net::{NetWork, Packet, CryptoOptions, Codec, KeepAlive};
+use crate::net::{NetWork, Packet, CryptoOptions, Codec, KeepAlive, FlowControl, NetStats, HandshakeConfig};
 use crate::Wire;
 use crate::crypto::Crypto;
 use crate::dict::*;
-use crate::error::{Result, Error, Code};
+use crate::error::{Result, Error, Code, ErrorInfo};
 use crate::util::message::read_block;
+use crate::node::ChallengeAuth;
 
 #[derive(Clone)]
 pub struct Port<C: Codec> {
@@ -27,16 +28,36 @@ pub struct Port<C: Codec> {
 struct PortInner<C: Codec> {
     queue: Queue<Packet<C>>,
     run: AtomicBool,
-    keep_alive: KeepAlive
+    keep_alive: KeepAlive,
+    handshake: HandshakeConfig,
+    stats: Arc<NetStats>
 }
 
 impl<C: Codec> Port<C> {
     pub fn new(keep_alive: KeepAlive) -> Result<Self> {
+        Port::with_flow_control(keep_alive, FlowControl::default())
+    }
+
+    pub fn with_flow_control(keep_alive: KeepAlive, flow: FlowControl) -> Result<Self> {
+        // 握手时的消息，默认不能超过 1024 字节，10 秒内完成
+        let handshake = HandshakeConfig {
+            max_size: 1024,
+            timeout: Duration::from_secs(10),
+            ..HandshakeConfig::default()
+        };
+
+        Port::with_config(keep_alive, flow, handshake)
+    }
+
+    // handshake 中的 max_pending 对 Port 无效
+    pub fn with_config(keep_alive: KeepAlive, flow: FlowControl, handshake: HandshakeConfig) -> Result<Self> {
         let port = Port {
             inner: Arc::new(PortInner {
                 queue: Queue::new()?,
                 run: AtomicBool::new(true),
-                keep_alive
+                keep_alive,
+                handshake,
+                stats: Arc::new(NetStats::default())
             })
         };
 
@@ -44,6 +65,9 @@ impl<C: Codec> Port<C> {
             port.inner.queue.clone(),
             port.inner.keep_alive.clone()
         )?;
+        net_work.set_name("port");
+        net_work.set_flow_control(flow);
+        net_work.set_stats(port.inner.stats.clone());
 
         let inner = port.inner.clone();
 
@@ -70,12 +94,46 @@ impl<C: Codec> Port<C> {
         self.inner.run.load(Ordering::Relaxed)
     }
 
+    pub fn stats(&self) -> Arc<NetStats> {
+        self.inner.stats.clone()
+    }
+
     pub fn connect<A: ToSocketAddrs>(
+        &self,
+        addr: A,
+        slot_id: MessageId,
+        root: bool,
+        attr: Message,
+        crypto_options: Option<CryptoOptions>,
+        capacity: Option<usize>
+    ) -> Result<Wire<Message>> {
+        self.connect_inner(addr, slot_id, root, attr, None, crypto_options, capacity)
+    }
+
+    // 用于挑战应答认证（node::ChallengeAuth），attr 中需要携带 USERNAME
+    // secret 不会在网络上传输
+    #[allow(clippy::too_many_arguments)]
+    pub fn connect_with_secret<A: ToSocketAddrs>(
+        &self,
+        addr: A,
+        slot_id: MessageId,
+        root: bool,
+        attr: Message,
+        secret: &str,
+        crypto_options: Option<CryptoOptions>,
+        capacity: Option<usize>
+    ) -> Result<Wire<Message>> {
+        self.connect_inner(addr, slot_id, root, attr, Some(secret), crypto_options, capacity)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn connect_inner<A: ToSocketAddrs>(
         &self,
         addr: A,
         slot_id: MessageId,
         root: bool,
         mut attr: Message,
+        secret: Option<&str>,
         crypto_options: Option<CryptoOptions>,
         capacity: Option<usize>
     ) -> Result<Wire<Message>> {
@@ -89,8 +147,8 @@ impl<C: Codec> Port<C> {
         stream.set_nodelay(true)?;
         // 握手开始
         stream.set_nonblocking(false)?;
-        stream.set_read_timeout(Some(Duration::from_secs(10)))?;
-        stream.set_write_timeout(Some(Duration::from_secs(10)))?;
+        stream.set_read_timeout(Some(self.inner.handshake.timeout))?;
+        stream.set_write_timeout(Some(self.inner.handshake.timeout))?;
 
         attr.insert(CHAN, HAND);
         attr.insert(ADDR, stream.peer_addr()?.to_string());
@@ -107,14 +165,30 @@ impl<C: Codec> Port<C> {
 
         let mut codec = C::new();
 
-        let bytes = codec.encode(&None, attr)?;
+        let bytes = codec.encode(&None, attr.clone())?;
 
         stream.write_all(&bytes)?;
 
-        // 握手时的消息，不能超过 1024 字节
-        let bytes = read_block(&mut stream, Some(1024))?;
+        let bytes = read_block(&mut stream, Some(self.inner.handshake.max_size))?;
         let mut message = codec.decode(&None, bytes)?;
 
+        // 挑战应答
+        if Code::get(&message).is_none() {
+            if let Ok(challenge) = message.get_str(CHALLENGE) {
+                let secret = secret.ok_or_else(|| Error::PermissionDenied("challenge requires a secret".to_string()))?;
+
+                attr.insert(CHALLENGE, challenge);
+                attr.insert(RESPONSE, ChallengeAuth::response(secret, challenge, &slot_id));
+
+                let bytes = codec.encode(&None, attr)?;
+
+                stream.write_all(&bytes)?;
+
+                let bytes = read_block(&mut stream, Some(self.inner.handshake.max_size))?;
+                message = codec.decode(&None, bytes)?;
+            }
+        }
+
         if let Some(code) = Code::get(&message) {
             if code == Code::Ok {
                 message.remove(CHAN);
@@ -137,7 +211,7 @@ impl<C: Codec> Port<C> {
 
                 return Ok(wire2)
             } else {
-                return Err(Error::ErrorCode(code))
+                return Err(ErrorInfo::get(&message).unwrap_or_else(|| ErrorInfo::new(code)).into())
             }
         }
 
@@ -1,5 +1,6 @@
 use std::time::Duration;
 use std::thread;
+use std::io::ErrorKind::WouldBlock;
 use std::sync::{
     Arc,
     atomic::{AtomicBool, Ordering}
@@ -9,7 +10,8 @@ use std::io::ErrorKind::Interrupted;
 
 use queen_io::{
     epoll::{Epoll, Events, Token, Ready, EpollOpt},
-    queue::mpsc::Queue
+    queue::mpsc::Queue,
+    sys::timerfd::{TimerFd, TimerSpec, SetTimeFlags}
 };
 
 use nson::{
@@ -18,15 +20,30 @@ use nson::{
 };
 
 use crate::Wire;
-use crate::error::{Result, Error, RecvError, Code};
+use crate::dict::BREAK_LOST;
+use crate::error::{Result, RecvError, ErrorInfo};
 
-pub use hook::{Hook, NonHook};
-pub use switch::Switch;
+pub use hook::{Hook, NonHook, HookChain};
+pub use switch::{Switch, Scheduled, Durable, Gather};
 pub use slot::Slot;
+pub use acl::{Acl, AclRules, AclRule, AclOp};
+pub use group::{Group, Strategy};
+pub use schema::Schema;
+pub use dedup::{Dedup, DedupConfig, DedupScope};
+pub use work_queue::{WorkQueue, Consumer, Unacked};
+pub use rules::{Rules, Rule, Action, Outcome};
+pub use sim::Sim;
 
 mod hook;
 mod switch;
 mod slot;
+mod acl;
+mod group;
+mod schema;
+mod dedup;
+mod work_queue;
+mod rules;
+mod sim;
 
 #[derive(Clone)]
 pub struct Socket {
@@ -59,6 +76,8 @@ impl Socket {
 
         let socket2 = socket.clone();
         thread::Builder::new().name("socket".to_string()).spawn(move || {
+            main_loop.hook.start(&mut main_loop.switch);
+
             let ret = main_loop.run();
             if ret.is_err() {
                 log::error!("socket loop exit: {:?}", ret);
@@ -94,6 +113,25 @@ impl Socket {
         attr: Message,
         capacity: Option<usize>,
         timeout: Option<Duration>
+    ) -> Result<Wire<Message>> {
+        let wire2 = self.connect_nowait(slot_id, root, attr, capacity)?;
+
+        let ret = wire2.wait(Some(timeout.unwrap_or_else(|| Duration::from_secs(10))))?;
+
+        if let Some(info) = ErrorInfo::get(&ret) {
+            return Err(info.into())
+        }
+
+        Ok(wire2)
+    }
+
+    // 不等待连接的结果，返回的 wire 收到的第一条消息即为结果，失败时带有错误信息
+    pub fn connect_nowait(
+        &self,
+        slot_id: MessageId,
+        root: bool,
+        attr: Message,
+        capacity: Option<usize>
     ) -> Result<Wire<Message>> {
         let (wire1, wire2) = Wire::pipe(capacity.unwrap_or(64), attr)?;
 
@@ -101,16 +139,6 @@ impl Socket {
 
         self.inner.queue.push(packet);
 
-        let ret = wire2.wait(Some(timeout.unwrap_or_else(|| Duration::from_secs(10))))?;
-
-        if let Some(code) = Code::get(&ret) {
-            if code != Code::Ok {
-                return Err(Error::ErrorCode(code))
-            }
-        } else {
-            unreachable!()
-        }
-
         Ok(wire2)
     }
 }
@@ -127,6 +155,7 @@ struct MainLoop<H> {
     epoll: Epoll,
     events: Events,
     queue: Queue<Packet>,
+    timer: TimerFd,
     hook: H,
     switch: Switch
 }
@@ -137,13 +166,15 @@ enum Packet {
 }
 
 impl<H: Hook> MainLoop<H> {
-    const QUEUE_TOKEN: Token = Token(usize::max_value());
+    const QUEUE_TOKEN: Token = Token(usize::MAX);
+    const TIMER_TOKEN: Token = Token(usize::MAX - 1);
 
     fn new(socket_id: MessageId, queue: Queue<Packet>, hook: H) -> Result<MainLoop<H>> {
         Ok(MainLoop {
             epoll: Epoll::new()?,
             events: Events::with_capacity(1024),
             queue,
+            timer: TimerFd::new()?,
             hook,
             switch: Switch::new(socket_id)
         })
@@ -151,6 +182,14 @@ impl<H: Hook> MainLoop<H> {
 
     fn run(&mut self) -> Result<()> {
         self.epoll.add(&self.queue, Self::QUEUE_TOKEN, Ready::readable(), EpollOpt::level())?;
+        self.epoll.add(&self.timer, Self::TIMER_TOKEN, Ready::readable(), EpollOpt::edge())?;
+
+        let timerspec = TimerSpec {
+            interval: Duration::new(1, 0),
+            value: Duration::new(1, 0)
+        };
+
+        self.timer.settime(timerspec, SetTimeFlags::Default)?;
 
         loop {
             let size = match self.epoll.wait(&mut self.events, None) {
@@ -177,11 +216,27 @@ impl<H: Hook> MainLoop<H> {
                                     self.switch.add_slot(&self.epoll, &self.hook, id, root, wire)?;
                                 }
                                 Packet::Close => {
+                                    self.switch.shutdown();
+
                                     return Ok(())
                                 }
                             }
                         }
                     }
+                    Self::TIMER_TOKEN => {
+                        match self.timer.read() {
+                            Ok(_) => (),
+                            Err(err) => {
+                                if err.kind() == WouldBlock {
+                                    continue;
+                                } else {
+                                    return Err(err.into())
+                                }
+                            }
+                        }
+
+                        self.switch.tick(&self.epoll, &self.hook)?;
+                    }
                     _ => {
                         let token = token.0;
                         if let Some(slot) = self.switch.slots.get(token) {
@@ -191,7 +246,10 @@ impl<H: Hook> MainLoop<H> {
                                 }
                                 Err(err) => {
                                     if !matches!(err, RecvError::Empty) {
-                                        self.switch.del_slot(&self.epoll, &self.hook, token)?;
+                                        // 没有发送 CLOSE 就断开了
+                                        let reason = slot.wire.reason().unwrap_or(BREAK_LOST);
+
+                                        self.switch.del_slot(&self.epoll, &self.hook, token, reason)?;
                                     }
                                 }
                             }
@@ -0,0 +1,413 @@
+use std::path::{Path, PathBuf};
+
+use nson::{Message, MessageId, Value};
+
+use crate::dict::*;
+use crate::error::{Result, Error, Code, ErrorInfo};
+use crate::util::lock::Lock;
+use crate::util::config;
+use crate::util::message::{match_attr, match_chan};
+
+use super::{Hook, NonHook, Switch, Slot};
+
+// 访问控制
+//
+// 规则文件示例（TOML）：
+//
+// default = "deny"
+//
+// [[rules]]
+// root = true
+// ops = ["*"]
+// chans = ["*"]
+//
+// [[rules]]
+// attr = { role = "sensor" }
+// ops = ["publish"]
+// chans = ["sensor/{slot_id}/*"]
+//
+// [[rules]]
+// permit = "deny"
+// slot_id = { "$mid" = "..." }
+// ops = ["subscribe"]
+// chans = ["secret/*"]
+//
+// 规则按顺序匹配，第一条匹配的规则决定结果，都不匹配时使用 default
+// chans 中可以使用 `*` 通配符，`{slot_id}` 会被替换成当前 SLOT 的 ID
+// BIND 和 KILL 时，chans 匹配的是目标 SLOT 的 ID
+pub struct Acl<H: Hook = NonHook> {
+    path: Option<PathBuf>,
+    rules: Lock<AclRules>,
+    inner: H
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AclOp {
+    Publish,
+    Subscribe,
+    Bind,
+    Kill
+}
+
+#[derive(Debug, Clone)]
+pub struct AclRule {
+    pub allow: bool,
+    pub slot_id: Option<MessageId>,
+    pub root: Option<bool>,
+    pub attr: Message,
+    // 为空时匹配所有操作
+    pub ops: Vec<AclOp>,
+    pub chans: Vec<String>
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct AclRules {
+    pub default_allow: bool,
+    pub rules: Vec<AclRule>
+}
+
+impl Acl<NonHook> {
+    pub fn new(rules: AclRules) -> Self {
+        Acl::with_hook(rules, NonHook)
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        Acl::from_file_with_hook(path, NonHook)
+    }
+}
+
+impl<H: Hook> Acl<H> {
+    pub fn with_hook(rules: AclRules, hook: H) -> Self {
+        Acl {
+            path: None,
+            rules: Lock::new(rules),
+            inner: hook
+        }
+    }
+
+    pub fn from_file_with_hook(path: impl AsRef<Path>, hook: H) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let rules = AclRules::from_message(&config::load(&path)?)?;
+
+        Ok(Acl {
+            path: Some(path),
+            rules: Lock::new(rules),
+            inner: hook
+        })
+    }
+
+    pub fn hook(&self) -> &H {
+        &self.inner
+    }
+
+    pub fn set_rules(&self, rules: AclRules) {
+        *self.rules.lock() = rules;
+    }
+
+    pub fn rules(&self) -> AclRules {
+        self.rules.lock().clone()
+    }
+
+    // 重新从文件加载规则，加载失败时保留原有规则
+    pub fn reload(&self) -> Result<()> {
+        if let Some(path) = &self.path {
+            let rules = AclRules::from_message(&config::load(path)?)?;
+            self.set_rules(rules);
+
+            return Ok(())
+        }
+
+        Err(Error::NotFound("acl file".to_string()))
+    }
+
+    pub fn check(&self, slot: &Slot, op: AclOp, target: &str) -> bool {
+        self.rules.lock().check(slot, op, target)
+    }
+}
+
+impl AclRules {
+    pub fn from_message(message: &Message) -> Result<Self> {
+        let default_allow = match message.get("default") {
+            Some(value) => parse_permit(value)?,
+            None => false
+        };
+
+        let mut rules = Vec::new();
+
+        if let Some(value) = message.get("rules") {
+            let array = value.as_array().ok_or_else(|| invalid("rules", "array"))?;
+
+            for item in array {
+                let item = item.as_message().ok_or_else(|| invalid("rules", "array of table"))?;
+                rules.push(AclRule::from_message(item)?);
+            }
+        }
+
+        Ok(AclRules {
+            default_allow,
+            rules
+        })
+    }
+
+    pub fn check(&self, slot: &Slot, op: AclOp, target: &str) -> bool {
+        for rule in &self.rules {
+            if rule.matches(slot, op, target) {
+                return rule.allow
+            }
+        }
+
+        self.default_allow
+    }
+}
+
+impl AclRule {
+    pub fn from_message(message: &Message) -> Result<Self> {
+        let allow = match message.get("permit") {
+            Some(value) => parse_permit(value)?,
+            None => true
+        };
+
+        let slot_id = match message.get("slot_id") {
+            Some(Value::MessageId(id)) => Some(*id),
+            Some(Value::String(hex)) => Some(MessageId::with_string(hex).map_err(|_| invalid("slot_id", "message id"))?),
+            Some(_) => return Err(invalid("slot_id", "message id")),
+            None => None
+        };
+
+        let root = match message.get("root") {
+            Some(value) => Some(value.as_bool().ok_or_else(|| invalid("root", "bool"))?),
+            None => None
+        };
+
+        let attr = match message.get("attr") {
+            Some(value) => value.as_message().ok_or_else(|| invalid("attr", "table"))?.clone(),
+            None => Message::new()
+        };
+
+        let mut ops = Vec::new();
+        let mut any = false;
+
+        if let Some(value) = message.get("ops") {
+            for op in value.as_array().ok_or_else(|| invalid("ops", "array"))? {
+                match op.as_str() {
+                    Some("*") => any = true,
+                    Some("publish") => ops.push(AclOp::Publish),
+                    Some("subscribe") => ops.push(AclOp::Subscribe),
+                    Some("bind") => ops.push(AclOp::Bind),
+                    Some("kill") => ops.push(AclOp::Kill),
+                    _ => return Err(invalid("ops", "publish, subscribe, bind, kill or *"))
+                }
+            }
+        }
+
+        if any {
+            ops.clear();
+        }
+
+        let mut chans = Vec::new();
+
+        match message.get("chans") {
+            Some(value) => {
+                for chan in value.as_array().ok_or_else(|| invalid("chans", "array"))? {
+                    chans.push(chan.as_str().ok_or_else(|| invalid("chans", "array of string"))?.to_string());
+                }
+            }
+            None => chans.push("*".to_string())
+        }
+
+        Ok(AclRule {
+            allow,
+            slot_id,
+            root,
+            attr,
+            ops,
+            chans
+        })
+    }
+
+    pub fn matches(&self, slot: &Slot, op: AclOp, target: &str) -> bool {
+        if !self.ops.is_empty() && !self.ops.contains(&op) {
+            return false
+        }
+
+        if let Some(slot_id) = &self.slot_id {
+            if slot_id != &slot.id {
+                return false
+            }
+        }
+
+        if let Some(root) = self.root {
+            if root != slot.root {
+                return false
+            }
+        }
+
+        if !self.attr.is_empty() && !match_attr(&slot.wire.attr(), &self.attr) {
+            return false
+        }
+
+        let slot_id = slot.id.to_hex();
+
+        self.chans.iter().any(|pattern| {
+            if pattern.contains("{slot_id}") {
+                match_chan(&pattern.replace("{slot_id}", &slot_id), target)
+            } else {
+                match_chan(pattern, target)
+            }
+        })
+    }
+}
+
+fn parse_permit(value: &Value) -> Result<bool> {
+    match value {
+        Value::Bool(allow) => Ok(*allow),
+        Value::String(s) if s == "allow" => Ok(true),
+        Value::String(s) if s == "deny" => Ok(false),
+        _ => Err(invalid("permit", "\"allow\" or \"deny\""))
+    }
+}
+
+fn invalid(field: &str, expect: &str) -> Error {
+    Error::InvalidData(format!("acl: `{}` must be {}", field, expect))
+}
+
+impl<H: Hook> Hook for Acl<H> {
+    fn accept(&self, slot: &Slot) -> bool {
+        self.inner.accept(slot)
+    }
+
+    fn remove(&self, slot: &Slot) {
+        self.inner.remove(slot)
+    }
+
+    fn recv(&self, slot: &Slot, message: &mut Message) -> bool {
+        self.inner.recv(slot, message)
+    }
+
+    fn send(&self, slot: &Slot, message: &mut Message) -> bool {
+        self.inner.send(slot, message)
+    }
+
+    fn attach(&self, slot: &Slot, message: &mut Message, chan: &str) -> bool {
+        self.check(slot, AclOp::Subscribe, chan) && self.inner.attach(slot, message, chan)
+    }
+
+    fn detach(&self, slot: &Slot, message: &mut Message, chan: &str) -> bool {
+        self.inner.detach(slot, message, chan)
+    }
+
+    fn bind(&self, slot: &Slot, message: &mut Message, slot_id: MessageId) -> bool {
+        self.check(slot, AclOp::Bind, &slot_id.to_hex()) && self.inner.bind(slot, message, slot_id)
+    }
+
+    fn unbind(&self, slot: &Slot, message: &mut Message, slot_id: MessageId) -> bool {
+        self.inner.unbind(slot, message, slot_id)
+    }
+
+    fn join(&self, slot: &Slot, message: &mut Message) -> bool {
+        self.inner.join(slot, message)
+    }
+
+    fn unjoin(&self, slot: &Slot, message: &mut Message) -> bool {
+        self.inner.unjoin(slot, message)
+    }
+
+    fn ping(&self, slot: &Slot, message: &mut Message) {
+        self.inner.ping(slot, message)
+    }
+
+    fn emit(&self, slot: &Slot, message: &mut Message) -> bool {
+        let allow = match message.get_str(CHAN) {
+            Ok(chan) => self.check(slot, AclOp::Publish, chan),
+            Err(_) => false
+        };
+
+        allow && self.inner.emit(slot, message)
+    }
+
+    fn push(&self, slot: &Slot, message: &mut Message) -> bool {
+        self.inner.push(slot, message)
+    }
+
+    fn kill(&self, slot: &Slot, message: &mut Message) -> bool {
+        let allow = match message.get_message_id(SLOT_ID) {
+            Ok(slot_id) => self.check(slot, AclOp::Kill, &slot_id.to_hex()),
+            Err(_) => true
+        };
+
+        allow && self.inner.kill(slot, message)
+    }
+
+    fn lookup(&self, slot: &Slot, message: &mut Message) -> bool {
+        self.inner.lookup(slot, message)
+    }
+
+    fn query(&self, switch: &Switch, token: usize, message: &mut Message) {
+        self.inner.query(switch, token, message)
+    }
+
+    fn custom(&self, switch: &Switch, token: usize, message: &mut Message) {
+        self.inner.custom(switch, token, message)
+    }
+
+    // CTRL 消息中带有 ACL 字段时，重新加载规则
+    // {
+    //     CHAN: CTRL,
+    //     ACL: true         // 从文件重新加载
+    //     ACL: { rules... } // 使用消息中的规则
+    // }
+    fn ctrl(&self, switch: &mut Switch, token: usize, message: &mut Message) {
+        let ret = match message.get(ACL) {
+            Some(Value::Message(rules)) => AclRules::from_message(rules).map(|rules| self.set_rules(rules)),
+            Some(_) => self.reload(),
+            None => return self.inner.ctrl(switch, token, message)
+        };
+
+        match ret {
+            Ok(()) => Code::Ok.set(message),
+            Err(err) => {
+                log::error!("acl reload: {}", err);
+
+                ErrorInfo::new(Code::BadValue).field(ACL).reason(err.to_string()).set(message);
+            }
+        }
+    }
+
+    fn start(&self, switch: &mut Switch) {
+        self.inner.start(switch)
+    }
+
+    fn stop(&self, switch: &Switch) {
+        self.inner.stop(switch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nson::msg;
+
+    use super::{AclRules, AclOp};
+
+    #[test]
+    fn test_from_message() {
+        let rules = AclRules::from_message(&msg!{
+            "default": "allow",
+            "rules": [
+                {
+                    "permit": "deny",
+                    "attr": {"role": "sensor"},
+                    "ops": ["subscribe", "bind"],
+                    "chans": ["secret/*"]
+                }
+            ]
+        }).unwrap();
+
+        assert!(rules.default_allow);
+        assert!(rules.rules.len() == 1);
+        assert!(!rules.rules[0].allow);
+        assert!(rules.rules[0].ops == vec![AclOp::Subscribe, AclOp::Bind]);
+
+        assert!(AclRules::from_message(&msg!{"rules": [{"ops": ["eat"]}]}).is_err());
+        assert!(AclRules::from_message(&msg!{"default": "maybe"}).is_err());
+    }
+}
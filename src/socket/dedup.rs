@@ -0,0 +1,210 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::str::FromStr;
+
+use nson::message_id::MessageId;
+
+use crate::error::{Error, Result};
+
+// 消息去重，用于幂等发布
+//
+// 发布者因为重试或重连可能会重复发送同一条消息，带有 ID 的消息在时间窗口内
+// 只会投递一次，重复的消息返回 Code::Ok 并附带 DUPLICATE: true
+//
+// 每个发布者（或每个 CHAN）最多记住 capacity 个 ID，超出时遗忘最早的
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DedupScope {
+    // 按发布者的 SLOT_ID 区分，重连后仍然有效
+    Slot,
+    // 按 CHAN 区分，不同发布者的相同 ID 也视为重复
+    Chan
+}
+
+impl FromStr for DedupScope {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "slot" => Ok(DedupScope::Slot),
+            "chan" => Ok(DedupScope::Chan),
+            _ => Err(Error::InvalidData(format!("unknown dedup scope: {}", s)))
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DedupConfig {
+    // 时间窗口（秒）
+    pub window: u32,
+    pub capacity: usize,
+    pub scope: DedupScope
+}
+
+impl DedupConfig {
+    pub fn new(window: u32, capacity: usize, scope: DedupScope) -> Self {
+        DedupConfig {
+            window,
+            capacity,
+            scope
+        }
+    }
+}
+
+impl Default for DedupConfig {
+    fn default() -> Self {
+        DedupConfig {
+            window: 60,
+            capacity: 1024,
+            scope: DedupScope::Slot
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Key {
+    Slot(MessageId),
+    Chan(String)
+}
+
+#[derive(Debug, Default)]
+struct Seen {
+    ids: HashSet<MessageId>,
+    // 记录的时间，UNIX 时间戳（秒），按时间先后排列
+    order: VecDeque<(u64, MessageId)>
+}
+
+impl Seen {
+    fn expire(&mut self, before: u64) {
+        while let Some((at, id)) = self.order.front() {
+            if *at > before {
+                break
+            }
+
+            self.ids.remove(id);
+            self.order.pop_front();
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Dedup {
+    pub config: DedupConfig,
+    seen: HashMap<Key, Seen>,
+    // 已丢弃的重复消息数
+    duplicates: usize
+}
+
+impl Dedup {
+    pub fn new(config: DedupConfig) -> Self {
+        Dedup {
+            config,
+            seen: HashMap::new(),
+            duplicates: 0
+        }
+    }
+
+    // 消息是否重复，不重复时记住这个 ID
+    pub fn check(&mut self, slot_id: &MessageId, chan: &str, id: &MessageId, now: u64) -> bool {
+        let key = match self.config.scope {
+            DedupScope::Slot => Key::Slot(*slot_id),
+            DedupScope::Chan => Key::Chan(chan.to_string())
+        };
+
+        let seen = self.seen.entry(key).or_default();
+
+        seen.expire(now.saturating_sub(u64::from(self.config.window)));
+
+        if seen.ids.contains(id) {
+            self.duplicates += 1;
+
+            return true
+        }
+
+        if self.config.capacity == 0 {
+            return false
+        }
+
+        while seen.order.len() >= self.config.capacity {
+            if let Some((_, id)) = seen.order.pop_front() {
+                seen.ids.remove(&id);
+            }
+        }
+
+        seen.ids.insert(*id);
+        seen.order.push_back((now, *id));
+
+        false
+    }
+
+    // 遗忘超出时间窗口的 ID
+    pub fn expire(&mut self, now: u64) {
+        let before = now.saturating_sub(u64::from(self.config.window));
+
+        self.seen.retain(|_, seen| {
+            seen.expire(before);
+            !seen.order.is_empty()
+        });
+    }
+
+    // 记住的 ID 数
+    pub fn len(&self) -> usize {
+        self.seen.values().map(|seen| seen.order.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.seen.values().all(|seen| seen.order.is_empty())
+    }
+
+    pub fn duplicates(&self) -> usize {
+        self.duplicates
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nson::MessageId;
+
+    use super::{Dedup, DedupConfig, DedupScope};
+
+    #[test]
+    fn test_check() {
+        let mut dedup = Dedup::new(DedupConfig::new(10, 2, DedupScope::Slot));
+
+        let slot1 = MessageId::new();
+        let slot2 = MessageId::new();
+
+        let id1 = MessageId::new();
+        let id2 = MessageId::new();
+        let id3 = MessageId::new();
+
+        assert!(!dedup.check(&slot1, "a", &id1, 100));
+        assert!(dedup.check(&slot1, "b", &id1, 101));
+        assert!(!dedup.check(&slot2, "a", &id1, 101));
+        assert!(dedup.duplicates() == 1);
+
+        // 超出容量时遗忘最早的
+        assert!(!dedup.check(&slot1, "a", &id2, 102));
+        assert!(!dedup.check(&slot1, "a", &id3, 102));
+        assert!(!dedup.check(&slot1, "a", &id1, 102));
+        assert!(dedup.len() == 3);
+
+        // 超出时间窗口
+        assert!(dedup.check(&slot1, "a", &id3, 111));
+        assert!(!dedup.check(&slot1, "a", &id3, 112));
+
+        dedup.expire(200);
+        assert!(dedup.is_empty());
+
+        let mut dedup = Dedup::new(DedupConfig::new(10, 16, DedupScope::Chan));
+
+        assert!(!dedup.check(&slot1, "a", &id1, 100));
+        assert!(dedup.check(&slot2, "a", &id1, 100));
+        assert!(!dedup.check(&slot2, "b", &id1, 100));
+    }
+
+    #[test]
+    fn test_scope() {
+        assert!("slot".parse::<DedupScope>().unwrap() == DedupScope::Slot);
+        assert!("chan".parse::<DedupScope>().unwrap() == DedupScope::Chan);
+        assert!("user".parse::<DedupScope>().is_err());
+    }
+}
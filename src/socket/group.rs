@@ -0,0 +1,149 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use queen_io::plus::slab::Slab;
+
+use nson::{Message, Value};
+
+use rand::{Rng, rngs::SmallRng};
+
+use crate::dict::*;
+
+use super::Slot;
+
+// 消费组的分发策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Strategy {
+    // 随机选择一个成员
+    #[default]
+    Random,
+    // 轮询
+    RoundRobin,
+    // 选择待处理消息（Wire::pending）最少的成员
+    LeastPending,
+    // 根据消息中的 KEY 字段做一致性哈希，相同 KEY 的消息总是发给同一个成员，保证顺序，
+    // 成员变化时，只有一部分 KEY 会迁移。消息中没有 KEY 时，随机选择
+    Hash
+}
+
+impl Strategy {
+    pub fn from_name(s: &str) -> Option<Strategy> {
+        match s {
+            "random" => Some(Strategy::Random),
+            "round_robin" => Some(Strategy::RoundRobin),
+            "least_pending" => Some(Strategy::LeastPending),
+            "hash" => Some(Strategy::Hash),
+            _ => None
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Strategy::Random => "random",
+            Strategy::RoundRobin => "round_robin",
+            Strategy::LeastPending => "least_pending",
+            Strategy::Hash => "hash"
+        }
+    }
+}
+
+// 消费组，同一个 CHAN 下可以有多个消费组，每个消费组都会收到一份消息，
+// 由组内的一个成员处理
+#[derive(Debug, Default)]
+pub struct Group {
+    pub strategy: Strategy,
+    // 按加入的顺序排列
+    pub members: Vec<usize>,
+    cursor: usize
+}
+
+impl Group {
+    pub fn new(strategy: Strategy) -> Self {
+        Group {
+            strategy,
+            members: Vec::new(),
+            cursor: 0
+        }
+    }
+
+    pub fn insert(&mut self, token: usize) -> bool {
+        if self.members.contains(&token) {
+            return false
+        }
+
+        self.members.push(token);
+
+        true
+    }
+
+    pub fn remove(&mut self, token: usize) -> bool {
+        if let Some(pos) = self.members.iter().position(|t| *t == token) {
+            self.members.remove(pos);
+
+            return true
+        }
+
+        false
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+
+    pub fn pick(&mut self, slots: &Slab<Slot>, rand: &mut SmallRng, message: &Message) -> Option<usize> {
+        if self.members.is_empty() {
+            return None
+        }
+
+        if self.members.len() == 1 {
+            return Some(self.members[0])
+        }
+
+        match self.strategy {
+            Strategy::Random => {
+                Some(self.members[rand.gen_range(0..self.members.len())])
+            }
+            Strategy::RoundRobin => {
+                let token = self.members[self.cursor % self.members.len()];
+                self.cursor = self.cursor.wrapping_add(1);
+
+                Some(token)
+            }
+            Strategy::LeastPending => {
+                self.members.iter()
+                    .filter_map(|token| slots.get(*token).map(|slot| (slot.wire.pending(), *token)))
+                    .min_by_key(|(pending, _)| *pending)
+                    .map(|(_, token)| token)
+            }
+            Strategy::Hash => {
+                let key = match message.get(KEY) {
+                    Some(key) => key,
+                    None => return Some(self.members[rand.gen_range(0..self.members.len())])
+                };
+
+                // rendezvous hashing，以 SLOT_ID 为权重，成员的 token 变化不影响结果
+                self.members.iter()
+                    .filter_map(|token| slots.get(*token))
+                    .max_by_key(|slot| weight(key, slot))
+                    .map(|slot| slot.token)
+            }
+        }
+    }
+}
+
+fn weight(key: &Value, slot: &Slot) -> u64 {
+    let mut hasher = DefaultHasher::new();
+
+    match key {
+        Value::String(s) => s.hash(&mut hasher),
+        other => {
+            let mut buf = Vec::new();
+            let _ = nson::encode::encode_value(&mut buf, other);
+            buf.hash(&mut hasher)
+        }
+    }
+
+    slot.id.bytes().hash(&mut hasher);
+
+    hasher.finish()
+}
@@ -31,12 +31,19 @@ pub trait Hook: Send + 'static {
 
     fn kill(&self, _: &Slot, _: &mut Message) -> bool { true }
 
+    // LOOKUP、WATCH 以及按属性发送（TO_ATTR）时调用，可以限制 SLOT 查找其他 SLOT
+    fn lookup(&self, _: &Slot, _: &mut Message) -> bool { true }
+
     fn query(&self, _: &Switch, _token: usize, _: &mut Message) {}
 
     fn custom(&self, _: &Switch, _token: usize, _: &mut Message) {}
 
+    // ROOT 的每次 CTRL 都会调用，设置了 CODE 时 Switch 不再处理 SCHEMA、RULES 等内置的操作
     fn ctrl(&self, _: &mut Switch, _token: usize, _: &mut Message) {}
 
+    // Socket 启动时调用，可以在这里配置 Switch
+    fn start(&self, _: &mut Switch) {}
+
     fn stop(&self, _: &Switch) {}
 }
 
@@ -44,3 +51,224 @@ pub struct NonHook;
 
 impl Hook for NonHook {}
 impl Hook for () {}
+
+// 将多个 Hook 串联起来，按顺序执行
+// 返回 bool 的回调，遇到 false 时立即返回，后面的 Hook 不再执行
+// 消息会被依次修改，前一个 Hook 修改后的消息会传给后一个 Hook
+// remove、ping、stop 等通知类的回调，每个 Hook 都会执行
+#[derive(Default)]
+pub struct HookChain {
+    hooks: Vec<Box<dyn Hook>>
+}
+
+impl HookChain {
+    pub fn new() -> Self {
+        HookChain::default()
+    }
+
+    pub fn with(mut self, hook: impl Hook) -> Self {
+        self.hooks.push(Box::new(hook));
+        self
+    }
+
+    pub fn push(&mut self, hook: impl Hook) {
+        self.hooks.push(Box::new(hook));
+    }
+
+    pub fn len(&self) -> usize {
+        self.hooks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hooks.is_empty()
+    }
+}
+
+macro_rules! chain {
+    ($iter: expr, all, $method: ident ( $($arg: expr),* )) => {
+        $iter.all(|hook| hook.$method($($arg),*))
+    };
+    ($iter: expr, each, $method: ident ( $($arg: expr),* )) => {
+        $iter.for_each(|hook| hook.$method($($arg),*))
+    };
+}
+
+impl Hook for HookChain {
+    fn accept(&self, slot: &Slot) -> bool {
+        chain!(self.hooks.iter(), all, accept(slot))
+    }
+
+    fn remove(&self, slot: &Slot) {
+        chain!(self.hooks.iter(), each, remove(slot))
+    }
+
+    fn recv(&self, slot: &Slot, message: &mut Message) -> bool {
+        chain!(self.hooks.iter(), all, recv(slot, message))
+    }
+
+    fn send(&self, slot: &Slot, message: &mut Message) -> bool {
+        chain!(self.hooks.iter(), all, send(slot, message))
+    }
+
+    fn attach(&self, slot: &Slot, message: &mut Message, chan: &str) -> bool {
+        chain!(self.hooks.iter(), all, attach(slot, message, chan))
+    }
+
+    fn detach(&self, slot: &Slot, message: &mut Message, chan: &str) -> bool {
+        chain!(self.hooks.iter(), all, detach(slot, message, chan))
+    }
+
+    fn bind(&self, slot: &Slot, message: &mut Message, slot_id: MessageId) -> bool {
+        chain!(self.hooks.iter(), all, bind(slot, message, slot_id))
+    }
+
+    fn unbind(&self, slot: &Slot, message: &mut Message, slot_id: MessageId) -> bool {
+        chain!(self.hooks.iter(), all, unbind(slot, message, slot_id))
+    }
+
+    fn join(&self, slot: &Slot, message: &mut Message) -> bool {
+        chain!(self.hooks.iter(), all, join(slot, message))
+    }
+
+    fn unjoin(&self, slot: &Slot, message: &mut Message) -> bool {
+        chain!(self.hooks.iter(), all, unjoin(slot, message))
+    }
+
+    fn ping(&self, slot: &Slot, message: &mut Message) {
+        chain!(self.hooks.iter(), each, ping(slot, message))
+    }
+
+    fn emit(&self, slot: &Slot, message: &mut Message) -> bool {
+        chain!(self.hooks.iter(), all, emit(slot, message))
+    }
+
+    fn push(&self, slot: &Slot, message: &mut Message) -> bool {
+        chain!(self.hooks.iter(), all, push(slot, message))
+    }
+
+    fn kill(&self, slot: &Slot, message: &mut Message) -> bool {
+        chain!(self.hooks.iter(), all, kill(slot, message))
+    }
+
+    fn lookup(&self, slot: &Slot, message: &mut Message) -> bool {
+        chain!(self.hooks.iter(), all, lookup(slot, message))
+    }
+
+    fn query(&self, switch: &Switch, token: usize, message: &mut Message) {
+        chain!(self.hooks.iter(), each, query(switch, token, message))
+    }
+
+    fn custom(&self, switch: &Switch, token: usize, message: &mut Message) {
+        chain!(self.hooks.iter(), each, custom(switch, token, message))
+    }
+
+    fn ctrl(&self, switch: &mut Switch, token: usize, message: &mut Message) {
+        for hook in &self.hooks {
+            hook.ctrl(switch, token, message)
+        }
+    }
+
+    fn start(&self, switch: &mut Switch) {
+        for hook in &self.hooks {
+            hook.start(switch)
+        }
+    }
+
+    fn stop(&self, switch: &Switch) {
+        chain!(self.hooks.iter(), each, stop(switch))
+    }
+}
+
+// 元组也可以作为 Hook 使用，语义与 HookChain 相同，例如：
+// Socket::new(id, (acl, metrics, logger))
+macro_rules! tuple_hook {
+    ($($name: ident $index: tt),+) => {
+        impl<$($name: Hook),+> Hook for ($($name,)+) {
+            fn accept(&self, slot: &Slot) -> bool {
+                $(self.$index.accept(slot))&&+
+            }
+
+            fn remove(&self, slot: &Slot) {
+                $(self.$index.remove(slot);)+
+            }
+
+            fn recv(&self, slot: &Slot, message: &mut Message) -> bool {
+                $(self.$index.recv(slot, message))&&+
+            }
+
+            fn send(&self, slot: &Slot, message: &mut Message) -> bool {
+                $(self.$index.send(slot, message))&&+
+            }
+
+            fn attach(&self, slot: &Slot, message: &mut Message, chan: &str) -> bool {
+                $(self.$index.attach(slot, message, chan))&&+
+            }
+
+            fn detach(&self, slot: &Slot, message: &mut Message, chan: &str) -> bool {
+                $(self.$index.detach(slot, message, chan))&&+
+            }
+
+            fn bind(&self, slot: &Slot, message: &mut Message, slot_id: MessageId) -> bool {
+                $(self.$index.bind(slot, message, slot_id))&&+
+            }
+
+            fn unbind(&self, slot: &Slot, message: &mut Message, slot_id: MessageId) -> bool {
+                $(self.$index.unbind(slot, message, slot_id))&&+
+            }
+
+            fn join(&self, slot: &Slot, message: &mut Message) -> bool {
+                $(self.$index.join(slot, message))&&+
+            }
+
+            fn unjoin(&self, slot: &Slot, message: &mut Message) -> bool {
+                $(self.$index.unjoin(slot, message))&&+
+            }
+
+            fn ping(&self, slot: &Slot, message: &mut Message) {
+                $(self.$index.ping(slot, message);)+
+            }
+
+            fn emit(&self, slot: &Slot, message: &mut Message) -> bool {
+                $(self.$index.emit(slot, message))&&+
+            }
+
+            fn push(&self, slot: &Slot, message: &mut Message) -> bool {
+                $(self.$index.push(slot, message))&&+
+            }
+
+            fn kill(&self, slot: &Slot, message: &mut Message) -> bool {
+                $(self.$index.kill(slot, message))&&+
+            }
+
+            fn lookup(&self, slot: &Slot, message: &mut Message) -> bool {
+                $(self.$index.lookup(slot, message))&&+
+            }
+
+            fn query(&self, switch: &Switch, token: usize, message: &mut Message) {
+                $(self.$index.query(switch, token, message);)+
+            }
+
+            fn custom(&self, switch: &Switch, token: usize, message: &mut Message) {
+                $(self.$index.custom(switch, token, message);)+
+            }
+
+            fn ctrl(&self, switch: &mut Switch, token: usize, message: &mut Message) {
+                $(self.$index.ctrl(switch, token, message);)+
+            }
+
+            fn start(&self, switch: &mut Switch) {
+                $(self.$index.start(switch);)+
+            }
+
+            fn stop(&self, switch: &Switch) {
+                $(self.$index.stop(switch);)+
+            }
+        }
+    };
+}
+
+tuple_hook!(A 0, B 1);
+tuple_hook!(A 0, B 1, C 2);
+tuple_hook!(A 0, B 1, C 2, D 3);
+tuple_hook!(A 0, B 1, C 2, D 3, E 4);
+tuple_hook!(A 0, B 1, C 2, D 3, E 4, F 5);
@@ -0,0 +1,321 @@
+use nson::{Message, MessageId, Value, Array};
+
+use crate::dict::*;
+use crate::error::{Result, Error};
+use crate::util::message::{match_attr, match_chan};
+
+// 消息转换和路由规则，在 Switch 中转发消息之前执行
+//
+// 规则文件示例（TOML）：
+//
+// [[rules]]
+// chans = ["orders"]
+// actions = [{ op = "publish", chan = "audit/orders" }]
+//
+// [[rules]]
+// chans = ["orders/*"]
+// fields = { status = ["paid", "refunded"] }
+// actions = [
+//     { op = "remove", fields = ["card"] },
+//     { op = "set", fields = { source = "broker" } }
+// ]
+//
+// [[rules]]
+// chans = ["legacy/order"]
+// actions = [{ op = "route", chan = "orders" }]
+//
+// 规则按顺序执行，所有匹配的规则都会生效，后面的规则匹配的是前面的规则修改之后的消息
+// chans 中可以使用 `*` 通配符，不写时匹配所有 CHAN，系统频道不受规则影响
+// fields 为字段条件，每个字段都必须满足，值为数组时表示满足其中任意一个即可
+//
+// 动作：
+// publish  复制一份消息发送到另一个 CHAN，复制的消息不再经过规则
+//          带有 DELAY 或 DELIVER_AT 时同样延时投递，但会使用新的 ID
+// route    修改消息的 CHAN
+// drop     丢弃消息，之后的规则不再执行
+// set      添加或修改字段
+// remove   移除字段
+// to       设置 TO，只发送给这些 SLOT
+#[derive(Debug, Clone, Default)]
+pub struct Rules {
+    pub rules: Vec<Rule>
+}
+
+#[derive(Debug, Clone)]
+pub struct Rule {
+    pub chans: Vec<String>,
+    pub fields: Message,
+    pub actions: Vec<Action>
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    Publish(String),
+    Route(String),
+    Drop,
+    Set(Message),
+    Remove(Vec<String>),
+    To(Vec<MessageId>)
+}
+
+// 执行规则的结果
+#[derive(Debug, Clone, Default)]
+pub struct Outcome {
+    pub chan: String,
+    // 为空时表示消息被丢弃
+    pub message: Option<Message>,
+    // 需要额外发送的消息，(CHAN, 消息)
+    pub copies: Vec<(String, Message)>
+}
+
+impl Rules {
+    pub fn from_array(array: &Array) -> Result<Self> {
+        let mut rules = Vec::new();
+
+        for item in array.iter() {
+            let item = item.as_message().ok_or_else(|| invalid("rules", "array of table"))?;
+            rules.push(Rule::from_message(item)?);
+        }
+
+        Ok(Rules { rules })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    pub fn apply(&self, mut chan: String, mut message: Message) -> Outcome {
+        let mut copies = Vec::new();
+
+        if chan.starts_with('_') {
+            return Outcome { chan, message: Some(message), copies }
+        }
+
+        for rule in &self.rules {
+            if !rule.matches(&chan, &message) {
+                continue
+            }
+
+            for action in &rule.actions {
+                match action {
+                    Action::Publish(to) => {
+                        let mut copy = message.clone();
+                        copy.insert(CHAN, to);
+
+                        copies.push((to.clone(), copy));
+                    }
+                    Action::Route(to) => {
+                        message.insert(CHAN, to);
+                        chan = to.clone();
+                    }
+                    Action::Drop => {
+                        return Outcome { chan, message: None, copies }
+                    }
+                    Action::Set(fields) => {
+                        for (key, value) in fields {
+                            message.insert(key, value.clone());
+                        }
+                    }
+                    Action::Remove(fields) => {
+                        for key in fields {
+                            message.remove(key);
+                        }
+                    }
+                    Action::To(ids) => {
+                        if ids.len() == 1 {
+                            message.insert(TO, ids[0]);
+                        } else {
+                            let mut to = Array::new();
+
+                            for id in ids {
+                                to.push(*id);
+                            }
+
+                            message.insert(TO, to);
+                        }
+                    }
+                }
+            }
+        }
+
+        Outcome { chan, message: Some(message), copies }
+    }
+}
+
+impl Rule {
+    pub fn from_message(message: &Message) -> Result<Self> {
+        let mut chans = Vec::new();
+
+        match message.get("chans") {
+            Some(value) => {
+                for chan in value.as_array().ok_or_else(|| invalid("chans", "array"))? {
+                    chans.push(chan.as_str().ok_or_else(|| invalid("chans", "array of string"))?.to_string());
+                }
+            }
+            None => chans.push("*".to_string())
+        }
+
+        let fields = match message.get("fields") {
+            Some(value) => value.as_message().ok_or_else(|| invalid("fields", "table"))?.clone(),
+            None => Message::new()
+        };
+
+        let mut actions = Vec::new();
+
+        let array = message.get("actions").and_then(Value::as_array).ok_or_else(|| invalid("actions", "array"))?;
+
+        for item in array {
+            let item = item.as_message().ok_or_else(|| invalid("actions", "array of table"))?;
+            actions.push(Action::from_message(item)?);
+        }
+
+        Ok(Rule {
+            chans,
+            fields,
+            actions
+        })
+    }
+
+    // 字段条件和 SLOT 的属性过滤条件的格式相同
+    pub fn matches(&self, chan: &str, message: &Message) -> bool {
+        self.chans.iter().any(|pattern| match_chan(pattern, chan)) && match_attr(message, &self.fields)
+    }
+}
+
+impl Action {
+    pub fn from_message(message: &Message) -> Result<Self> {
+        let chan = || -> Result<String> {
+            match message.get_str("chan") {
+                Ok(chan) if !chan.is_empty() && !chan.starts_with('_') => Ok(chan.to_string()),
+                _ => Err(invalid("chan", "non-system chan"))
+            }
+        };
+
+        match message.get_str("op") {
+            Ok("publish") => Ok(Action::Publish(chan()?)),
+            Ok("route") => Ok(Action::Route(chan()?)),
+            Ok("drop") => Ok(Action::Drop),
+            Ok("set") => {
+                let fields = message.get_message("fields").map_err(|_| invalid("fields", "table"))?;
+
+                // 不能修改 CHAN 等系统字段
+                if fields.iter().any(|(key, _)| key.starts_with('_')) {
+                    return Err(invalid("fields", "table of non-system fields"))
+                }
+
+                Ok(Action::Set(fields.clone()))
+            }
+            Ok("remove") => {
+                let mut fields = Vec::new();
+
+                for field in message.get_array("fields").map_err(|_| invalid("fields", "array"))?.iter() {
+                    match field.as_str() {
+                        Some(field) if field != CHAN => fields.push(field.to_string()),
+                        _ => return Err(invalid("fields", "array of string"))
+                    }
+                }
+
+                Ok(Action::Remove(fields))
+            }
+            Ok("to") => {
+                let mut ids = Vec::new();
+
+                let to = match message.get("to") {
+                    Some(Value::Array(array)) => array.iter().cloned().collect(),
+                    Some(value) => vec![value.clone()],
+                    None => vec![]
+                };
+
+                for id in to {
+                    match id {
+                        Value::MessageId(id) => ids.push(id),
+                        Value::String(hex) => ids.push(MessageId::with_string(&hex).map_err(|_| invalid("to", "message id"))?),
+                        _ => return Err(invalid("to", "message id or array of message id"))
+                    }
+                }
+
+                if ids.is_empty() {
+                    return Err(invalid("to", "message id or array of message id"))
+                }
+
+                Ok(Action::To(ids))
+            }
+            _ => Err(invalid("op", "publish, route, drop, set, remove or to"))
+        }
+    }
+}
+
+fn invalid(field: &str, expect: &str) -> Error {
+    Error::InvalidData(format!("rules: `{}` must be {}", field, expect))
+}
+
+#[cfg(test)]
+mod tests {
+    use nson::{msg, MessageId};
+
+    use crate::dict::*;
+
+    use super::{Rules, Action};
+
+    #[test]
+    fn test_apply() {
+        let id = MessageId::new();
+
+        let rules = Rules::from_array(&vec![
+            msg!{
+                "chans": ["orders"],
+                "actions": [{"op": "publish", "chan": "audit/orders"}]
+            },
+            msg!{
+                "chans": ["orders", "legacy/*"],
+                "fields": {"status": ["paid", "refunded"]},
+                "actions": [
+                    {"op": "remove", "fields": ["card"]},
+                    {"op": "set", "fields": {"source": "broker"}},
+                    {"op": "route", "chan": "paid"}
+                ]
+            },
+            msg!{
+                "chans": ["paid"],
+                "actions": [{"op": "to", "to": id.to_hex()}]
+            },
+            msg!{
+                "chans": ["spam"],
+                "actions": [{"op": "drop"}, {"op": "publish", "chan": "never"}]
+            }
+        ].into()).unwrap();
+
+        assert!(rules.rules[2].actions == vec![Action::To(vec![id])]);
+
+        let outcome = rules.apply("orders".to_string(), msg!{CHAN: "orders", "status": "paid", "card": "1234"});
+
+        assert!(outcome.chan == "paid");
+        assert!(outcome.copies.len() == 1);
+        assert!(outcome.copies[0].0 == "audit/orders");
+        assert!(outcome.copies[0].1.get_str("card").unwrap() == "1234");
+
+        let message = outcome.message.unwrap();
+        assert!(message.get_str(CHAN).unwrap() == "paid");
+        assert!(message.get_str("source").unwrap() == "broker");
+        assert!(message.get_message_id(TO).unwrap() == &id);
+        assert!(!message.contains_key("card"));
+
+        // 字段条件不满足
+        let outcome = rules.apply("orders".to_string(), msg!{CHAN: "orders", "status": "new", "card": "1234"});
+        assert!(outcome.chan == "orders");
+        assert!(outcome.message.unwrap().contains_key("card"));
+
+        let outcome = rules.apply("spam".to_string(), msg!{CHAN: "spam"});
+        assert!(outcome.message.is_none());
+        assert!(outcome.copies.is_empty());
+
+        // 系统频道不受影响
+        let outcome = rules.apply(AUDIT.to_string(), msg!{CHAN: AUDIT, "status": "paid"});
+        assert!(outcome.message.unwrap().get_str(CHAN).unwrap() == AUDIT);
+
+        assert!(Rules::from_array(&vec![msg!{"actions": [{"op": "route", "chan": "_ah"}]}].into()).is_err());
+        assert!(Rules::from_array(&vec![msg!{"actions": [{"op": "set", "fields": {CHAN: "a"}}]}].into()).is_err());
+        assert!(Rules::from_array(&vec![msg!{"actions": [{"op": "rename"}]}].into()).is_err());
+        assert!(Rules::from_array(&vec![msg!{"chans": ["a"]}].into()).is_err());
+    }
+}
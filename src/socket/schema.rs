@@ -0,0 +1,216 @@
+use nson::{Message, Value};
+
+use crate::error::{Result, Error};
+
+// 消息结构，用于在 Switch 中校验某个 CHAN 的消息
+//
+// 示例：
+//
+// {
+//     "temp": "f64",
+//     "unit": "string?",
+//     "meta": {
+//         "sensor": "message_id"
+//     }
+// }
+//
+// 字段类型后加 `?` 表示可选，字段值为 Message 时表示嵌套的结构
+// 只校验 schema 中列出的字段，其他字段不做限制
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schema {
+    pub fields: Vec<Field>
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Field {
+    pub name: String,
+    pub kind: Kind,
+    pub optional: bool
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Kind {
+    Any,
+    Null,
+    Bool,
+    String,
+    I32,
+    I64,
+    U32,
+    U64,
+    F32,
+    F64,
+    // 任意整数
+    Int,
+    // 任意数字
+    Number,
+    Binary,
+    Array,
+    Message,
+    MessageId,
+    TimeStamp,
+    Struct(Schema)
+}
+
+impl Schema {
+    pub fn from_message(message: &Message) -> Result<Schema> {
+        let mut fields = Vec::new();
+
+        for (name, value) in message {
+            let field = match value {
+                Value::String(kind) => {
+                    let (kind, optional) = match kind.strip_suffix('?') {
+                        Some(kind) => (kind, true),
+                        None => (kind.as_str(), false)
+                    };
+
+                    Field {
+                        name: name.to_owned(),
+                        kind: Kind::from_name(kind).ok_or_else(|| {
+                            Error::InvalidData(format!("schema: unknown type `{}` of `{}`", kind, name))
+                        })?,
+                        optional
+                    }
+                }
+                Value::Message(message) => {
+                    Field {
+                        name: name.to_owned(),
+                        kind: Kind::Struct(Schema::from_message(message)?),
+                        optional: false
+                    }
+                }
+                _ => return Err(Error::InvalidData(format!("schema: `{}` must be a string or a table", name)))
+            };
+
+            fields.push(field);
+        }
+
+        Ok(Schema { fields })
+    }
+
+    // 校验失败时，返回错误描述
+    pub fn validate(&self, message: &Message) -> std::result::Result<(), String> {
+        for field in &self.fields {
+            match message.get(&field.name) {
+                Some(value) => {
+                    if let Kind::Struct(schema) = &field.kind {
+                        match value {
+                            Value::Message(message) => {
+                                schema.validate(message).map_err(|err| format!("{}.{}", field.name, err))?
+                            }
+                            _ => return Err(format!("{}: expect message", field.name))
+                        }
+                    } else if !field.kind.matches(value) {
+                        return Err(format!("{}: expect {}", field.name, field.kind.as_str()))
+                    }
+                }
+                None => {
+                    if !field.optional {
+                        return Err(format!("{}: missing", field.name))
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Kind {
+    fn from_name(s: &str) -> Option<Kind> {
+        let kind = match s {
+            "any" => Kind::Any,
+            "null" => Kind::Null,
+            "bool" => Kind::Bool,
+            "string" => Kind::String,
+            "i32" => Kind::I32,
+            "i64" => Kind::I64,
+            "u32" => Kind::U32,
+            "u64" => Kind::U64,
+            "f32" => Kind::F32,
+            "f64" => Kind::F64,
+            "int" => Kind::Int,
+            "number" => Kind::Number,
+            "binary" => Kind::Binary,
+            "array" => Kind::Array,
+            "message" => Kind::Message,
+            "message_id" => Kind::MessageId,
+            "timestamp" => Kind::TimeStamp,
+            _ => return None
+        };
+
+        Some(kind)
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Kind::Any => "any",
+            Kind::Null => "null",
+            Kind::Bool => "bool",
+            Kind::String => "string",
+            Kind::I32 => "i32",
+            Kind::I64 => "i64",
+            Kind::U32 => "u32",
+            Kind::U64 => "u64",
+            Kind::F32 => "f32",
+            Kind::F64 => "f64",
+            Kind::Int => "int",
+            Kind::Number => "number",
+            Kind::Binary => "binary",
+            Kind::Array => "array",
+            Kind::Message | Kind::Struct(_) => "message",
+            Kind::MessageId => "message_id",
+            Kind::TimeStamp => "timestamp"
+        }
+    }
+
+    pub fn matches(&self, value: &Value) -> bool {
+        matches!(
+            (self, value),
+            (Kind::Any, _) |
+            (Kind::Null, Value::Null) |
+            (Kind::Bool, Value::Bool(_)) |
+            (Kind::String, Value::String(_)) |
+            (Kind::I32, Value::I32(_)) |
+            (Kind::I64, Value::I64(_)) |
+            (Kind::U32, Value::U32(_)) |
+            (Kind::U64, Value::U64(_)) |
+            (Kind::F32, Value::F32(_)) |
+            (Kind::F64, Value::F64(_)) |
+            (Kind::Int, Value::I32(_) | Value::I64(_) | Value::U32(_) | Value::U64(_)) |
+            (Kind::Number, Value::I32(_) | Value::I64(_) | Value::U32(_) | Value::U64(_) | Value::F32(_) | Value::F64(_)) |
+            (Kind::Binary, Value::Binary(_)) |
+            (Kind::Array, Value::Array(_)) |
+            (Kind::Message | Kind::Struct(_), Value::Message(_)) |
+            (Kind::MessageId, Value::MessageId(_)) |
+            (Kind::TimeStamp, Value::TimeStamp(_))
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nson::{msg, MessageId};
+
+    use super::Schema;
+
+    #[test]
+    fn test_validate() {
+        let schema = Schema::from_message(&msg!{
+            "temp": "number",
+            "unit": "string?",
+            "meta": {
+                "sensor": "message_id"
+            }
+        }).unwrap();
+
+        assert!(schema.validate(&msg!{"temp": 1.5, "meta": {"sensor": MessageId::new()}}).is_ok());
+        assert!(schema.validate(&msg!{"temp": 1, "unit": "c", "meta": {"sensor": MessageId::new()}, "other": 1}).is_ok());
+        assert!(schema.validate(&msg!{"temp": "1", "meta": {"sensor": MessageId::new()}}).is_err());
+        assert!(schema.validate(&msg!{"temp": 1, "unit": 1, "meta": {"sensor": MessageId::new()}}).is_err());
+        assert!(schema.validate(&msg!{"temp": 1, "meta": {"sensor": "x"}}).unwrap_err() == "meta.sensor: expect message_id");
+        assert!(schema.validate(&msg!{"meta": {"sensor": MessageId::new()}}).unwrap_err() == "temp: missing");
+
+        assert!(Schema::from_message(&msg!{"temp": "float"}).is_err());
+    }
+}
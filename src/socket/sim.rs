@@ -0,0 +1,166 @@
+use std::collections::HashMap;
+
+use queen_io::epoll::Epoll;
+
+use nson::{Message, MessageId};
+
+use crate::Wire;
+use crate::dict::BREAK_LOST;
+use crate::error::{Result, Error, ErrorInfo};
+
+use super::{Hook, Switch};
+
+// 单线程、逐步驱动的 Switch，用于确定性的测试
+//
+// 不启动线程，也不依赖系统时间：
+// send 会立即交给 Switch 处理，处理完成后，各个 SLOT 收到的消息可以通过 recv 查看；
+// advance 推进虚拟时钟，每秒调用一次 Switch::tick，延时消息、心跳超时等定时行为因此可以精确控制；
+// 随机数使用固定的种子，SHARE 等随机选择的结果可以重现。
+//
+// let mut sim = Sim::new(MessageId::new(), ())?;
+//
+// let a = sim.connect(MessageId::new(), false, msg!{})?;
+// let b = sim.connect(MessageId::new(), false, msg!{})?;
+//
+// sim.send(b, msg!{CHAN: ATTACH, VALUE: "hello"})?;
+// sim.send(a, msg!{CHAN: "hello", DELAY: 5})?;
+//
+// sim.advance(5)?;
+//
+// let message = sim.recv(b).unwrap();
+pub struct Sim<H: Hook> {
+    epoll: Epoll,
+    hook: H,
+    switch: Switch,
+    // SLOT 的 token，客户端一侧的 wire
+    wires: HashMap<usize, Wire<Message>>,
+    capacity: usize
+}
+
+impl<H: Hook> Sim<H> {
+    // 虚拟时钟的起始时间
+    pub const EPOCH: u64 = 1_600_000_000;
+    pub const DEFAULT_SEED: u64 = 0;
+
+    pub fn new(socket_id: MessageId, hook: H) -> Result<Self> {
+        Sim::with_seed(socket_id, hook, Self::DEFAULT_SEED)
+    }
+
+    pub fn with_seed(socket_id: MessageId, hook: H, seed: u64) -> Result<Self> {
+        let mut switch = Switch::new(socket_id);
+        switch.set_clock(Self::EPOCH);
+        switch.set_seed(seed);
+
+        hook.start(&mut switch);
+
+        Ok(Sim {
+            epoll: Epoll::new()?,
+            hook,
+            switch,
+            wires: HashMap::new(),
+            capacity: 1024
+        })
+    }
+
+    // 之后连接的 SLOT 的 wire 容量，默认 1024
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+    }
+
+    pub fn switch(&self) -> &Switch {
+        &self.switch
+    }
+
+    pub fn switch_mut(&mut self) -> &mut Switch {
+        &mut self.switch
+    }
+
+    pub fn hook(&self) -> &H {
+        &self.hook
+    }
+
+    pub fn now(&self) -> u64 {
+        self.switch.now()
+    }
+
+    // 连接一个 SLOT，返回其 token
+    pub fn connect(&mut self, slot_id: MessageId, root: bool, attr: Message) -> Result<usize> {
+        let (wire1, wire2) = Wire::pipe(self.capacity, attr)?;
+
+        self.switch.add_slot(&self.epoll, &self.hook, slot_id, root, wire1)?;
+
+        let ret = wire2.recv()?;
+
+        if let Some(info) = ErrorInfo::get(&ret) {
+            return Err(info.into())
+        }
+
+        let token = self.token(&slot_id).ok_or_else(|| Error::NotFound(format!("slot {}", slot_id)))?;
+
+        self.wires.insert(token, wire2);
+
+        Ok(token)
+    }
+
+    pub fn token(&self, slot_id: &MessageId) -> Option<usize> {
+        self.switch.slot_ids.get(slot_id).copied()
+    }
+
+    // SLOT 发送一条消息，Switch 立即处理
+    pub fn send(&mut self, token: usize, message: Message) -> Result<()> {
+        if !self.switch.slots.contains(token) {
+            return Err(Error::NotFound(format!("slot token {}", token)))
+        }
+
+        self.switch.recv_message(&self.epoll, &self.hook, token, message)
+    }
+
+    // SLOT 收到的下一条消息
+    pub fn recv(&self, token: usize) -> Option<Message> {
+        self.wires.get(&token).and_then(|wire| wire.recv().ok())
+    }
+
+    // SLOT 收到的所有消息
+    pub fn drain(&self, token: usize) -> Vec<Message> {
+        let mut messages = Vec::new();
+
+        while let Some(message) = self.recv(token) {
+            messages.push(message);
+        }
+
+        messages
+    }
+
+    // SLOT 是否已经被断开，比如被 SLOT_KILL
+    pub fn is_closed(&self, token: usize) -> bool {
+        match self.wires.get(&token) {
+            Some(wire) => wire.is_close(),
+            None => true
+        }
+    }
+
+    // SLOT 意外断开
+    pub fn disconnect(&mut self, token: usize) -> Result<()> {
+        self.wires.remove(&token);
+
+        self.switch.del_slot(&self.epoll, &self.hook, token, BREAK_LOST)
+    }
+
+    // 推进虚拟时钟，每秒调用一次 Switch::tick
+    pub fn advance(&mut self, secs: u32) -> Result<()> {
+        for _ in 0..secs {
+            let now = self.switch.now();
+            self.switch.set_clock(now + 1);
+
+            self.switch.tick(&self.epoll, &self.hook)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<H: Hook> Drop for Sim<H> {
+    fn drop(&mut self) {
+        self.hook.stop(&self.switch);
+    }
+}
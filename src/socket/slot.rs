@@ -15,8 +15,22 @@ pub struct Slot {
     pub joined: bool,
     pub chans: HashSet<String>,
     pub share_chans: HashSet<String>,
+    // (CHAN, GROUP)
+    pub groups: HashSet<(String, String)>,
+    // 作为消费者加入的工作队列
+    pub queues: HashSet<String>,
     pub bind: HashSet<usize>,
     pub bound: HashSet<usize>,
+    // 遗嘱消息，SLOT 意外断开时发布
+    pub will: Option<Message>,
+    // 遗嘱随 ATTACH 注册时，DETACH 该 CHAN 会取消遗嘱
+    pub will_chan: Option<String>,
+    // 在线状态的订阅，属性过滤条件
+    pub watches: Vec<Message>,
+    // 心跳间隔（秒），超过这个时间没有收到 SLOT 的任何消息时断开
+    pub heartbeat: Option<u32>,
+    // 最后一次收到 SLOT 消息的时间，UNIX 时间戳（秒）
+    pub active_at: u64,
     pub wire: Wire<Message>
 }
 
@@ -29,8 +43,15 @@ impl Slot {
             joined: false,
             chans: HashSet::new(),
             share_chans: HashSet::new(),
+            groups: HashSet::new(),
+            queues: HashSet::new(),
             bind: HashSet::new(),
             bound: HashSet::new(),
+            will: None,
+            will_chan: None,
+            watches: Vec::new(),
+            heartbeat: None,
+            active_at: 0,
             wire
         }
     }
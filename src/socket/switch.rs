@@ -1,5 +1,6 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::cell::Cell;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use queen_io::{
     epoll::{Epoll, Token, Ready, EpollOpt},
@@ -9,23 +10,31 @@ use queen_io::{
 use nson::{
     Message, msg,
     message_id::MessageId,
-    Array
+    Array, Value
 };
 
 use rand::{SeedableRng, seq::SliceRandom, rngs::SmallRng};
 
 use crate::Wire;
 use crate::dict::*;
-use crate::error::{Code, Result};
+use crate::error::{Code, ErrorInfo, Result};
+use crate::timer::wheel::Wheel;
+use crate::util::trace;
+use crate::util::message::match_attr;
 
 use super::Hook;
 use super::Slot;
+use super::{Group, Strategy, Schema, Dedup, DedupConfig, WorkQueue, Rules};
 
 pub struct Switch {
     pub socket_id: MessageId,
     // CHAN，Token
     pub chans: HashMap<String, HashSet<usize>>,
     pub share_chans: HashMap<String, HashSet<usize>>,
+    // 消费组，CHAN，GROUP
+    pub groups: HashMap<String, HashMap<String, Group>>,
+    // 消息结构，CHAN，Schema
+    pub schemas: HashMap<String, Schema>,
     // SLOT_ID，Token
     pub slot_ids: HashMap<MessageId, usize>,
     // SOCKET_ID, Token
@@ -33,22 +42,264 @@ pub struct Switch {
     pub slots: Slab<Slot>,
     pub send_num: Cell<usize>,
     pub recv_num: Cell<usize>,
-    rand: SmallRng
+    // 延时消息，ID，Scheduled
+    pub scheduled: HashMap<MessageId, Scheduled>,
+    // 持久频道，CHAN，Durable
+    pub durables: HashMap<String, Durable>,
+    // 工作队列，CHAN，WorkQueue
+    pub queues: HashMap<String, WorkQueue>,
+    // 聚合请求，ID，Gather
+    pub gathers: HashMap<MessageId, Gather>,
+    // 消息转换和路由规则
+    pub rules: Rules,
+    wheel: Wheel<(MessageId, usize)>,
+    time_id_counter: usize,
+    rand: SmallRng,
+    // 虚拟时钟，UNIX 时间戳（秒），为空时使用系统时间，用于模拟测试
+    clock: Option<u64>,
+    // 默认的心跳间隔（秒），SLOT 没有指定时使用
+    heartbeat: Option<u32>,
+    // 消息去重，为空时不去重
+    pub dedup: Option<Dedup>
+}
+
+// 延时消息
+#[derive(Debug, Clone)]
+pub struct Scheduled {
+    pub token: usize,
+    pub slot_id: MessageId,
+    pub chan: String,
+    // 投递时间，UNIX 时间戳（秒）
+    pub deliver_at: u64,
+    pub message: Message,
+    time_id: usize
+}
+
+// 持久频道
+// 没有订阅者时，消息会暂存起来，等到有 SLOT ATTACH 时再投递给它
+// 超过容量时丢弃最早的消息
+#[derive(Debug, Clone)]
+pub struct Durable {
+    pub capacity: usize,
+    pub messages: VecDeque<Message>
+}
+
+impl Durable {
+    pub fn new(capacity: usize) -> Self {
+        Durable {
+            capacity,
+            messages: VecDeque::new()
+        }
+    }
+
+    fn push(&mut self, message: Message) {
+        if self.capacity == 0 {
+            return
+        }
+
+        if self.messages.len() >= self.capacity {
+            self.messages.pop_front();
+        }
+
+        self.messages.push_back(message);
+    }
+}
+
+// 聚合请求，等待目标 SLOT 的回复
+#[derive(Debug, Clone)]
+pub struct Gather {
+    // 发起请求的 SLOT
+    pub token: usize,
+    pub slot_id: MessageId,
+    pub chan: String,
+    // 还没有回复的目标，SLOT_ID
+    pub waiting: Vec<MessageId>,
+    pub replies: Vec<Message>,
+    // 收到这么多回复时提前返回
+    pub count: usize,
+    // 截止时间，UNIX 时间戳（秒）
+    pub deadline: u64,
+    time_id: usize
+}
+
+impl Gather {
+    fn is_done(&self) -> bool {
+        self.replies.len() >= self.count || self.waiting.is_empty()
+    }
 }
 
 impl Switch {
+    // 聚合请求默认的超时时间（秒）
+    pub const DEFAULT_GATHER_TIMEOUT: u64 = 5;
+
     pub(crate) fn new(socket_id: MessageId) -> Self {
         Self {
             socket_id,
             chans: HashMap::new(),
             share_chans: HashMap::new(),
+            groups: HashMap::new(),
+            schemas: HashMap::new(),
             slot_ids: HashMap::new(),
             socket_ids: HashMap::new(),
             slots: Slab::new(),
             send_num: Cell::new(0),
             recv_num: Cell::new(0),
-            rand: SmallRng::from_entropy()
+            scheduled: HashMap::new(),
+            durables: HashMap::new(),
+            queues: HashMap::new(),
+            gathers: HashMap::new(),
+            rules: Rules::default(),
+            wheel: Wheel::default(),
+            time_id_counter: 0,
+            rand: SmallRng::from_entropy(),
+            clock: None,
+            heartbeat: None,
+            dedup: None
+        }
+    }
+
+    // 当前时间，UNIX 时间戳（秒）
+    pub fn now(&self) -> u64 {
+        self.clock.unwrap_or_else(now_secs)
+    }
+
+    pub(crate) fn set_clock(&mut self, now: u64) {
+        self.clock = Some(now);
+    }
+
+    // 固定随机数种子，使 SHARE 等随机选择的结果可以重现
+    pub(crate) fn set_seed(&mut self, seed: u64) {
+        self.rand = SmallRng::seed_from_u64(seed);
+    }
+
+    // 设置默认的心跳间隔，只对之后连接的 SLOT 生效
+    pub fn set_heartbeat(&mut self, heartbeat: Option<u32>) {
+        self.heartbeat = heartbeat;
+    }
+
+    // 开启消息去重，带有 ID 的消息在时间窗口内重复发送时会被丢弃
+    // 重新设置时会清空已经记住的 ID
+    pub fn set_dedup(&mut self, config: Option<DedupConfig>) {
+        self.dedup = config.map(Dedup::new);
+    }
+
+    // 设置消息转换和路由规则，替换之前的规则
+    pub fn set_rules(&mut self, rules: Rules) {
+        self.rules = rules;
+    }
+
+    // 设置某个 CHAN 的消息结构，发送到该 CHAN 的消息不符合时，会返回 SchemaMismatch
+    pub fn set_schema(&mut self, chan: impl Into<String>, schema: Schema) {
+        self.schemas.insert(chan.into(), schema);
+    }
+
+    pub fn remove_schema(&mut self, chan: &str) -> Option<Schema> {
+        self.schemas.remove(chan)
+    }
+
+    // 设置持久频道，已经暂存的消息会保留
+    pub fn set_durable(&mut self, chan: impl Into<String>, capacity: usize) {
+        let durable = self.durables.entry(chan.into()).or_insert_with(|| Durable::new(capacity));
+        durable.capacity = capacity;
+
+        while durable.messages.len() > capacity {
+            durable.messages.pop_front();
+        }
+    }
+
+    pub fn remove_durable(&mut self, chan: &str) -> Option<Durable> {
+        self.durables.remove(chan)
+    }
+
+    // 设置工作队列，发送到该 CHAN 的消息只投递给一个消费者，已经暂存的消息会保留
+    // 之后 ATTACH 该 CHAN 的 SLOT 会作为消费者加入队列
+    pub fn set_queue(&mut self, chan: impl Into<String>, capacity: usize) {
+        self.queues.entry(chan.into()).or_insert_with(|| WorkQueue::new(capacity)).capacity = capacity;
+    }
+
+    // 移除工作队列，暂存和未确认的消息随队列返回
+    pub fn remove_queue(&mut self, chan: &str) -> Option<WorkQueue> {
+        let queue = self.queues.remove(chan)?;
+
+        for consumer in &queue.consumers {
+            if let Some(slot) = self.slots.get_mut(consumer.token) {
+                slot.queues.remove(chan);
+            }
+        }
+
+        Some(queue)
+    }
+
+    pub fn metrics(&self) -> Message {
+        let durable: usize = self.durables.values().map(|durable| durable.messages.len()).sum();
+
+        msg!{
+            "socket_id": self.socket_id,
+            "slots": self.slots.len() as u64,
+            "root_slots": self.slots.iter().filter(|(_, slot)| slot.root).count() as u64,
+            "chans": self.chans.len() as u64,
+            "share_chans": self.share_chans.len() as u64,
+            "groups": self.groups.values().map(|groups| groups.len()).sum::<usize>() as u64,
+            "scheduled": self.scheduled.len() as u64,
+            "durable": durable as u64,
+            "queued": self.queues.values().map(|queue| queue.len()).sum::<usize>() as u64,
+            "unacked": self.queues.values().map(|queue| queue.unacked.len()).sum::<usize>() as u64,
+            "gathers": self.gathers.len() as u64,
+            "dedup_ids": self.dedup.as_ref().map(|dedup| dedup.len()).unwrap_or(0) as u64,
+            "duplicates": self.dedup.as_ref().map(|dedup| dedup.duplicates()).unwrap_or(0) as u64,
+            "send_num": self.send_num.get() as u64,
+            "recv_num": self.recv_num.get() as u64
+        }
+    }
+
+    fn next_time_id(&mut self) -> usize {
+        self.time_id_counter = self.time_id_counter.wrapping_add(1);
+        self.time_id_counter
+    }
+
+    // 每秒调用一次，投递到期的延时消息，返回超时的聚合请求，断开心跳超时的 SLOT，遗忘过期的去重 ID
+    pub(crate) fn tick(&mut self, epoll: &Epoll, hook: &impl Hook) -> Result<()> {
+        for (id, time_id) in self.wheel.tick() {
+            if self.gathers.get(&id).map(|g| g.time_id) == Some(time_id) {
+                self.finish_gather(hook, &id);
+
+                continue
+            }
+
+            if self.scheduled.get(&id).map(|s| s.time_id) != Some(time_id) {
+                continue
+            }
+
+            if let Some(scheduled) = self.scheduled.remove(&id) {
+                // 发送者可能已经断开，SLOT 的 token 也可能已经被复用
+                let from = self.slots.get(scheduled.token)
+                    .filter(|slot| slot.id == scheduled.slot_id)
+                    .map(|slot| slot.token);
+
+                self.route_message(hook, from, scheduled.chan, scheduled.message);
+            }
+        }
+
+        let now = self.now();
+
+        if let Some(dedup) = &mut self.dedup {
+            dedup.expire(now);
+        }
+
+        let dead: Vec<usize> = self.slots.iter()
+            .filter(|(_, slot)| {
+                slot.heartbeat.map(|heartbeat| now.saturating_sub(slot.active_at) > u64::from(heartbeat)).unwrap_or(false)
+            })
+            .map(|(token, _)| token)
+            .collect();
+
+        for token in dead {
+            log::debug!("slot heartbeat timeout: {}", self.slots[token].id);
+
+            self.del_slot(epoll, hook, token, BREAK_HEARTBEAT)?;
         }
+
+        Ok(())
     }
 
     pub(crate) fn add_slot(
@@ -65,16 +316,72 @@ impl Switch {
             return Ok(())
         }
 
+        let now = self.now();
+
         let entry = self.slots.vacant_entry();
         let token = entry.key();
 
-        let slot = Slot::new(token, id, root, wire);
+        let mut slot = Slot::new(token, id, root, wire);
 
         // 此处可以验证一下 SLOT 的属性，不过目前只能验证 wire.attr
         // 并且，wire.attr 是可以修改的
         // 但是，SLOT 的属性是不能在这里修改的
         let success = hook.accept(&slot);
 
+        // 握手时注册的遗嘱，Socket::connect 时在 attr 中，经过 Node 时在 attr.ORIGIN 中
+        // {
+        //     WILL: {
+        //         CHAN: $chan,
+        //         ...
+        //     }
+        // }
+        if success {
+            let will = {
+                let attr = slot.wire.attr();
+
+                attr.get(WILL).cloned()
+                    .or_else(|| attr.get_message(ORIGIN).ok().and_then(|origin| origin.get(WILL).cloned()))
+            };
+
+            if let Some(will) = will {
+                match check_will(hook, &slot, &will) {
+                    Ok(will) => slot.will = will,
+                    Err(code) => {
+                        let _ = slot.wire.send(msg!{CODE: code.code()});
+
+                        return Ok(())
+                    }
+                }
+            }
+
+            // 心跳间隔，同样可以在 attr 或 attr.ORIGIN 中
+            // 超过心跳间隔没有收到任何消息时断开，通过 Node 连接的 SLOT，网络层的 KEEP_ALIVE 也算作心跳，
+            // 因此 KeepAlive 的 idle 应当小于心跳间隔，否则客户端需要定时发送 PING
+            // {
+            //     HEARTBEAT: $secs
+            // }
+            let heartbeat = {
+                let attr = slot.wire.attr();
+
+                attr.get(HEARTBEAT).cloned()
+                    .or_else(|| attr.get_message(ORIGIN).ok().and_then(|origin| origin.get(HEARTBEAT).cloned()))
+            };
+
+            match heartbeat {
+                Some(value) => match to_secs(&value).filter(|secs| *secs > 0 && *secs <= u64::from(u32::MAX)) {
+                    Some(secs) => slot.heartbeat = Some(secs as u32),
+                    None => {
+                        let _ = slot.wire.send(msg!{CODE: Code::InvalidHeartbeatFieldType.code()});
+
+                        return Ok(())
+                    }
+                },
+                None => slot.heartbeat = self.heartbeat
+            }
+
+            slot.active_at = now;
+        }
+
         if success && slot.wire.send(msg!{CODE: Code::Ok.code()}) == Ok(()) {
             epoll.add(&slot.wire, Token(token), Ready::readable(), EpollOpt::level())?;
 
@@ -100,6 +407,8 @@ impl Switch {
             entry.insert(slot);
 
             self.relay_root_message(hook, token, SLOT_READY, event_message);
+
+            self.presence(hook, &self.slots[token], "online");
         } else {
             let _ = slot.wire.send(msg!{CODE: Code::AuthenticationFailed.code()});
         }
@@ -107,17 +416,34 @@ impl Switch {
         Ok(())
     }
 
+    // 通知所有 SLOT 即将关闭
+    pub(crate) fn shutdown(&self) {
+        for (_, slot) in self.slots.iter() {
+            let _ = slot.wire.send(msg!{CHAN: CLOSE, REASON: BREAK_SHUTDOWN});
+        }
+    }
+
+    // reason 为断开的原因，比如 BREAK_CLOSED、BREAK_LOST、BREAK_KILLED、BREAK_HEARTBEAT
+    // BREAK_CLOSED 表示 SLOT 发送 CLOSE 主动关闭，BREAK_LOST 表示连接意外断开
     pub(crate) fn del_slot(
         &mut self,
         epoll: &Epoll,
         hook: &impl Hook,
-        token: usize
+        token: usize,
+        reason: &str
     ) -> Result<()> {
         if self.slots.contains(token) {
             let slot = self.slots.remove(token);
             // slot.wire.close(); 这里不需要主动关闭，离开作用域后会自动关闭
             epoll.delete(&slot.wire)?;
 
+            // 关闭前告知 SLOT 断开的原因，这是 SLOT 收到的最后一条消息
+            // {
+            //     CHAN: CLOSE,
+            //     REASON: $reason
+            // }
+            let _ = slot.wire.send(msg!{CHAN: CLOSE, REASON: reason});
+
             for chan in &slot.chans {
                 if let Some(ids) = self.chans.get_mut(chan) {
                     ids.remove(&token);
@@ -134,15 +460,42 @@ impl Switch {
                     ids.remove(&token);
 
                     if ids.is_empty() {
-                        self.chans.remove(chan);
+                        self.share_chans.remove(chan);
                     }
                 }
             }
 
+            // 移除消费组
+            for (chan, group) in &slot.groups {
+                self.leave_group(hook, token, slot.id, chan, group);
+            }
+
+            // 离开工作队列，未确认的消息重新投递给其他消费者
+            for chan in &slot.queues {
+                if let Some(queue) = self.queues.get_mut(chan) {
+                    queue.unsubscribe(token);
+                }
+
+                self.dispatch_queue(hook, chan);
+            }
+
             // 这里要记得移除 SLOT_ID，因为 wire 在一开始建立连接时就会默认分配一个
             // 认证成功时可以修改
             self.slot_ids.remove(&slot.id);
 
+            // 聚合请求，发起者断开时丢弃，等待的目标都已断开时提前返回
+            self.gathers.retain(|_, gather| gather.token != token || gather.slot_id != slot.id);
+
+            let ids: Vec<MessageId> = self.gathers.iter()
+                .filter(|(_, gather)| gather.waiting.contains(&slot.id))
+                .filter(|(_, gather)| gather.waiting.iter().all(|id| !self.slot_ids.contains_key(id)))
+                .map(|(id, _)| *id)
+                .collect();
+
+            for id in ids {
+                self.finish_gather(hook, &id);
+            }
+
             // 清除 BIND
             for target_token in &slot.bind {
                 if let Some(target_slot) = self.slots.get_mut(*target_token) {
@@ -152,6 +505,19 @@ impl Switch {
 
             hook.remove(&slot);
 
+            // 遗嘱
+            // 只在意外断开时发布，SLOT 主动关闭和服务关闭时不发布
+            // 和 SLOT 发送的消息一样经过规则、消息结构、去重和延时，FROM 为断开的 SLOT
+            if reason != BREAK_CLOSED && reason != BREAK_SHUTDOWN {
+                if let Some(mut will) = slot.will.clone() {
+                    if let Ok(chan) = will.get_str(CHAN).map(ToOwned::to_owned) {
+                        will.insert(FROM, slot.id);
+
+                        self.publish(hook, token, slot.id, chan, will);
+                    }
+                }
+            }
+
             // 这里发一个事件，表示有 SLOT 断开
             // 注意，只有在 SLOT_READY 和 SLOT_BREAK 这两个事件才会返回
             // SLOT 的 ATTR
@@ -159,16 +525,20 @@ impl Switch {
             // {
             //     CHAN: SLOT_BREAK,
             //     SLOT_ID: $slot_id,
-            //     ATTR: $attr
+            //     ATTR: $attr,
+            //     REASON: $reason
             // }
             let event_message = msg!{
                 CHAN: SLOT_BREAK,
                 SLOT_ID: slot.id,
                 ROOT: slot.root,
-                ATTR: slot.wire.attr().clone()
+                ATTR: slot.wire.attr().clone(),
+                REASON: reason
             };
 
             self.relay_root_message(hook, token, SLOT_BREAK, event_message);
+
+            self.presence(hook, &slot, "offline");
         }
 
         Ok(())
@@ -183,10 +553,36 @@ impl Switch {
     ) -> Result<()> {
         self.recv_num.set(self.recv_num.get() + 1);
 
+        // 收到任何消息都表示 SLOT 还活着
+        let now = self.now();
+        self.slots[token].active_at = now;
+
+        // 网络线程收到 KEEP_ALIVE 时会转发过来，只用于刷新 active_at，不回复
+        if message.get_str(CHAN) == Ok(KEEP_ALIVE) {
+            return Ok(())
+        }
+
+        // 网络线程在连接上追加一跳时发送的 TRACE_HOP 事件，转发给 ATTACH 了 TRACE_HOP 的 ROOT
+        // 客户端通过网络发送的 TRACE_HOP 会被网络线程丢弃
+        if message.get_str(CHAN) == Ok(TRACE_HOP) {
+            self.relay_root_message(hook, token, TRACE_HOP, message);
+
+            return Ok(())
+        }
+
+        // SLOT 主动断开，比如网络线程因为解码失败断开连接
+        // 不经过 Hook.recv，SLOT 总是可以断开自己
+        // 消息中的 REASON 可以由客户端任意填写，不能信任，只使用网络线程等在 wire 上设置的原因
+        if message.get_str(CHAN) == Ok(CLOSE) {
+            let reason = self.slots[token].wire.reason().unwrap_or(BREAK_CLOSED);
+
+            return self.del_slot(epoll, hook, token, reason)
+        }
+
         let success = hook.recv(&self.slots[token], &mut message);
 
         if !success {
-            Code::PermissionDenied.set(&mut message);
+            deny(&mut message);
 
             self.send_message(hook, token, message);
 
@@ -217,7 +613,30 @@ impl Switch {
                 QUERY => self.query(hook, token, message),
                 CUSTOM => self.custom(hook, token, message),
                 CTRL => self.ctrl(hook, token, message),
+                CANCEL => self.cancel(hook, token, message),
+                ACK => self.ack(hook, token, message),
+                GATHER => self.gather(hook, token, message),
+                REPLY => self.reply(hook, token, message),
+                WILL => self.will(hook, token, message),
+                LOOKUP => self.lookup(hook, token, message),
+                WATCH => self.watch(hook, token, message),
+                UNWATCH => self.unwatch(hook, token, message),
                 SLOT_KILL => self.kill(epoll, hook, token, message)?,
+                AUDIT => self.relay_message(hook, token, chan.to_string(), message),
+                // 流式传输只能点对点发送
+                STREAM => match message.get(TO) {
+                    Some(Value::MessageId(_)) => self.relay_message(hook, token, chan.to_string(), message),
+                    Some(_) => {
+                        Code::InvalidToFieldType.set(&mut message);
+
+                        self.send_message(hook, token, message);
+                    }
+                    None => {
+                        Code::CannotGetToField.set(&mut message);
+
+                        self.send_message(hook, token, message);
+                    }
+                },
                 _ => {
                     Code::UnsupportedChan.set(&mut message);
 
@@ -231,19 +650,24 @@ impl Switch {
         Ok(())
     }
 
+    // 返回消息是否已经放入 wire，Hook.send 拒绝或者 wire 已满时返回 false
     pub(crate) fn send_message(
         &self,
         hook: &impl Hook,
         token: usize,
         mut message: Message
-    ) {
+    ) -> bool {
         if let Some(slot) = self.slots.get(token) {
             let success = hook.send(slot, &mut message);
 
             if success && slot.wire.send(message).is_ok() {
                 self.send_num.set(self.send_num.get() + 1);
+
+                return true
             }
         }
+
+        false
     }
 
     fn relay_root_message(
@@ -272,7 +696,6 @@ impl Switch {
         }
     }
 
-    #[allow(clippy::cognitive_complexity)]
     fn relay_message(
         &mut self,
         hook: &impl Hook,
@@ -280,135 +703,700 @@ impl Switch {
         chan: String,
         mut message: Message
     ) {
-        let success = hook.emit(&self.slots[token], &mut message);
+        let slot_id = self.slots[token].id;
+
+        // 只有 ROOT 可以发送审计日志
+        let success = (chan != AUDIT || self.slots[token].root) && hook.emit(&self.slots[token], &mut message);
 
         if !success {
-            Code::PermissionDenied.set(&mut message);
+            deny(&mut message);
+
+            self.trace(hook, token, slot_id, &mut message, "switch.drop");
 
             self.send_message(hook, token, message);
 
             return
         }
 
-        macro_rules! send_bind {
-            ($self: ident, $hook: ident, $slot: ident, $message: ident) => {
-                let success = $hook.push($slot, &mut $message);
+        // 按属性发送相当于先查找 SLOT，同样要经过 Hook.lookup
+        if message.contains_key(TO_ATTR) && !hook.lookup(&self.slots[token], &mut message) {
+            deny(&mut message);
 
-                if success {
-                    $self.send_message($hook, $slot.token, $message.clone());
-                }
-            };
+            self.trace(hook, token, slot_id, &mut message, "switch.drop");
+
+            self.send_message(hook, token, message);
+
+            return
         }
 
-        macro_rules! send {
-            ($self: ident, $hook: ident, $slot: ident, $message: ident) => {
-                let success = $hook.push($slot, &mut $message);
+        self.publish(hook, token, slot_id, chan, message);
+    }
 
-                if success {
-                    let mut message = $message.clone();
+    // 执行规则，校验消息结构，去重，然后投递或者延时投递
+    // SLOT 发送的消息和遗嘱都经过这里，发布遗嘱时 SLOT 已经移除，失败时的回复会被丢弃
+    fn publish(
+        &mut self,
+        hook: &impl Hook,
+        token: usize,
+        slot_id: MessageId,
+        chan: String,
+        mut message: Message
+    ) {
 
-                    if $slot.joined {
-                        // 如果对方已 JOIN，则填充 FROM_SOCKET
-                        if !message.contains_key(FROM_SOCKET) {
-                            message.insert(FROM_SOCKET, self.socket_id);
-                        }
-                    }
+        // 转换和路由规则，先执行规则，消息结构按照最终的 CHAN 校验
+        let outcome = if self.rules.is_empty() {
+            None
+        } else {
+            let mut message = message.clone();
 
-                    $self.send_message($hook, $slot.token, message.clone());
+            if !message.contains_key(FROM) {
+                message.insert(FROM, slot_id);
+            }
 
-                    // BIND
-                    // 此模式可以接收到所 BIND 的 SLOT 发送的消息
-                    let bounds = &$slot.bound;
+            Some(self.rules.apply(chan.clone(), message))
+        };
 
-                    let mut bind_message = msg! {
-                        CHAN: BIND_RECV,
-                        SLOT_ID: $slot.id,
-                        VALUE: message.clone()
-                    };
+        // 校验消息结构，复制的消息按照各自的 CHAN 校验，有一条不通过时都不发送
+        let info = match &outcome {
+            Some(outcome) => outcome.message.iter()
+                .map(|message| (&outcome.chan, message))
+                .chain(outcome.copies.iter().map(|(chan, copy)| (chan, copy)))
+                .find_map(|(chan, message)| self.check_schema(chan, message)),
+            None => self.check_schema(&chan, &message)
+        };
 
-                    for bound in bounds {
-                        if let Some(slot) = self.slots.get(*bound) {
-                            send_bind!(self, hook, slot, bind_message);
-                        }
-                    }
+        if let Some(info) = info {
+            info.set(&mut message);
 
-                    // slot event
-                    // {
-                    //     CHAN: SLOT_RECV,
-                    //     VALUE: $message
-                    // }
-                    let event_message = msg!{
-                        CHAN: SLOT_RECV,
-                        VALUE: message.clone(),
-                        TO: $slot.id
-                    };
+            self.trace(hook, token, slot_id, &mut message, "switch.drop");
 
-                    let id = $slot.token;
+            self.send_message(hook, token, message);
 
-                    $self.relay_root_message($hook, id, SLOT_RECV, event_message);
-                }
-            };
+            return
         }
 
-        if !message.contains_key(FROM) {
-            message.insert(FROM, self.slots[token].id);
-        }
+        // 幂等发布，重复的消息不再投递，告知发送者已经收到
+        // {
+        //     CHAN: $chan,
+        //     ID: $id,
+        //     CODE: 0,
+        //     DUPLICATE: true
+        // }
+        if let Ok(id) = message.get_message_id(ID) {
+            let now = self.now();
 
-        // BIND
-        // 此模式可以接收到所 BIND 的 SLOT 发送的消息
-        let bounds = &self.slots[token].bound;
+            if let Some(dedup) = &mut self.dedup {
+                if dedup.check(&slot_id, &chan, id, now) {
+                    Code::Ok.set(&mut message);
+                    message.insert(DUPLICATE, true);
 
-        let mut bind_message = msg! {
-            CHAN: BIND_SEND,
-            SLOT_ID: self.slots[token].id,
-            VALUE: message.clone()
-        };
+                    self.trace(hook, token, slot_id, &mut message, "switch.drop");
+
+                    self.send_message(hook, token, message);
 
-        for bound in bounds {
-            if let Some(slot) = self.slots.get(*bound) {
-                send_bind!(self, hook, slot, bind_message);
+                    return
+                }
             }
         }
 
-        // TO SOCKET
-        let mut goon = true;
-
-        if let Some(to_socket) = message.get(TO_SOCKET).cloned() {
-            if let Some(to_socket_id) = to_socket.as_message_id() {
-                // 目前 Socket ID 不等于本 Socket ID 时，该功能才生效
-                if to_socket_id != &self.socket_id {
-                    goon = false;
+        if !message.contains_key(FROM) {
+            message.insert(FROM, slot_id);
+        }
 
-                    // 移除 TO
-                    message.remove(TO_SOCKET);
+        // 复制的消息不再经过规则，延时和原消息一样处理
+        let (chan, mut message) = match outcome {
+            None => (chan, message),
+            Some(outcome) => {
+                for (chan, mut copy) in outcome.copies {
+                    if copy.contains_key(DELAY) || copy.contains_key(DELIVER_AT) {
+                        // 延时消息按 ID 保存，复制的消息使用新的 ID，避免覆盖原消息
+                        copy.insert(ID, MessageId::new());
 
-                    if let Some(socket_token) = self.socket_ids.get(to_socket_id) {
-                        if let Some(slot) = self.slots.get(*socket_token) {
-                            send!(self, hook, slot, message);
-                        }
+                        self.schedule(hook, token, slot_id, chan, copy);
+                    } else {
+                        self.route_message(hook, None, chan, copy);
                     }
                 }
-            } else {
-                Code::InvalidToSocketFieldType.set(&mut message);
-
-                self.send_message(hook, token, message);
-
-                return
-            }
-        }
 
-        if goon {
-            // 如果 TO == 本 Socket ID，则移除
-            if let Ok(to) = message.get_message_id(TO) {
-                if to == &self.socket_id {
-                    message.remove(TO);
+                match outcome.message {
+                    Some(message) => (outcome.chan, message),
+                    None => return
                 }
             }
+        };
 
-            // 两种模式下，自己都可以收到自己发送的消息，如果不想处理，可以利用 `FROM` 进行过滤，
-            // 也就是此时 FROM == 自己的 SLOT_ID
+        self.trace(hook, token, slot_id, &mut message, "switch");
 
-            // P2P 的优先级比较高
+        // 延时消息
+        if message.contains_key(DELAY) || message.contains_key(DELIVER_AT) {
+            self.schedule(hook, token, slot_id, chan, message);
+
+            return
+        }
+
+        self.route_message(hook, Some(token), chan, message);
+    }
+
+    // 按照 CHAN 的 SCHEMA 校验消息，不通过时返回错误信息
+    fn check_schema(&self, chan: &str, message: &Message) -> Option<ErrorInfo> {
+        let err = self.schemas.get(chan)?.validate(message).err()?;
+
+        let mut info = ErrorInfo::new(Code::SchemaMismatch);
+
+        // 错误格式为 "$field: $reason"
+        if let Some((field, _)) = err.split_once(": ") {
+            info = info.field(field);
+        }
+
+        Some(info.reason(err))
+    }
+
+    // 消息中带有 TRACE 时，追加一跳，并发送 TRACE_HOP 事件
+    // {
+    //     CHAN: TRACE_HOP,
+    //     TRACE: $traceparent,
+    //     VALUE: $hop,
+    //     HOPS: [$hop, ...]
+    // }
+    fn trace(&self, hook: &impl Hook, token: usize, slot_id: MessageId, message: &mut Message, kind: &str) {
+        if !message.contains_key(TRACE) {
+            return
+        }
+
+        let mut info = msg!{
+            SOCKET_ID: self.socket_id,
+            SLOT_ID: slot_id
+        };
+
+        if let Some(code) = message.get(CODE) {
+            info.insert(CODE, code.clone());
+        }
+
+        if let Some(hop) = trace::hop(message, kind, info) {
+            let event_message = msg!{
+                CHAN: TRACE_HOP,
+                TRACE: message.get(TRACE).cloned().unwrap_or(Value::Null),
+                VALUE: hop,
+                HOPS: message.get(HOPS).cloned().unwrap_or(Value::Null)
+            };
+
+            self.relay_root_message(hook, token, TRACE_HOP, event_message);
+        }
+    }
+
+    // 延时消息，DELAY 为延迟的秒数，DELIVER_AT 为投递时间的 UNIX 时间戳（秒）
+    // 消息中没有 ID 时会自动生成，可以通过 CANCEL 和 ID 取消
+    // 到期后会按照正常的消息进行路由
+    fn schedule(
+        &mut self,
+        hook: &impl Hook,
+        token: usize,
+        slot_id: MessageId,
+        chan: String,
+        mut message: Message
+    ) {
+        let now = self.now();
+
+        let deliver_at = match (message.remove(DELAY), message.remove(DELIVER_AT)) {
+            (Some(delay), None) => to_secs(&delay).and_then(|delay| now.checked_add(delay)),
+            (None, Some(at)) => to_secs(&at),
+            _ => None
+        };
+
+        let deliver_at = match deliver_at {
+            Some(deliver_at) => deliver_at,
+            None => {
+                Code::InvalidDelayFieldType.set(&mut message);
+
+                self.send_message(hook, token, message);
+
+                return
+            }
+        };
+
+        let delay = deliver_at.saturating_sub(now);
+
+        if delay == 0 {
+            self.route_message(hook, Some(token), chan, message);
+
+            return
+        }
+
+        if delay >= u64::from(self.wheel.remaining()) {
+            Code::BadValue.set(&mut message);
+
+            self.send_message(hook, token, message);
+
+            return
+        }
+
+        let id = match message.get_message_id(ID) {
+            Ok(id) => *id,
+            Err(_) => {
+                let id = MessageId::new();
+                message.insert(ID, id);
+                id
+            }
+        };
+
+        // 不能覆盖已有的延时消息，否则可以绕过 CANCEL 的权限检查
+        if self.scheduled.contains_key(&id) {
+            Code::DuplicateId.set(&mut message);
+
+            self.send_message(hook, token, message);
+
+            return
+        }
+
+        let time_id = self.next_time_id();
+
+        self.wheel.insert((id, time_id), delay as u32).expect("can't insert id into wheel");
+
+        self.scheduled.insert(id, Scheduled {
+            token,
+            slot_id,
+            chan,
+            deliver_at,
+            message,
+            time_id
+        });
+    }
+
+    // 聚合请求，把请求投递给 CHAN 的所有订阅者（或者 TO 中的 SLOT），收集回复后一次性返回
+    // {
+    //     CHAN: GATHER,
+    //     VALUE: $chan,
+    //     TO: [$slot_id, ...],
+    //     TIMEOUT: $secs,
+    //     COUNT: $count,
+    //     ID: $id,
+    //     ...
+    // }
+    // TO、TIMEOUT、COUNT、ID 都是可选的，TIMEOUT 默认为 5 秒，COUNT 默认为目标的数量，
+    // 没有 ID 时自动生成。发起者自己不会收到请求
+    //
+    // 目标收到的请求，需要通过 REPLY 回复：
+    // {
+    //     CHAN: $chan,
+    //     ID: $id,
+    //     FROM: $slot_id,
+    //     GATHER: true,
+    //     ...
+    // }
+    fn gather(&mut self, hook: &impl Hook, token: usize, mut message: Message) {
+        macro_rules! fail {
+            ($code: expr) => {
+                $code.set(&mut message);
+
+                self.send_message(hook, token, message);
+
+                return
+            };
+        }
+
+        let chan = match message.get_str(VALUE) {
+            Ok(chan) if chan.starts_with('_') => {
+                fail!(Code::UnsupportedChan);
+            }
+            Ok(chan) => chan.to_string(),
+            Err(_) => {
+                fail!(Code::CannotGetValueField);
+            }
+        };
+
+        let timeout = match message.get(TIMEOUT) {
+            Some(value) => match to_secs(value).filter(|secs| *secs > 0) {
+                Some(timeout) => timeout,
+                None => {
+                    fail!(Code::InvalidTimeoutFieldType);
+                }
+            },
+            None => Self::DEFAULT_GATHER_TIMEOUT
+        };
+
+        if timeout >= u64::from(self.wheel.remaining()) {
+            fail!(Code::BadValue);
+        }
+
+        let count = match message.get(COUNT) {
+            Some(value) => match to_count(value) {
+                Some(count) => Some(count),
+                None => {
+                    fail!(Code::InvalidCountFieldType);
+                }
+            },
+            None => None
+        };
+
+        let id = match message.get_message_id(ID) {
+            Ok(id) => *id,
+            Err(_) => MessageId::new()
+        };
+
+        if self.gathers.contains_key(&id) {
+            fail!(Code::BadValue);
+        }
+
+        let targets: Vec<usize> = match message.get(TO) {
+            Some(Value::MessageId(to)) => self.slot_ids.get(to).copied().into_iter().collect(),
+            Some(Value::Array(array)) => {
+                let mut targets = Vec::new();
+
+                for to in array.iter() {
+                    match to.as_message_id() {
+                        Some(to) => targets.extend(self.slot_ids.get(to)),
+                        None => {
+                            fail!(Code::InvalidToFieldType);
+                        }
+                    }
+                }
+
+                targets
+            }
+            Some(_) => {
+                fail!(Code::InvalidToFieldType);
+            }
+            None => {
+                let mut targets: Vec<usize> = self.chans.get(&chan)
+                    .map(|tokens| tokens.iter().copied().collect())
+                    .unwrap_or_default();
+
+                // HashSet 的顺序不固定，排序后结果可以重现
+                targets.sort_unstable();
+
+                targets
+            }
+        };
+
+        let mut request = message.clone();
+
+        for key in &[VALUE, TO, TIMEOUT, COUNT] {
+            request.remove(key);
+        }
+
+        request.insert(CHAN, &chan);
+        request.insert(ID, id);
+        request.insert(FROM, self.slots[token].id);
+        request.insert(GATHER, true);
+
+        // 这里可以验证该 SLOT 是否有权限向 CHAN 发送消息
+        let success = hook.emit(&self.slots[token], &mut request);
+
+        if !success {
+            deny(&mut message);
+
+            self.send_message(hook, token, message);
+
+            return
+        }
+
+        let mut waiting = Vec::new();
+
+        for target in targets {
+            if target == token {
+                continue
+            }
+
+            if let Some(slot) = self.slots.get(target) {
+                if waiting.contains(&slot.id) {
+                    continue
+                }
+
+                let mut request = request.clone();
+
+                if hook.push(slot, &mut request) {
+                    waiting.push(slot.id);
+
+                    self.send_message(hook, target, request);
+                }
+            }
+        }
+
+        let deadline = self.now() + timeout;
+        let time_id = self.next_time_id();
+
+        let gather = Gather {
+            token,
+            slot_id: self.slots[token].id,
+            chan,
+            count: count.unwrap_or(waiting.len()),
+            waiting,
+            replies: Vec::new(),
+            deadline,
+            time_id
+        };
+
+        let done = gather.is_done();
+
+        self.gathers.insert(id, gather);
+
+        // 没有目标时直接返回
+        if done {
+            self.finish_gather(hook, &id);
+
+            return
+        }
+
+        self.wheel.insert((id, time_id), timeout as u32).expect("can't insert id into wheel");
+    }
+
+    // 回复聚合请求，只有请求的目标可以回复，并且只能回复一次
+    // {
+    //     CHAN: REPLY,
+    //     ID: $id,
+    //     ...
+    // }
+    // 成功时不会收到回应
+    fn reply(&mut self, hook: &impl Hook, token: usize, mut message: Message) {
+        let id = match message.get_message_id(ID) {
+            Ok(id) => *id,
+            Err(_) => {
+                Code::CannotGetIdField.set(&mut message);
+
+                self.send_message(hook, token, message);
+
+                return
+            }
+        };
+
+        let slot_id = self.slots[token].id;
+
+        let gather = match self.gathers.get_mut(&id) {
+            Some(gather) => gather,
+            None => {
+                Code::NotFound.set(&mut message);
+
+                self.send_message(hook, token, message);
+
+                return
+            }
+        };
+
+        let pos = match gather.waiting.iter().position(|id| *id == slot_id) {
+            Some(pos) => pos,
+            None => {
+                Code::NotFound.set(&mut message);
+
+                self.send_message(hook, token, message);
+
+                return
+            }
+        };
+
+        gather.waiting.remove(pos);
+
+        message.remove(CHAN);
+        message.remove(ID);
+        message.insert(FROM, slot_id);
+
+        gather.replies.push(message);
+
+        if gather.is_done() {
+            self.finish_gather(hook, &id);
+        }
+    }
+
+    // 返回聚合的回复，MISSING 为没有回复的目标
+    // {
+    //     CHAN: GATHER,
+    //     ID: $id,
+    //     VALUE: $chan,
+    //     CODE: 0,
+    //     REPLIES: [{FROM: $slot_id, ...}, ...],
+    //     MISSING: [$slot_id, ...]
+    // }
+    fn finish_gather(&mut self, hook: &impl Hook, id: &MessageId) {
+        let gather = match self.gathers.remove(id) {
+            Some(gather) => gather,
+            None => return
+        };
+
+        // 发起者可能已经断开，SLOT 的 token 也可能已经被复用
+        if self.slots.get(gather.token).map(|slot| slot.id != gather.slot_id).unwrap_or(true) {
+            return
+        }
+
+        let mut replies = Array::new();
+
+        for reply in gather.replies {
+            replies.push(reply);
+        }
+
+        let mut missing = Array::new();
+
+        for slot_id in gather.waiting {
+            missing.push(slot_id);
+        }
+
+        let mut message = msg!{
+            CHAN: GATHER,
+            ID: *id,
+            VALUE: gather.chan,
+            REPLIES: replies,
+            MISSING: missing
+        };
+
+        Code::Ok.set(&mut message);
+
+        self.send_message(hook, gather.token, message);
+    }
+
+    // 取消延时消息，只有发送者和 ROOT 可以取消
+    // {
+    //     CHAN: CANCEL,
+    //     ID: $id
+    // }
+    fn cancel(&mut self, hook: &impl Hook, token: usize, mut message: Message) {
+        if let Ok(id) = message.get_message_id(ID).map(ToOwned::to_owned) {
+            let slot = &self.slots[token];
+
+            match self.scheduled.get(&id) {
+                Some(scheduled) => {
+                    if slot.root || scheduled.slot_id == slot.id {
+                        self.scheduled.remove(&id);
+
+                        Code::Ok.set(&mut message);
+                    } else {
+                        Code::PermissionDenied.set(&mut message);
+                    }
+                }
+                None => {
+                    Code::NotFound.set(&mut message);
+                }
+            }
+        } else {
+            Code::CannotGetIdField.set(&mut message);
+        }
+
+        self.send_message(hook, token, message);
+    }
+
+    #[allow(clippy::cognitive_complexity)]
+    fn route_message(
+        &mut self,
+        hook: &impl Hook,
+        from: Option<usize>,
+        chan: String,
+        mut message: Message
+    ) {
+        // 发送者不存在时（比如延时消息到期时发送者已断开），不会发送 BIND_SEND 和 SLOT_SEND 事件
+        let token = from.unwrap_or(usize::MAX);
+
+        macro_rules! send_bind {
+            ($self: ident, $hook: ident, $slot: ident, $message: ident) => {
+                let success = $hook.push($slot, &mut $message);
+
+                if success {
+                    $self.send_message($hook, $slot.token, $message.clone());
+                }
+            };
+        }
+
+        macro_rules! send {
+            ($self: ident, $hook: ident, $slot: ident, $message: ident) => {
+                let success = $hook.push($slot, &mut $message);
+
+                if success {
+                    let mut message = $message.clone();
+
+                    if $slot.joined {
+                        // 如果对方已 JOIN，则填充 FROM_SOCKET
+                        if !message.contains_key(FROM_SOCKET) {
+                            message.insert(FROM_SOCKET, self.socket_id);
+                        }
+                    }
+
+                    $self.send_message($hook, $slot.token, message.clone());
+
+                    // BIND
+                    // 此模式可以接收到所 BIND 的 SLOT 发送的消息
+                    let bounds = &$slot.bound;
+
+                    let mut bind_message = msg! {
+                        CHAN: BIND_RECV,
+                        SLOT_ID: $slot.id,
+                        VALUE: message.clone()
+                    };
+
+                    for bound in bounds {
+                        if let Some(slot) = self.slots.get(*bound) {
+                            send_bind!(self, hook, slot, bind_message);
+                        }
+                    }
+
+                    // slot event
+                    // {
+                    //     CHAN: SLOT_RECV,
+                    //     VALUE: $message
+                    // }
+                    let event_message = msg!{
+                        CHAN: SLOT_RECV,
+                        VALUE: message.clone(),
+                        TO: $slot.id
+                    };
+
+                    let id = $slot.token;
+
+                    $self.relay_root_message($hook, id, SLOT_RECV, event_message);
+                }
+            };
+        }
+
+        // BIND
+        // 此模式可以接收到所 BIND 的 SLOT 发送的消息
+        if let Some(from_slot) = self.slots.get(token) {
+            let mut bind_message = msg! {
+                CHAN: BIND_SEND,
+                SLOT_ID: from_slot.id,
+                VALUE: message.clone()
+            };
+
+            for bound in &from_slot.bound {
+                if let Some(slot) = self.slots.get(*bound) {
+                    send_bind!(self, hook, slot, bind_message);
+                }
+            }
+        }
+
+        // TO SOCKET
+        let mut goon = true;
+
+        if let Some(to_socket) = message.get(TO_SOCKET).cloned() {
+            if let Some(to_socket_id) = to_socket.as_message_id() {
+                // 目前 Socket ID 不等于本 Socket ID 时，该功能才生效
+                if to_socket_id != &self.socket_id {
+                    goon = false;
+
+                    // 移除 TO
+                    message.remove(TO_SOCKET);
+
+                    if let Some(socket_token) = self.socket_ids.get(to_socket_id) {
+                        if let Some(slot) = self.slots.get(*socket_token) {
+                            send!(self, hook, slot, message);
+                        }
+                    }
+                }
+            } else {
+                Code::InvalidToSocketFieldType.set(&mut message);
+
+                self.send_message(hook, token, message);
+
+                return
+            }
+        }
+
+        if goon {
+            // 如果 TO == 本 Socket ID，则移除
+            if let Ok(to) = message.get_message_id(TO) {
+                if to == &self.socket_id {
+                    message.remove(TO);
+                }
+            }
+
+            // 两种模式下，自己都可以收到自己发送的消息，如果不想处理，可以利用 `FROM` 进行过滤，
+            // 也就是此时 FROM == 自己的 SLOT_ID
+
+            // P2P 的优先级比较高
             // 不管 SLOT 是否 ATTACH，都可给其发送消息
             // 自己可以收到自己发送的消息
             if let Some(to) = message.get(TO).cloned() {
@@ -460,17 +1448,76 @@ impl Switch {
                                 }
                             }
                         }
-                    } else {
-                        for to in &to_ids {
-                            if let Some(slot_token) = self.slot_ids.get(to) {
-                                if let Some(slot) = self.slots.get(*slot_token) {
-                                    send!(self, hook, slot, message);
-                                }
-                            }
+                    } else {
+                        for to in &to_ids {
+                            if let Some(slot_token) = self.slot_ids.get(to) {
+                                if let Some(slot) = self.slots.get(*slot_token) {
+                                    send!(self, hook, slot, message);
+                                }
+                            }
+                        }
+                    }
+                }
+            } else if let Some(to_attr) = message.get(TO_ATTR).cloned() {
+                // 发送给属性满足条件的 SLOT，带有 SHARE 时只发送给其中一个
+                // {
+                //     CHAN: $chan,
+                //     TO_ATTR: { role: "printer" },
+                //     SHARE: true
+                // }
+                let filter = match to_attr {
+                    Value::Message(filter) => filter,
+                    _ => {
+                        Code::InvalidAttrFieldType.set(&mut message);
+
+                        self.send_message(hook, token, message);
+
+                        return
+                    }
+                };
+
+                message.remove(TO_ATTR);
+
+                let tokens: Vec<usize> = self.slots.iter()
+                    .filter(|(_, slot)| match_attr(&slot.wire.attr(), &filter))
+                    .map(|(slot_token, _)| slot_token)
+                    .collect();
+
+                if message.get_bool(SHARE).ok().unwrap_or(false) {
+                    if let Some(slot_token) = tokens.choose(&mut self.rand) {
+                        if let Some(slot) = self.slots.get(*slot_token) {
+                            send!(self, hook, slot, message);
+                        }
+                    }
+                } else {
+                    for slot_token in tokens {
+                        if let Some(slot) = self.slots.get(slot_token) {
+                            send!(self, hook, slot, message);
                         }
                     }
                 }
+            } else if let Some(queue) = self.queues.get_mut(&chan) {
+                // 工作队列，消息只投递给一个消费者，队列已满时告知发送者
+                match queue.push(message.clone()) {
+                    Ok(()) => self.dispatch_queue(hook, &chan),
+                    Err(mut message) => {
+                        Code::QueueFull.set(&mut message);
+
+                        self.send_message(hook, token, message);
+                    }
+                }
             } else {
+                // 持久频道，没有订阅者时暂存
+                if let Some(durable) = self.durables.get_mut(&chan) {
+                    let subscribed = self.chans.get(&chan).map(|tokens| !tokens.is_empty()).unwrap_or(false) ||
+                        self.share_chans.get(&chan).map(|tokens| !tokens.is_empty()).unwrap_or(false) ||
+                        self.groups.get(&chan).map(|groups| !groups.is_empty()).unwrap_or(false);
+
+                    if !subscribed {
+                        durable.push(message.clone());
+                    }
+                }
+
                 if let Some(tokens) = self.chans.get(&chan) {
                     if message.get_bool(SHARE).ok().unwrap_or(false) {
                         let mut array: Vec<usize> = Vec::new();
@@ -481,6 +1528,9 @@ impl Switch {
                             array.push(*slot_token);
                         }
 
+                        // HashSet 的顺序不固定，排序后再随机选择，固定种子时结果可以重现
+                        array.sort_unstable();
+
                         if !array.is_empty() {
                             if array.len() == 1 {
                                 if let Some(slot) = self.slots.get(array[0]) {
@@ -514,6 +1564,8 @@ impl Switch {
                         array.push(*slot_token);
                     }
 
+                    array.sort_unstable();
+
                     if !array.is_empty() {
                         if array.len() == 1 {
                             if let Some(slot) = self.slots.get(array[0]) {
@@ -526,108 +1578,624 @@ impl Switch {
                         }
                     }
                 }
+
+                // 消费组
+                // 每个消费组都会收到一份消息，由组内的一个成员处理
+                if let Some(groups) = self.groups.get_mut(&chan) {
+                    let mut array: Vec<usize> = Vec::new();
+
+                    for group in groups.values_mut() {
+                        if let Some(slot_token) = group.pick(&self.slots, &mut self.rand, &message) {
+                            array.push(slot_token);
+                        }
+                    }
+
+                    for slot_token in array {
+                        if let Some(slot) = self.slots.get(slot_token) {
+                            send!(self, hook, slot, message);
+                        }
+                    }
+                }
+            }
+
+        } // end goon
+
+        if from.is_none() {
+            return
+        }
+
+        // slot event
+        // {
+        //     CHAN: SLOT_SEND,
+        //     VALUE: $message
+        // }
+        let event_message = msg!{
+            CHAN: SLOT_SEND,
+            VALUE: message
+        };
+
+        self.relay_root_message(hook, token, SLOT_SEND, event_message);
+    }
+
+    // ATTACH 的时候，可以附带自定义数据，可以通过 Hook.attach 或 SLOT_ATTACH 事件获取
+    fn attach(
+        &mut self,
+        hook: &impl Hook,
+        token: usize,
+        mut message: Message
+    ) {
+        let mut group_join = None;
+        let mut durable_chan = None;
+        let mut queue_chan = None;
+
+        if let Ok(chan) = message.get_str(VALUE).map(ToOwned::to_owned) {
+            // check ROOT
+            match chan.as_str() {
+                SLOT_READY | SLOT_BREAK | SLOT_ATTACH | SLOT_DETACH | SLOT_SEND | SLOT_RECV | TRACE_HOP | AUDIT
+                    if !self.slots[token].root => {
+                    Code::PermissionDenied.set(&mut message);
+
+                    self.send_message(hook, token, message);
+
+                    return
+                }
+                _ => ()
+            }
+
+            // share
+            let mut share = false;
+
+            if let Some(share2) = message.get(SHARE) {
+                if let Some(share2) = share2.as_bool() {
+                    share = share2
+                } else {
+                    Code::InvalidShareFieldType.set(&mut message);
+
+                    self.send_message(hook, token, message);
+
+                    return
+                }
+            }
+
+            // group
+            // {
+            //     CHAN: ATTACH,
+            //     VALUE: $chan,
+            //     GROUP: $group,
+            //     STRATEGY: "random" | "round_robin" | "least_pending" | "hash"
+            // }
+            // 带有 GROUP 时，SHARE 会被忽略，STRATEGY 只在创建消费组时生效，
+            // 之后加入的成员如果指定了不同的 STRATEGY，会返回 BadValue
+            let group = match self.group_params(&message, &chan) {
+                Ok(group) => group,
+                Err(code) => {
+                    code.set(&mut message);
+
+                    self.send_message(hook, token, message);
+
+                    return
+                }
+            };
+
+            // 工作队列
+            // {
+            //     CHAN: ATTACH,
+            //     VALUE: $chan,
+            //     PREFETCH: $prefetch
+            // }
+            // CHAN 为工作队列时，作为消费者加入，SHARE 和 GROUP 会被忽略
+            // PREFETCH 为最多同时持有的未确认消息数，默认为 1，再次 ATTACH 时可以修改
+            let prefetch = if self.queues.contains_key(&chan) {
+                match message.get(PREFETCH) {
+                    Some(value) => match to_count(value) {
+                        Some(prefetch) => Some(prefetch),
+                        None => {
+                            Code::InvalidPrefetchFieldType.set(&mut message);
+
+                            self.send_message(hook, token, message);
+
+                            return
+                        }
+                    },
+                    None => Some(1)
+                }
+            } else {
+                None
+            };
+
+            // 随 ATTACH 注册遗嘱
+            // {
+            //     CHAN: ATTACH,
+            //     VALUE: $chan,
+            //     WILL: {
+            //         CHAN: $will_chan,
+            //         ...
+            //     }
+            // }
+            let will = match message.get(WILL) {
+                Some(will) => match check_will(hook, &self.slots[token], will) {
+                    Ok(will) => Some(will),
+                    Err(code) => {
+                        code.set(&mut message);
+
+                        self.send_message(hook, token, message);
+
+                        return
+                    }
+                },
+                None => None
+            };
+
+            // 这里可以验证该 SLOT 是否有权限
+            let success = hook.attach(&self.slots[token], &mut message, &chan);
+
+            if !success {
+                deny(&mut message);
+
+                self.send_message(hook, token, message);
+
+                return
+            }
+
+            // slot event
+            // {
+            //     CHAN: SLOT_ATTACH,
+            //     VALUE: $chan,
+            //     slot_id: $slot_id
+            // }
+            let mut event_message = msg!{
+                CHAN: SLOT_ATTACH,
+                VALUE: &chan,
+                SLOT_ID: self.slots[token].id
+            };
+
+            if self.durables.contains_key(&chan) {
+                durable_chan = Some(chan.clone());
+            }
+
+            if let Some(will) = will {
+                let slot = &mut self.slots[token];
+
+                slot.will_chan = will.as_ref().map(|_| chan.clone());
+                slot.will = will;
+            }
+
+            // session_attach
+            if let Some(prefetch) = prefetch {
+                event_message.insert(PREFETCH, prefetch as u64);
+
+                if let Some(queue) = self.queues.get_mut(&chan) {
+                    queue.subscribe(token, prefetch);
+                }
+
+                self.slots[token].queues.insert(chan.to_owned());
+
+                queue_chan = Some(chan);
+            } else if let Some((group, strategy)) = group {
+                event_message.insert(GROUP, &group);
+
+                let slot_id = self.slots[token].id;
+
+                let inserted = self.groups.entry(chan.to_owned())
+                    .or_default()
+                    .entry(group.to_owned())
+                    .or_insert_with(|| Group::new(strategy.unwrap_or_default()))
+                    .insert(token);
+
+                if inserted {
+                    self.slots[token].groups.insert((chan.to_owned(), group.to_owned()));
+
+                    group_join = Some((slot_id, chan.to_owned(), group));
+                }
+            } else if share {
+                event_message.insert(SHARE, true);
+
+                let ids = self.share_chans.entry(chan.to_owned()).or_default();
+                ids.insert(token);
+
+                self.slots[token].share_chans.insert(chan);
+            } else {
+                let ids = self.chans.entry(chan.to_owned()).or_default();
+                ids.insert(token);
+
+                self.slots[token].chans.insert(chan);
+            }
+
+            self.relay_root_message(hook, token, SLOT_ATTACH, event_message);
+
+            Code::Ok.set(&mut message);
+        } else {
+            Code::CannotGetValueField.set(&mut message);
+        }
+
+        self.send_message(hook, token, message);
+
+        // 先回复 ATTACH，再通知消费组成员
+        if let Some((slot_id, chan, group)) = group_join {
+            self.group_event(hook, GROUP_JOIN, slot_id, &chan, &group);
+        }
+
+        // 投递持久频道中暂存的消息
+        if let Some(chan) = durable_chan {
+            self.flush_durable(hook, token, &chan);
+        }
+
+        // 新的消费者，或者 PREFETCH 变大了
+        if let Some(chan) = queue_chan {
+            self.dispatch_queue(hook, &chan);
+        }
+    }
+
+    // 把工作队列中的消息按顺序投递给有空闲额度的消费者
+    fn dispatch_queue(&mut self, hook: &impl Hook, chan: &str) {
+        // Hook.push 拒绝或者发送失败的消费者，这一轮不再投递给它
+        let mut skip = Vec::new();
+
+        loop {
+            let (token, seq, message) = match self.queues.get_mut(chan).and_then(|queue| queue.pop(&skip)) {
+                Some(next) => next,
+                None => return
+            };
+
+            let mut push_message = message.clone();
+
+            let success = match self.slots.get(token) {
+                Some(slot) => hook.push(slot, &mut push_message),
+                None => false
+            };
+
+            // 发送成功后才记录为待确认，Hook.send 拒绝或者 wire 已满时重新入队
+            let success = success && self.send_message(hook, token, push_message);
+
+            if let Some(queue) = self.queues.get_mut(chan) {
+                if success {
+                    queue.deliver(token, seq, message);
+                } else {
+                    queue.requeue(seq, message);
+                    skip.push(token);
+                }
+            }
+        }
+    }
+
+    // 确认工作队列中的消息
+    // {
+    //     CHAN: ACK,
+    //     ID: $id,
+    //     REQUEUE: false
+    // }
+    // REQUEUE 为 true 时，消息按原来的顺序重新入队，投递给其他消费者
+    fn ack(&mut self, hook: &impl Hook, token: usize, mut message: Message) {
+        let id = match message.get_message_id(ID) {
+            Ok(id) => *id,
+            Err(_) => {
+                Code::CannotGetIdField.set(&mut message);
+
+                self.send_message(hook, token, message);
+
+                return
+            }
+        };
+
+        let requeue = message.get_bool(REQUEUE).unwrap_or(false);
+
+        let chan = self.slots[token].queues.iter()
+            .find(|chan| self.queues.get(*chan).map(|queue| queue.find_unacked(token, &id).is_some()).unwrap_or(false))
+            .cloned();
+
+        let acked = match &chan {
+            Some(chan) => self.queues.get_mut(chan).map(|queue| queue.ack(token, &id, requeue)).unwrap_or(false),
+            None => false
+        };
+
+        if acked {
+            Code::Ok.set(&mut message);
+        } else {
+            Code::NotFound.set(&mut message);
+        }
+
+        self.send_message(hook, token, message);
+
+        // 消费者有了空闲额度
+        if let Some(chan) = chan {
+            self.dispatch_queue(hook, &chan);
+        }
+    }
+
+    fn flush_durable(&mut self, hook: &impl Hook, token: usize, chan: &str) {
+        let messages = match self.durables.get_mut(chan) {
+            Some(durable) => std::mem::take(&mut durable.messages),
+            None => return
+        };
+
+        for mut message in messages {
+            if let Some(slot) = self.slots.get(token) {
+                if hook.push(slot, &mut message) {
+                    self.send_message(hook, token, message);
+                }
+            }
+        }
+    }
+
+    // 查找属性满足条件的在线 SLOT，不需要 ROOT 权限，可以在 Hook.lookup 中限制
+    // 非 ROOT 查找时，不返回 ROOT SLOT，属性中不包括握手消息（ORIGIN）和凭据
+    // {
+    //     CHAN: LOOKUP,
+    //     ATTR: { role: "printer" } // 为空或者不存在时返回所有 SLOT
+    // }
+    // 返回：
+    // {
+    //     SLOTS: [{ SLOT_ID: $slot_id, ROOT: $root, ATTR: $attr }]
+    // }
+    fn lookup(&self, hook: &impl Hook, token: usize, mut message: Message) {
+        let filter = match attr_filter(&message) {
+            Ok(filter) => filter,
+            Err(code) => {
+                code.set(&mut message);
+
+                self.send_message(hook, token, message);
+
+                return
+            }
+        };
+
+        if !hook.lookup(&self.slots[token], &mut message) {
+            deny(&mut message);
+
+            self.send_message(hook, token, message);
+
+            return
+        }
+
+        let root = self.slots[token].root;
+
+        let mut slots = Array::new();
+
+        for (_, slot) in self.slots.iter() {
+            if !root && slot.root {
+                continue
+            }
+
+            let attr = slot.wire.attr();
+
+            let attr = if root {
+                attr.clone()
+            } else {
+                redact_attr(&attr)
+            };
+
+            if match_attr(&attr, &filter) {
+                slots.push(msg!{
+                    SLOT_ID: slot.id,
+                    ROOT: slot.root,
+                    ATTR: public_attr(root, attr)
+                });
+            }
+        }
+
+        message.insert(SLOTS, slots);
+
+        Code::Ok.set(&mut message);
+
+        self.send_message(hook, token, message);
+    }
+
+    // 订阅在线状态，属性满足条件的 SLOT 连接或断开时，会收到：
+    // {
+    //     CHAN: PRESENCE,
+    //     EVENT: "online" | "offline",
+    //     SLOT_ID: $slot_id,
+    //     ROOT: $root,
+    //     ATTR: $attr
+    // }
+    // 请求：
+    // {
+    //     CHAN: WATCH,
+    //     ATTR: { role: "printer" }
+    // }
+    fn watch(&mut self, hook: &impl Hook, token: usize, mut message: Message) {
+        let filter = match attr_filter(&message) {
+            Ok(filter) => filter,
+            Err(code) => {
+                code.set(&mut message);
+
+                self.send_message(hook, token, message);
+
+                return
             }
+        };
 
-        } // end goon
+        if !hook.lookup(&self.slots[token], &mut message) {
+            deny(&mut message);
 
-        // slot event
-        // {
-        //     CHAN: SLOT_SEND,
-        //     VALUE: $message
-        // }
-        let event_message = msg!{
-            CHAN: SLOT_SEND,
-            VALUE: message
-        };
+            self.send_message(hook, token, message);
 
-        self.relay_root_message(hook, token, SLOT_SEND, event_message);
+            return
+        }
+
+        let slot = &mut self.slots[token];
+
+        if !slot.watches.contains(&filter) {
+            slot.watches.push(filter);
+        }
+
+        Code::Ok.set(&mut message);
+
+        self.send_message(hook, token, message);
     }
 
-    // ATTACH 的时候，可以附带自定义数据，可以通过 Hook.attach 或 SLOT_ATTACH 事件获取
-    fn attach(
-        &mut self,
-        hook: &impl Hook,
-        token: usize,
-        mut message: Message
-    ) {
-        if let Ok(chan) = message.get_str(VALUE).map(ToOwned::to_owned) {
-            // check ROOT
-            match chan.as_str() {
-                SLOT_READY | SLOT_BREAK | SLOT_ATTACH | SLOT_DETACH | SLOT_SEND | SLOT_RECV => {
+    // 取消订阅在线状态，不带 ATTR 时取消所有
+    fn unwatch(&mut self, hook: &impl Hook, token: usize, mut message: Message) {
+        match message.get(ATTR) {
+            Some(Value::Message(filter)) => {
+                let filter = filter.clone();
+                self.slots[token].watches.retain(|watch| watch != &filter);
+            }
+            Some(_) => {
+                Code::InvalidAttrFieldType.set(&mut message);
 
-                    if !self.slots[token].root {
-                        Code::PermissionDenied.set(&mut message);
+                self.send_message(hook, token, message);
 
-                        self.send_message(hook, token, message);
+                return
+            }
+            None => self.slots[token].watches.clear()
+        }
 
-                        return
-                    }
+        Code::Ok.set(&mut message);
 
-                }
-                _ => ()
+        self.send_message(hook, token, message);
+    }
+
+    fn presence(&self, hook: &impl Hook, target: &Slot, event: &str) {
+        let attr = target.wire.attr().clone();
+        let redacted = redact_attr(&attr);
+
+        for (slot_token, slot) in self.slots.iter() {
+            // 和 LOOKUP 一样，非 ROOT 看不到 ROOT SLOT，也看不到握手消息和凭据
+            if slot.id == target.id || (!slot.root && target.root) {
+                continue
             }
 
-            // share
-            let mut share = false;
+            let attr = if slot.root { &attr } else { &redacted };
 
-            if let Some(share2) = message.get(SHARE) {
-                if let Some(share2) = share2.as_bool() {
-                    share = share2
-                } else {
-                    Code::InvalidShareFieldType.set(&mut message);
+            if !slot.watches.iter().any(|filter| match_attr(attr, filter)) {
+                continue
+            }
 
-                    self.send_message(hook, token, message);
+            let mut message = msg!{
+                CHAN: PRESENCE,
+                EVENT: event,
+                SLOT_ID: target.id,
+                ROOT: target.root,
+                ATTR: public_attr(slot.root, attr.clone())
+            };
 
-                    return
-                }
+            if hook.push(slot, &mut message) {
+                self.send_message(hook, slot_token, message);
             }
+        }
+    }
 
-            // 这里可以验证该 SLOT 是否有权限
-            let success = hook.attach(&self.slots[token], &mut message, &chan);
+    // 设置或取消遗嘱
+    // {
+    //     CHAN: WILL,
+    //     VALUE: { CHAN: $chan, ... } // 设置，会替换之前的遗嘱
+    //     VALUE: null                 // 取消
+    // }
+    fn will(&mut self, hook: &impl Hook, token: usize, mut message: Message) {
+        let will = match message.get(VALUE) {
+            Some(will) => check_will(hook, &self.slots[token], will),
+            None => Err(Code::CannotGetValueField)
+        };
 
-            if !success {
-                Code::PermissionDenied.set(&mut message);
+        match will {
+            Ok(will) => {
+                let slot = &mut self.slots[token];
 
-                self.send_message(hook, token, message);
+                slot.will = will;
+                slot.will_chan = None;
 
-                return
+                Code::Ok.set(&mut message);
             }
+            Err(code) => code.set(&mut message)
+        }
 
-            // slot event
-            // {
-            //     CHAN: SLOT_ATTACH,
-            //     VALUE: $chan,
-            //     slot_id: $slot_id
-            // }
-            let mut event_message = msg!{
-                CHAN: SLOT_ATTACH,
-                VALUE: &chan,
-                SLOT_ID: self.slots[token].id
-            };
+        self.send_message(hook, token, message);
+    }
 
-            // session_attach
-            if share {
-                event_message.insert(SHARE, true);
+    fn group_params(
+        &self,
+        message: &Message,
+        chan: &str
+    ) -> std::result::Result<Option<(String, Option<Strategy>)>, Code> {
+        let group = match message.get(GROUP) {
+            Some(Value::String(group)) => group.to_owned(),
+            Some(_) => return Err(Code::InvalidGroupFieldType),
+            None => return Ok(None)
+        };
 
-                let ids = self.share_chans.entry(chan.to_owned()).or_insert_with(HashSet::new);
-                ids.insert(token);
+        let strategy = match message.get(STRATEGY) {
+            Some(Value::String(strategy)) => {
+                Some(Strategy::from_name(strategy).ok_or(Code::InvalidStrategyFieldType)?)
+            }
+            Some(_) => return Err(Code::InvalidStrategyFieldType),
+            None => None
+        };
 
-                self.slots[token].share_chans.insert(chan);
-            } else {
-                let ids = self.chans.entry(chan.to_owned()).or_insert_with(HashSet::new);
-                ids.insert(token);
+        if let (Some(strategy), Some(exist)) = (strategy, self.groups.get(chan).and_then(|groups| groups.get(&group))) {
+            if strategy != exist.strategy {
+                return Err(Code::BadValue)
+            }
+        }
 
-                self.slots[token].chans.insert(chan);
+        Ok(Some((group, strategy)))
+    }
+
+    fn leave_group(
+        &mut self,
+        hook: &impl Hook,
+        token: usize,
+        slot_id: MessageId,
+        chan: &str,
+        group: &str
+    ) {
+        if let Some(groups) = self.groups.get_mut(chan) {
+            if let Some(g) = groups.get_mut(group) {
+                g.remove(token);
+
+                if g.is_empty() {
+                    groups.remove(group);
+                }
             }
 
-            self.relay_root_message(hook, token, SLOT_ATTACH, event_message);
+            if groups.is_empty() {
+                self.groups.remove(chan);
+            }
+        }
 
-            Code::Ok.set(&mut message);
-        } else {
-            Code::CannotGetValueField.set(&mut message);
+        self.group_event(hook, GROUP_LEAVE, slot_id, chan, group);
+    }
+
+    // 消费组成员变化时，通知组内的所有成员
+    // {
+    //     CHAN: GROUP_JOIN | GROUP_LEAVE,
+    //     VALUE: $chan,
+    //     GROUP: $group,
+    //     SLOT_ID: $slot_id,
+    //     MEMBERS: [$slot_id, ...]
+    // }
+    fn group_event(
+        &self,
+        hook: &impl Hook,
+        event: &str,
+        slot_id: MessageId,
+        chan: &str,
+        group: &str
+    ) {
+        let tokens = match self.groups.get(chan).and_then(|groups| groups.get(group)) {
+            Some(g) => g.members.clone(),
+            None => return
+        };
+
+        let mut members = Array::new();
+
+        for member in &tokens {
+            if let Some(slot) = self.slots.get(*member) {
+                members.push(slot.id);
+            }
         }
 
-        self.send_message(hook, token, message);
+        let event_message = msg!{
+            CHAN: event,
+            VALUE: chan,
+            GROUP: group,
+            SLOT_ID: slot_id,
+            MEMBERS: members
+        };
+
+        for member in tokens {
+            self.send_message(hook, member, event_message.clone());
+        }
     }
 
     // DETACH 的时候，可以附带自定义数据，可以通过 Hook.detach 或 SLOT_DETACH 事件获取
@@ -637,6 +2205,8 @@ impl Switch {
         token: usize,
         mut message: Message
     ) {
+        let mut queue_chan = None;
+
         if let Ok(chan) = message.get_str(VALUE).map(ToOwned::to_owned) {
             // share
             let mut share = false;
@@ -653,18 +2223,40 @@ impl Switch {
                 }
             }
 
+            let group = match message.get(GROUP) {
+                Some(Value::String(group)) => Some(group.to_owned()),
+                Some(_) => {
+                    Code::InvalidGroupFieldType.set(&mut message);
+
+                    self.send_message(hook, token, message);
+
+                    return
+                }
+                None => None
+            };
+
             // 这里可以验证该 SLOT 是否有权限
             // 可以让 SLOT 不能 DETACH 某些 CHAN
             let success = hook.detach(&self.slots[token], &mut message, &chan);
 
             if !success {
-                Code::PermissionDenied.set(&mut message);
+                deny(&mut message);
 
                 self.send_message(hook, token, message);
 
                 return
             }
 
+            // 正常 DETACH 时，取消随 ATTACH 注册的遗嘱
+            {
+                let slot = &mut self.slots[token];
+
+                if slot.will_chan.as_ref() == Some(&chan) {
+                    slot.will = None;
+                    slot.will_chan = None;
+                }
+            }
+
             // slot event
             // {
             //     CHAN: SLOT_DETACH,
@@ -678,7 +2270,21 @@ impl Switch {
             };
 
             // session_detach
-            if share {
+            if self.slots[token].queues.remove(&chan) {
+                if let Some(queue) = self.queues.get_mut(&chan) {
+                    queue.unsubscribe(token);
+                }
+
+                queue_chan = Some(chan.to_owned());
+            } else if let Some(group) = group {
+                event_message.insert(GROUP, &group);
+
+                let slot_id = self.slots[token].id;
+
+                if self.slots[token].groups.remove(&(chan.to_owned(), group.to_owned())) {
+                    self.leave_group(hook, token, slot_id, &chan, &group);
+                }
+            } else if share {
                 event_message.insert(SHARE, true);
 
                 self.slots[token].share_chans.remove(&chan);
@@ -711,6 +2317,11 @@ impl Switch {
         }
 
         self.send_message(hook, token, message);
+
+        // 未确认的消息重新投递给其他消费者
+        if let Some(chan) = queue_chan {
+            self.dispatch_queue(hook, &chan);
+        }
     }
 
     fn bind(
@@ -725,7 +2336,7 @@ impl Switch {
             let success = hook.bind(&self.slots[token], &mut message, slot_id);
 
             if !success {
-                Code::PermissionDenied.set(&mut message);
+                deny(&mut message);
 
                 self.send_message(hook, token, message);
 
@@ -759,7 +2370,7 @@ impl Switch {
             let success = hook.unbind(&self.slots[token], &mut message, slot_id);
 
             if !success {
-                Code::PermissionDenied.set(&mut message);
+                deny(&mut message);
 
                 self.send_message(hook, token, message);
 
@@ -791,7 +2402,7 @@ impl Switch {
         let success = hook.join(&self.slots[token], &mut message);
 
         if !success {
-            Code::PermissionDenied.set(&mut message);
+            deny(&mut message);
 
             self.send_message(hook, token, message);
 
@@ -816,7 +2427,7 @@ impl Switch {
         let success = hook.unjoin(&self.slots[token], &mut message);
 
         if !success {
-            Code::PermissionDenied.set(&mut message);
+            deny(&mut message);
 
             self.send_message(hook, token, message);
 
@@ -847,6 +2458,15 @@ impl Switch {
             let chans: Vec<&String> = slot.chans.iter().collect();
             let share_chans: Vec<&String> = slot.share_chans.iter().collect();
 
+            let mut groups = Array::new();
+
+            for (chan, group) in &slot.groups {
+                groups.push(msg!{
+                    VALUE: chan,
+                    GROUP: group
+                });
+            }
+
             let mut binded = Array::new();
 
             for bind_token in &slot.bind {
@@ -863,13 +2483,14 @@ impl Switch {
                 }
             }
 
-            let slot = msg!{
+            let mut value = msg!{
                 SOCKET_ID: self.socket_id,
                 SLOT_ID: slot.id,
                 ROOT: slot.root,
                 ATTR: slot.wire.attr().clone(),
                 CHANS: chans,
                 SHARE_CHANS: share_chans,
+                GROUPS: groups,
                 SEND_NUM: slot.wire.send_num() as u64,
                 RECV_NUM: slot.wire.recv_num() as u64,
                 BINDED: binded,
@@ -877,7 +2498,11 @@ impl Switch {
                 JOINED: slot.joined
             };
 
-            message.insert(VALUE, slot);
+            if let Some(will) = &slot.will {
+                value.insert(WILL, will.clone());
+            }
+
+            message.insert(VALUE, value);
         }
 
         Code::Ok.set(&mut message);
@@ -900,6 +2525,47 @@ impl Switch {
             }
         }
 
+        // 列出所有的延时消息
+        // {
+        //     CHAN: QUERY,
+        //     VALUE: SCHEDULED
+        // }
+        if message.get_str(VALUE) == Ok(SCHEDULED) {
+            let mut list = Array::new();
+
+            for (id, scheduled) in &self.scheduled {
+                list.push(msg!{
+                    ID: id,
+                    CHAN: &scheduled.chan,
+                    FROM: scheduled.slot_id,
+                    DELIVER_AT: scheduled.deliver_at
+                });
+            }
+
+            message.insert(SCHEDULED, list);
+
+            Code::Ok.set(&mut message);
+
+            self.send_message(hook, token, message);
+
+            return
+        }
+
+        // 运行统计
+        // {
+        //     CHAN: QUERY,
+        //     VALUE: METRICS
+        // }
+        if message.get_str(VALUE) == Ok(METRICS) {
+            message.insert(METRICS, self.metrics());
+
+            Code::Ok.set(&mut message);
+
+            self.send_message(hook, token, message);
+
+            return
+        }
+
         hook.query(self, token, &mut message);
 
         // QUERY 的时候，不会插入 CODE: 0, 由 hook 函数决定
@@ -933,9 +2599,88 @@ impl Switch {
             }
         }
 
+        // 每次 CTRL 都会先调用 Hook.ctrl，Hook 设置了 CODE 时表示已经处理（或者拒绝），不再继续
         hook.ctrl(self, token, &mut message);
 
-        // QUERY 的时候，不会插入 CODE: 0, 由 hook 函数决定
+        if Code::get(&message).is_some() {
+            self.send_message(hook, token, message);
+
+            return
+        }
+
+        // 设置消息结构，SCHEMA 为 null 时移除
+        // {
+        //     CHAN: CTRL,
+        //     VALUE: $chan,
+        //     SCHEMA: $schema
+        // }
+        if let Some(schema) = message.get(SCHEMA).cloned() {
+            match (message.get_str(VALUE).map(ToOwned::to_owned), schema) {
+                (Ok(chan), Value::Null) => {
+                    self.remove_schema(&chan);
+
+                    Code::Ok.set(&mut message);
+                }
+                (Ok(chan), Value::Message(schema)) => {
+                    match Schema::from_message(&schema) {
+                        Ok(schema) => {
+                            self.set_schema(chan, schema);
+
+                            Code::Ok.set(&mut message);
+                        }
+                        Err(err) => {
+                            ErrorInfo::new(Code::BadValue).field(SCHEMA).reason(err.to_string()).set(&mut message);
+                        }
+                    }
+                }
+                (Ok(_), _) => {
+                    Code::BadValue.set(&mut message);
+                }
+                (Err(_), _) => {
+                    Code::CannotGetValueField.set(&mut message);
+                }
+            }
+
+            self.send_message(hook, token, message);
+
+            return
+        }
+
+        // 设置转换和路由规则，替换之前的规则，RULES 为 null 时清空
+        // {
+        //     CHAN: CTRL,
+        //     RULES: [$rule, ...]
+        // }
+        if let Some(rules) = message.get(RULES).cloned() {
+            match rules {
+                Value::Null => {
+                    self.set_rules(Rules::default());
+
+                    Code::Ok.set(&mut message);
+                }
+                Value::Array(array) => {
+                    match Rules::from_array(&array) {
+                        Ok(rules) => {
+                            self.set_rules(rules);
+
+                            Code::Ok.set(&mut message);
+                        }
+                        Err(err) => {
+                            ErrorInfo::new(Code::BadValue).field(RULES).reason(err.to_string()).set(&mut message);
+                        }
+                    }
+                }
+                _ => {
+                    Code::BadValue.set(&mut message);
+                }
+            }
+
+            self.send_message(hook, token, message);
+
+            return
+        }
+
+        // 其它 CTRL 不会插入 CODE: 0, 由 hook 函数决定
 
         self.send_message(hook, token, message);
     }
@@ -963,7 +2708,7 @@ impl Switch {
         let success = hook.kill(&self.slots[token], &mut message);
 
         if !success {
-            Code::PermissionDenied.set(&mut message);
+            deny(&mut message);
 
             self.send_message(hook, token, message);
 
@@ -1003,9 +2748,107 @@ impl Switch {
         self.send_message(hook, token, message);
 
         if let Some(remove_token) = remove_token {
-            self.del_slot(epoll, hook, remove_token)?;
+            self.del_slot(epoll, hook, remove_token, BREAK_KILLED)?;
         }
 
         Ok(())
     }
 }
+
+fn now_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+fn to_count(value: &Value) -> Option<usize> {
+    match value {
+        Value::I32(v) if *v > 0 => Some(*v as usize),
+        Value::U32(v) if *v > 0 => Some(*v as usize),
+        Value::I64(v) if *v > 0 => Some(*v as usize),
+        Value::U64(v) if *v > 0 => Some(*v as usize),
+        _ => None
+    }
+}
+
+fn to_secs(value: &Value) -> Option<u64> {
+    match value {
+        Value::I32(v) if *v >= 0 => Some(*v as u64),
+        Value::U32(v) => Some(u64::from(*v)),
+        Value::I64(v) if *v >= 0 => Some(*v as u64),
+        Value::U64(v) => Some(*v),
+        Value::TimeStamp(v) => Some(v.0),
+        _ => None
+    }
+}
+
+// 检查遗嘱，必须带有 CHAN，不能是系统频道，并且 SLOT 有权限向该 CHAN 发送消息
+// null 表示取消遗嘱
+fn check_will(hook: &impl Hook, slot: &Slot, value: &Value) -> std::result::Result<Option<Message>, Code> {
+    match value {
+        Value::Null => Ok(None),
+        Value::Message(will) => {
+            let mut will = will.clone();
+
+            match will.get_str(CHAN) {
+                Ok(chan) if !chan.starts_with('_') => (),
+                _ => return Err(Code::InvalidWillFieldType)
+            }
+
+            if !hook.emit(slot, &mut will) {
+                return Err(Code::PermissionDenied)
+            }
+
+            if will.contains_key(TO_ATTR) && !hook.lookup(slot, &mut will) {
+                return Err(Code::PermissionDenied)
+            }
+
+            Ok(Some(will))
+        }
+        _ => Err(Code::InvalidWillFieldType)
+    }
+}
+
+// 握手消息中的凭据，不能让其他 SLOT 看到，也不能用来匹配
+const SECRET_FIELDS: &[&str] = &[PASSWORD, TOKEN, ACCESS, CHALLENGE, RESPONSE];
+
+// 去掉属性和握手消息（ORIGIN）中的凭据
+fn redact_attr(attr: &Message) -> Message {
+    let mut attr = attr.clone();
+
+    for field in SECRET_FIELDS {
+        attr.remove(field);
+    }
+
+    if let Some(Value::Message(origin)) = attr.get_mut(ORIGIN) {
+        for field in SECRET_FIELDS {
+            origin.remove(field);
+        }
+    }
+
+    attr
+}
+
+// 返回给非 ROOT 的属性不包括握手消息
+fn public_attr(root: bool, mut attr: Message) -> Message {
+    if !root {
+        attr.remove(ORIGIN);
+    }
+
+    attr
+}
+
+fn attr_filter(message: &Message) -> std::result::Result<Message, Code> {
+    match message.get(ATTR) {
+        Some(Value::Message(filter)) => Ok(filter.clone()),
+        Some(_) => Err(Code::InvalidAttrFieldType),
+        None => Ok(Message::new())
+    }
+}
+
+// Hook 拒绝时调用，如果 Hook 已经设置了用户自定义的错误码（Code::USER_MIN 以上），则保留，
+// 否则返回 PermissionDenied
+fn deny(message: &mut Message) {
+    match Code::get(message) {
+        Some(code) if code.is_user() => (),
+        _ => Code::PermissionDenied.set(message)
+    }
+}
@@ -0,0 +1,279 @@
+use std::collections::{HashMap, VecDeque};
+
+use nson::{Message, message_id::MessageId};
+
+use crate::dict::*;
+
+// 工作队列，点对点分发任务
+//
+// 消息暂存在队列中，按先后顺序投递给有空闲额度（PREFETCH）的消费者，每条消息只投递给一个消费者，
+// 消费者 ACK 之后才会从队列中移除。消费者在 ACK 之前断开或 DETACH 时，未确认的消息按原来的顺序
+// 重新入队，投递给其他消费者
+//
+// 队列只保证至少投递一次，消费者处理完但 ACK 之前断开时，消息会被重复投递
+#[derive(Debug)]
+pub struct WorkQueue {
+    // 最多暂存的消息数，不包括已投递未确认的
+    pub capacity: usize,
+    // (SEQ, 消息)，按 SEQ 排列
+    pub messages: VecDeque<(u64, Message)>,
+    // 按加入的顺序排列
+    pub consumers: Vec<Consumer>,
+    // SEQ，已投递未确认的消息
+    // 发布方可能复用 ID，不能用 ID 作为键
+    pub unacked: HashMap<u64, Unacked>,
+    seq: u64,
+    cursor: usize
+}
+
+#[derive(Debug, Clone)]
+pub struct Consumer {
+    pub token: usize,
+    // 最多同时持有的未确认消息数
+    pub prefetch: usize,
+    pub unacked: usize
+}
+
+#[derive(Debug, Clone)]
+pub struct Unacked {
+    pub token: usize,
+    pub seq: u64,
+    pub message: Message
+}
+
+impl WorkQueue {
+    pub fn new(capacity: usize) -> Self {
+        WorkQueue {
+            capacity,
+            messages: VecDeque::new(),
+            consumers: Vec::new(),
+            unacked: HashMap::new(),
+            seq: 0,
+            cursor: 0
+        }
+    }
+
+    // 消息入队，没有 ID 时会自动生成，用于 ACK
+    // 队列已满时返回消息
+    pub fn push(&mut self, mut message: Message) -> Result<(), Message> {
+        if self.messages.len() >= self.capacity {
+            return Err(message)
+        }
+
+        if message.get_message_id(ID).is_err() {
+            message.insert(ID, MessageId::new());
+        }
+
+        self.seq += 1;
+        self.messages.push_back((self.seq, message));
+
+        Ok(())
+    }
+
+    // 加入队列，已经加入时更新 PREFETCH
+    pub fn subscribe(&mut self, token: usize, prefetch: usize) -> bool {
+        if let Some(consumer) = self.consumers.iter_mut().find(|c| c.token == token) {
+            consumer.prefetch = prefetch;
+
+            return false
+        }
+
+        self.consumers.push(Consumer {
+            token,
+            prefetch,
+            unacked: 0
+        });
+
+        true
+    }
+
+    // 离开队列，未确认的消息重新入队
+    pub fn unsubscribe(&mut self, token: usize) -> bool {
+        let pos = match self.consumers.iter().position(|c| c.token == token) {
+            Some(pos) => pos,
+            None => return false
+        };
+
+        self.consumers.remove(pos);
+
+        let seqs: Vec<u64> = self.unacked.iter()
+            .filter(|(_, unacked)| unacked.token == token)
+            .map(|(seq, _)| *seq)
+            .collect();
+
+        for seq in seqs {
+            if let Some(unacked) = self.unacked.remove(&seq) {
+                self.requeue(unacked.seq, unacked.message);
+            }
+        }
+
+        true
+    }
+
+    // 投递给消费者的、ID 相同的未确认消息中最早的一条的 SEQ
+    pub fn find_unacked(&self, token: usize, id: &MessageId) -> Option<u64> {
+        self.unacked.iter()
+            .filter(|(_, unacked)| unacked.token == token && unacked.message.get_message_id(ID) == Ok(id))
+            .map(|(seq, _)| *seq)
+            .min()
+    }
+
+    // 确认消息，requeue 为 true 时重新入队
+    // 消费者持有多条 ID 相同的消息时，按投递的顺序逐条确认
+    pub fn ack(&mut self, token: usize, id: &MessageId, requeue: bool) -> bool {
+        let unacked = match self.find_unacked(token, id).and_then(|seq| self.unacked.remove(&seq)) {
+            Some(unacked) => unacked,
+            None => return false
+        };
+
+        if let Some(consumer) = self.consumers.iter_mut().find(|c| c.token == token) {
+            consumer.unacked -= 1;
+        }
+
+        if requeue {
+            self.requeue(unacked.seq, unacked.message);
+        }
+
+        true
+    }
+
+    // 取出队首的消息，以及接收它的消费者，轮询有空闲额度的消费者，跳过 skip 中的
+    pub fn pop(&mut self, skip: &[usize]) -> Option<(usize, u64, Message)> {
+        if self.messages.is_empty() || self.consumers.is_empty() {
+            return None
+        }
+
+        let len = self.consumers.len();
+
+        for i in 0..len {
+            let consumer = &self.consumers[(self.cursor + i) % len];
+
+            if consumer.unacked < consumer.prefetch && !skip.contains(&consumer.token) {
+                let token = consumer.token;
+
+                self.cursor = (self.cursor + i + 1) % len;
+
+                let (seq, message) = self.messages.pop_front()?;
+
+                return Some((token, seq, message))
+            }
+        }
+
+        None
+    }
+
+    // 记录已投递给消费者的消息
+    pub fn deliver(&mut self, token: usize, seq: u64, message: Message) {
+        if message.get_message_id(ID).is_err() {
+            return
+        }
+
+        if let Some(consumer) = self.consumers.iter_mut().find(|c| c.token == token) {
+            consumer.unacked += 1;
+        }
+
+        self.unacked.insert(seq, Unacked { token, seq, message });
+    }
+
+    // 按 SEQ 放回队列，保持原来的顺序
+    pub fn requeue(&mut self, seq: u64, message: Message) {
+        let pos = self.messages.partition_point(|(s, _)| *s < seq);
+
+        self.messages.insert(pos, (seq, message));
+    }
+
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nson::{msg, message_id::MessageId};
+
+    use crate::dict::*;
+
+    use super::WorkQueue;
+
+    #[test]
+    fn test_queue() {
+        let mut queue = WorkQueue::new(3);
+
+        for n in 0..3 {
+            assert!(queue.push(msg!{"n": n}).is_ok());
+        }
+
+        assert!(queue.push(msg!{"n": 3}).is_err());
+
+        // 没有消费者
+        assert!(queue.pop(&[]).is_none());
+
+        assert!(queue.subscribe(1, 1));
+        assert!(queue.subscribe(2, 1));
+        assert!(!queue.subscribe(2, 1));
+
+        let (token, seq, message) = queue.pop(&[]).unwrap();
+        assert!(token == 1 && message.get_i32("n").unwrap() == 0);
+        queue.deliver(token, seq, message.clone());
+
+        let (token, seq, message2) = queue.pop(&[]).unwrap();
+        assert!(token == 2 && message2.get_i32("n").unwrap() == 1);
+        queue.deliver(token, seq, message2);
+
+        // 没有空闲额度
+        assert!(queue.pop(&[]).is_none());
+
+        // 只有接收的消费者可以 ACK
+        let id = *message.get_message_id(ID).unwrap();
+        assert!(!queue.ack(2, &id, false));
+        assert!(queue.ack(1, &id, false));
+        assert!(!queue.ack(1, &id, false));
+
+        // 未确认的消息按原来的顺序重新入队
+        assert!(queue.unsubscribe(2));
+        assert!(queue.len() == 2);
+
+        let (token, seq, message) = queue.pop(&[]).unwrap();
+        assert!(token == 1 && message.get_i32("n").unwrap() == 1);
+        queue.deliver(token, seq, message);
+
+        assert!(queue.pop(&[]).is_none());
+        assert!(queue.len() == 1);
+    }
+
+    #[test]
+    fn test_same_id() {
+        let mut queue = WorkQueue::new(3);
+
+        let id = MessageId::new();
+
+        for n in 0..2 {
+            assert!(queue.push(msg!{ID: id, "n": n}).is_ok());
+        }
+
+        assert!(queue.subscribe(1, 2));
+
+        for _ in 0..2 {
+            let (token, seq, message) = queue.pop(&[]).unwrap();
+            queue.deliver(token, seq, message);
+        }
+
+        // ID 相同的消息不会相互覆盖
+        assert!(queue.unacked.len() == 2);
+
+        assert!(queue.ack(1, &id, true));
+        assert!(queue.unacked.len() == 1);
+        assert!(queue.consumers[0].unacked == 1);
+
+        let (_, _, message) = queue.pop(&[]).unwrap();
+        assert!(message.get_i32("n").unwrap() == 0);
+
+        assert!(queue.ack(1, &id, false));
+        assert!(queue.unacked.is_empty());
+        assert!(!queue.ack(1, &id, false));
+    }
+}
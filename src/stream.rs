@@ -0,0 +1,338 @@
+use std::collections::HashMap;
+
+use ring::digest::{Context, SHA256};
+
+use nson::{Message, MessageId, Value, msg};
+
+use crate::Wire;
+use crate::MAX_MESSAGE_LEN;
+use crate::dict::*;
+use crate::error::{Result, Error, SendError};
+
+// 大数据的流式传输
+//
+// 数据被切分成多个块，每个块都是一条普通的消息，通过 STREAM 频道点对点发送，
+// 因此不会阻塞同一个 wire 上的其他消息。接收方按顺序重组，结束时校验长度和 SHA256。
+// 任何一方都可以取消传输。
+//
+// 打开：
+// {
+//     CHAN: STREAM,
+//     TO: $slot_id,
+//     STREAM_ID: $stream_id,
+//     EVENT: "open",
+//     VALUE: { ... }       // 自定义的元数据，比如文件名
+// }
+//
+// 数据块：
+// {
+//     CHAN: STREAM,
+//     TO: $slot_id,
+//     STREAM_ID: $stream_id,
+//     EVENT: "data",
+//     SEQ: $seq,           // 从 0 开始
+//     VALUE: $binary
+// }
+//
+// 结束：
+// {
+//     CHAN: STREAM,
+//     TO: $slot_id,
+//     STREAM_ID: $stream_id,
+//     EVENT: "end",
+//     SEQ: $count,         // 数据块的数量
+//     SIZE: $size,         // 数据的总长度
+//     DIGEST: $sha256
+// }
+//
+// 取消：
+// {
+//     CHAN: STREAM,
+//     TO: $slot_id,
+//     STREAM_ID: $stream_id,
+//     EVENT: "cancel",
+//     REASON: $reason
+// }
+pub const OPEN: &str = "open";
+pub const DATA: &str = "data";
+pub const END: &str = "end";
+pub const CANCEL: &str = "cancel";
+
+// 默认的块大小
+pub const DEFAULT_CHUNK_SIZE: usize = 256 * 1024;
+
+// 块大小的上限，需要给消息中的其他字段留出空间
+pub const MAX_CHUNK_SIZE: usize = MAX_MESSAGE_LEN - 1024;
+
+pub struct Sender {
+    id: MessageId,
+    to: MessageId,
+    chunk_size: usize,
+    seq: u32,
+    size: u64,
+    digest: Context,
+    buffer: Vec<u8>
+}
+
+impl Sender {
+    pub fn open(wire: &Wire<Message>, to: MessageId, meta: Message, chunk_size: usize) -> Result<Self> {
+        if chunk_size == 0 || chunk_size > MAX_CHUNK_SIZE {
+            return Err(Error::InvalidData(format!("chunk size must be between 1 and {}", MAX_CHUNK_SIZE)))
+        }
+
+        let sender = Sender {
+            id: MessageId::new(),
+            to,
+            chunk_size,
+            seq: 0,
+            size: 0,
+            digest: Context::new(&SHA256),
+            buffer: Vec::new()
+        };
+
+        let mut message = sender.frame(OPEN);
+        message.insert(VALUE, meta);
+
+        send(wire, message)?;
+
+        Ok(sender)
+    }
+
+    pub fn id(&self) -> MessageId {
+        self.id
+    }
+
+    pub fn to(&self) -> MessageId {
+        self.to
+    }
+
+    // 已经写入的字节数，包括还在缓冲中的
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    // 写入数据，攒够一个块就发送
+    // wire 已满时返回 Error::Full，数据已经被接收，会在下一次 write 或 finish 时继续发送
+    pub fn write(&mut self, wire: &Wire<Message>, data: &[u8]) -> Result<()> {
+        self.digest.update(data);
+        self.size += data.len() as u64;
+        self.buffer.extend_from_slice(data);
+
+        while self.buffer.len() >= self.chunk_size {
+            self.flush_chunk(wire, self.chunk_size)?;
+        }
+
+        Ok(())
+    }
+
+    // 发送剩余的数据和结束消息，wire 已满时返回 Error::Full，可以稍后重试
+    pub fn finish(&mut self, wire: &Wire<Message>) -> Result<()> {
+        while !self.buffer.is_empty() {
+            self.flush_chunk(wire, self.chunk_size.min(self.buffer.len()))?;
+        }
+
+        let mut message = self.frame(END);
+        message.insert(SEQ, self.seq);
+        message.insert(SIZE, self.size);
+        message.insert(DIGEST, self.digest.clone().finish().as_ref().to_vec());
+
+        send(wire, message)
+    }
+
+    pub fn cancel(self, wire: &Wire<Message>, reason: &str) -> Result<()> {
+        let mut message = self.frame(CANCEL);
+        message.insert(REASON, reason);
+
+        send(wire, message)
+    }
+
+    fn flush_chunk(&mut self, wire: &Wire<Message>, len: usize) -> Result<()> {
+        let mut message = self.frame(DATA);
+        message.insert(SEQ, self.seq);
+        message.insert(VALUE, self.buffer[..len].to_vec());
+
+        send(wire, message)?;
+
+        self.buffer.drain(..len);
+        self.seq += 1;
+
+        Ok(())
+    }
+
+    fn frame(&self, event: &str) -> Message {
+        msg!{
+            CHAN: STREAM,
+            TO: self.to,
+            STREAM_ID: self.id,
+            EVENT: event
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StreamEvent {
+    Open {
+        id: MessageId,
+        from: MessageId,
+        meta: Message
+    },
+    Data {
+        id: MessageId,
+        data: Vec<u8>
+    },
+    // 传输完成，长度和 SHA256 都已校验
+    End {
+        id: MessageId,
+        size: u64
+    },
+    // 对方取消了传输，id 可能是接收的流，也可能是自己发送的流
+    Cancel {
+        id: MessageId,
+        reason: String
+    },
+    // 校验失败，已经通知发送方取消
+    Error {
+        id: MessageId,
+        reason: String
+    }
+}
+
+struct Incoming {
+    from: MessageId,
+    seq: u32,
+    size: u64,
+    digest: Context
+}
+
+// 接收并重组数据流，收到的消息交给 handle 处理
+#[derive(Default)]
+pub struct Receiver {
+    streams: HashMap<MessageId, Incoming>
+}
+
+impl Receiver {
+    pub fn new() -> Self {
+        Receiver::default()
+    }
+
+    // 正在接收的流的数量
+    pub fn len(&self) -> usize {
+        self.streams.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.streams.is_empty()
+    }
+
+    // 不是 STREAM 消息，或者是无效的 STREAM 消息时返回 None
+    // 流建立后只接受 OPEN 的发送方发来的消息，其它 FROM 的消息被忽略
+    pub fn handle(&mut self, wire: &Wire<Message>, message: &Message) -> Option<StreamEvent> {
+        if message.get_str(CHAN) != Ok(STREAM) {
+            return None
+        }
+
+        let id = *message.get_message_id(STREAM_ID).ok()?;
+        let from = *message.get_message_id(FROM).ok()?;
+
+        match message.get_str(EVENT).ok()? {
+            OPEN => {
+                // 不能覆盖其它发送方正在传输的流
+                if matches!(self.streams.get(&id), Some(incoming) if incoming.from != from) {
+                    return None
+                }
+
+                let meta = message.get_message(VALUE).cloned().unwrap_or_default();
+
+                self.streams.insert(id, Incoming {
+                    from,
+                    seq: 0,
+                    size: 0,
+                    digest: Context::new(&SHA256)
+                });
+
+                Some(StreamEvent::Open { id, from, meta })
+            }
+            DATA => {
+                let incoming = self.streams.get_mut(&id).filter(|incoming| incoming.from == from)?;
+
+                let data = match message.get(VALUE) {
+                    Some(Value::Binary(data)) => &data.0,
+                    _ => return Some(self.fail(wire, id, "invalid chunk"))
+                };
+
+                if message.get_u32(SEQ).ok() != Some(incoming.seq) {
+                    return Some(self.fail(wire, id, "chunk out of order"))
+                }
+
+                incoming.seq += 1;
+                incoming.size += data.len() as u64;
+                incoming.digest.update(data);
+
+                Some(StreamEvent::Data { id, data: data.clone() })
+            }
+            END => {
+                let incoming = self.streams.get(&id).filter(|incoming| incoming.from == from)?;
+
+                if message.get_u32(SEQ).ok() != Some(incoming.seq) {
+                    return Some(self.fail(wire, id, "missing chunks"))
+                }
+
+                if message.get_u64(SIZE).ok() != Some(incoming.size) {
+                    return Some(self.fail(wire, id, "size mismatch"))
+                }
+
+                let digest = incoming.digest.clone().finish();
+
+                match message.get_binary(DIGEST) {
+                    Ok(expect) if expect.0 == digest.as_ref() => (),
+                    _ => return Some(self.fail(wire, id, "digest mismatch"))
+                }
+
+                let incoming = self.streams.remove(&id)?;
+
+                Some(StreamEvent::End { id, size: incoming.size })
+            }
+            CANCEL => {
+                // 只接受流的发送方发来的取消
+                if matches!(self.streams.get(&id), Some(incoming) if incoming.from != from) {
+                    return None
+                }
+
+                self.streams.remove(&id);
+
+                let reason = message.get_str(REASON).unwrap_or_default().to_string();
+
+                Some(StreamEvent::Cancel { id, reason })
+            }
+            _ => None
+        }
+    }
+
+    // 接收方取消传输，通知发送方
+    pub fn cancel(&mut self, wire: &Wire<Message>, id: MessageId, reason: &str) -> Result<()> {
+        let incoming = self.streams.remove(&id).ok_or_else(|| Error::NotFound(format!("stream {}", id)))?;
+
+        send(wire, msg!{
+            CHAN: STREAM,
+            TO: incoming.from,
+            STREAM_ID: id,
+            EVENT: CANCEL,
+            REASON: reason
+        })
+    }
+
+    fn fail(&mut self, wire: &Wire<Message>, id: MessageId, reason: &str) -> StreamEvent {
+        if let Err(err) = self.cancel(wire, id, reason) {
+            log::debug!("stream cancel: {}", err);
+        }
+
+        StreamEvent::Error { id, reason: reason.to_string() }
+    }
+}
+
+fn send(wire: &Wire<Message>, message: Message) -> Result<()> {
+    wire.send(message).map_err(|err| match err {
+        SendError::Full(_) => Error::Full("stream::send".to_string()),
+        SendError::Disconnected(_) => Error::Disconnected("stream::send".to_string())
+    })
+}
@@ -198,7 +198,7 @@ where
 const LEVEL1_LEN: u32 = 1 << 8; // 2^8
 const LEVEL2_LEN: u32 = 1 << 16; // 2^16
 const LEVEL3_LEN: u32 = 1 << 24; // 2^24
-const LEVEL4_LEN: u32 = u32::max_value(); // 2^32
+const LEVEL4_LEN: u32 = u32::MAX; // 2^32
 
 pub fn default_prune<E>(_e: &E) -> bool {
     true
@@ -230,7 +230,7 @@ where
 
     /// Described how many ticks are left before the timer has wrapped around completely
     pub fn remaining(&self) -> u32 {
-        LEVEL4_LEN - (self.current() as u32)
+        LEVEL4_LEN - self.current()
     }
 
     /// Produces a 32-bit timestamp including the current index of every wheel
@@ -415,6 +415,7 @@ where
 }
 
 #[cfg(test)]
+#[allow(clippy::len_zero, clippy::needless_range_loop)]
 mod tests {
     use super::Wheel;
 
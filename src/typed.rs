@@ -0,0 +1,127 @@
+use std::time::Duration;
+use std::marker::PhantomData;
+
+use serde::{Serialize, de::DeserializeOwned};
+
+use nson::{Message, Value, msg};
+
+use crate::Wire;
+use crate::dict::*;
+use crate::error::{Result, Error, Code, ErrorInfo, SendError};
+
+// 将结构体转换为 Message，结构体必须序列化为 Message
+pub fn to_message<T: Serialize>(value: &T) -> Result<Message> {
+    match nson::encode::to_nson(value) {
+        Ok(Value::Message(message)) => Ok(message),
+        Ok(_) => Err(Error::InvalidData("value must be serialized as a message".to_string())),
+        Err(err) => Err(Error::InvalidData(format!("{}", err)))
+    }
+}
+
+// 将 Message 转换为结构体，多余的字段（比如 CHAN、FROM）会被忽略
+pub fn from_message<T: DeserializeOwned>(message: Message) -> Result<T> {
+    nson::decode::from_nson(Value::Message(message)).map_err(|err| Error::InvalidData(format!("{}", err)))
+}
+
+// 绑定到某个 CHAN 的 Wire，发送和接收的都是 T
+// 收到其他 CHAN 的消息时会被丢弃
+pub struct TypedWire<T> {
+    wire: Wire<Message>,
+    chan: String,
+    _phantom: PhantomData<T>
+}
+
+impl<T: Serialize + DeserializeOwned> TypedWire<T> {
+    pub fn new(wire: Wire<Message>, chan: impl Into<String>) -> Self {
+        TypedWire {
+            wire,
+            chan: chan.into(),
+            _phantom: PhantomData
+        }
+    }
+
+    pub fn wire(&self) -> &Wire<Message> {
+        &self.wire
+    }
+
+    pub fn chan(&self) -> &str {
+        &self.chan
+    }
+
+    pub fn into_inner(self) -> Wire<Message> {
+        self.wire
+    }
+
+    pub fn attach(&self, timeout: Option<Duration>) -> Result<()> {
+        self.call(ATTACH, timeout)
+    }
+
+    pub fn detach(&self, timeout: Option<Duration>) -> Result<()> {
+        self.call(DETACH, timeout)
+    }
+
+    pub fn send(&self, value: &T) -> Result<()> {
+        let mut message = to_message(value)?;
+        message.insert(CHAN, &self.chan);
+
+        self.wire.send(message).map_err(|err| match err {
+            SendError::Full(_) => Error::Full("TypedWire::send".to_string()),
+            SendError::Disconnected(_) => Error::Disconnected("TypedWire::send".to_string())
+        })
+    }
+
+    pub fn recv(&self) -> Result<T> {
+        loop {
+            let message = self.wire.recv()?;
+
+            if let Some(value) = self.filter(message)? {
+                return Ok(value)
+            }
+        }
+    }
+
+    pub fn wait(&self, timeout: Option<Duration>) -> Result<T> {
+        loop {
+            let message = self.wire.wait(timeout)?;
+
+            if let Some(value) = self.filter(message)? {
+                return Ok(value)
+            }
+        }
+    }
+
+    fn filter(&self, message: Message) -> Result<Option<T>> {
+        if message.get_str(CHAN).ok() != Some(self.chan.as_str()) {
+            return Ok(None)
+        }
+
+        // 发送失败时，比如 SchemaMismatch，会原样返回消息并附带 CODE 和 ERROR
+        if let Some(info) = ErrorInfo::get(&message) {
+            return Err(info.into())
+        }
+
+        from_message(message).map(Some)
+    }
+
+    fn call(&self, chan: &str, timeout: Option<Duration>) -> Result<()> {
+        let id = nson::MessageId::new();
+
+        let _ = self.wire.send(msg!{
+            CHAN: chan,
+            VALUE: &self.chan,
+            ID: id
+        });
+
+        loop {
+            let message = self.wire.wait(timeout)?;
+
+            if message.get_str(CHAN).ok() == Some(chan) && message.get_message_id(ID).ok() == Some(&id) {
+                return match ErrorInfo::get(&message) {
+                    Some(info) => Err(info.into()),
+                    None if Code::get(&message).is_some() => Ok(()),
+                    None => Err(Error::InvalidData("missing code".to_string()))
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,78 @@
+use std::fs;
+use std::path::Path;
+
+use nson::Message;
+
+use crate::error::{Result, Error};
+
+// 加载配置文件，根据扩展名选择格式：
+// .toml => TOML
+// .json => JSON
+// 其他  => NSON
+// TOML 和 JSON 中可以使用 {"$mid": "..."} 这样的写法表示 NSON 的扩展类型
+pub fn load(path: impl AsRef<Path>) -> Result<Message> {
+    let path = path.as_ref();
+
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("toml") => parse_toml(&fs::read_to_string(path)?),
+        Some("json") => parse_json(&fs::read_to_string(path)?),
+        _ => {
+            let bytes = fs::read(path)?;
+
+            Message::from_bytes(&bytes).map_err(|err| Error::InvalidData(format!("{}", err)))
+        }
+    }
+}
+
+pub fn parse_toml(s: &str) -> Result<Message> {
+    let value: toml::Value = toml::from_str(s).map_err(|err| Error::InvalidData(format!("{}", err)))?;
+    let json = serde_json::to_value(value).map_err(|err| Error::InvalidData(format!("{}", err)))?;
+
+    into_message(json)
+}
+
+pub fn parse_json(s: &str) -> Result<Message> {
+    let json: serde_json::Value = serde_json::from_str(s).map_err(|err| Error::InvalidData(format!("{}", err)))?;
+
+    into_message(json)
+}
+
+fn into_message(json: serde_json::Value) -> Result<Message> {
+    if !json.is_object() {
+        return Err(Error::InvalidData("config root must be a table".to_string()))
+    }
+
+    Ok(json.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use nson::MessageId;
+
+    use super::{parse_toml, parse_json};
+
+    #[test]
+    fn test_parse() {
+        let id = MessageId::new();
+
+        let message = parse_toml(&format!(r#"
+            a = 1
+            b = "hello"
+            c = {{ "$mid" = "{}" }}
+
+            [[d]]
+            e = true
+        "#, id.to_hex())).unwrap();
+
+        assert!(message.get_i32("a").unwrap() == 1);
+        assert!(message.get_str("b").unwrap() == "hello");
+        assert!(message.get_message_id("c").unwrap() == &id);
+        assert!(message.get_array("d").unwrap().len() == 1);
+
+        let message2 = parse_json(&format!(r#"{{"a": 1, "b": "hello", "c": {{"$mid": "{}"}}, "d": [{{"e": true}}]}}"#, id.to_hex())).unwrap();
+
+        assert!(message == message2);
+
+        assert!(parse_json("[1, 2]").is_err());
+    }
+}
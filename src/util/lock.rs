@@ -25,7 +25,7 @@ impl<T> Lock<T> {
         }
     }
 
-    pub fn try_lock(&self) -> Option<LockGuard<T>> {
+    pub fn try_lock(&self) -> Option<LockGuard<'_, T>> {
         if match self.lock.compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed) {
             Ok(x) => x,
             Err(x) => x,
@@ -39,7 +39,7 @@ impl<T> Lock<T> {
         })
     }
 
-    pub fn lock(&self) -> LockGuard<T> {
+    pub fn lock(&self) -> LockGuard<'_, T> {
         while match self.lock.compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed) {
             Ok(x) => x,
             Err(x) => x,
@@ -84,7 +84,7 @@ impl<'a, T: ?Sized> Drop for LockGuard<'a, T> {
     }
 }
 
-impl<T: ?Sized + Default> Default for Lock<T> {
+impl<T: Default> Default for Lock<T> {
     fn default() -> Lock<T> {
         Lock::new(Default::default())
     }
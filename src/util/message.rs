@@ -1,8 +1,10 @@
 use std::io::{self, Read};
 use std::io::ErrorKind::InvalidData;
 
+use nson::{Message, Value};
 
 use crate::MAX_MESSAGE_LEN;
+use crate::dict::ORIGIN;
 
 pub fn read_block(reader: &mut impl Read, max_len: Option<usize>) -> io::Result<Vec<u8>> {
     let mut len_bytes = [0u8; 4];
@@ -25,12 +27,70 @@ pub fn read_block(reader: &mut impl Read, max_len: Option<usize>) -> io::Result<
     Ok(buf)
 }
 
+// 判断 SLOT 的属性是否满足过滤条件
+// 过滤条件中的每个字段都必须满足，字段先在 attr 中查找，找不到时再到 attr.ORIGIN（握手消息）中查找
+// 过滤条件的值为数组时，表示满足其中任意一个即可
+pub fn match_attr(attr: &Message, filter: &Message) -> bool {
+    for (key, expect) in filter {
+        let value = match attr.get(key) {
+            Some(value) => value,
+            None => match attr.get_message(ORIGIN).ok().and_then(|origin| origin.get(key)) {
+                Some(value) => value,
+                None => return false
+            }
+        };
+
+        let matched = match expect {
+            Value::Array(array) => array.iter().any(|expect| expect == value),
+            _ => expect == value
+        };
+
+        if !matched {
+            return false
+        }
+    }
+
+    true
+}
+
+// 通配符匹配，`*` 可以匹配任意长度的字符
+pub fn match_chan(pattern: &str, chan: &str) -> bool {
+    let pattern = pattern.as_bytes();
+    let chan = chan.as_bytes();
+
+    let (mut p, mut c) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+
+    while c < chan.len() {
+        if p < pattern.len() && pattern[p] == b'*' {
+            star = Some((p, c));
+            p += 1;
+        } else if p < pattern.len() && pattern[p] == chan[c] {
+            p += 1;
+            c += 1;
+        } else if let Some((star_p, star_c)) = star {
+            p = star_p + 1;
+            c = star_c + 1;
+            star = Some((star_p, star_c + 1));
+        } else {
+            return false
+        }
+    }
+
+    while p < pattern.len() && pattern[p] == b'*' {
+        p += 1;
+    }
+
+    p == pattern.len()
+}
+
 #[cfg(test)]
 mod tests {
     use std::io::Cursor;
 
     use crate::nson::msg;
-    use super::read_block;
+    use crate::dict::ORIGIN;
+    use super::{read_block, match_attr, match_chan};
 
     #[test]
     fn test_read_block() {
@@ -45,4 +105,33 @@ mod tests {
 
         assert!(ret == vec);
     }
+
+    #[test]
+    fn test_match_attr() {
+        let attr = msg!{
+            "a": 1,
+            ORIGIN: {
+                "role": "printer"
+            }
+        };
+
+        assert!(match_attr(&attr, &msg!{}));
+        assert!(match_attr(&attr, &msg!{"a": 1}));
+        assert!(match_attr(&attr, &msg!{"a": 1, "role": "printer"}));
+        assert!(match_attr(&attr, &msg!{"role": ["scanner", "printer"]}));
+        assert!(!match_attr(&attr, &msg!{"a": 2}));
+        assert!(!match_attr(&attr, &msg!{"b": 1}));
+    }
+
+    #[test]
+    fn test_match_chan() {
+        assert!(match_chan("*", "hello"));
+        assert!(match_chan("hello", "hello"));
+        assert!(match_chan("sensor/*", "sensor/1/temp"));
+        assert!(match_chan("sensor/*/temp", "sensor/1/temp"));
+        assert!(match_chan("*/temp", "sensor/temp"));
+        assert!(!match_chan("sensor/*", "device/1"));
+        assert!(!match_chan("hello", "hello2"));
+        assert!(!match_chan("sensor/*/temp", "sensor/1/humidity"));
+    }
 }
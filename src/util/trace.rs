@@ -0,0 +1,202 @@
+use std::fmt;
+use std::convert::TryInto;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use nson::{Message, Array, Value};
+
+use crate::dict::*;
+
+use super::{to_hex, from_hex};
+
+// 最多记录的跳数，超过后只更新 TRACE，不再追加 HOPS
+pub const MAX_HOPS: usize = 64;
+
+// W3C traceparent
+// https://www.w3.org/TR/trace-context/#traceparent-header
+//
+// 00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01
+// 版本-trace id-parent id-flags
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceParent {
+    pub trace_id: [u8; 16],
+    pub parent_id: [u8; 8],
+    pub flags: u8
+}
+
+impl TraceParent {
+    pub fn new() -> Self {
+        TraceParent {
+            trace_id: random_nonzero(),
+            parent_id: random_nonzero(),
+            flags: 1
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        let parts: Vec<&str> = s.split('-').collect();
+
+        if parts.len() != 4 || parts[0] != "00" {
+            return None
+        }
+
+        let trace_id = parse_hex(parts[1])?;
+        let parent_id = parse_hex(parts[2])?;
+        let flags: [u8; 1] = parse_hex(parts[3])?;
+
+        if trace_id == [0; 16] || parent_id == [0; 8] {
+            return None
+        }
+
+        Some(TraceParent {
+            trace_id,
+            parent_id,
+            flags: flags[0]
+        })
+    }
+
+    // 同一个 trace 下新的 span
+    pub fn child(&self) -> Self {
+        TraceParent {
+            trace_id: self.trace_id,
+            parent_id: random_nonzero(),
+            flags: self.flags
+        }
+    }
+}
+
+impl Default for TraceParent {
+    fn default() -> Self {
+        TraceParent::new()
+    }
+}
+
+impl fmt::Display for TraceParent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "00-{}-{}-{:02x}", to_hex(&self.trace_id), to_hex(&self.parent_id), self.flags)
+    }
+}
+
+// 开始追踪，消息中已有 TRACE 时不会覆盖
+pub fn start(message: &mut Message) -> Option<TraceParent> {
+    if let Ok(trace) = message.get_str(TRACE) {
+        return TraceParent::parse(trace)
+    }
+
+    let trace = TraceParent::new();
+    message.insert(TRACE, trace.to_string());
+
+    Some(trace)
+}
+
+// 消息经过一跳时调用，消息中没有 TRACE 或 TRACE 不合法时什么也不做
+// 会生成新的 span，更新 TRACE，并在 HOPS 中追加：
+// {
+//     HOP: $kind,
+//     SPAN: $span_id,
+//     PARENT: $parent_id,
+//     TIME: $unix_millis,
+//     ...$info
+// }
+// 返回追加的 hop
+pub fn hop(message: &mut Message, kind: &str, mut info: Message) -> Option<Message> {
+    let trace = TraceParent::parse(message.get_str(TRACE).ok()?)?;
+    let child = trace.child();
+
+    info.insert(HOP, kind);
+    info.insert(SPAN, to_hex(&child.parent_id));
+    info.insert(PARENT, to_hex(&trace.parent_id));
+    info.insert(TIME, now_millis());
+
+    message.insert(TRACE, child.to_string());
+
+    match message.get_mut(HOPS) {
+        Some(Value::Array(hops)) => {
+            if hops.len() < MAX_HOPS {
+                hops.push(info.clone());
+            }
+        }
+        _ => {
+            let mut hops = Array::new();
+            hops.push(info.clone());
+
+            message.insert(HOPS, hops);
+        }
+    }
+
+    Some(info)
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0)
+}
+
+fn random_nonzero<const N: usize>() -> [u8; N] {
+    loop {
+        let mut bytes = [0u8; N];
+
+        for b in bytes.iter_mut() {
+            *b = rand::random();
+        }
+
+        if bytes != [0u8; N] {
+            return bytes
+        }
+    }
+}
+
+fn parse_hex<const N: usize>(s: &str) -> Option<[u8; N]> {
+    // W3C traceparent 只允许小写
+    if s.bytes().any(|b| b.is_ascii_uppercase()) {
+        return None
+    }
+
+    from_hex(s)?.try_into().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use nson::msg;
+
+    use crate::dict::*;
+
+    use super::{TraceParent, start, hop};
+
+    #[test]
+    fn test_traceparent() {
+        let s = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+        let trace = TraceParent::parse(s).unwrap();
+        assert!(trace.to_string() == s);
+        assert!(trace.flags == 1);
+
+        let child = trace.child();
+        assert!(child.trace_id == trace.trace_id);
+        assert!(child.parent_id != trace.parent_id);
+
+        assert!(TraceParent::parse("01-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01").is_none());
+        assert!(TraceParent::parse("00-00000000000000000000000000000000-00f067aa0ba902b7-01").is_none());
+        assert!(TraceParent::parse("00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01").is_none());
+        assert!(TraceParent::parse("00-4bf92f-00f067aa0ba902b7-01").is_none());
+    }
+
+    #[test]
+    fn test_hop() {
+        let mut message = msg!{};
+        assert!(hop(&mut message, "switch", msg!{}).is_none());
+
+        let trace = start(&mut message).unwrap();
+
+        let hop1 = hop(&mut message, "port.send", msg!{}).unwrap();
+        let hop2 = hop(&mut message, "node.recv", msg!{}).unwrap();
+
+        assert!(hop1.get_str(PARENT).unwrap() == super::to_hex(&trace.parent_id));
+        assert!(hop2.get_str(PARENT).unwrap() == hop1.get_str(SPAN).unwrap());
+
+        let hops = message.get_array(HOPS).unwrap();
+        assert!(hops.len() == 2);
+
+        let now = TraceParent::parse(message.get_str(TRACE).unwrap()).unwrap();
+        assert!(now.trace_id == trace.trace_id);
+        assert!(super::to_hex(&now.parent_id) == hop2.get_str(SPAN).unwrap());
+    }
+}
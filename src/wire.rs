@@ -10,7 +10,8 @@ use std::cell::Cell;
 
 use queen_io::{
     epoll::{Epoll, Token, Ready, EpollOpt, Source},
-    queue::spsc::Queue
+    queue::spsc::Queue,
+    waker::Waker
 };
 
 use queen_io::poll;
@@ -26,6 +27,11 @@ pub struct Wire<T: Send> {
     rx: Queue<result::Result<T, RecvError>>,
     close: Arc<AtomicBool>,
     attr: Arc<Lock<Message>>,
+    // wire 已满时发送方设置的 waker，对端取走消息后唤醒一次
+    tx_space: Arc<Lock<Option<Waker>>>,
+    rx_space: Arc<Lock<Option<Waker>>>,
+    // 断开的原因，只能由 crate 内部设置，比如网络线程
+    reason: Arc<Lock<Option<&'static str>>>,
     send_num: Cell<usize>,
     recv_num: Cell<usize>,
     _not_sync: PhantomData<*const ()>
@@ -51,12 +57,20 @@ impl<T: Send> Wire<T> {
         let close = Arc::new(AtomicBool::new(false));
         let attr = Arc::new(Lock::new(attr));
 
+        let space1 = Arc::new(Lock::new(None));
+        let space2 = Arc::new(Lock::new(None));
+
+        let reason = Arc::new(Lock::new(None));
+
         let wire1 = Wire {
             capacity,
             tx: queue1.clone(),
             rx: queue2.clone(),
             close: close.clone(),
             attr: attr.clone(),
+            tx_space: space1.clone(),
+            rx_space: space2.clone(),
+            reason: reason.clone(),
             send_num: Cell::new(0),
             recv_num: Cell::new(0),
             _not_sync: PhantomData
@@ -68,6 +82,9 @@ impl<T: Send> Wire<T> {
             rx: queue1,
             close,
             attr,
+            tx_space: space2,
+            rx_space: space1,
+            reason,
             send_num: Cell::new(0),
             recv_num: Cell::new(0),
             _not_sync: PhantomData
@@ -82,7 +99,7 @@ impl<T: Send> Wire<T> {
     }
 
     #[inline]
-    pub fn attr(&self) -> LockGuard<Message> {
+    pub fn attr(&self) -> LockGuard<'_, Message> {
         self.attr.lock()
     }
 
@@ -123,6 +140,21 @@ impl<T: Send> Wire<T> {
         Ok(())
     }
 
+    // wire 已满时，对端取走消息后唤醒 waker，只唤醒一次，需要时再次设置
+    // 设置之前对端可能已经取走了消息，设置之后应该再尝试一次 send
+    pub fn notify_space(&self, waker: Waker) {
+        *self.tx_space.lock() = Some(waker);
+    }
+
+    // SLOT 可以发送任意的 CLOSE 消息，Switch 只信任这里设置的原因
+    pub(crate) fn set_reason(&self, reason: &'static str) {
+        *self.reason.lock() = Some(reason);
+    }
+
+    pub(crate) fn reason(&self) -> Option<&'static str> {
+        *self.reason.lock()
+    }
+
     #[inline]
     pub fn send_num(&self) -> usize {
         self.send_num.get()
@@ -131,6 +163,11 @@ impl<T: Send> Wire<T> {
     pub fn recv(&self) -> result::Result<T, RecvError> {
         match self.rx.pop() {
             Some(data) => {
+                // 取出消息之后再检查，发送方设置 waker 之后的重试和这里至少有一个能看到对方
+                if let Some(waker) = self.rx_space.lock().take() {
+                    let _ = waker.wakeup();
+                }
+
                 if data.is_ok() {
                     self.recv_num.set(self.recv_num.get() + 1);
                 }
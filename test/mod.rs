@@ -1,8 +1,27 @@
+#![allow(clippy::bool_comparison, clippy::needless_return)]
+
 use std::net::TcpListener;
 
 mod test_queen;
 mod test_port;
 mod test_hook;
+mod test_acl;
+mod test_auth;
+mod test_schedule;
+mod test_group;
+mod test_typed;
+mod test_trace;
+mod test_audit;
+mod test_broker;
+mod test_will;
+mod test_presence;
+mod test_error;
+mod test_flow;
+mod test_stream;
+mod test_sim;
+mod test_handshake;
+mod test_admission;
+mod test_close;
 
 pub fn get_free_addr() -> String {
     let socket = TcpListener::bind("127.0.0.1:0").unwrap();
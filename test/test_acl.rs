@@ -0,0 +1,193 @@
+use std::time::Duration;
+use std::fs;
+
+use queen::{Socket, Acl};
+use queen::socket::AclRules;
+use queen::nson::{msg, MessageId};
+use queen::dict::*;
+use queen::error::Code;
+
+#[test]
+fn acl_publish_subscribe() {
+    let rules = AclRules::from_message(&msg!{
+        "default": "deny",
+        "rules": [
+            {
+                "root": true
+            },
+            {
+                "attr": {"role": "sensor"},
+                "ops": ["publish"],
+                "chans": ["sensor/*"]
+            },
+            {
+                "attr": {"role": "viewer"},
+                "ops": ["subscribe"],
+                "chans": ["sensor/*"]
+            }
+        ]
+    }).unwrap();
+
+    let socket = Socket::new(MessageId::new(), Acl::new(rules)).unwrap();
+
+    let sensor = socket.connect(MessageId::new(), false, msg!{"role": "sensor"}, None, None).unwrap();
+    let viewer = socket.connect(MessageId::new(), false, msg!{"role": "viewer"}, None, None).unwrap();
+
+    // sensor can't subscribe
+    let _ = sensor.send(msg!{
+        CHAN: ATTACH,
+        VALUE: "sensor/1"
+    });
+
+    let recv = sensor.wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(Code::get(&recv) == Some(Code::PermissionDenied));
+
+    // viewer can subscribe
+    let _ = viewer.send(msg!{
+        CHAN: ATTACH,
+        VALUE: "sensor/1"
+    });
+
+    let recv = viewer.wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(Code::get(&recv) == Some(Code::Ok));
+
+    // viewer can't publish
+    let _ = viewer.send(msg!{
+        CHAN: "sensor/1",
+        "temp": 20
+    });
+
+    let recv = viewer.wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(Code::get(&recv) == Some(Code::PermissionDenied));
+
+    // sensor can publish
+    let _ = sensor.send(msg!{
+        CHAN: "sensor/1",
+        "temp": 21
+    });
+
+    let recv = viewer.wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(recv.get_str(CHAN).unwrap() == "sensor/1");
+    assert!(recv.get_i32("temp").unwrap() == 21);
+
+    // sensor can't publish to other chan
+    let _ = sensor.send(msg!{
+        CHAN: "device/1",
+    });
+
+    let recv = sensor.wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(Code::get(&recv) == Some(Code::PermissionDenied));
+
+    // root can do anything
+    let root = socket.connect(MessageId::new(), true, msg!{}, None, None).unwrap();
+
+    let _ = root.send(msg!{
+        CHAN: ATTACH,
+        VALUE: "device/1"
+    });
+
+    let recv = root.wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(Code::get(&recv) == Some(Code::Ok));
+}
+
+#[test]
+fn acl_reload() {
+    let path = std::env::temp_dir().join(format!("queen_acl_{}.toml", MessageId::new()));
+
+    fs::write(&path, r#"
+        default = "deny"
+
+        [[rules]]
+        root = true
+    "#).unwrap();
+
+    let socket = Socket::new(MessageId::new(), Acl::from_file(&path).unwrap()).unwrap();
+
+    let root = socket.connect(MessageId::new(), true, msg!{}, None, None).unwrap();
+    let wire = socket.connect(MessageId::new(), false, msg!{}, None, None).unwrap();
+
+    let _ = wire.send(msg!{
+        CHAN: ATTACH,
+        VALUE: "hello"
+    });
+
+    let recv = wire.wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(Code::get(&recv) == Some(Code::PermissionDenied));
+
+    fs::write(&path, r#"
+        default = "deny"
+
+        [[rules]]
+        root = true
+
+        [[rules]]
+        ops = ["subscribe"]
+        chans = ["hello"]
+    "#).unwrap();
+
+    // not root
+    let _ = wire.send(msg!{
+        CHAN: CTRL,
+        ACL: true
+    });
+
+    let recv = wire.wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(Code::get(&recv) == Some(Code::PermissionDenied));
+
+    let _ = root.send(msg!{
+        CHAN: CTRL,
+        ACL: true
+    });
+
+    let recv = root.wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(Code::get(&recv) == Some(Code::Ok));
+
+    let _ = wire.send(msg!{
+        CHAN: ATTACH,
+        VALUE: "hello"
+    });
+
+    let recv = wire.wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(Code::get(&recv) == Some(Code::Ok));
+
+    // rules in message
+    let _ = root.send(msg!{
+        CHAN: CTRL,
+        ACL: {
+            "default": "allow",
+            "rules": [{"permit": "deny", "chans": ["world"]}]
+        }
+    });
+
+    let recv = root.wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(Code::get(&recv) == Some(Code::Ok));
+
+    let _ = wire.send(msg!{
+        CHAN: ATTACH,
+        VALUE: "world"
+    });
+
+    let recv = wire.wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(Code::get(&recv) == Some(Code::PermissionDenied));
+
+    // bad rules keep the old ones
+    let _ = root.send(msg!{
+        CHAN: CTRL,
+        ACL: {
+            "default": "maybe"
+        }
+    });
+
+    let recv = root.wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(Code::get(&recv) == Some(Code::BadValue));
+
+    let _ = wire.send(msg!{
+        CHAN: ATTACH,
+        VALUE: "other"
+    });
+
+    let recv = wire.wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(Code::get(&recv) == Some(Code::Ok));
+
+    let _ = fs::remove_file(&path);
+}
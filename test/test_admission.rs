@@ -0,0 +1,124 @@
+use std::time::Duration;
+use std::thread;
+
+use queen::{Socket, Node, Port};
+use queen::nson::{MessageId, msg};
+use queen::node::NodeConfig;
+use queen::net::{NsonCodec, KeepAlive, AdmissionConfig};
+
+use super::get_free_addr;
+
+fn node(addr: &str, admission: AdmissionConfig) -> Node<NsonCodec> {
+    let socket = Socket::new(MessageId::new(), ()).unwrap();
+
+    Node::<NsonCodec>::with_config(
+        socket,
+        2,
+        vec![addr.parse().unwrap()],
+        NodeConfig {
+            admission,
+            ..NodeConfig::default()
+        },
+        ()
+    ).unwrap()
+}
+
+#[test]
+fn max_per_ip() {
+    let addr = get_free_addr();
+
+    let node = node(&addr, AdmissionConfig {
+        max_per_ip: Some(2),
+        ..AdmissionConfig::default()
+    });
+
+    let port = Port::<NsonCodec>::new(KeepAlive::default()).unwrap();
+
+    let wire1 = port.connect(&addr, MessageId::new(), false, msg!{}, None, None).unwrap();
+    let _wire2 = port.connect(&addr, MessageId::new(), false, msg!{}, None, None).unwrap();
+
+    assert!(port.connect(&addr, MessageId::new(), false, msg!{}, None, None).is_err());
+    assert!(node.stats().ip_rejects() == 1);
+
+    // 连接断开后释放名额
+    drop(wire1);
+    thread::sleep(Duration::from_millis(100));
+
+    let _wire3 = port.connect(&addr, MessageId::new(), false, msg!{}, None, None).unwrap();
+    assert!(node.stats().conns() == 2);
+}
+
+#[test]
+fn max_conns() {
+    let addr = get_free_addr();
+
+    let node = node(&addr, AdmissionConfig {
+        max_conns: Some(1),
+        ..AdmissionConfig::default()
+    });
+
+    let port = Port::<NsonCodec>::new(KeepAlive::default()).unwrap();
+
+    let _wire1 = port.connect(&addr, MessageId::new(), false, msg!{}, None, None).unwrap();
+
+    assert!(port.connect(&addr, MessageId::new(), false, msg!{}, None, None).is_err());
+    assert!(node.stats().conn_rejects() == 1);
+}
+
+#[test]
+fn allow_deny() {
+    let addr = get_free_addr();
+
+    let node1 = node(&addr, AdmissionConfig {
+        deny: vec!["127.0.0.0/8".parse().unwrap()],
+        ..AdmissionConfig::default()
+    });
+
+    let port = Port::<NsonCodec>::new(KeepAlive::default()).unwrap();
+
+    assert!(port.connect(&addr, MessageId::new(), false, msg!{}, None, None).is_err());
+    assert!(node1.stats().denied() == 1);
+
+    let addr = get_free_addr();
+
+    let node2 = node(&addr, AdmissionConfig {
+        allow: vec!["10.0.0.0/8".parse().unwrap()],
+        ..AdmissionConfig::default()
+    });
+
+    assert!(port.connect(&addr, MessageId::new(), false, msg!{}, None, None).is_err());
+    assert!(node2.stats().denied() == 1);
+
+    let addr = get_free_addr();
+
+    let node3 = node(&addr, AdmissionConfig {
+        allow: vec!["127.0.0.1".parse().unwrap()],
+        ..AdmissionConfig::default()
+    });
+
+    let _wire = port.connect(&addr, MessageId::new(), false, msg!{}, None, None).unwrap();
+    assert!(node3.stats().denied() == 0);
+}
+
+#[test]
+fn rate() {
+    let addr = get_free_addr();
+
+    let node = node(&addr, AdmissionConfig {
+        rate: Some(1),
+        burst: 2,
+        ..AdmissionConfig::default()
+    });
+
+    let port = Port::<NsonCodec>::new(KeepAlive::default()).unwrap();
+
+    let _wire1 = port.connect(&addr, MessageId::new(), false, msg!{}, None, None).unwrap();
+    let _wire2 = port.connect(&addr, MessageId::new(), false, msg!{}, None, None).unwrap();
+
+    assert!(port.connect(&addr, MessageId::new(), false, msg!{}, None, None).is_err());
+    assert!(node.stats().rate_limited() == 1);
+
+    thread::sleep(Duration::from_millis(1100));
+
+    let _wire3 = port.connect(&addr, MessageId::new(), false, msg!{}, None, None).unwrap();
+}
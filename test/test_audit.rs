@@ -0,0 +1,224 @@
+use std::time::Duration;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::fs;
+
+use queen::{Socket, Node, Port, Audit, Slot};
+use queen::socket;
+use queen::audit::{Sink, FileSink};
+use queen::node::{Credentials, PasswordAuth};
+use queen::nson::{MessageId, Message, msg};
+use queen::net::{NsonCodec, KeepAlive};
+use queen::dict::*;
+use queen::error::Code;
+
+use super::get_free_addr;
+
+#[test]
+fn audit() {
+    let records = Arc::new(Mutex::new(Vec::<Message>::new()));
+    let records2 = records.clone();
+
+    let audit = Audit::with_sink(move |record: &Message| {
+        records2.lock().unwrap().push(record.clone());
+    });
+
+    let socket = Socket::new(MessageId::new(), audit.clone()).unwrap();
+    audit.attach(&socket).unwrap();
+
+    let mut credentials = Credentials::default();
+    credentials.add_password("alice", "123456", true, msg!{});
+
+    let addr = get_free_addr();
+
+    let _node = Node::<NsonCodec>::new(
+        socket.clone(),
+        1,
+        vec![addr.parse().unwrap()],
+        KeepAlive::default(),
+        (PasswordAuth::new(credentials), audit.clone())
+    ).unwrap();
+
+    // root login
+    let port = Port::<NsonCodec>::new(KeepAlive::default()).unwrap();
+    let root = port.connect(&addr, MessageId::new(), true, msg!{USERNAME: "alice", PASSWORD: "123456"}, None, None).unwrap();
+
+    let _ = root.send(msg!{
+        CHAN: ATTACH,
+        VALUE: AUDIT
+    });
+
+    assert!(Code::get(&root.wait(Some(Duration::from_secs(1))).unwrap()) == Some(Code::Ok));
+
+    // failed handshake
+    let ret = port.connect(&addr, MessageId::new(), false, msg!{USERNAME: "alice", PASSWORD: "000000"}, None, None);
+    assert!(ret.is_err());
+
+    let event = root.wait(Some(Duration::from_secs(1))).unwrap();
+    let record = event.get_message(VALUE).unwrap();
+    assert!(record.get_str(EVENT).unwrap() == "handshake");
+    assert!(record.get_i32(CODE).unwrap() == Code::AuthenticationFailed.code());
+    assert!(record.get_str(ADDR).is_ok());
+
+    // permission denied
+    let wire = socket.connect(MessageId::new(), false, msg!{}, None, None).unwrap();
+
+    let _ = wire.send(msg!{
+        CHAN: ATTACH,
+        VALUE: AUDIT
+    });
+
+    assert!(Code::get(&wire.wait(Some(Duration::from_secs(1))).unwrap()) == Some(Code::PermissionDenied));
+
+    let event = root.wait(Some(Duration::from_secs(1))).unwrap();
+    let record = event.get_message(VALUE).unwrap();
+    assert!(record.get_str(EVENT).unwrap() == "denied");
+    assert!(record.get_str(CHAN).unwrap() == ATTACH);
+    assert!(record.get_str(VALUE).unwrap() == AUDIT);
+
+    // can't forge audit records
+    let _ = wire.send(msg!{
+        CHAN: AUDIT,
+        VALUE: {}
+    });
+
+    assert!(Code::get(&wire.wait(Some(Duration::from_secs(1))).unwrap()) == Some(Code::PermissionDenied));
+
+    let event = root.wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(event.get_message(VALUE).unwrap().get_str(CHAN).unwrap() == AUDIT);
+
+    // kill，每次尝试都会记录目标
+    let target = MessageId::new();
+
+    let _ = root.send(msg!{
+        CHAN: SLOT_KILL,
+        SLOT_ID: target
+    });
+
+    assert!(Code::get(&root.wait(Some(Duration::from_secs(1))).unwrap()) == Some(Code::TargetSlotIdNotExist));
+
+    let event = root.wait(Some(Duration::from_secs(1))).unwrap();
+    let record = event.get_message(VALUE).unwrap();
+    assert!(record.get_str(EVENT).unwrap() == "kill");
+    assert!(record.get_str(USERNAME).unwrap() == "alice");
+    assert!(record.get_message_id(VALUE).unwrap() == &target);
+
+    // 非 ROOT 的 kill 被拒绝
+    let _ = wire.send(msg!{
+        CHAN: SLOT_KILL,
+        SLOT_ID: target
+    });
+
+    assert!(Code::get(&wire.wait(Some(Duration::from_secs(1))).unwrap()) == Some(Code::PermissionDenied));
+
+    let event = root.wait(Some(Duration::from_secs(1))).unwrap();
+    let record = event.get_message(VALUE).unwrap();
+    assert!(record.get_str(EVENT).unwrap() == "denied");
+    assert!(record.get_str(CHAN).unwrap() == SLOT_KILL);
+    assert!(record.get_message_id(VALUE).unwrap() == &target);
+
+    // ctrl，记录操作的字段
+    let _ = root.send(msg!{
+        CHAN: CTRL,
+        VALUE: "a",
+        SCHEMA: null
+    });
+
+    assert!(Code::get(&root.wait(Some(Duration::from_secs(1))).unwrap()) == Some(Code::Ok));
+
+    let event = root.wait(Some(Duration::from_secs(1))).unwrap();
+    let record = event.get_message(VALUE).unwrap();
+    assert!(record.get_str(EVENT).unwrap() == "ctrl");
+    assert!(record.get_array(KEY).unwrap().iter().any(|key| key.as_str() == Some(SCHEMA)));
+    assert!(record.get_str(VALUE).unwrap() == "a");
+
+    thread::sleep(Duration::from_millis(100));
+
+    let events: Vec<String> = records.lock().unwrap().iter()
+        .map(|record| record.get_str(EVENT).unwrap().to_string())
+        .collect();
+
+    assert!(events == vec!["login", "handshake", "denied", "denied", "kill", "denied", "ctrl"]);
+}
+
+#[test]
+fn file_sink() {
+    let dir = std::env::temp_dir().join(format!("queen_audit_{}", MessageId::new()));
+    fs::create_dir_all(&dir).unwrap();
+
+    let path = dir.join("audit.log");
+
+    let sink = FileSink::new(&path, 100, 2).unwrap();
+
+    for i in 0..10 {
+        sink.write(&msg!{"i": i, "pad": "0123456789012345678901234567890123456789"});
+    }
+
+    let content = fs::read_to_string(&path).unwrap();
+    let last: serde_json::Value = serde_json::from_str(content.lines().last().unwrap()).unwrap();
+    assert!(last["i"] == 9);
+
+    assert!(dir.join("audit.log.1").exists());
+    assert!(dir.join("audit.log.2").exists());
+    assert!(!dir.join("audit.log.3").exists());
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn user_code() {
+    struct MyHook;
+
+    impl socket::Hook for MyHook {
+        fn emit(&self, _: &Slot, message: &mut Message) -> bool {
+            if message.get_str(CHAN) == Ok("secret") {
+                Code::User(Code::USER_MIN + 1).set(message);
+
+                return false
+            }
+
+            true
+        }
+    }
+
+    let records = Arc::new(Mutex::new(Vec::<Message>::new()));
+    let records2 = records.clone();
+
+    let audit = Audit::with_sink(move |record: &Message| {
+        records2.lock().unwrap().push(record.clone());
+    });
+
+    let socket = Socket::new(MessageId::new(), (audit.clone(), MyHook)).unwrap();
+
+    let wire = socket.connect(MessageId::new(), false, msg!{}, None, None).unwrap();
+
+    let _ = wire.send(msg!{
+        CHAN: ATTACH,
+        VALUE: "reply"
+    });
+
+    assert!(Code::get(&wire.wait(Some(Duration::from_secs(1))).unwrap()) == Some(Code::Ok));
+
+    // Hook 以自定义的错误码拒绝
+    let _ = wire.send(msg!{
+        CHAN: "secret"
+    });
+
+    assert!(Code::get(&wire.wait(Some(Duration::from_secs(1))).unwrap()) == Some(Code::User(Code::USER_MIN + 1)));
+
+    // 转发的消息中的 CODE 不是拒绝
+    let _ = wire.send(msg!{
+        CHAN: "reply",
+        CODE: Code::USER_MIN + 2
+    });
+
+    assert!(wire.wait(Some(Duration::from_secs(1))).unwrap().get_i32(CODE).unwrap() == Code::USER_MIN + 2);
+
+    thread::sleep(Duration::from_millis(100));
+
+    let records = records.lock().unwrap();
+    assert!(records.len() == 1);
+    assert!(records[0].get_str(EVENT).unwrap() == "denied");
+    assert!(records[0].get_str(CHAN).unwrap() == "secret");
+    assert!(records[0].get_i32(CODE).unwrap() == Code::USER_MIN + 1);
+}
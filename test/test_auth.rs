@@ -0,0 +1,129 @@
+use std::time::Duration;
+
+use queen::{Socket, Node, Port};
+use queen::node::{Credentials, PasswordAuth, TokenAuth, ChallengeAuth};
+use queen::nson::{MessageId, msg};
+use queen::net::{NsonCodec, KeepAlive};
+use queen::dict::*;
+use queen::error::{Error, Code};
+
+use super::get_free_addr;
+
+#[test]
+fn password_auth() {
+    let socket = Socket::new(MessageId::new(), ()).unwrap();
+
+    let mut credentials = Credentials::default();
+    credentials.add_password("alice", "123456", false, msg!{"role": "printer"});
+
+    let addr = get_free_addr();
+
+    let _node = Node::<NsonCodec>::new(
+        socket.clone(),
+        1,
+        vec![addr.parse().unwrap()],
+        KeepAlive::default(),
+        PasswordAuth::new(credentials)
+    ).unwrap();
+
+    let port = Port::<NsonCodec>::new(KeepAlive::default()).unwrap();
+
+    let ret = port.connect(&addr, MessageId::new(), false, msg!{USERNAME: "alice", PASSWORD: "654321"}, None, None);
+    assert!(matches!(ret, Err(Error::Reply(ref info)) if info.code == Code::AuthenticationFailed));
+
+    let ret = port.connect(&addr, MessageId::new(), true, msg!{USERNAME: "alice", PASSWORD: "123456"}, None, None);
+    assert!(matches!(ret, Err(Error::Reply(ref info)) if info.code == Code::AuthenticationFailed));
+
+    let wire = port.connect(&addr, MessageId::new(), false, msg!{USERNAME: "alice", PASSWORD: "123456"}, None, None).unwrap();
+    assert!(wire.attr().get(PASSWORD).is_none());
+
+    let _ = wire.send(msg!{
+        CHAN: MINE
+    });
+
+    let recv = wire.wait(Some(Duration::from_secs(1))).unwrap();
+    let origin = recv.get_message(VALUE).unwrap().get_message(ATTR).unwrap().get_message(ORIGIN).unwrap();
+
+    assert!(origin.get_str(USERNAME).unwrap() == "alice");
+    assert!(origin.get_str("role").unwrap() == "printer");
+    assert!(origin.get(PASSWORD).is_none());
+}
+
+#[test]
+fn token_auth() {
+    let socket = Socket::new(MessageId::new(), ()).unwrap();
+
+    let addr = get_free_addr();
+
+    let _node = Node::<NsonCodec>::new(
+        socket.clone(),
+        1,
+        vec![addr.parse().unwrap()],
+        KeepAlive::default(),
+        TokenAuth::new(b"key")
+    ).unwrap();
+
+    let port = Port::<NsonCodec>::new(KeepAlive::default()).unwrap();
+
+    let token = TokenAuth::new(b"other").sign("alice", false, Duration::from_secs(60));
+    let ret = port.connect(&addr, MessageId::new(), false, msg!{TOKEN: token}, None, None);
+    assert!(matches!(ret, Err(Error::Reply(ref info)) if info.code == Code::AuthenticationFailed));
+
+    let token = TokenAuth::new(b"key").sign("alice", false, Duration::from_secs(60));
+    let wire = port.connect(&addr, MessageId::new(), false, msg!{TOKEN: token, "role": "admin"}, None, None).unwrap();
+    assert!(wire.attr().get_str(USERNAME).unwrap() == "alice");
+
+    // 认证后客户端自定义的属性被移除，不能冒充
+    let _ = wire.send(msg!{
+        CHAN: MINE
+    });
+
+    let recv = wire.wait(Some(Duration::from_secs(1))).unwrap();
+    let origin = recv.get_message(VALUE).unwrap().get_message(ATTR).unwrap().get_message(ORIGIN).unwrap();
+
+    assert!(origin.get_str(USERNAME).unwrap() == "alice");
+    assert!(origin.get("role").is_none());
+}
+
+#[test]
+fn challenge_auth() {
+    let socket = Socket::new(MessageId::new(), ()).unwrap();
+
+    let mut credentials = Credentials::default();
+    credentials.add_secret("alice", "secret", true, msg!{});
+
+    let addr = get_free_addr();
+
+    let _node = Node::<NsonCodec>::new(
+        socket.clone(),
+        1,
+        vec![addr.parse().unwrap()],
+        KeepAlive::default(),
+        ChallengeAuth::new(credentials)
+    ).unwrap();
+
+    let port = Port::<NsonCodec>::new(KeepAlive::default()).unwrap();
+
+    let ret = port.connect(&addr, MessageId::new(), false, msg!{USERNAME: "alice"}, None, None);
+    assert!(matches!(ret, Err(Error::PermissionDenied(_))));
+
+    // 不接受客户端自己选择的挑战，服务端仍然会发出新的挑战
+    let slot_id = MessageId::new();
+    let response = ChallengeAuth::response("secret", "00", &slot_id);
+    let ret = port.connect(&addr, slot_id, false, msg!{USERNAME: "alice", CHALLENGE: "00", RESPONSE: response}, None, None);
+    assert!(matches!(ret, Err(Error::PermissionDenied(_))));
+
+    let ret = port.connect_with_secret(&addr, MessageId::new(), false, msg!{USERNAME: "alice"}, "wrong", None, None);
+    assert!(matches!(ret, Err(Error::Reply(ref info)) if info.code == Code::AuthenticationFailed));
+
+    let wire = port.connect_with_secret(&addr, MessageId::new(), true, msg!{USERNAME: "alice"}, "secret", None, None).unwrap();
+    assert!(wire.attr().get_str(USERNAME).unwrap() == "alice");
+    assert!(wire.attr().get(CHALLENGE).is_none());
+
+    let _ = wire.send(msg!{
+        CHAN: PING
+    });
+
+    let recv = wire.wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(Code::get(&recv) == Some(Code::Ok));
+}
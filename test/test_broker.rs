@@ -0,0 +1,254 @@
+use std::time::Duration;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::thread;
+
+use queen::{Port, Broker, BrokerConfig};
+use queen::socket::{DedupConfig, DedupScope};
+use queen::nson::{MessageId, msg};
+use queen::net::{NsonCodec, KeepAlive, CryptoOptions, FlowControl, Overflow, HandshakeConfig};
+use queen::node::{Credentials, TokenAuth};
+use queen::crypto::Method;
+use queen::util::config;
+use queen::dict::*;
+use queen::error::Code;
+
+use super::get_free_addr;
+
+#[test]
+fn parse_config() {
+    let config = BrokerConfig::from_message(&config::parse_toml(r#"
+        listen = ["127.0.0.1:8888", "127.0.0.1:8889"]
+        workers = 2
+        durable = ["a", { chan = "b", capacity = 10 }]
+        queue = [{ chan = "task", capacity = 100 }]
+
+        [keep_alive]
+        idle = 30
+
+        [flow]
+        overflow = "close"
+
+        [handshake]
+        timeout = 10
+
+        [admission]
+        max_per_ip = 10
+        ipv6_prefix = 64
+        deny = ["10.0.0.0/8", "::1"]
+
+        [crypto]
+        methods = ["aes-256-gcm"]
+        secrets = { key = "secret" }
+
+        [auth]
+        token_key = "key"
+        credentials = { users = { alice = { secret = "secret", root = true } } }
+
+        [acl]
+        default = "deny"
+
+        [[rules]]
+        chans = ["orders"]
+        actions = [{ op = "publish", chan = "audit/orders" }]
+
+        [dedup]
+        window = 30
+        scope = "chan"
+    "#).unwrap()).unwrap();
+
+    assert!(config.listen.len() == 2);
+    assert!(config.workers == 2);
+    assert!(config.keep_alive.idle == 30);
+    assert!(config.keep_alive.interval == KeepAlive::default().interval);
+    assert!(config.flow.overflow == Overflow::Close);
+    assert!(config.flow.max_buffer == FlowControl::default().max_buffer);
+    assert!(config.handshake.timeout == Duration::from_secs(10));
+    assert!(config.handshake.max_size == HandshakeConfig::default().max_size);
+    assert!(config.admission.max_per_ip == Some(10));
+    assert!(config.admission.max_conns.is_none());
+    assert!(config.admission.ipv6_prefix == 64);
+    assert!(config.admission.deny.len() == 2);
+    assert!(config.methods.len() == 1);
+    assert!(config.secrets.get("key").unwrap() == "secret");
+    assert!(config.token_key.as_deref() == Some("key"));
+    assert!(config.credentials.as_ref().unwrap().users.get("alice").unwrap().root);
+    assert!(config.acl.get_str("default").unwrap() == "deny");
+    assert!(config.durable == vec![("a".to_string(), BrokerConfig::DEFAULT_DURABLE_CAPACITY), ("b".to_string(), 10)]);
+    assert!(config.queue == vec![("task".to_string(), 100)]);
+    assert!(config.rules.len() == 1);
+    assert!(config.metrics.is_none());
+    assert!(config.dedup == Some(DedupConfig::new(30, DedupConfig::default().capacity, DedupScope::Chan)));
+
+    assert!(BrokerConfig::from_message(&msg!{}).is_err());
+    assert!(BrokerConfig::from_message(&msg!{"listen": "127.0.0.1:8888", "workers": 0}).is_err());
+    assert!(BrokerConfig::from_message(&msg!{"listen": "127.0.0.1:8888", "crypto": {"methods": ["rot13"]}}).is_err());
+    assert!(BrokerConfig::from_message(&msg!{"listen": "127.0.0.1:8888", "acl": {"default": "maybe"}}).is_err());
+    assert!(BrokerConfig::from_message(&msg!{"listen": "127.0.0.1:8888", "flow": {"overflow": "drop"}}).is_err());
+    assert!(BrokerConfig::from_message(&msg!{"listen": "127.0.0.1:8888", "handshake": {"max_pending": 0}}).is_err());
+    assert!(BrokerConfig::from_message(&msg!{"listen": "127.0.0.1:8888", "admission": {"deny": ["10.0.0.0/40"]}}).is_err());
+    assert!(BrokerConfig::from_message(&msg!{"listen": "127.0.0.1:8888", "dedup": {"scope": "user"}}).is_err());
+    assert!(BrokerConfig::from_message(&msg!{"listen": "127.0.0.1:8888", "queue": [{"chan": "task", "capacity": 0}]}).is_err());
+    assert!(BrokerConfig::from_message(&msg!{"listen": "127.0.0.1:8888", "rules": [{"actions": [{"op": "drop", "chan": "a"}, {"op": "copy"}]}]}).is_err());
+    assert!(BrokerConfig::from_message(&msg!{"listen": "127.0.0.1:8888", "auth": {"token_key": ""}}).is_err());
+    assert!(BrokerConfig::from_message(&msg!{"listen": "127.0.0.1:8888"}).unwrap().dedup.is_none());
+    assert!(BrokerConfig::from_message(&msg!{"listen": "127.0.0.1:8888"}).unwrap().credentials.is_none());
+}
+
+#[test]
+fn broker() {
+    let addr = get_free_addr();
+    let metrics = get_free_addr();
+
+    let mut config = BrokerConfig::from_message(&msg!{
+        "listen": &addr,
+        "metrics": &metrics,
+        "durable": ["order"],
+        "crypto": {
+            "methods": ["aes-128-gcm"],
+            "secrets": {
+                "key1": "secret1"
+            }
+        },
+        "acl": {
+            "default": "allow",
+            "rules": [
+                {"permit": "deny", "ops": ["subscribe"], "chans": ["secret"]}
+            ]
+        }
+    }).unwrap();
+
+    let broker = Broker::new(config.clone()).unwrap();
+
+    let port = Port::<NsonCodec>::new(KeepAlive::default()).unwrap();
+
+    let crypto = |method: Method, secret: &str| Some(CryptoOptions {
+        method,
+        secret: secret.to_string()
+    });
+
+    // plain text and unknown method are rejected
+    assert!(port.connect(&addr, MessageId::new(), false, msg!{ACCESS: "key1"}, None, None).is_err());
+    assert!(port.connect(&addr, MessageId::new(), false, msg!{ACCESS: "key1"}, crypto(Method::Aes256Gcm, "secret1"), None).is_err());
+
+    // 没有配置认证时，不能以 ROOT 身份连接
+    assert!(port.connect(&addr, MessageId::new(), true, msg!{ACCESS: "key1"}, crypto(Method::Aes128Gcm, "secret1"), None).is_err());
+
+    let wire1 = port.connect(&addr, MessageId::new(), false, msg!{ACCESS: "key1"}, crypto(Method::Aes128Gcm, "secret1"), None).unwrap();
+    let wire2 = port.connect(&addr, MessageId::new(), false, msg!{ACCESS: "key1"}, crypto(Method::Aes128Gcm, "secret1"), None).unwrap();
+
+    // durable
+    let _ = wire1.send(msg!{
+        CHAN: "order",
+        "n": 1
+    });
+
+    let _ = wire1.send(msg!{
+        CHAN: "order",
+        "n": 2
+    });
+
+    thread::sleep(Duration::from_millis(100));
+
+    let _ = wire2.send(msg!{
+        CHAN: ATTACH,
+        VALUE: "order"
+    });
+
+    assert!(Code::get(&wire2.wait(Some(Duration::from_secs(1))).unwrap()) == Some(Code::Ok));
+    assert!(wire2.wait(Some(Duration::from_secs(1))).unwrap().get_i32("n").unwrap() == 1);
+    assert!(wire2.wait(Some(Duration::from_secs(1))).unwrap().get_i32("n").unwrap() == 2);
+
+    // acl
+    let _ = wire2.send(msg!{
+        CHAN: ATTACH,
+        VALUE: "secret"
+    });
+
+    assert!(Code::get(&wire2.wait(Some(Duration::from_secs(1))).unwrap()) == Some(Code::PermissionDenied));
+
+    // metrics
+    let mut stream = TcpStream::connect(&metrics).unwrap();
+    stream.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+
+    assert!(response.starts_with("HTTP/1.1 200 OK"));
+
+    let body: serde_json::Value = serde_json::from_str(response.split("\r\n\r\n").nth(1).unwrap()).unwrap();
+    assert!(body["slots"].as_u64().unwrap() >= 2);
+    assert!(body["socket_id"].as_str().unwrap() == config.socket_id.to_hex());
+    assert!(body["net_conns"].as_u64().unwrap() >= 1);
+
+    // reload
+    config.acl = msg!{"default": "allow"};
+    config.rules = vec![msg!{"chans": ["order"], "actions": [{"op": "route", "chan": "secret"}]}].into();
+    config.secrets.insert("key2".to_string(), "secret2".to_string());
+    config.methods.push(Method::Aes256Gcm);
+
+    broker.reload(&config).unwrap();
+
+    let _ = wire2.send(msg!{
+        CHAN: ATTACH,
+        VALUE: "secret"
+    });
+
+    assert!(Code::get(&wire2.wait(Some(Duration::from_secs(1))).unwrap()) == Some(Code::Ok));
+
+    // 重新加载的规则生效
+    let _ = wire1.send(msg!{
+        CHAN: "order",
+        "n": 3
+    });
+
+    let recv = wire2.wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(recv.get_str(CHAN).unwrap() == "secret");
+    assert!(recv.get_i32("n").unwrap() == 3);
+
+    assert!(port.connect(&addr, MessageId::new(), false, msg!{ACCESS: "key2"}, crypto(Method::Aes128Gcm, "secret2"), None).is_ok());
+    assert!(port.connect(&addr, MessageId::new(), false, msg!{ACCESS: "key2"}, crypto(Method::Aes256Gcm, "secret2"), None).is_ok());
+
+    broker.stop();
+
+    thread::sleep(Duration::from_millis(100));
+
+    assert!(!broker.running());
+}
+
+#[test]
+fn broker_auth() {
+    let addr = get_free_addr();
+
+    let mut credentials = Credentials::default();
+    credentials.add_password("admin", "123456", true, msg!{});
+    credentials.add_password("alice", "123456", false, msg!{});
+
+    let mut config = BrokerConfig::from_message(&msg!{
+        "listen": &addr,
+        "auth": {
+            "token_key": "key"
+        }
+    }).unwrap();
+
+    config.credentials = Some(credentials);
+
+    let broker = Broker::new(config).unwrap();
+
+    let port = Port::<NsonCodec>::new(KeepAlive::default()).unwrap();
+
+    // 配置认证后，匿名连接被拒绝
+    assert!(port.connect(&addr, MessageId::new(), false, msg!{}, None, None).is_err());
+    assert!(port.connect(&addr, MessageId::new(), true, msg!{}, None, None).is_err());
+
+    // 只有允许 ROOT 的用户可以以 ROOT 身份连接
+    assert!(port.connect(&addr, MessageId::new(), true, msg!{USERNAME: "alice", PASSWORD: "123456"}, None, None).is_err());
+    assert!(port.connect(&addr, MessageId::new(), false, msg!{USERNAME: "alice", PASSWORD: "123456"}, None, None).is_ok());
+    assert!(port.connect(&addr, MessageId::new(), true, msg!{USERNAME: "admin", PASSWORD: "123456"}, None, None).is_ok());
+
+    let token = TokenAuth::new(b"key").sign("bob", false, Duration::from_secs(60));
+    assert!(port.connect(&addr, MessageId::new(), true, msg!{TOKEN: token.clone()}, None, None).is_err());
+    assert!(port.connect(&addr, MessageId::new(), false, msg!{TOKEN: token}, None, None).is_ok());
+
+    broker.stop();
+}
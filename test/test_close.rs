@@ -0,0 +1,161 @@
+use std::time::Duration;
+use std::thread;
+
+use queen::{Socket, Node, Port};
+use queen::nson::{MessageId, msg};
+use queen::net::{NsonCodec, KeepAlive};
+use queen::dict::*;
+use queen::error::Code;
+
+use super::get_free_addr;
+
+#[test]
+fn kill() {
+    let socket = Socket::new(MessageId::new(), ()).unwrap();
+
+    let addr = get_free_addr();
+
+    let _node = Node::<NsonCodec>::new(
+        socket.clone(),
+        1,
+        vec![addr.parse().unwrap()],
+        KeepAlive::default(),
+        ()
+    ).unwrap();
+
+    let root = socket.connect(MessageId::new(), true, msg!{}, None, None).unwrap();
+
+    let _ = root.send(msg!{
+        CHAN: ATTACH,
+        VALUE: SLOT_BREAK
+    });
+
+    assert!(Code::get(&root.wait(Some(Duration::from_secs(1))).unwrap()) == Some(Code::Ok));
+
+    let port = Port::<NsonCodec>::new(KeepAlive::default()).unwrap();
+
+    let slot_id = MessageId::new();
+    let wire = port.connect(&addr, slot_id, false, msg!{}, None, None).unwrap();
+
+    let _ = root.send(msg!{
+        CHAN: SLOT_KILL,
+        SLOT_ID: slot_id
+    });
+
+    assert!(Code::get(&root.wait(Some(Duration::from_secs(1))).unwrap()) == Some(Code::Ok));
+
+    let event = root.wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(event.get_str(CHAN).unwrap() == SLOT_BREAK);
+    assert!(event.get_str(REASON).unwrap() == BREAK_KILLED);
+
+    // 经过网络，客户端同样先收到断开的原因
+    let recv = wire.wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(recv.get_str(CHAN).unwrap() == CLOSE);
+    assert!(recv.get_str(REASON).unwrap() == BREAK_KILLED);
+
+    assert!(wire.wait(Some(Duration::from_secs(1))).is_err());
+}
+
+#[test]
+fn shutdown() {
+    let socket = Socket::new(MessageId::new(), ()).unwrap();
+
+    let addr = get_free_addr();
+
+    let node = Node::<NsonCodec>::new(
+        socket.clone(),
+        1,
+        vec![addr.parse().unwrap()],
+        KeepAlive::default(),
+        ()
+    ).unwrap();
+
+    let root = socket.connect(MessageId::new(), true, msg!{}, None, None).unwrap();
+
+    let _ = root.send(msg!{
+        CHAN: ATTACH,
+        VALUE: SLOT_BREAK
+    });
+
+    assert!(Code::get(&root.wait(Some(Duration::from_secs(1))).unwrap()) == Some(Code::Ok));
+
+    // 客户端关闭，客户端告知的原因不可信，node 一侧视为正常关闭
+    let port = Port::<NsonCodec>::new(KeepAlive::default()).unwrap();
+    let _wire = port.connect(&addr, MessageId::new(), false, msg!{}, None, None).unwrap();
+
+    port.stop();
+
+    let event = root.wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(event.get_str(CHAN).unwrap() == SLOT_BREAK);
+    assert!(event.get_str(REASON).unwrap() == BREAK_CLOSED);
+
+    // node 关闭，客户端收到原因
+    let port = Port::<NsonCodec>::new(KeepAlive::default()).unwrap();
+    let wire = port.connect(&addr, MessageId::new(), false, msg!{}, None, None).unwrap();
+
+    thread::sleep(Duration::from_millis(100));
+
+    node.stop();
+
+    let recv = wire.wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(recv.get_str(CHAN).unwrap() == CLOSE);
+    assert!(recv.get_str(REASON).unwrap() == BREAK_SHUTDOWN);
+
+    let event = root.wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(event.get_str(REASON).unwrap() == BREAK_SHUTDOWN);
+
+    // socket 关闭，所有 SLOT 收到原因
+    socket.stop();
+
+    let recv = root.wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(recv.get_str(CHAN).unwrap() == CLOSE);
+    assert!(recv.get_str(REASON).unwrap() == BREAK_SHUTDOWN);
+}
+
+#[test]
+fn spoofed_reason() {
+    let socket = Socket::new(MessageId::new(), ()).unwrap();
+
+    let addr = get_free_addr();
+
+    let _node = Node::<NsonCodec>::new(
+        socket.clone(),
+        1,
+        vec![addr.parse().unwrap()],
+        KeepAlive::default(),
+        ()
+    ).unwrap();
+
+    let root = socket.connect(MessageId::new(), true, msg!{}, None, None).unwrap();
+
+    let _ = root.send(msg!{
+        CHAN: ATTACH,
+        VALUE: SLOT_BREAK
+    });
+
+    assert!(Code::get(&root.wait(Some(Duration::from_secs(1))).unwrap()) == Some(Code::Ok));
+
+    // 客户端自己填写的原因不可信
+    let port = Port::<NsonCodec>::new(KeepAlive::default()).unwrap();
+    let wire = port.connect(&addr, MessageId::new(), false, msg!{}, None, None).unwrap();
+
+    let _ = wire.send(msg!{
+        CHAN: CLOSE,
+        REASON: BREAK_KILLED
+    });
+
+    let event = root.wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(event.get_str(CHAN).unwrap() == SLOT_BREAK);
+    assert!(event.get_str(REASON).unwrap() == BREAK_CLOSED);
+
+    // 本地的 SLOT 也是一样
+    let local = socket.connect(MessageId::new(), false, msg!{}, None, None).unwrap();
+
+    let _ = local.send(msg!{
+        CHAN: CLOSE,
+        REASON: BREAK_HEARTBEAT
+    });
+
+    let event = root.wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(event.get_str(REASON).unwrap() == BREAK_CLOSED);
+}
@@ -0,0 +1,89 @@
+use std::time::Duration;
+
+use queen::{Socket, Node, Port, Hook, Slot};
+use queen::nson::{MessageId, Message, msg};
+use queen::net::{NsonCodec, KeepAlive};
+use queen::dict::*;
+use queen::error::{Code, Error, ErrorInfo};
+
+use super::get_free_addr;
+
+const QUOTA_EXCEEDED: Code = Code::User(1001);
+
+#[test]
+fn user_code() {
+    struct MyHook;
+
+    impl Hook for MyHook {
+        fn emit(&self, _: &Slot, message: &mut Message) -> bool {
+            if message.get_str(CHAN) == Ok("limited") {
+                ErrorInfo::new(QUOTA_EXCEEDED)
+                    .reason("quota exceeded")
+                    .details(msg!{"limit": 10})
+                    .set(message);
+
+                return false
+            }
+
+            true
+        }
+
+        fn attach(&self, _: &Slot, _: &mut Message, chan: &str) -> bool {
+            chan != "secret"
+        }
+    }
+
+    let socket = Socket::new(MessageId::new(), MyHook).unwrap();
+
+    let addr = get_free_addr();
+
+    let _node = Node::<NsonCodec>::new(
+        socket.clone(),
+        1,
+        vec![addr.parse().unwrap()],
+        KeepAlive::default(),
+        ()
+    ).unwrap();
+
+    let port = Port::<NsonCodec>::new(KeepAlive::default()).unwrap();
+    let wire = port.connect(&addr, MessageId::new(), false, msg!{}, None, None).unwrap();
+
+    // user code survives the round-trip
+    let _ = wire.send(msg!{
+        CHAN: "limited"
+    });
+
+    let recv = wire.wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(Code::get(&recv) == Some(QUOTA_EXCEEDED));
+
+    let err: Error = ErrorInfo::get(&recv).unwrap().into();
+    assert!(err.code() == Some(QUOTA_EXCEEDED));
+
+    match err {
+        Error::Reply(info) => {
+            assert!(info.reason == "quota exceeded");
+            assert!(info.details.unwrap().get_i32("limit").unwrap() == 10);
+        }
+        _ => unreachable!()
+    }
+
+    // built-in codes carry a reason and the offending field
+    let _ = wire.send(msg!{
+        CHAN: ATTACH,
+        VALUE: "secret"
+    });
+
+    let info = ErrorInfo::get(&wire.wait(Some(Duration::from_secs(1))).unwrap()).unwrap();
+    assert!(info.code == Code::PermissionDenied);
+    assert!(info.reason == Code::PermissionDenied.reason());
+
+    let _ = wire.send(msg!{
+        CHAN: ATTACH,
+        VALUE: "hello",
+        SHARE: 1
+    });
+
+    let info = ErrorInfo::get(&wire.wait(Some(Duration::from_secs(1))).unwrap()).unwrap();
+    assert!(info.code == Code::InvalidShareFieldType);
+    assert!(info.field.as_deref() == Some(SHARE));
+}
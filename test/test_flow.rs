@@ -0,0 +1,167 @@
+use std::time::Duration;
+use std::thread;
+
+use queen::{Socket, Node, Port};
+use queen::nson::{MessageId, msg};
+use queen::net::{NsonCodec, KeepAlive, FlowControl, Overflow};
+use queen::dict::*;
+use queen::error::{Code, SendError};
+
+use super::get_free_addr;
+
+#[test]
+fn pause_read() {
+    let socket = Socket::new(MessageId::new(), ()).unwrap();
+
+    let addr = get_free_addr();
+
+    let _node = Node::<NsonCodec>::new(
+        socket.clone(),
+        1,
+        vec![addr.parse().unwrap()],
+        KeepAlive::default(),
+        ()
+    ).unwrap();
+
+    let port = Port::<NsonCodec>::new(KeepAlive::default()).unwrap();
+
+    // wire 的容量很小，不读取时网络线程会暂停读取
+    let sub = port.connect(&addr, MessageId::new(), false, msg!{}, None, Some(4)).unwrap();
+
+    let _ = sub.send(msg!{
+        CHAN: ATTACH,
+        VALUE: "hello"
+    });
+
+    assert!(Code::get(&sub.wait(Some(Duration::from_secs(1))).unwrap()) == Some(Code::Ok));
+
+    let publisher = socket.connect(MessageId::new(), false, msg!{}, None, None).unwrap();
+
+    // 不超过 SLOT wire 的默认容量，避免在 Switch 端被丢弃
+    for i in 0..50 {
+        let _ = publisher.send(msg!{
+            CHAN: "hello",
+            "n": i
+        });
+    }
+
+    thread::sleep(Duration::from_millis(500));
+
+    let stats = port.stats();
+    assert!(stats.conns() == 1);
+    assert!(stats.paused() == 1);
+    assert!(stats.pauses() >= 1);
+
+    // 不丢消息，顺序不变
+    for i in 0..50 {
+        let recv = sub.wait(Some(Duration::from_secs(1))).unwrap();
+        assert!(recv.get_i32("n").unwrap() == i);
+    }
+
+    assert!(stats.paused() == 0);
+}
+
+#[test]
+fn overflow_close() {
+    let socket = Socket::new(MessageId::new(), ()).unwrap();
+
+    let addr = get_free_addr();
+
+    let node = Node::<NsonCodec>::with_flow_control(
+        socket.clone(),
+        1,
+        vec![addr.parse().unwrap()],
+        KeepAlive::default(),
+        FlowControl::new(64 * 1024, Overflow::Close),
+        ()
+    ).unwrap();
+
+    let port = Port::<NsonCodec>::new(KeepAlive::default()).unwrap();
+
+    let sub = port.connect(&addr, MessageId::new(), false, msg!{}, None, Some(4)).unwrap();
+
+    let _ = sub.send(msg!{
+        CHAN: ATTACH,
+        VALUE: "hello"
+    });
+
+    assert!(Code::get(&sub.wait(Some(Duration::from_secs(1))).unwrap()) == Some(Code::Ok));
+
+    let publisher = socket.connect(MessageId::new(), false, msg!{}, None, None).unwrap();
+
+    let data = vec![0u8; 64 * 1024];
+
+    // 订阅方不读取，TCP 缓冲区填满后 node 的写缓冲超出上限，连接被断开
+    for _ in 0..1000 {
+        let mut message = msg!{
+            CHAN: "hello",
+            "data": data.clone()
+        };
+
+        loop {
+            match publisher.send(message) {
+                Ok(()) => break,
+                Err(SendError::Full(m)) => {
+                    message = m;
+                    thread::sleep(Duration::from_millis(1));
+                }
+                Err(err) => panic!("{:?}", err)
+            }
+        }
+
+        if node.stats().overflows() > 0 {
+            break
+        }
+    }
+
+    thread::sleep(Duration::from_millis(100));
+
+    assert!(node.stats().overflows() == 1);
+    assert!(node.stats().conns() == 0);
+    assert!(node.stats().buffered() == 0);
+}
+
+#[test]
+fn small_write_buffer() {
+    let socket = Socket::new(MessageId::new(), ()).unwrap();
+
+    let addr = get_free_addr();
+
+    let _node = Node::<NsonCodec>::with_flow_control(
+        socket.clone(),
+        1,
+        vec![addr.parse().unwrap()],
+        KeepAlive::default(),
+        FlowControl::new(1024, Overflow::Wait),
+        ()
+    ).unwrap();
+
+    let port = Port::<NsonCodec>::new(KeepAlive::default()).unwrap();
+
+    let sub = port.connect(&addr, MessageId::new(), false, msg!{}, None, None).unwrap();
+
+    let _ = sub.send(msg!{
+        CHAN: ATTACH,
+        VALUE: "hello"
+    });
+
+    assert!(Code::get(&sub.wait(Some(Duration::from_secs(1))).unwrap()) == Some(Code::Ok));
+
+    let publisher = socket.connect(MessageId::new(), false, msg!{}, None, None).unwrap();
+
+    let data = vec![0u8; 600];
+
+    // 写缓冲每次只能放下两条消息，写空之后要继续从 wire 中取
+    for i in 0..20 {
+        let _ = publisher.send(msg!{
+            CHAN: "hello",
+            "n": i,
+            "data": data.clone()
+        });
+    }
+
+    for i in 0..20 {
+        let recv = sub.wait(Some(Duration::from_secs(1))).unwrap();
+        assert!(recv.get_i32("n").unwrap() == i);
+    }
+}
@@ -0,0 +1,189 @@
+use std::time::Duration;
+use std::thread;
+
+use nson::{msg, MessageId};
+
+use queen::{Socket, Wire};
+use queen::nson::Message;
+use queen::dict::*;
+use queen::error::Code;
+
+fn attach(wire: &Wire<Message>, chan: &str, group: &str, strategy: &str) {
+    let _ = wire.send(msg!{
+        CHAN: ATTACH,
+        VALUE: chan,
+        GROUP: group,
+        STRATEGY: strategy
+    });
+
+    let recv = wire.wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(Code::get(&recv) == Some(Code::Ok));
+
+    let recv = wire.wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(recv.get_str(CHAN).unwrap() == GROUP_JOIN);
+}
+
+fn count(wires: &[&Wire<Message>]) -> Vec<usize> {
+    wires.iter().map(|wire| {
+        let mut n = 0;
+
+        while wire.wait(Some(Duration::from_millis(100))).is_ok() {
+            n += 1;
+        }
+
+        n
+    }).collect()
+}
+
+#[test]
+fn round_robin() {
+    let socket = Socket::new(MessageId::new(), ()).unwrap();
+
+    let sender = socket.connect(MessageId::new(), false, msg!{}, None, None).unwrap();
+    let wire1 = socket.connect(MessageId::new(), false, msg!{}, None, None).unwrap();
+    let wire2 = socket.connect(MessageId::new(), false, msg!{}, None, None).unwrap();
+    let wire3 = socket.connect(MessageId::new(), false, msg!{}, None, None).unwrap();
+
+    attach(&wire1, "aaa", "g1", "round_robin");
+    attach(&wire2, "aaa", "g1", "round_robin");
+    // other group gets every message
+    attach(&wire3, "aaa", "g2", "random");
+
+    // wire1 receives the join event of wire2
+    let recv = wire1.wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(recv.get_str(CHAN).unwrap() == GROUP_JOIN);
+    assert!(recv.get_array(MEMBERS).unwrap().len() == 2);
+
+    for _ in 0..10 {
+        let _ = sender.send(msg!{
+            CHAN: "aaa"
+        });
+    }
+
+    assert!(count(&[&wire1, &wire2, &wire3]) == vec![5, 5, 10]);
+
+    // conflict strategy
+    let _ = wire3.send(msg!{
+        CHAN: ATTACH,
+        VALUE: "aaa",
+        GROUP: "g1",
+        STRATEGY: "hash"
+    });
+
+    let recv = wire3.wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(Code::get(&recv) == Some(Code::BadValue));
+
+    let _ = wire3.send(msg!{
+        CHAN: ATTACH,
+        VALUE: "aaa",
+        GROUP: "g1",
+        STRATEGY: "eat"
+    });
+
+    let recv = wire3.wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(Code::get(&recv) == Some(Code::InvalidStrategyFieldType));
+
+    // leave
+    drop(wire2);
+
+    let recv = wire1.wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(recv.get_str(CHAN).unwrap() == GROUP_LEAVE);
+    assert!(recv.get_array(MEMBERS).unwrap().len() == 1);
+
+    for _ in 0..4 {
+        let _ = sender.send(msg!{
+            CHAN: "aaa"
+        });
+    }
+
+    assert!(count(&[&wire1, &wire3]) == vec![4, 4]);
+
+    let _ = wire3.send(msg!{
+        CHAN: DETACH,
+        VALUE: "aaa",
+        GROUP: "g2"
+    });
+
+    let recv = wire3.wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(Code::get(&recv) == Some(Code::Ok));
+
+    let _ = sender.send(msg!{
+        CHAN: "aaa"
+    });
+
+    assert!(count(&[&wire1, &wire3]) == vec![1, 0]);
+}
+
+#[test]
+fn hash() {
+    let socket = Socket::new(MessageId::new(), ()).unwrap();
+
+    let sender = socket.connect(MessageId::new(), false, msg!{}, None, None).unwrap();
+    let wire1 = socket.connect(MessageId::new(), false, msg!{}, None, None).unwrap();
+    let wire2 = socket.connect(MessageId::new(), false, msg!{}, None, None).unwrap();
+
+    attach(&wire1, "aaa", "g1", "hash");
+    attach(&wire2, "aaa", "g1", "hash");
+
+    let _ = wire1.wait(Some(Duration::from_secs(1))).unwrap();
+
+    for i in 0..20 {
+        let _ = sender.send(msg!{
+            CHAN: "aaa",
+            KEY: format!("key-{}", i % 4),
+            "i": i
+        });
+    }
+
+    // messages with the same key go to the same member, in order
+    let mut owner: Vec<Option<usize>> = vec![None; 4];
+
+    for (n, wire) in [&wire1, &wire2].iter().enumerate() {
+        let mut last: Vec<i32> = vec![-1; 4];
+
+        while let Ok(recv) = wire.wait(Some(Duration::from_millis(100))) {
+            let i = recv.get_i32("i").unwrap();
+            let key = (i % 4) as usize;
+
+            assert!(owner[key].is_none() || owner[key] == Some(n));
+            owner[key] = Some(n);
+
+            assert!(i > last[key]);
+            last[key] = i;
+        }
+    }
+
+    assert!(owner.iter().all(|o| o.is_some()));
+}
+
+#[test]
+fn least_pending() {
+    let socket = Socket::new(MessageId::new(), ()).unwrap();
+
+    let sender = socket.connect(MessageId::new(), false, msg!{}, None, None).unwrap();
+    let wire1 = socket.connect(MessageId::new(), false, msg!{}, None, None).unwrap();
+    let wire2 = socket.connect(MessageId::new(), false, msg!{}, None, None).unwrap();
+
+    attach(&wire1, "aaa", "g1", "least_pending");
+    attach(&wire2, "aaa", "g1", "least_pending");
+
+    let _ = wire1.wait(Some(Duration::from_secs(1))).unwrap();
+
+    // nobody reads, so the messages are spread evenly
+    for _ in 0..10 {
+        let _ = sender.send(msg!{
+            CHAN: "aaa"
+        });
+    }
+
+    thread::sleep(Duration::from_millis(100));
+
+    assert!(count(&[&wire1, &wire2]) == vec![5, 5]);
+
+    let _ = wire1.send(msg!{
+        CHAN: MINE
+    });
+
+    let recv = wire1.wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(recv.get_message(VALUE).unwrap().get_array(GROUPS).unwrap().len() == 1);
+}
@@ -0,0 +1,211 @@
+use std::time::{Duration, Instant};
+use std::thread;
+use std::net::TcpStream;
+use std::io::Read;
+use std::sync::Mutex;
+
+use queen::{Socket, Node, Port, Wire};
+use queen::nson::{Message, MessageId, msg};
+use queen::node::{NodeConfig, Connector};
+use queen::dict::*;
+use queen::error::Result;
+use queen::net::{NsonCodec, KeepAlive, FlowControl, HandshakeConfig};
+
+use super::get_free_addr;
+
+#[test]
+fn slow_client() {
+    let socket = Socket::new(MessageId::new(), ()).unwrap();
+
+    let addr = get_free_addr();
+
+    let node = Node::<NsonCodec>::with_config(
+        socket.clone(),
+        1,
+        vec![addr.parse().unwrap()],
+        NodeConfig {
+            handshake: HandshakeConfig::new(2048, Duration::from_secs(1), 16),
+            ..NodeConfig::default()
+        },
+        ()
+    ).unwrap();
+
+    let stats = node.stats();
+
+    // 连接后不发送握手消息
+    let mut slow = TcpStream::connect(&addr).unwrap();
+
+    thread::sleep(Duration::from_millis(100));
+    assert!(stats.handshakes() == 1);
+
+    // 不影响其他连接的握手
+    let port = Port::<NsonCodec>::new(KeepAlive::default()).unwrap();
+
+    let start = Instant::now();
+    let _wire = port.connect(&addr, MessageId::new(), false, msg!{}, None, None).unwrap();
+    assert!(start.elapsed() < Duration::from_millis(500));
+
+    assert!(stats.handshakes() == 1);
+    assert!(stats.conns() == 1);
+
+    // 超时后断开
+    slow.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+
+    let mut buf = [0u8; 16];
+    assert!(slow.read(&mut buf).unwrap() == 0);
+
+    assert!(stats.handshakes() == 0);
+    assert!(stats.hand_timeouts() == 1);
+}
+
+#[test]
+fn max_size() {
+    let socket = Socket::new(MessageId::new(), ()).unwrap();
+
+    let addr = get_free_addr();
+
+    let node = Node::<NsonCodec>::with_config(
+        socket.clone(),
+        1,
+        vec![addr.parse().unwrap()],
+        NodeConfig {
+            handshake: HandshakeConfig::new(256, Duration::from_secs(5), 16),
+            ..NodeConfig::default()
+        },
+        ()
+    ).unwrap();
+
+    let port = Port::<NsonCodec>::new(KeepAlive::default()).unwrap();
+
+    assert!(port.connect(&addr, MessageId::new(), false, msg!{"data": vec![0u8; 512]}, None, None).is_err());
+    let _wire = port.connect(&addr, MessageId::new(), false, msg!{"data": vec![0u8; 16]}, None, None).unwrap();
+
+    thread::sleep(Duration::from_millis(100));
+    assert!(node.stats().handshakes() == 0);
+    assert!(node.stats().conns() == 1);
+
+    // Port 一侧的限制
+    let port = Port::<NsonCodec>::with_config(
+        KeepAlive::default(),
+        FlowControl::default(),
+        HandshakeConfig::new(64, Duration::from_secs(5), 0)
+    ).unwrap();
+
+    // 握手消息会被发回，超过了 Port 的限制
+    assert!(port.connect(&addr, MessageId::new(), false, msg!{"data": vec![0u8; 64]}, None, None).is_err());
+}
+
+#[test]
+fn max_pending() {
+    let socket = Socket::new(MessageId::new(), ()).unwrap();
+
+    let addr = get_free_addr();
+
+    let node = Node::<NsonCodec>::with_config(
+        socket.clone(),
+        2,
+        vec![addr.parse().unwrap()],
+        NodeConfig {
+            handshake: HandshakeConfig::new(2048, Duration::from_secs(5), 2),
+            ..NodeConfig::default()
+        },
+        ()
+    ).unwrap();
+
+    let stats = node.stats();
+
+    let _slow1 = TcpStream::connect(&addr).unwrap();
+    let _slow2 = TcpStream::connect(&addr).unwrap();
+
+    thread::sleep(Duration::from_millis(100));
+    assert!(stats.handshakes() == 2);
+
+    let port = Port::<NsonCodec>::new(KeepAlive::default()).unwrap();
+
+    assert!(port.connect(&addr, MessageId::new(), false, msg!{}, None, None).is_err());
+    assert!(stats.hand_rejects() == 1);
+
+    drop(_slow1);
+
+    thread::sleep(Duration::from_millis(100));
+    assert!(stats.handshakes() == 1);
+
+    assert!(port.connect(&addr, MessageId::new(), false, msg!{}, None, None).is_ok());
+}
+
+// 等待 Socket 回复的连接不会阻塞网络线程
+struct SlowConnector {
+    socket: Socket,
+    pending: Mutex<Vec<Wire<Message>>>
+}
+
+impl Connector for SlowConnector {
+    fn connect(
+        &self,
+        slot_id: MessageId,
+        root: bool,
+        attr: Message,
+        capacity: Option<usize>
+    ) -> Result<Wire<Message>> {
+        if attr.get_message(ORIGIN).unwrap().contains_key("slow") {
+            // 一直不回复
+            let (wire1, wire2) = Wire::pipe(4, attr)?;
+            self.pending.lock().unwrap().push(wire1);
+
+            return Ok(wire2)
+        }
+
+        self.socket.connect_nowait(slot_id, root, attr, capacity)
+    }
+
+    fn running(&self) -> bool {
+        self.socket.running()
+    }
+}
+
+#[test]
+fn slow_connector() {
+    let socket = Socket::new(MessageId::new(), ()).unwrap();
+
+    let addr = get_free_addr();
+
+    let connector = SlowConnector {
+        socket: socket.clone(),
+        pending: Mutex::new(Vec::new())
+    };
+
+    let node = Node::<NsonCodec>::with_config(
+        connector,
+        1,
+        vec![addr.parse().unwrap()],
+        NodeConfig {
+            handshake: HandshakeConfig::new(2048, Duration::from_secs(1), 16),
+            ..NodeConfig::default()
+        },
+        ()
+    ).unwrap();
+
+    let addr2 = addr.clone();
+
+    let slow = thread::spawn(move || {
+        let port = Port::<NsonCodec>::new(KeepAlive::default()).unwrap();
+        port.connect(&addr2, MessageId::new(), false, msg!{"slow": true}, None, None).is_err()
+    });
+
+    thread::sleep(Duration::from_millis(100));
+    assert!(node.stats().handshakes() == 1);
+
+    // 只有一个网络线程，仍然可以完成其他连接的握手
+    let port = Port::<NsonCodec>::new(KeepAlive::default()).unwrap();
+
+    let start = Instant::now();
+    let _wire = port.connect(&addr, MessageId::new(), false, msg!{}, None, None).unwrap();
+    assert!(start.elapsed() < Duration::from_millis(500));
+
+    // 等待连接的结果超时
+    assert!(slow.join().unwrap());
+
+    assert!(node.stats().handshakes() == 0);
+    assert!(node.stats().hand_timeouts() == 1);
+    assert!(node.stats().conns() == 1);
+}
@@ -6,6 +6,7 @@ use std::time::Duration;
 use std::thread;
 
 use queen::{Socket, Hook, Switch, Slot};
+use queen::socket::HookChain;
 use queen::nson::{msg, MessageId, Message};
 use queen::dict::*;
 use queen::error::Code;
@@ -219,3 +220,83 @@ fn test_hook() {
 
     assert!(hook.run() == false);
 }
+
+#[test]
+fn test_hook_chain() {
+    struct Counter {
+        removes: Arc<AtomicUsize>
+    }
+
+    impl Hook for Counter {
+        fn remove(&self, _: &Slot) {
+            self.removes.fetch_add(1, Ordering::SeqCst);
+        }
+
+        fn attach(&self, _: &Slot, message: &mut Message, _chan: &str) -> bool {
+            message.insert("count", 1);
+
+            true
+        }
+    }
+
+    struct Deny;
+
+    impl Hook for Deny {
+        fn attach(&self, _: &Slot, message: &mut Message, chan: &str) -> bool {
+            if chan == "deny" {
+                return false
+            }
+
+            // 能看到前一个 Hook 的修改
+            let count = message.get_i32("count").unwrap();
+            message.insert("count", count + 1);
+
+            true
+        }
+    }
+
+    struct Never;
+
+    impl Hook for Never {
+        fn attach(&self, _: &Slot, _: &mut Message, _chan: &str) -> bool {
+            panic!("short-circuit")
+        }
+    }
+
+    let removes = Arc::new(AtomicUsize::new(0));
+
+    let chain = HookChain::new()
+        .with(Counter { removes: removes.clone() })
+        .with(Counter { removes: removes.clone() });
+
+    let socket = Socket::new(MessageId::new(), (chain, Deny, ())).unwrap();
+
+    let wire1 = socket.connect(MessageId::new(), false, msg!{}, None, None).unwrap();
+
+    let _ = wire1.send(msg!{
+        CHAN: ATTACH,
+        VALUE: "hello"
+    });
+
+    let recv = wire1.wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(Code::get(&recv) == Some(Code::Ok));
+    assert!(recv.get_i32("count").unwrap() == 2);
+
+    drop(wire1);
+
+    thread::sleep(Duration::from_millis(100));
+
+    assert!(removes.load(Ordering::SeqCst) == 2);
+
+    let socket = Socket::new(MessageId::new(), (Deny, Never)).unwrap();
+
+    let wire1 = socket.connect(MessageId::new(), false, msg!{}, None, None).unwrap();
+
+    let _ = wire1.send(msg!{
+        CHAN: ATTACH,
+        VALUE: "deny"
+    });
+
+    let recv = wire1.wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(Code::get(&recv) == Some(Code::PermissionDenied));
+}
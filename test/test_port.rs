@@ -1,4 +1,5 @@
 use std::time::Duration;
+use std::thread;
 
 use queen::{Socket, Node, Port, Wire};
 use queen::node::Hook;
@@ -70,6 +71,36 @@ fn port() {
     assert!(recv.get_str("hello").unwrap() == "world");
 }
 
+#[test]
+fn heartbeat() {
+    let socket = Socket::new(MessageId::new(), ()).unwrap();
+
+    let addr = get_free_addr();
+
+    let _node = Node::<NsonCodec>::new(
+        socket.clone(),
+        1,
+        vec![addr.parse().unwrap()],
+        KeepAlive::new(1, 1, 3),
+        ()
+    ).unwrap();
+
+    let port = Port::<NsonCodec>::new(KeepAlive::new(1, 1, 3)).unwrap();
+
+    let wire = port.connect(addr, MessageId::new(), false, msg!{HEARTBEAT: 2}, None, None).unwrap();
+
+    // 空闲的连接只有网络层的 KEEP_ALIVE，同样算作心跳，不会被断开
+    thread::sleep(Duration::from_secs(4));
+
+    let _ = wire.send(msg!{
+        CHAN: PING
+    });
+
+    let recv = wire.wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(recv.get_i32(CODE).unwrap() == 0);
+    assert!(!wire.is_close());
+}
+
 #[test]
 fn port_secure() {
     // start node
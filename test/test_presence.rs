@@ -0,0 +1,235 @@
+use std::time::Duration;
+
+use queen::{Socket, Hook, Slot};
+use queen::nson::{MessageId, Message, msg};
+use queen::dict::*;
+use queen::error::Code;
+
+#[test]
+fn lookup() {
+    let socket = Socket::new(MessageId::new(), ()).unwrap();
+
+    let printer1 = socket.connect(MessageId::new(), false, msg!{"role": "printer", "floor": 1}, None, None).unwrap();
+    let printer2 = socket.connect(MessageId::new(), false, msg!{"role": "printer", "floor": 2}, None, None).unwrap();
+    let client = socket.connect(MessageId::new(), false, msg!{"role": "client"}, None, None).unwrap();
+
+    let _ = client.send(msg!{
+        CHAN: LOOKUP,
+        ATTR: {"role": "printer"}
+    });
+
+    let recv = client.wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(Code::get(&recv) == Some(Code::Ok));
+    assert!(recv.get_array(SLOTS).unwrap().len() == 2);
+
+    let _ = client.send(msg!{
+        CHAN: LOOKUP,
+        ATTR: {"role": "printer", "floor": [2, 3]}
+    });
+
+    let recv = client.wait(Some(Duration::from_secs(1))).unwrap();
+    let slots = recv.get_array(SLOTS).unwrap();
+    assert!(slots.len() == 1);
+    assert!(slots[0].as_message().unwrap().get_message(ATTR).unwrap().get_i32("floor").unwrap() == 2);
+
+    let _ = client.send(msg!{
+        CHAN: LOOKUP
+    });
+
+    let recv = client.wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(recv.get_array(SLOTS).unwrap().len() == 3);
+
+    let _ = client.send(msg!{
+        CHAN: LOOKUP,
+        ATTR: "printer"
+    });
+
+    assert!(Code::get(&client.wait(Some(Duration::from_secs(1))).unwrap()) == Some(Code::InvalidAttrFieldType));
+
+    // send to all matching slots
+    let _ = client.send(msg!{
+        CHAN: "print",
+        TO_ATTR: {"role": "printer"},
+        "doc": 1
+    });
+
+    assert!(printer1.wait(Some(Duration::from_secs(1))).unwrap().get_i32("doc").unwrap() == 1);
+    assert!(printer2.wait(Some(Duration::from_secs(1))).unwrap().get_i32("doc").unwrap() == 1);
+    assert!(client.wait(Some(Duration::from_millis(100))).is_err());
+
+    // send to any matching slot
+    let _ = client.send(msg!{
+        CHAN: "print",
+        TO_ATTR: {"role": "printer"},
+        SHARE: true,
+        "doc": 2
+    });
+
+    let recv1 = printer1.wait(Some(Duration::from_millis(100)));
+    let recv2 = printer2.wait(Some(Duration::from_millis(100)));
+    assert!(recv1.is_ok() != recv2.is_ok());
+}
+
+#[test]
+fn watch() {
+    let socket = Socket::new(MessageId::new(), ()).unwrap();
+
+    let client = socket.connect(MessageId::new(), false, msg!{}, None, None).unwrap();
+
+    let _ = client.send(msg!{
+        CHAN: WATCH,
+        ATTR: {"role": "printer"}
+    });
+
+    assert!(Code::get(&client.wait(Some(Duration::from_secs(1))).unwrap()) == Some(Code::Ok));
+
+    let slot_id = MessageId::new();
+    let printer = socket.connect(slot_id, false, msg!{"role": "printer"}, None, None).unwrap();
+
+    let recv = client.wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(recv.get_str(CHAN).unwrap() == PRESENCE);
+    assert!(recv.get_str(EVENT).unwrap() == "online");
+    assert!(recv.get_message_id(SLOT_ID).unwrap() == &slot_id);
+
+    // not matching
+    let _other = socket.connect(MessageId::new(), false, msg!{"role": "scanner"}, None, None).unwrap();
+    assert!(client.wait(Some(Duration::from_millis(100))).is_err());
+
+    drop(printer);
+
+    let recv = client.wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(recv.get_str(EVENT).unwrap() == "offline");
+    assert!(recv.get_message_id(SLOT_ID).unwrap() == &slot_id);
+
+    let _ = client.send(msg!{
+        CHAN: UNWATCH,
+        ATTR: {"role": "printer"}
+    });
+
+    assert!(Code::get(&client.wait(Some(Duration::from_secs(1))).unwrap()) == Some(Code::Ok));
+
+    let _printer = socket.connect(MessageId::new(), false, msg!{"role": "printer"}, None, None).unwrap();
+    assert!(client.wait(Some(Duration::from_millis(100))).is_err());
+}
+
+#[test]
+fn lookup_hook() {
+    struct MyHook;
+
+    impl Hook for MyHook {
+        fn lookup(&self, slot: &Slot, _: &mut Message) -> bool {
+            slot.root
+        }
+    }
+
+    let socket = Socket::new(MessageId::new(), MyHook).unwrap();
+
+    let client = socket.connect(MessageId::new(), false, msg!{}, None, None).unwrap();
+
+    let _ = client.send(msg!{
+        CHAN: LOOKUP
+    });
+
+    assert!(Code::get(&client.wait(Some(Duration::from_secs(1))).unwrap()) == Some(Code::PermissionDenied));
+
+    let _ = client.send(msg!{
+        CHAN: WATCH
+    });
+
+    assert!(Code::get(&client.wait(Some(Duration::from_secs(1))).unwrap()) == Some(Code::PermissionDenied));
+
+    let printer = socket.connect(MessageId::new(), false, msg!{"role": "printer"}, None, None).unwrap();
+
+    // 按属性发送同样受限制
+    let _ = client.send(msg!{
+        CHAN: "print",
+        TO_ATTR: {"role": "printer"}
+    });
+
+    assert!(Code::get(&client.wait(Some(Duration::from_secs(1))).unwrap()) == Some(Code::PermissionDenied));
+    assert!(printer.wait(Some(Duration::from_millis(100))).is_err());
+
+    assert!(socket.connect(MessageId::new(), false, msg!{WILL: {CHAN: "print", TO_ATTR: {"role": "printer"}}}, None, None).is_err());
+
+    let root = socket.connect(MessageId::new(), true, msg!{}, None, None).unwrap();
+
+    let _ = root.send(msg!{
+        CHAN: LOOKUP
+    });
+
+    assert!(Code::get(&root.wait(Some(Duration::from_secs(1))).unwrap()) == Some(Code::Ok));
+
+    let _ = root.send(msg!{
+        CHAN: "print",
+        TO_ATTR: {"role": "printer"}
+    });
+
+    assert!(printer.wait(Some(Duration::from_secs(1))).unwrap().get_str(CHAN).unwrap() == "print");
+}
+
+#[test]
+fn lookup_private() {
+    let socket = Socket::new(MessageId::new(), ()).unwrap();
+
+    let client = socket.connect(MessageId::new(), false, msg!{}, None, None).unwrap();
+    let root = socket.connect(MessageId::new(), true, msg!{}, None, None).unwrap();
+
+    let _ = client.send(msg!{
+        CHAN: WATCH,
+        ATTR: {"role": "printer"}
+    });
+
+    assert!(Code::get(&client.wait(Some(Duration::from_secs(1))).unwrap()) == Some(Code::Ok));
+
+    // 非 ROOT 看不到 ROOT SLOT
+    let _admin = socket.connect(MessageId::new(), true, msg!{"role": "printer"}, None, None).unwrap();
+    assert!(client.wait(Some(Duration::from_millis(100))).is_err());
+
+    let _printer = socket.connect(MessageId::new(), false, msg!{
+        "role": "printer",
+        ORIGIN: {USERNAME: "alice", TOKEN: "token", ACCESS: "key", "floor": 1}
+    }, None, None).unwrap();
+
+    let recv = client.wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(recv.get_str(EVENT).unwrap() == "online");
+    assert!(recv.get_message(ATTR).unwrap().get(ORIGIN).is_none());
+
+    let _ = client.send(msg!{
+        CHAN: LOOKUP,
+        ATTR: {"role": "printer"}
+    });
+
+    let recv = client.wait(Some(Duration::from_secs(1))).unwrap();
+    let slots = recv.get_array(SLOTS).unwrap();
+    assert!(slots.len() == 1);
+
+    let attr = slots[0].as_message().unwrap().get_message(ATTR).unwrap();
+    assert!(attr.get_str("role").unwrap() == "printer");
+    assert!(attr.get(ORIGIN).is_none());
+
+    // 握手消息中的其他字段可以匹配，凭据不能
+    let _ = client.send(msg!{
+        CHAN: LOOKUP,
+        ATTR: {"floor": 1}
+    });
+
+    assert!(client.wait(Some(Duration::from_secs(1))).unwrap().get_array(SLOTS).unwrap().len() == 1);
+
+    let _ = client.send(msg!{
+        CHAN: LOOKUP,
+        ATTR: {ACCESS: "key"}
+    });
+
+    assert!(client.wait(Some(Duration::from_secs(1))).unwrap().get_array(SLOTS).unwrap().is_empty());
+
+    // ROOT 可以看到全部
+    let _ = root.send(msg!{
+        CHAN: LOOKUP,
+        ATTR: {"role": "printer"}
+    });
+
+    let recv = root.wait(Some(Duration::from_secs(1))).unwrap();
+    let slots = recv.get_array(SLOTS).unwrap();
+    assert!(slots.len() == 2);
+    assert!(slots.iter().any(|slot| slot.as_message().unwrap().get_message(ATTR).unwrap().contains_key(ORIGIN)));
+}
@@ -31,7 +31,7 @@ fn conn() {
     match ret {
         Ok(_) => unreachable!(),
         Err(err) => {
-            assert!(matches!(err, Error::ErrorCode(Code::AuthenticationFailed)));
+            assert!(err.code() == Some(Code::AuthenticationFailed));
         }
     }
 }
@@ -432,7 +432,7 @@ fn wire_to_wire() {
     let slot_id = MessageId::with_string("016f9dd0c97338e09f5c61e9").unwrap();
     let ret = socket.connect(slot_id, false, msg!{}, None, None);
 
-    assert!(matches!(ret, Err(Error::ErrorCode(Code::DuplicateSlotId))));
+    assert!(matches!(ret, Err(Error::Reply(ref info)) if info.code == Code::DuplicateSlotId));
 
     let _ = wire1.send(msg!{
         CHAN: PING
@@ -659,6 +659,12 @@ fn slot_event() {
     thread::sleep(Duration::from_millis(100));
 
     assert!(wire2.is_close());
+
+    // 关闭前的最后一条消息告知断开的原因
+    let recv = wire2.wait(Some(Duration::from_millis(100))).unwrap();
+    assert!(recv.get_str(CHAN).unwrap() == CLOSE);
+    assert!(recv.get_str(REASON).unwrap() == BREAK_KILLED);
+
     assert!(wire2.wait(Some(Duration::from_millis(100))).is_err());
 
     let _ = wire1.send(msg!{
@@ -795,16 +801,16 @@ fn slot_event_send_recv() {
         VALUE: "aaa"
     });
 
+    let recv = wire2.wait(Some(Duration::from_millis(100))).unwrap();
+
+    assert!(recv.get_i32(CODE).unwrap() == 0);
+
     // send
     let _ = wire1.send(msg!{
         CHAN: "aaa",
         "hello": "world"
     });
 
-    let recv = wire2.wait(Some(Duration::from_millis(100))).unwrap();
-
-    assert!(recv.get_i32(CODE).unwrap() == 0);
-
     // recv
     let recv = wire2.wait(Some(Duration::from_millis(100))).unwrap();
 
@@ -1102,3 +1108,32 @@ fn to_socket() {
 
     assert!(wire2.wait(Some(Duration::from_millis(100))) == Err(RecvError::TimedOut));
 }
+
+#[test]
+fn heartbeat() {
+    let socket = Socket::new(MessageId::new(), ()).unwrap();
+
+    let root = socket.connect(MessageId::new(), true, msg!{}, None, None).unwrap();
+
+    let _ = root.send(msg!{
+        CHAN: ATTACH,
+        VALUE: SLOT_BREAK
+    });
+
+    assert!(Code::get(&root.wait(Some(Duration::from_secs(1))).unwrap()) == Some(Code::Ok));
+
+    // 不发送任何消息，像是线程卡住了
+    let slot_id = MessageId::new();
+    let wire = socket.connect(slot_id, false, msg!{HEARTBEAT: 1}, None, None).unwrap();
+
+    let event = root.wait(Some(Duration::from_secs(4))).unwrap();
+    assert!(event.get_str(CHAN).unwrap() == SLOT_BREAK);
+    assert!(event.get_message_id(SLOT_ID).unwrap() == &slot_id);
+    assert!(event.get_str(REASON).unwrap() == BREAK_HEARTBEAT);
+
+    let recv = wire.wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(recv.get_str(CHAN).unwrap() == CLOSE);
+    assert!(recv.get_str(REASON).unwrap() == BREAK_HEARTBEAT);
+
+    assert!(wire.wait(Some(Duration::from_secs(1))) == Err(RecvError::Disconnected));
+}
@@ -0,0 +1,177 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::thread;
+
+use nson::{msg, MessageId};
+
+use queen::Socket;
+use queen::dict::*;
+use queen::error::{Code, RecvError};
+
+#[test]
+fn delay() {
+    let socket = Socket::new(MessageId::new(), ()).unwrap();
+
+    let id1 = MessageId::new();
+
+    let wire1 = socket.connect(id1, false, msg!{}, None, None).unwrap();
+    let wire2 = socket.connect(MessageId::new(), false, msg!{}, None, None).unwrap();
+
+    let _ = wire2.send(msg!{
+        CHAN: ATTACH,
+        VALUE: "aaa"
+    });
+
+    assert!(Code::get(&wire2.wait(Some(Duration::from_secs(1))).unwrap()) == Some(Code::Ok));
+
+    let _ = wire1.send(msg!{
+        CHAN: "aaa",
+        DELAY: 1,
+        "hello": "world"
+    });
+
+    assert!(wire2.wait(Some(Duration::from_millis(500))).is_err());
+
+    let recv = wire2.wait(Some(Duration::from_secs(3))).unwrap();
+    assert!(recv.get_str("hello").unwrap() == "world");
+    assert!(recv.get_message_id(FROM).unwrap() == &id1);
+    assert!(recv.get(DELAY).is_none());
+    assert!(recv.get(ID).is_some());
+
+    // deliver at
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+
+    let _ = wire1.send(msg!{
+        CHAN: "aaa",
+        DELIVER_AT: now + 2
+    });
+
+    assert!(wire2.wait(Some(Duration::from_millis(500))).is_err());
+
+    let recv = wire2.wait(Some(Duration::from_secs(4))).unwrap();
+    assert!(recv.get(DELIVER_AT).is_none());
+
+    // delay 0, deliver immediately
+    let _ = wire1.send(msg!{
+        CHAN: "aaa",
+        DELAY: 0
+    });
+
+    assert!(wire2.wait(Some(Duration::from_millis(500))).is_ok());
+
+    // invalid
+    let _ = wire1.send(msg!{
+        CHAN: "aaa",
+        DELAY: "1s"
+    });
+
+    let recv = wire1.wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(Code::get(&recv) == Some(Code::InvalidDelayFieldType));
+}
+
+#[test]
+fn cancel_and_query() {
+    let socket = Socket::new(MessageId::new(), ()).unwrap();
+
+    let root = socket.connect(MessageId::new(), true, msg!{}, None, None).unwrap();
+    let wire1 = socket.connect(MessageId::new(), false, msg!{}, None, None).unwrap();
+    let wire2 = socket.connect(MessageId::new(), false, msg!{}, None, None).unwrap();
+
+    let _ = wire2.send(msg!{
+        CHAN: ATTACH,
+        VALUE: "aaa"
+    });
+
+    assert!(Code::get(&wire2.wait(Some(Duration::from_secs(1))).unwrap()) == Some(Code::Ok));
+
+    let id1 = MessageId::new();
+    let id2 = MessageId::new();
+
+    let _ = wire1.send(msg!{
+        CHAN: "aaa",
+        ID: id1,
+        DELAY: 2
+    });
+
+    let _ = wire1.send(msg!{
+        CHAN: "aaa",
+        ID: id2,
+        DELAY: 60
+    });
+
+    thread::sleep(Duration::from_millis(100));
+
+    // root can list scheduled messages
+    let _ = root.send(msg!{
+        CHAN: QUERY,
+        VALUE: SCHEDULED
+    });
+
+    let recv = root.wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(Code::get(&recv) == Some(Code::Ok));
+    assert!(recv.get_array(SCHEDULED).unwrap().len() == 2);
+
+    // other slot can't cancel
+    let _ = wire2.send(msg!{
+        CHAN: CANCEL,
+        ID: id1
+    });
+
+    let recv = wire2.wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(Code::get(&recv) == Some(Code::PermissionDenied));
+
+    // owner can cancel
+    let _ = wire1.send(msg!{
+        CHAN: CANCEL,
+        ID: id1
+    });
+
+    let recv = wire1.wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(Code::get(&recv) == Some(Code::Ok));
+
+    let _ = wire1.send(msg!{
+        CHAN: CANCEL,
+        ID: id1
+    });
+
+    let recv = wire1.wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(Code::get(&recv) == Some(Code::NotFound));
+
+    // root can cancel
+    let _ = root.send(msg!{
+        CHAN: CANCEL,
+        ID: id2
+    });
+
+    let recv = root.wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(Code::get(&recv) == Some(Code::Ok));
+
+    let ret = wire2.wait(Some(Duration::from_secs(3)));
+    assert!(matches!(ret, Err(RecvError::TimedOut)));
+}
+
+#[test]
+fn sender_gone() {
+    let socket = Socket::new(MessageId::new(), ()).unwrap();
+
+    let wire1 = socket.connect(MessageId::new(), false, msg!{}, None, None).unwrap();
+    let wire2 = socket.connect(MessageId::new(), false, msg!{}, None, None).unwrap();
+
+    let _ = wire2.send(msg!{
+        CHAN: ATTACH,
+        VALUE: "aaa"
+    });
+
+    assert!(Code::get(&wire2.wait(Some(Duration::from_secs(1))).unwrap()) == Some(Code::Ok));
+
+    let _ = wire1.send(msg!{
+        CHAN: "aaa",
+        DELAY: 1
+    });
+
+    thread::sleep(Duration::from_millis(100));
+
+    drop(wire1);
+
+    let recv = wire2.wait(Some(Duration::from_secs(3))).unwrap();
+    assert!(recv.get_str(CHAN).unwrap() == "aaa");
+}
@@ -0,0 +1,739 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use queen::{Hook, Slot};
+use queen::socket::{Sim, DedupConfig, DedupScope};
+use queen::nson::{MessageId, Message, msg};
+use queen::dict::*;
+use queen::error::{Code, ErrorInfo};
+
+#[test]
+fn route() {
+    let mut sim = Sim::new(MessageId::new(), ()).unwrap();
+
+    let a = sim.connect(MessageId::new(), false, msg!{}).unwrap();
+    let b = sim.connect(MessageId::new(), false, msg!{}).unwrap();
+    let c = sim.connect(MessageId::new(), false, msg!{}).unwrap();
+
+    sim.send(b, msg!{CHAN: ATTACH, VALUE: "hello"}).unwrap();
+    assert!(Code::get(&sim.recv(b).unwrap()) == Some(Code::Ok));
+
+    sim.send(a, msg!{CHAN: "hello", "n": 1}).unwrap();
+
+    let recv = sim.recv(b).unwrap();
+    assert!(recv.get_i32("n").unwrap() == 1);
+    assert!(recv.get_message_id(FROM).unwrap() == &sim.switch().slots[a].id);
+
+    assert!(sim.recv(a).is_none());
+    assert!(sim.recv(c).is_none());
+
+    assert!(sim.switch().chans.get("hello").unwrap().contains(&b));
+}
+
+#[test]
+fn delay() {
+    let mut sim = Sim::new(MessageId::new(), ()).unwrap();
+
+    let a = sim.connect(MessageId::new(), false, msg!{}).unwrap();
+    let b = sim.connect(MessageId::new(), false, msg!{}).unwrap();
+
+    sim.send(b, msg!{CHAN: ATTACH, VALUE: "hello"}).unwrap();
+    sim.drain(b);
+
+    sim.send(a, msg!{CHAN: "hello", DELAY: 5, "n": 1}).unwrap();
+    sim.send(a, msg!{CHAN: "hello", DELIVER_AT: sim.now() + 3, "n": 2}).unwrap();
+
+    assert!(sim.switch().scheduled.len() == 2);
+
+    sim.advance(2).unwrap();
+    assert!(sim.recv(b).is_none());
+
+    sim.advance(1).unwrap();
+    assert!(sim.recv(b).unwrap().get_i32("n").unwrap() == 2);
+    assert!(sim.recv(b).is_none());
+
+    sim.advance(1).unwrap();
+    assert!(sim.recv(b).is_none());
+
+    sim.advance(1).unwrap();
+    assert!(sim.recv(b).unwrap().get_i32("n").unwrap() == 1);
+    assert!(sim.switch().scheduled.is_empty());
+
+    assert!(sim.now() == Sim::<()>::EPOCH + 5);
+
+    // 溢出
+    sim.send(a, msg!{CHAN: "hello", DELAY: u64::MAX}).unwrap();
+    assert!(Code::get(&sim.recv(a).unwrap()) == Some(Code::InvalidDelayFieldType));
+
+    // 不能覆盖其他 SLOT 的延时消息
+    let id = MessageId::new();
+
+    sim.send(a, msg!{CHAN: "hello", ID: id, DELAY: 5, "n": 3}).unwrap();
+    sim.send(b, msg!{CHAN: "hello", ID: id, DELAY: 1, "n": 4}).unwrap();
+    assert!(Code::get(&sim.recv(b).unwrap()) == Some(Code::DuplicateId));
+    assert!(sim.switch().scheduled.get(&id).unwrap().slot_id == sim.switch().slots[a].id);
+
+    sim.send(b, msg!{CHAN: CANCEL}).unwrap();
+    assert!(Code::get(&sim.recv(b).unwrap()) == Some(Code::CannotGetIdField));
+
+    sim.advance(5).unwrap();
+    assert!(sim.recv(b).unwrap().get_i32("n").unwrap() == 3);
+    assert!(sim.recv(b).is_none());
+}
+
+#[test]
+fn share_with_seed() {
+    fn run(seed: u64) -> Vec<usize> {
+        let mut sim = Sim::with_seed(MessageId::new(), (), seed).unwrap();
+
+        let publisher = sim.connect(MessageId::new(), false, msg!{}).unwrap();
+
+        let mut subs = Vec::new();
+
+        for _ in 0..4 {
+            let sub = sim.connect(MessageId::new(), false, msg!{}).unwrap();
+
+            sim.send(sub, msg!{CHAN: ATTACH, VALUE: "hello", SHARE: true}).unwrap();
+            sim.drain(sub);
+
+            subs.push(sub);
+        }
+
+        let mut picked = Vec::new();
+
+        for _ in 0..20 {
+            sim.send(publisher, msg!{CHAN: "hello", SHARE: true}).unwrap();
+
+            let got: Vec<usize> = subs.iter().copied().filter(|sub| sim.recv(*sub).is_some()).collect();
+            assert!(got.len() == 1);
+
+            picked.push(got[0]);
+        }
+
+        picked
+    }
+
+    assert!(run(1) == run(1));
+    assert!(run(7) == run(7));
+}
+
+#[test]
+fn hook_and_will() {
+    struct MyHook;
+
+    impl Hook for MyHook {
+        fn emit(&self, _: &Slot, message: &mut Message) -> bool {
+            message.get_str(CHAN) != Ok("secret")
+        }
+    }
+
+    let mut sim = Sim::new(MessageId::new(), MyHook).unwrap();
+
+    let a_id = MessageId::new();
+    let a = sim.connect(a_id, false, msg!{WILL: {CHAN: "status", "online": false}}).unwrap();
+    let b = sim.connect(MessageId::new(), false, msg!{}).unwrap();
+    let root = sim.connect(MessageId::new(), true, msg!{}).unwrap();
+
+    assert!(sim.connect(a_id, false, msg!{}).is_err());
+
+    sim.send(a, msg!{CHAN: "secret"}).unwrap();
+    assert!(Code::get(&sim.recv(a).unwrap()) == Some(Code::PermissionDenied));
+
+    sim.send(b, msg!{CHAN: ATTACH, VALUE: "status"}).unwrap();
+    sim.drain(b);
+
+    sim.disconnect(a).unwrap();
+
+    let recv = sim.recv(b).unwrap();
+    assert!(recv.get_bool("online").unwrap() == false);
+    assert!(recv.get_message_id(FROM).unwrap() == &a_id);
+
+    // kill
+    let b_id = sim.switch().slots[b].id;
+
+    sim.send(root, msg!{CHAN: SLOT_KILL, SLOT_ID: b_id}).unwrap();
+    assert!(Code::get(&sim.recv(root).unwrap()) == Some(Code::Ok));
+
+    assert!(sim.is_closed(b));
+    assert!(sim.token(&b_id).is_none());
+    assert!(sim.send(b, msg!{CHAN: PING}).is_err());
+}
+
+#[test]
+fn heartbeat() {
+    let mut sim = Sim::new(MessageId::new(), ()).unwrap();
+
+    let root = sim.connect(MessageId::new(), true, msg!{}).unwrap();
+    sim.send(root, msg!{CHAN: ATTACH, VALUE: SLOT_BREAK}).unwrap();
+    sim.drain(root);
+
+    let a = sim.connect(MessageId::new(), false, msg!{HEARTBEAT: 3}).unwrap();
+    let b = sim.connect(MessageId::new(), false, msg!{}).unwrap();
+
+    assert!(sim.switch().slots[a].heartbeat == Some(3));
+    assert!(sim.switch().slots[b].heartbeat.is_none());
+
+    // 任何消息都算作心跳
+    sim.advance(3).unwrap();
+    sim.send(a, msg!{CHAN: PING}).unwrap();
+    sim.drain(a);
+
+    sim.advance(3).unwrap();
+    assert!(!sim.is_closed(a));
+
+    sim.advance(1).unwrap();
+    assert!(sim.is_closed(a));
+    assert!(!sim.is_closed(b));
+
+    let event = sim.recv(root).unwrap();
+    assert!(event.get_str(CHAN).unwrap() == SLOT_BREAK);
+    assert!(event.get_str(REASON).unwrap() == BREAK_HEARTBEAT);
+
+    // 默认的心跳间隔
+    sim.switch_mut().set_heartbeat(Some(1));
+
+    let c = sim.connect(MessageId::new(), false, msg!{}).unwrap();
+
+    sim.advance(2).unwrap();
+    assert!(sim.is_closed(c));
+    assert!(!sim.is_closed(b));
+    assert!(sim.recv(root).unwrap().get_str(REASON).unwrap() == BREAK_HEARTBEAT);
+
+    assert!(sim.connect(MessageId::new(), false, msg!{HEARTBEAT: 0}).is_err());
+    assert!(sim.connect(MessageId::new(), false, msg!{HEARTBEAT: "1"}).is_err());
+
+    // 其他断开的原因
+    let b_id = sim.switch().slots[b].id;
+
+    sim.send(root, msg!{CHAN: SLOT_KILL, SLOT_ID: b_id}).unwrap();
+    assert!(Code::get(&sim.recv(root).unwrap()) == Some(Code::Ok));
+    assert!(sim.recv(root).unwrap().get_str(REASON).unwrap() == BREAK_KILLED);
+
+    let d = sim.connect(MessageId::new(), false, msg!{HEARTBEAT: 10}).unwrap();
+    sim.disconnect(d).unwrap();
+    assert!(sim.recv(root).unwrap().get_str(REASON).unwrap() == BREAK_LOST);
+}
+
+#[test]
+fn close() {
+    let mut sim = Sim::new(MessageId::new(), ()).unwrap();
+
+    let root = sim.connect(MessageId::new(), true, msg!{}).unwrap();
+    sim.send(root, msg!{CHAN: ATTACH, VALUE: SLOT_BREAK}).unwrap();
+    sim.drain(root);
+
+    // 被 SLOT_KILL 时，最后一条消息告知原因
+    let a_id = MessageId::new();
+    let a = sim.connect(a_id, false, msg!{}).unwrap();
+
+    sim.send(root, msg!{CHAN: SLOT_KILL, SLOT_ID: a_id}).unwrap();
+    assert!(Code::get(&sim.recv(root).unwrap()) == Some(Code::Ok));
+    assert!(sim.recv(root).unwrap().get_str(REASON).unwrap() == BREAK_KILLED);
+
+    let messages = sim.drain(a);
+    assert!(messages.len() == 1);
+    assert!(messages[0].get_str(CHAN).unwrap() == CLOSE);
+    assert!(messages[0].get_str(REASON).unwrap() == BREAK_KILLED);
+    assert!(sim.is_closed(a));
+
+    // SLOT 主动断开，自己填写的原因不可信，视为正常关闭
+    let b = sim.connect(MessageId::new(), false, msg!{}).unwrap();
+
+    sim.send(b, msg!{CHAN: CLOSE, REASON: BREAK_KILLED}).unwrap();
+    assert!(sim.is_closed(b));
+
+    let event = sim.recv(root).unwrap();
+    assert!(event.get_str(CHAN).unwrap() == SLOT_BREAK);
+    assert!(event.get_str(REASON).unwrap() == BREAK_CLOSED);
+
+    // 没有原因时视为正常关闭
+    let c = sim.connect(MessageId::new(), false, msg!{}).unwrap();
+
+    sim.send(c, msg!{CHAN: CLOSE}).unwrap();
+    assert!(sim.recv(root).unwrap().get_str(REASON).unwrap() == BREAK_CLOSED);
+}
+
+#[test]
+fn dedup() {
+    let mut sim = Sim::new(MessageId::new(), ()).unwrap();
+
+    sim.switch_mut().set_dedup(Some(DedupConfig::new(10, 16, DedupScope::Slot)));
+
+    let sub = sim.connect(MessageId::new(), false, msg!{}).unwrap();
+    sim.send(sub, msg!{CHAN: ATTACH, VALUE: "order"}).unwrap();
+    sim.drain(sub);
+
+    let pub_id = MessageId::new();
+    let publisher = sim.connect(pub_id, false, msg!{}).unwrap();
+
+    let id = MessageId::new();
+
+    sim.send(publisher, msg!{CHAN: "order", ID: id, "n": 1}).unwrap();
+    assert!(sim.drain(sub).len() == 1);
+    assert!(sim.drain(publisher).is_empty());
+
+    // 重复的消息不再投递，发送者收到 Code::Ok 和 DUPLICATE
+    sim.send(publisher, msg!{CHAN: "order", ID: id, "n": 1}).unwrap();
+    assert!(sim.drain(sub).is_empty());
+
+    let recv = sim.recv(publisher).unwrap();
+    assert!(Code::get(&recv) == Some(Code::Ok));
+    assert!(recv.get_bool(DUPLICATE).unwrap() == true);
+    assert!(recv.get_message_id(ID).unwrap() == &id);
+
+    // 没有 ID 的消息不去重
+    sim.send(publisher, msg!{CHAN: "order", "n": 2}).unwrap();
+    sim.send(publisher, msg!{CHAN: "order", "n": 2}).unwrap();
+    assert!(sim.drain(sub).len() == 2);
+
+    // 重连后仍然有效
+    sim.disconnect(publisher).unwrap();
+    let publisher = sim.connect(pub_id, false, msg!{}).unwrap();
+
+    sim.send(publisher, msg!{CHAN: "order", ID: id, "n": 1}).unwrap();
+    assert!(sim.drain(sub).is_empty());
+    assert!(sim.recv(publisher).unwrap().get_bool(DUPLICATE).unwrap() == true);
+
+    // 其他发布者的相同 ID 不算重复
+    let other = sim.connect(MessageId::new(), false, msg!{}).unwrap();
+    sim.send(other, msg!{CHAN: "order", ID: id, "n": 1}).unwrap();
+    assert!(sim.drain(sub).len() == 1);
+
+    assert!(sim.switch().metrics().get_u64("duplicates").unwrap() == 2);
+    assert!(sim.switch().metrics().get_u64("dedup_ids").unwrap() == 2);
+
+    // 超出时间窗口后遗忘
+    sim.advance(10).unwrap();
+    assert!(sim.switch().metrics().get_u64("dedup_ids").unwrap() == 0);
+
+    sim.send(publisher, msg!{CHAN: "order", ID: id, "n": 1}).unwrap();
+    assert!(sim.drain(sub).len() == 1);
+
+    // 按 CHAN 去重
+    sim.switch_mut().set_dedup(Some(DedupConfig::new(10, 16, DedupScope::Chan)));
+
+    sim.send(publisher, msg!{CHAN: "order", ID: id, "n": 1}).unwrap();
+    sim.send(other, msg!{CHAN: "order", ID: id, "n": 1}).unwrap();
+    assert!(sim.drain(sub).len() == 1);
+    assert!(sim.recv(other).unwrap().get_bool(DUPLICATE).unwrap() == true);
+
+    sim.switch_mut().set_dedup(None);
+
+    sim.send(other, msg!{CHAN: "order", ID: id, "n": 1}).unwrap();
+    assert!(sim.drain(sub).len() == 1);
+}
+
+#[test]
+fn work_queue() {
+    let mut sim = Sim::new(MessageId::new(), ()).unwrap();
+
+    sim.switch_mut().set_queue("task", 4);
+
+    let publisher = sim.connect(MessageId::new(), false, msg!{}).unwrap();
+
+    // 没有消费者时暂存
+    for n in 0..4 {
+        sim.send(publisher, msg!{CHAN: "task", "n": n}).unwrap();
+    }
+
+    sim.send(publisher, msg!{CHAN: "task", "n": 4}).unwrap();
+    assert!(Code::get(&sim.recv(publisher).unwrap()) == Some(Code::QueueFull));
+
+    let a = sim.connect(MessageId::new(), false, msg!{}).unwrap();
+    let b = sim.connect(MessageId::new(), false, msg!{}).unwrap();
+
+    sim.send(a, msg!{CHAN: ATTACH, VALUE: "task", PREFETCH: 0}).unwrap();
+    assert!(Code::get(&sim.recv(a).unwrap()) == Some(Code::InvalidPrefetchFieldType));
+
+    // 默认 PREFETCH 为 1
+    sim.send(a, msg!{CHAN: ATTACH, VALUE: "task"}).unwrap();
+    let messages = sim.drain(a);
+    assert!(messages.len() == 2);
+    assert!(Code::get(&messages[0]) == Some(Code::Ok));
+    assert!(messages[1].get_i32("n").unwrap() == 0);
+
+    let a0 = *messages[1].get_message_id(ID).unwrap();
+
+    sim.send(b, msg!{CHAN: ATTACH, VALUE: "task", PREFETCH: 2}).unwrap();
+    let messages = sim.drain(b);
+    assert!(messages.len() == 3);
+    assert!(messages[1].get_i32("n").unwrap() == 1);
+    assert!(messages[2].get_i32("n").unwrap() == 2);
+
+    assert!(sim.switch().metrics().get_u64("queued").unwrap() == 1);
+    assert!(sim.switch().metrics().get_u64("unacked").unwrap() == 3);
+
+    // 只有接收的消费者可以 ACK
+    sim.send(b, msg!{CHAN: ACK, ID: a0}).unwrap();
+    assert!(Code::get(&sim.recv(b).unwrap()) == Some(Code::NotFound));
+
+    sim.send(a, msg!{CHAN: ACK}).unwrap();
+    assert!(Code::get(&sim.recv(a).unwrap()) == Some(Code::CannotGetIdField));
+
+    // ACK 之后有了空闲额度，投递下一条
+    sim.send(a, msg!{CHAN: ACK, ID: a0}).unwrap();
+    let messages = sim.drain(a);
+    assert!(messages.len() == 2);
+    assert!(Code::get(&messages[0]) == Some(Code::Ok));
+    assert!(messages[1].get_i32("n").unwrap() == 3);
+
+    // 消费者断开，未确认的消息按原来的顺序投递给其他消费者
+    sim.disconnect(b).unwrap();
+
+    sim.send(a, msg!{CHAN: ACK, ID: *messages[1].get_message_id(ID).unwrap()}).unwrap();
+    let messages = sim.drain(a);
+    assert!(messages.len() == 2);
+    assert!(messages[1].get_i32("n").unwrap() == 1);
+
+    // REQUEUE 时重新入队
+    let id = *messages[1].get_message_id(ID).unwrap();
+
+    sim.send(a, msg!{CHAN: ACK, ID: id, REQUEUE: true}).unwrap();
+    let messages = sim.drain(a);
+    assert!(messages.len() == 2);
+    assert!(messages[1].get_i32("n").unwrap() == 1);
+    assert!(messages[1].get_message_id(ID).unwrap() == &id);
+
+    // DETACH 同样会重新入队
+    let c = sim.connect(MessageId::new(), false, msg!{}).unwrap();
+    sim.send(c, msg!{CHAN: ATTACH, VALUE: "task", PREFETCH: 10}).unwrap();
+    assert!(sim.drain(c).len() == 2);
+
+    sim.send(a, msg!{CHAN: DETACH, VALUE: "task"}).unwrap();
+    assert!(sim.drain(a).len() == 1);
+
+    let messages = sim.drain(c);
+    assert!(messages.len() == 1);
+    assert!(messages[0].get_i32("n").unwrap() == 1);
+
+    // 只投递给一个消费者
+    sim.send(publisher, msg!{CHAN: "task", "n": 5}).unwrap();
+    assert!(sim.drain(c).len() == 1);
+    assert!(sim.drain(a).is_empty());
+
+    assert!(sim.switch().metrics().get_u64("queued").unwrap() == 0);
+    assert!(sim.switch().metrics().get_u64("unacked").unwrap() == 3);
+
+    let queue = sim.switch_mut().remove_queue("task").unwrap();
+    assert!(queue.unacked.len() == 3);
+    assert!(sim.switch().slots[c].queues.is_empty());
+}
+
+#[test]
+fn work_queue_send_failed() {
+    struct MyHook(Arc<AtomicBool>);
+
+    impl Hook for MyHook {
+        fn send(&self, _: &Slot, message: &mut Message) -> bool {
+            !(self.0.load(Ordering::Relaxed) && message.get_str(CHAN) == Ok("task"))
+        }
+    }
+
+    let block = Arc::new(AtomicBool::new(true));
+
+    let mut sim = Sim::new(MessageId::new(), MyHook(block.clone())).unwrap();
+
+    sim.switch_mut().set_queue("task", 4);
+
+    let publisher = sim.connect(MessageId::new(), false, msg!{}).unwrap();
+    sim.send(publisher, msg!{CHAN: "task", "n": 0}).unwrap();
+
+    let a = sim.connect(MessageId::new(), false, msg!{}).unwrap();
+
+    // 发送失败时消息留在队列中，不会变成待确认
+    sim.send(a, msg!{CHAN: ATTACH, VALUE: "task"}).unwrap();
+    assert!(sim.drain(a).len() == 1);
+
+    assert!(sim.switch().metrics().get_u64("queued").unwrap() == 1);
+    assert!(sim.switch().metrics().get_u64("unacked").unwrap() == 0);
+
+    // 恢复后按原来的顺序投递
+    block.store(false, Ordering::Relaxed);
+
+    sim.send(publisher, msg!{CHAN: "task", "n": 1}).unwrap();
+
+    let messages = sim.drain(a);
+    assert!(messages.len() == 1);
+    assert!(messages[0].get_i32("n").unwrap() == 0);
+
+    assert!(sim.switch().metrics().get_u64("queued").unwrap() == 1);
+    assert!(sim.switch().metrics().get_u64("unacked").unwrap() == 1);
+}
+
+#[test]
+fn gather() {
+    let mut sim = Sim::new(MessageId::new(), ()).unwrap();
+
+    let requester = sim.connect(MessageId::new(), false, msg!{}).unwrap();
+
+    let a_id = MessageId::new();
+    let b_id = MessageId::new();
+    let c_id = MessageId::new();
+
+    let a = sim.connect(a_id, false, msg!{}).unwrap();
+    let b = sim.connect(b_id, false, msg!{}).unwrap();
+    let c = sim.connect(c_id, false, msg!{}).unwrap();
+
+    for token in &[requester, a, b, c] {
+        sim.send(*token, msg!{CHAN: ATTACH, VALUE: "status"}).unwrap();
+        sim.drain(*token);
+    }
+
+    sim.send(requester, msg!{CHAN: GATHER}).unwrap();
+    assert!(Code::get(&sim.recv(requester).unwrap()) == Some(Code::CannotGetValueField));
+
+    sim.send(requester, msg!{CHAN: GATHER, VALUE: "status", TIMEOUT: 0}).unwrap();
+    assert!(Code::get(&sim.recv(requester).unwrap()) == Some(Code::InvalidTimeoutFieldType));
+
+    // 投递给所有订阅者，发起者自己除外
+    sim.send(requester, msg!{CHAN: GATHER, VALUE: "status", TIMEOUT: 3, "verbose": true}).unwrap();
+    assert!(sim.drain(requester).is_empty());
+
+    let request = sim.recv(a).unwrap();
+    assert!(request.get_str(CHAN).unwrap() == "status");
+    assert!(request.get_bool(GATHER).unwrap() == true);
+    assert!(request.get_bool("verbose").unwrap() == true);
+    assert!(!request.contains_key(TIMEOUT));
+
+    let id = *request.get_message_id(ID).unwrap();
+
+    assert!(sim.drain(b).len() == 1);
+    assert!(sim.drain(c).len() == 1);
+
+    sim.send(a, msg!{CHAN: REPLY, ID: id, "ok": true}).unwrap();
+    assert!(sim.drain(a).is_empty());
+
+    // 只能回复一次
+    sim.send(a, msg!{CHAN: REPLY, ID: id, "ok": true}).unwrap();
+    assert!(Code::get(&sim.recv(a).unwrap()) == Some(Code::NotFound));
+
+    sim.send(b, msg!{CHAN: REPLY, ID: id, "ok": false}).unwrap();
+    assert!(sim.drain(requester).is_empty());
+
+    // 超时后返回，c 没有回复
+    sim.advance(3).unwrap();
+
+    let response = sim.recv(requester).unwrap();
+    assert!(response.get_str(CHAN).unwrap() == GATHER);
+    assert!(Code::get(&response) == Some(Code::Ok));
+    assert!(response.get_message_id(ID).unwrap() == &id);
+    assert!(response.get_str(VALUE).unwrap() == "status");
+
+    let replies = response.get_array(REPLIES).unwrap();
+    assert!(replies.len() == 2);
+    assert!(replies[0].as_message().unwrap().get_message_id(FROM).unwrap() == &a_id);
+    assert!(replies[1].as_message().unwrap().get_bool("ok").unwrap() == false);
+
+    let missing = response.get_array(MISSING).unwrap();
+    assert!(missing.len() == 1);
+    assert!(missing[0].as_message_id().unwrap() == &c_id);
+
+    assert!(sim.switch().gathers.is_empty());
+
+    // 超时后的回复
+    sim.send(c, msg!{CHAN: REPLY, ID: id}).unwrap();
+    assert!(Code::get(&sim.recv(c).unwrap()) == Some(Code::NotFound));
+
+    // 收到 COUNT 个回复时提前返回
+    sim.send(requester, msg!{CHAN: GATHER, VALUE: "status", TO: [a_id, c_id], COUNT: 1}).unwrap();
+    assert!(sim.drain(b).is_empty());
+    assert!(sim.drain(a).len() == 1);
+
+    let request = sim.recv(c).unwrap();
+    sim.send(c, msg!{CHAN: REPLY, ID: *request.get_message_id(ID).unwrap()}).unwrap();
+
+    let response = sim.recv(requester).unwrap();
+    assert!(response.get_array(REPLIES).unwrap().len() == 1);
+    assert!(response.get_array(MISSING).unwrap()[0].as_message_id().unwrap() == &a_id);
+
+    // 等待的目标都已断开时提前返回
+    sim.send(requester, msg!{CHAN: GATHER, VALUE: "status", TO: b_id}).unwrap();
+    assert!(sim.drain(b).len() == 1);
+
+    sim.disconnect(b).unwrap();
+
+    let response = sim.recv(requester).unwrap();
+    assert!(response.get_array(REPLIES).unwrap().is_empty());
+    assert!(response.get_array(MISSING).unwrap()[0].as_message_id().unwrap() == &b_id);
+
+    // 没有目标时直接返回
+    sim.send(requester, msg!{CHAN: GATHER, VALUE: "nobody"}).unwrap();
+
+    let response = sim.recv(requester).unwrap();
+    assert!(Code::get(&response) == Some(Code::Ok));
+    assert!(response.get_array(REPLIES).unwrap().is_empty());
+    assert!(response.get_array(MISSING).unwrap().is_empty());
+
+    // 发起者断开时丢弃
+    sim.send(requester, msg!{CHAN: GATHER, VALUE: "status"}).unwrap();
+    assert!(sim.switch().gathers.len() == 1);
+
+    sim.disconnect(requester).unwrap();
+    assert!(sim.switch().gathers.is_empty());
+}
+
+#[test]
+fn rules() {
+    let mut sim = Sim::new(MessageId::new(), ()).unwrap();
+
+    let root = sim.connect(MessageId::new(), true, msg!{}).unwrap();
+    let publisher_id = MessageId::new();
+    let publisher = sim.connect(publisher_id, false, msg!{}).unwrap();
+
+    let audit_id = MessageId::new();
+    let audit = sim.connect(audit_id, false, msg!{}).unwrap();
+    let orders = sim.connect(MessageId::new(), false, msg!{}).unwrap();
+
+    sim.send(audit, msg!{CHAN: ATTACH, VALUE: "audit/orders"}).unwrap();
+    sim.send(orders, msg!{CHAN: ATTACH, VALUE: "orders"}).unwrap();
+    sim.drain(audit);
+    sim.drain(orders);
+
+    // 只有 ROOT 可以设置
+    sim.send(publisher, msg!{CHAN: CTRL, RULES: null}).unwrap();
+    assert!(Code::get(&sim.recv(publisher).unwrap()) == Some(Code::PermissionDenied));
+
+    sim.send(root, msg!{CHAN: CTRL, RULES: [{"actions": [{"op": "copy"}]}]}).unwrap();
+    assert!(Code::get(&sim.recv(root).unwrap()) == Some(Code::BadValue));
+
+    sim.send(root, msg!{CHAN: CTRL, RULES: [
+        {
+            "chans": ["orders"],
+            "actions": [
+                {"op": "remove", "fields": ["card"]},
+                {"op": "publish", "chan": "audit/orders"}
+            ]
+        },
+        {
+            "chans": ["legacy/orders"],
+            "actions": [{"op": "route", "chan": "orders"}, {"op": "set", "fields": {"legacy": true}}]
+        },
+        {
+            "chans": ["orders"],
+            "fields": {"status": "test"},
+            "actions": [{"op": "drop"}]
+        },
+        {
+            "chans": ["vip"],
+            "actions": [{"op": "to", "to": audit_id.to_hex()}]
+        }
+    ]}).unwrap();
+    assert!(Code::get(&sim.recv(root).unwrap()) == Some(Code::Ok));
+
+    // 复制到另一个 CHAN，并移除字段
+    sim.send(publisher, msg!{CHAN: "orders", "status": "paid", "card": "1234"}).unwrap();
+
+    let recv = sim.recv(orders).unwrap();
+    assert!(recv.get_str("status").unwrap() == "paid");
+    assert!(!recv.contains_key("card"));
+
+    let recv = sim.recv(audit).unwrap();
+    assert!(recv.get_str(CHAN).unwrap() == "audit/orders");
+    assert!(recv.get_message_id(FROM).unwrap() == &publisher_id);
+    assert!(!recv.contains_key("card"));
+
+    // 修改 CHAN，只有之后的规则会匹配新的 CHAN
+    sim.send(publisher, msg!{CHAN: "legacy/orders", "status": "paid"}).unwrap();
+
+    let recv = sim.recv(orders).unwrap();
+    assert!(recv.get_str(CHAN).unwrap() == "orders");
+    assert!(recv.get_bool("legacy").unwrap() == true);
+    assert!(sim.drain(audit).is_empty());
+
+    // 丢弃，复制的消息在丢弃之前已经发出
+    sim.send(publisher, msg!{CHAN: "orders", "status": "test"}).unwrap();
+    assert!(sim.drain(orders).is_empty());
+    assert!(sim.drain(audit).len() == 1);
+
+    // 设置 TO
+    sim.send(publisher, msg!{CHAN: "vip", "n": 1}).unwrap();
+    assert!(sim.recv(audit).unwrap().get_i32("n").unwrap() == 1);
+
+    // 按照最终的 CHAN 校验消息结构
+    sim.send(root, msg!{CHAN: CTRL, VALUE: "orders", SCHEMA: {"status": "string"}}).unwrap();
+    assert!(Code::get(&sim.recv(root).unwrap()) == Some(Code::Ok));
+
+    sim.send(publisher, msg!{CHAN: "legacy/orders", "n": 1}).unwrap();
+    assert!(Code::get(&sim.recv(publisher).unwrap()) == Some(Code::SchemaMismatch));
+    assert!(sim.drain(orders).is_empty());
+
+    // 复制的消息按照目标 CHAN 校验，不通过时都不发送
+    sim.send(root, msg!{CHAN: CTRL, VALUE: "audit/orders", SCHEMA: {"card": "string"}}).unwrap();
+    assert!(Code::get(&sim.recv(root).unwrap()) == Some(Code::Ok));
+
+    sim.send(publisher, msg!{CHAN: "orders", "status": "paid", "card": "1234"}).unwrap();
+
+    let recv = sim.recv(publisher).unwrap();
+    assert!(Code::get(&recv) == Some(Code::SchemaMismatch));
+    assert!(ErrorInfo::get(&recv).unwrap().field.as_deref() == Some("card"));
+    assert!(sim.drain(orders).is_empty());
+    assert!(sim.drain(audit).is_empty());
+
+    sim.send(root, msg!{CHAN: CTRL, VALUE: "audit/orders", SCHEMA: null}).unwrap();
+    assert!(Code::get(&sim.recv(root).unwrap()) == Some(Code::Ok));
+
+    // 复制的消息同样延时投递
+    let id = MessageId::new();
+    sim.send(publisher, msg!{CHAN: "orders", ID: id, "status": "paid", DELAY: 2}).unwrap();
+    assert!(sim.drain(orders).is_empty());
+    assert!(sim.drain(audit).is_empty());
+
+    sim.advance(2).unwrap();
+
+    assert!(sim.recv(orders).unwrap().get_message_id(ID).unwrap() == &id);
+
+    let recv = sim.recv(audit).unwrap();
+    assert!(recv.get_str(CHAN).unwrap() == "audit/orders");
+    assert!(recv.get_message_id(ID).unwrap() != &id);
+
+    // 清空规则
+    sim.send(root, msg!{CHAN: CTRL, RULES: null}).unwrap();
+    assert!(Code::get(&sim.recv(root).unwrap()) == Some(Code::Ok));
+    assert!(sim.switch().rules.is_empty());
+
+    sim.send(publisher, msg!{CHAN: "orders", "status": "test", "card": "1234"}).unwrap();
+    assert!(sim.recv(orders).unwrap().contains_key("card"));
+    assert!(sim.drain(audit).is_empty());
+}
+
+#[test]
+fn will_pipeline() {
+    let mut sim = Sim::new(MessageId::new(), ()).unwrap();
+
+    let root = sim.connect(MessageId::new(), true, msg!{}).unwrap();
+    let sub = sim.connect(MessageId::new(), false, msg!{}).unwrap();
+
+    sim.send(sub, msg!{CHAN: ATTACH, VALUE: "status"}).unwrap();
+    sim.send(sub, msg!{CHAN: ATTACH, VALUE: "offline"}).unwrap();
+    sim.drain(sub);
+
+    sim.send(root, msg!{CHAN: CTRL, VALUE: "status", SCHEMA: {"online": "bool"}}).unwrap();
+    assert!(Code::get(&sim.recv(root).unwrap()) == Some(Code::Ok));
+
+    // 不满足消息结构的遗嘱不发布
+    let a = sim.connect(MessageId::new(), false, msg!{WILL: {CHAN: "status", "online": "no"}}).unwrap();
+    sim.disconnect(a).unwrap();
+    assert!(sim.drain(sub).is_empty());
+
+    // 遗嘱经过规则
+    sim.send(root, msg!{CHAN: CTRL, RULES: [
+        {"chans": ["status"], "fields": {"online": false}, "actions": [{"op": "route", "chan": "offline"}]}
+    ]}).unwrap();
+    assert!(Code::get(&sim.recv(root).unwrap()) == Some(Code::Ok));
+
+    let b_id = MessageId::new();
+    let b = sim.connect(b_id, false, msg!{WILL: {CHAN: "status", "online": false}}).unwrap();
+    sim.disconnect(b).unwrap();
+
+    let recv = sim.recv(sub).unwrap();
+    assert!(recv.get_str(CHAN).unwrap() == "offline");
+    assert!(recv.get_message_id(FROM).unwrap() == &b_id);
+
+    // 延时的遗嘱
+    let c = sim.connect(MessageId::new(), false, msg!{WILL: {CHAN: "status", "online": true, DELAY: 2}}).unwrap();
+    sim.disconnect(c).unwrap();
+    assert!(sim.recv(sub).is_none());
+
+    sim.advance(2).unwrap();
+    assert!(sim.recv(sub).unwrap().get_bool("online").unwrap() == true);
+}
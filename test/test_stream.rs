@@ -0,0 +1,258 @@
+use std::time::Duration;
+
+use queen::{Socket, Node, Port};
+use queen::nson::{MessageId, msg};
+use queen::net::{NsonCodec, KeepAlive};
+use queen::stream::{Sender, Receiver, StreamEvent};
+use queen::dict::*;
+use queen::error::Code;
+
+use super::get_free_addr;
+
+#[test]
+fn stream() {
+    let socket = Socket::new(MessageId::new(), ()).unwrap();
+
+    let addr = get_free_addr();
+
+    let _node = Node::<NsonCodec>::new(
+        socket.clone(),
+        1,
+        vec![addr.parse().unwrap()],
+        KeepAlive::default(),
+        ()
+    ).unwrap();
+
+    let port = Port::<NsonCodec>::new(KeepAlive::default()).unwrap();
+
+    let receiver_id = MessageId::new();
+    let wire1 = port.connect(&addr, receiver_id, false, msg!{}, None, None).unwrap();
+
+    let sender_id = MessageId::new();
+    let wire2 = socket.connect(sender_id, false, msg!{}, None, None).unwrap();
+
+    let data: Vec<u8> = (0..1024 * 1024).map(|i| (i % 251) as u8).collect();
+
+    let mut sender = Sender::open(&wire2, receiver_id, msg!{"name": "a.bin"}, 64 * 1024).unwrap();
+
+    for (i, part) in data.chunks(100 * 1000).enumerate() {
+        sender.write(&wire2, part).unwrap();
+
+        // 普通消息和数据块交替发送
+        if i == 3 {
+            let _ = wire2.send(msg!{
+                CHAN: "hello",
+                TO: receiver_id
+            });
+        }
+    }
+
+    sender.finish(&wire2).unwrap();
+
+    let mut receiver = Receiver::new();
+    let mut recv_data = Vec::new();
+    let mut hello = false;
+
+    loop {
+        let message = wire1.wait(Some(Duration::from_secs(1))).unwrap();
+
+        match receiver.handle(&wire1, &message) {
+            Some(StreamEvent::Open { id, from, meta }) => {
+                assert!(id == sender.id());
+                assert!(from == sender_id);
+                assert!(meta.get_str("name").unwrap() == "a.bin");
+            }
+            Some(StreamEvent::Data { data, .. }) => {
+                // 普通消息在数据块之间到达
+                if recv_data.len() >= 400 * 1000 {
+                    assert!(hello);
+                }
+
+                assert!(data.len() <= 64 * 1024);
+                recv_data.extend_from_slice(&data);
+            }
+            Some(StreamEvent::End { size, .. }) => {
+                assert!(size == data.len() as u64);
+                break
+            }
+            Some(event) => panic!("{:?}", event),
+            None => {
+                assert!(message.get_str(CHAN).unwrap() == "hello");
+                hello = true;
+            }
+        }
+    }
+
+    assert!(hello);
+    assert!(recv_data == data);
+    assert!(receiver.is_empty());
+}
+
+#[test]
+fn cancel() {
+    let socket = Socket::new(MessageId::new(), ()).unwrap();
+
+    let receiver_id = MessageId::new();
+    let wire1 = socket.connect(receiver_id, false, msg!{}, None, None).unwrap();
+    let wire2 = socket.connect(MessageId::new(), false, msg!{}, None, None).unwrap();
+
+    // 接收方取消
+    let mut sender = Sender::open(&wire2, receiver_id, msg!{}, 4).unwrap();
+    sender.write(&wire2, b"12345678").unwrap();
+
+    let mut receiver = Receiver::new();
+
+    let open = wire1.wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(matches!(receiver.handle(&wire1, &open), Some(StreamEvent::Open { .. })));
+    assert!(receiver.len() == 1);
+
+    receiver.cancel(&wire1, sender.id(), "no space").unwrap();
+    assert!(receiver.is_empty());
+
+    let mut sender_events = Receiver::new();
+    let message = wire2.wait(Some(Duration::from_secs(1))).unwrap();
+
+    match sender_events.handle(&wire2, &message) {
+        Some(StreamEvent::Cancel { id, reason }) => {
+            assert!(id == sender.id());
+            assert!(reason == "no space");
+        }
+        event => panic!("{:?}", event)
+    }
+
+    // 取消之后收到的数据块被忽略
+    for _ in 0..2 {
+        let message = wire1.wait(Some(Duration::from_secs(1))).unwrap();
+        assert!(receiver.handle(&wire1, &message).is_none());
+    }
+
+    // 发送方取消
+    let sender = Sender::open(&wire2, receiver_id, msg!{}, 4).unwrap();
+    let id = sender.id();
+    sender.cancel(&wire2, "abort").unwrap();
+
+    let open = wire1.wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(matches!(receiver.handle(&wire1, &open), Some(StreamEvent::Open { .. })));
+
+    let message = wire1.wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(receiver.handle(&wire1, &message) == Some(StreamEvent::Cancel { id, reason: "abort".to_string() }));
+    assert!(receiver.is_empty());
+}
+
+#[test]
+fn integrity() {
+    let socket = Socket::new(MessageId::new(), ()).unwrap();
+
+    let receiver_id = MessageId::new();
+    let wire1 = socket.connect(receiver_id, false, msg!{}, None, None).unwrap();
+    let wire2 = socket.connect(MessageId::new(), false, msg!{}, None, None).unwrap();
+
+    let mut receiver = Receiver::new();
+
+    let sender = Sender::open(&wire2, receiver_id, msg!{}, 4).unwrap();
+    let id = sender.id();
+
+    // 伪造一个错误的结束消息
+    let _ = wire2.send(msg!{
+        CHAN: STREAM,
+        TO: receiver_id,
+        STREAM_ID: id,
+        EVENT: "end",
+        SEQ: 0u32,
+        SIZE: 0u64,
+        DIGEST: vec![0u8; 32]
+    });
+
+    let open = wire1.wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(matches!(receiver.handle(&wire1, &open), Some(StreamEvent::Open { .. })));
+
+    let message = wire1.wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(receiver.handle(&wire1, &message) == Some(StreamEvent::Error { id, reason: "digest mismatch".to_string() }));
+
+    // 发送方收到取消
+    let message = wire2.wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(message.get_str(EVENT).unwrap() == "cancel");
+    assert!(message.get_message_id(STREAM_ID).unwrap() == &id);
+
+    // STREAM 只能点对点发送
+    let _ = wire2.send(msg!{
+        CHAN: STREAM,
+        STREAM_ID: id,
+        EVENT: "data"
+    });
+
+    assert!(Code::get(&wire2.wait(Some(Duration::from_secs(1))).unwrap()) == Some(Code::CannotGetToField));
+
+    assert!(Sender::open(&wire2, receiver_id, msg!{}, 0).is_err());
+}
+
+#[test]
+fn other_sender() {
+    let socket = Socket::new(MessageId::new(), ()).unwrap();
+
+    let receiver_id = MessageId::new();
+    let wire1 = socket.connect(receiver_id, false, msg!{}, None, None).unwrap();
+    let wire2 = socket.connect(MessageId::new(), false, msg!{}, None, None).unwrap();
+    let wire3 = socket.connect(MessageId::new(), false, msg!{}, None, None).unwrap();
+
+    let mut receiver = Receiver::new();
+
+    let mut sender = Sender::open(&wire2, receiver_id, msg!{}, 4).unwrap();
+    let id = sender.id();
+
+    let open = wire1.wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(matches!(receiver.handle(&wire1, &open), Some(StreamEvent::Open { .. })));
+
+    // 其它 SLOT 冒用同一个 STREAM_ID 发送的消息被忽略
+    let _ = wire3.send(msg!{
+        CHAN: STREAM,
+        TO: receiver_id,
+        STREAM_ID: id,
+        EVENT: "data",
+        SEQ: 0u32,
+        VALUE: vec![1u8, 2, 3, 4]
+    });
+
+    let _ = wire3.send(msg!{
+        CHAN: STREAM,
+        TO: receiver_id,
+        STREAM_ID: id,
+        EVENT: "end",
+        SEQ: 0u32,
+        SIZE: 0u64,
+        DIGEST: vec![0u8; 32]
+    });
+
+    let _ = wire3.send(msg!{
+        CHAN: STREAM,
+        TO: receiver_id,
+        STREAM_ID: id,
+        EVENT: "cancel",
+        REASON: "spoofed"
+    });
+
+    let _ = wire3.send(msg!{
+        CHAN: STREAM,
+        TO: receiver_id,
+        STREAM_ID: id,
+        EVENT: "open"
+    });
+
+    for _ in 0..4 {
+        let message = wire1.wait(Some(Duration::from_secs(1))).unwrap();
+        assert!(receiver.handle(&wire1, &message).is_none());
+    }
+
+    assert!(receiver.len() == 1);
+
+    // 发送方不受影响
+    sender.write(&wire2, b"1234").unwrap();
+    sender.finish(&wire2).unwrap();
+
+    let message = wire1.wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(receiver.handle(&wire1, &message) == Some(StreamEvent::Data { id, data: b"1234".to_vec() }));
+
+    let message = wire1.wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(receiver.handle(&wire1, &message) == Some(StreamEvent::End { id, size: 4 }));
+    assert!(receiver.is_empty());
+}
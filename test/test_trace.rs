@@ -0,0 +1,110 @@
+use std::time::Duration;
+
+use queen::{Socket, Node, Port};
+use queen::nson::{MessageId, msg};
+use queen::net::{NsonCodec, KeepAlive};
+use queen::util::trace::{self, TraceParent};
+use queen::dict::*;
+use queen::error::Code;
+
+use super::get_free_addr;
+
+#[test]
+fn trace_hops() {
+    let socket = Socket::new(MessageId::new(), ()).unwrap();
+
+    let addr = get_free_addr();
+
+    let _node = Node::<NsonCodec>::new(
+        socket.clone(),
+        1,
+        vec![addr.parse().unwrap()],
+        KeepAlive::default(),
+        ()
+    ).unwrap();
+
+    let root = socket.connect(MessageId::new(), true, msg!{}, None, None).unwrap();
+
+    let _ = root.send(msg!{
+        CHAN: ATTACH,
+        VALUE: TRACE_HOP
+    });
+
+    assert!(Code::get(&root.wait(Some(Duration::from_secs(1))).unwrap()) == Some(Code::Ok));
+
+    let port = Port::<NsonCodec>::new(KeepAlive::default()).unwrap();
+
+    let wire1 = port.connect(&addr, MessageId::new(), false, msg!{}, None, None).unwrap();
+    let wire2 = port.connect(&addr, MessageId::new(), false, msg!{}, None, None).unwrap();
+
+    let _ = wire2.send(msg!{
+        CHAN: ATTACH,
+        VALUE: "hello"
+    });
+
+    assert!(Code::get(&wire2.wait(Some(Duration::from_secs(1))).unwrap()) == Some(Code::Ok));
+
+    let mut message = msg!{
+        CHAN: "hello"
+    };
+
+    let start = trace::start(&mut message).unwrap();
+
+    let _ = wire1.send(message);
+
+    let recv = wire2.wait(Some(Duration::from_secs(1))).unwrap();
+
+    let hops: Vec<String> = recv.get_array(HOPS).unwrap()
+        .iter()
+        .map(|hop| hop.as_message().unwrap().get_str(HOP).unwrap().to_string())
+        .collect();
+
+    assert!(hops == vec!["port.send", "node.recv", "switch", "node.send", "port.recv"]);
+
+    let now = TraceParent::parse(recv.get_str(TRACE).unwrap()).unwrap();
+    assert!(now.trace_id == start.trace_id);
+
+    // root receives the node and switch hops, port hops are only in HOPS
+    let mut events = Vec::new();
+
+    for _ in 0..3 {
+        let event = root.wait(Some(Duration::from_secs(1))).unwrap();
+        assert!(event.get_str(CHAN).unwrap() == TRACE_HOP);
+        assert!(event.get_str(TRACE).is_ok());
+
+        let hop = event.get_message(VALUE).unwrap().get_str(HOP).unwrap().to_string();
+        let len = event.get_array(HOPS).unwrap().len();
+
+        events.push((hop, len));
+    }
+
+    assert!(events == vec![("node.recv".to_string(), 2), ("switch".to_string(), 3), ("node.send".to_string(), 4)]);
+
+    // untraced messages are not touched
+    let _ = wire1.send(msg!{
+        CHAN: "hello"
+    });
+
+    let recv = wire2.wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(recv.get(TRACE).is_none());
+    assert!(recv.get(HOPS).is_none());
+
+    assert!(root.wait(Some(Duration::from_millis(100))).is_err());
+
+    // not root
+    let _ = wire1.send(msg!{
+        CHAN: ATTACH,
+        VALUE: TRACE_HOP
+    });
+
+    assert!(Code::get(&wire1.wait(Some(Duration::from_secs(1))).unwrap()) == Some(Code::PermissionDenied));
+
+    // clients can't forge hop events
+    let _ = wire1.send(msg!{
+        CHAN: TRACE_HOP,
+        VALUE: {HOP: "fake"}
+    });
+
+    assert!(root.wait(Some(Duration::from_millis(100))).is_err());
+    assert!(wire1.wait(Some(Duration::from_millis(100))).is_err());
+}
@@ -0,0 +1,127 @@
+use std::time::Duration;
+
+use serde::{Serialize, Deserialize};
+
+use nson::{msg, MessageId};
+
+use queen::{Socket, Switch, Hook, TypedWire};
+use queen::socket::Schema;
+use queen::typed::{to_message, from_message};
+use queen::dict::*;
+use queen::error::{Code, Error, ErrorInfo};
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+struct Temp {
+    sensor: String,
+    value: f64,
+    unit: Option<String>
+}
+
+#[test]
+fn convert() {
+    let temp = Temp {
+        sensor: "a".to_string(),
+        value: 21.5,
+        unit: None
+    };
+
+    let message = to_message(&temp).unwrap();
+    assert!(message.get_str("sensor").unwrap() == "a");
+    assert!(message.get_f64("value").unwrap() == 21.5);
+
+    let temp2: Temp = from_message(message).unwrap();
+    assert!(temp == temp2);
+
+    assert!(to_message(&1).is_err());
+    assert!(from_message::<Temp>(msg!{"sensor": 1}).is_err());
+}
+
+#[test]
+fn typed_wire() {
+    struct MyHook;
+
+    impl Hook for MyHook {
+        fn start(&self, switch: &mut Switch) {
+            switch.set_schema("temp", Schema::from_message(&msg!{
+                "sensor": "string",
+                "value": "number",
+                "unit": "string?"
+            }).unwrap());
+        }
+    }
+
+    let socket = Socket::new(MessageId::new(), MyHook).unwrap();
+
+    let sub: TypedWire<Temp> = TypedWire::new(socket.connect(MessageId::new(), false, msg!{}, None, None).unwrap(), "temp");
+    let publ: TypedWire<Temp> = TypedWire::new(socket.connect(MessageId::new(), false, msg!{}, None, None).unwrap(), "temp");
+
+    sub.attach(Some(Duration::from_secs(1))).unwrap();
+
+    let temp = Temp {
+        sensor: "a".to_string(),
+        value: 21.5,
+        unit: Some("c".to_string())
+    };
+
+    publ.send(&temp).unwrap();
+
+    let recv = sub.wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(recv == temp);
+
+    // malformed message is rejected before fanout
+    let _ = publ.wire().send(msg!{
+        CHAN: "temp",
+        "sensor": "a",
+        "value": "hot"
+    });
+
+    let ret = publ.wait(Some(Duration::from_secs(1)));
+    assert!(matches!(ret, Err(Error::Reply(ref info)) if info.code == Code::SchemaMismatch));
+
+    assert!(sub.wait(Some(Duration::from_millis(100))).is_err());
+
+    // remove schema by CTRL
+    let root = socket.connect(MessageId::new(), true, msg!{}, None, None).unwrap();
+
+    let _ = root.send(msg!{
+        CHAN: CTRL,
+        VALUE: "temp",
+        SCHEMA: null
+    });
+
+    let recv = root.wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(Code::get(&recv) == Some(Code::Ok));
+
+    let _ = publ.wire().send(msg!{
+        CHAN: "temp",
+        "value": "hot"
+    });
+
+    let recv = sub.wire().wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(recv.get_str("value").unwrap() == "hot");
+
+    // set schema by CTRL
+    let _ = root.send(msg!{
+        CHAN: CTRL,
+        VALUE: "temp",
+        SCHEMA: {
+            "value": "f64"
+        }
+    });
+
+    let recv = root.wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(Code::get(&recv) == Some(Code::Ok));
+
+    let _ = publ.wire().send(msg!{
+        CHAN: "temp",
+        "value": 1
+    });
+
+    let recv = publ.wire().wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(Code::get(&recv) == Some(Code::SchemaMismatch));
+
+    let info = ErrorInfo::get(&recv).unwrap();
+    assert!(info.code == Code::SchemaMismatch);
+    assert!(info.reason == "value: expect f64");
+    assert!(info.field.as_deref() == Some("value"));
+}
@@ -0,0 +1,174 @@
+use std::time::Duration;
+
+use queen::{Socket, Node, Port};
+use queen::nson::{MessageId, msg};
+use queen::net::{NsonCodec, KeepAlive};
+use queen::dict::*;
+use queen::error::Code;
+
+use super::get_free_addr;
+
+#[test]
+fn will() {
+    let socket = Socket::new(MessageId::new(), ()).unwrap();
+
+    let sub = socket.connect(MessageId::new(), false, msg!{}, None, None).unwrap();
+
+    let _ = sub.send(msg!{
+        CHAN: ATTACH,
+        VALUE: "status"
+    });
+
+    assert!(Code::get(&sub.wait(Some(Duration::from_secs(1))).unwrap()) == Some(Code::Ok));
+
+    // register at handshake
+    let slot_id = MessageId::new();
+    let device = socket.connect(slot_id, false, msg!{WILL: {CHAN: "status", "online": false}}, None, None).unwrap();
+
+    let _ = device.send(msg!{
+        CHAN: MINE
+    });
+
+    let mine = device.wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(mine.get_message(VALUE).unwrap().get_message(WILL).unwrap().get_str(CHAN).unwrap() == "status");
+
+    drop(device);
+
+    let recv = sub.wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(recv.get_str(CHAN).unwrap() == "status");
+    assert!(recv.get_bool("online").unwrap() == false);
+    assert!(recv.get_message_id(FROM).unwrap() == &slot_id);
+
+    // invalid will
+    assert!(socket.connect(MessageId::new(), false, msg!{WILL: "status"}, None, None).is_err());
+
+    // register at attach, clean detach cancels it
+    let device = socket.connect(MessageId::new(), false, msg!{}, None, None).unwrap();
+
+    let _ = device.send(msg!{
+        CHAN: ATTACH,
+        VALUE: "cmd",
+        WILL: {CHAN: "status", "online": false}
+    });
+
+    assert!(Code::get(&device.wait(Some(Duration::from_secs(1))).unwrap()) == Some(Code::Ok));
+
+    let _ = device.send(msg!{
+        CHAN: DETACH,
+        VALUE: "cmd"
+    });
+
+    assert!(Code::get(&device.wait(Some(Duration::from_secs(1))).unwrap()) == Some(Code::Ok));
+
+    drop(device);
+
+    assert!(sub.wait(Some(Duration::from_millis(100))).is_err());
+
+    // replace and cancel
+    let device = socket.connect(MessageId::new(), false, msg!{}, None, None).unwrap();
+
+    let _ = device.send(msg!{
+        CHAN: WILL,
+        VALUE: {CHAN: "status", "n": 1}
+    });
+
+    assert!(Code::get(&device.wait(Some(Duration::from_secs(1))).unwrap()) == Some(Code::Ok));
+
+    let _ = device.send(msg!{
+        CHAN: WILL,
+        VALUE: {CHAN: "status", "n": 2}
+    });
+
+    assert!(Code::get(&device.wait(Some(Duration::from_secs(1))).unwrap()) == Some(Code::Ok));
+
+    let _ = device.send(msg!{
+        CHAN: WILL,
+        VALUE: {CHAN: SLOT_BREAK}
+    });
+
+    assert!(Code::get(&device.wait(Some(Duration::from_secs(1))).unwrap()) == Some(Code::InvalidWillFieldType));
+
+    drop(device);
+
+    let recv = sub.wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(recv.get_i32("n").unwrap() == 2);
+
+    let device = socket.connect(MessageId::new(), false, msg!{WILL: {CHAN: "status"}}, None, None).unwrap();
+
+    let _ = device.send(msg!{
+        CHAN: WILL,
+        VALUE: null
+    });
+
+    assert!(Code::get(&device.wait(Some(Duration::from_secs(1))).unwrap()) == Some(Code::Ok));
+
+    drop(device);
+
+    assert!(sub.wait(Some(Duration::from_millis(100))).is_err());
+
+    // clean close
+    let device = socket.connect(MessageId::new(), false, msg!{WILL: {CHAN: "status"}}, None, None).unwrap();
+
+    let _ = device.send(msg!{
+        CHAN: CLOSE
+    });
+
+    let recv = device.wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(recv.get_str(CHAN).unwrap() == CLOSE);
+    assert!(recv.get_str(REASON).unwrap() == BREAK_CLOSED);
+
+    assert!(sub.wait(Some(Duration::from_millis(100))).is_err());
+}
+
+#[test]
+fn will_over_network() {
+    let socket = Socket::new(MessageId::new(), ()).unwrap();
+
+    let addr = get_free_addr();
+
+    let _node = Node::<NsonCodec>::new(
+        socket.clone(),
+        1,
+        vec![addr.parse().unwrap()],
+        KeepAlive::default(),
+        ()
+    ).unwrap();
+
+    let sub = socket.connect(MessageId::new(), false, msg!{}, None, None).unwrap();
+
+    let _ = sub.send(msg!{
+        CHAN: ATTACH,
+        VALUE: "status"
+    });
+
+    assert!(Code::get(&sub.wait(Some(Duration::from_secs(1))).unwrap()) == Some(Code::Ok));
+
+    let port = Port::<NsonCodec>::new(KeepAlive::default()).unwrap();
+
+    let slot_id = MessageId::new();
+    let device = port.connect(&addr, slot_id, false, msg!{WILL: {CHAN: "status", "online": false}}, None, None).unwrap();
+
+    drop(device);
+
+    let recv = sub.wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(recv.get_bool("online").unwrap() == false);
+    assert!(recv.get_message_id(FROM).unwrap() == &slot_id);
+
+    // clean close
+    let device = port.connect(&addr, MessageId::new(), false, msg!{WILL: {CHAN: "status"}}, None, None).unwrap();
+
+    let _ = device.send(msg!{
+        CHAN: CLOSE
+    });
+
+    let recv = device.wait(Some(Duration::from_secs(1))).unwrap();
+    assert!(recv.get_str(CHAN).unwrap() == CLOSE);
+    assert!(recv.get_str(REASON).unwrap() == BREAK_CLOSED);
+
+    // port shutdown
+    let _device = port.connect(&addr, MessageId::new(), false, msg!{WILL: {CHAN: "status"}}, None, None).unwrap();
+
+    port.stop();
+
+    assert!(sub.wait(Some(Duration::from_millis(200))).is_err());
+}